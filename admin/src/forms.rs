@@ -1,5 +1,7 @@
 use super::clients::*;
 use super::*;
+use futures::TryStreamExt;
+use rocket::http::ContentType;
 // use rocket::data::ToByteUnit;
 use rocket::fs::TempFile;
 use std::io::Read;
@@ -9,21 +11,65 @@ use std::str::FromStr;
 
 #[derive(FromForm)]
 pub struct Login {
+    name: String,
     pass: String,
 }
 #[post("/form/login", data = "<login>")]
-pub async fn login(cookies: &CookieJar<'_>, login: Form<Strict<Login>>) -> Redirect {
-    if login.pass == PASS {
-        cookies.add_private(Cookie::new("admin", "admin"));
-        Redirect::to(uri!(templates::submissions_template))
-    } else {
-        Redirect::to(uri!(root))
+pub async fn login(
+    cookies: &CookieJar<'_>,
+    contest_client: &State<ContestClient>,
+    login: Form<Strict<Login>>,
+) -> Result<Redirect, status::Custom<String>> {
+    let mut contest_client = contest_client.inner().clone();
+    let response = contest_client
+        .auth_user(tonic::Request::new(contest::AuthUserRequest {
+            username: login.name.clone(),
+            password: login.pass.clone(),
+            admin: Some(true),
+        }))
+        .await
+        .map_err(|err| {
+            status::Custom(
+                Status::InternalServerError,
+                format!("Error in rpc request:\n{:?}", err),
+            )
+        })?;
+    match response.into_inner().response {
+        Some(contest::auth_user_response::Response::Success(success)) => {
+            cookies.add_private(Cookie::new("admin", success.token));
+            Ok(Redirect::to(uri!(templates::submissions_template)))
+        }
+        _ => Ok(Redirect::to(uri!(root))),
+    }
+}
+
+// ends every session of the admin, on any device
+#[get("/form/logout")]
+pub async fn logout(
+    cookies: &CookieJar<'_>,
+    contest_client: &State<ContestClient>,
+) -> Result<Redirect, status::Custom<String>> {
+    if let Some(cookie) = cookies.get_private("admin") {
+        let mut contest_client = contest_client.inner().clone();
+        contest_client
+            .logout(tonic::Request::new(contest::LogoutRequest {
+                token: cookie.value().to_string(),
+            }))
+            .await
+            .map_err(|err| {
+                status::Custom(
+                    Status::InternalServerError,
+                    format!("Error in rpc request:\n{:?}", err),
+                )
+            })?;
     }
+    cookies.remove_private(Cookie::named("admin"));
+    Ok(Redirect::to(uri!(root)))
 }
 
 #[post("/form/update_contest", data = "<contest>")]
 pub async fn update_contest(
-    _admin: Admin,
+    admin: Admin,
     contest: Form<Strict<templates::ContestTemplate>>,
     contest_client: &State<ContestClient>,
     evaluation_client: &State<EvaluationClient>,
@@ -40,8 +86,8 @@ pub async fn update_contest(
     let evaluation_req = evaluation::SetContestRequest::from(contest);
 
     let (contest_response, evaluation_response) = future::join(
-        contest_client.set_contest_metadata(tonic::Request::new(user_req)),
-        evaluation_client.set_contest(tonic::Request::new(evaluation_req)),
+        contest_client.set_contest_metadata(admin.request(user_req)),
+        evaluation_client.set_contest(admin.request(evaluation_req)),
     )
     .await;
 
@@ -54,21 +100,21 @@ pub async fn update_contest(
             ));
         }
     }
-    match evaluation_response {
-        Ok(_) => {}
+    let (recomputed, recompute_error) = match evaluation_response {
+        Ok(response) => {
+            let response = response.into_inner();
+            (response.affected_users, response.recompute_error)
+        }
         Err(err) => {
             return Err(status::Custom(
                 Status::InternalServerError,
                 format!("Error in sending requests :(\n{:?}\n)", err),
             ));
         }
-    }
+    };
 
     for i in problem_reqs {
-        match contest_client
-            .update_problem_info(tonic::Request::new(i))
-            .await
-        {
+        match contest_client.update_problem_info(admin.request(i)).await {
             // TODO: batch
             Ok(_) => {}
             Err(err) => {
@@ -79,6 +125,129 @@ pub async fn update_contest(
             }
         }
     }
+    Ok(Redirect::to(uri!(templates::contest_template(
+        recomputed,
+        recompute_error
+    ))))
+}
+
+#[derive(FromForm)]
+pub struct SetTeam {
+    id: String,
+    name: String,
+    affiliation: String,
+    country: String,
+    members: String, // comma separated usernames
+}
+// empty fields are not set
+fn non_empty(field: &str) -> Option<String> {
+    Some(field.trim().to_string()).filter(|field| !field.is_empty())
+}
+#[post("/form/set_team", data = "<team>")]
+pub async fn set_team(
+    admin: Admin,
+    team: Form<Strict<SetTeam>>,
+    contest_client: &State<ContestClient>,
+) -> Result<Redirect, status::Custom<String>> {
+    let mut contest_client = contest_client.inner().clone();
+    let req = contest::SetTeamRequest {
+        team: contest::Team {
+            id: team.id.clone(),
+            name: team.name.clone(),
+            affiliation: non_empty(&team.affiliation),
+            country: non_empty(&team.country),
+            members: team.members.split(',').filter_map(non_empty).collect(),
+        },
+    };
+    match contest_client.set_team(admin.request(req)).await {
+        Ok(_) => Ok(Redirect::to("/teams")),
+        Err(err) => Err(status::Custom(
+            Status::InternalServerError,
+            format!("Error in rpc request:\n{:?}", err),
+        )),
+    }
+}
+
+#[derive(FromForm)]
+pub struct SetUserWindow {
+    user: String, // username or team id
+    extra_minutes: u64,
+    delay_minutes: u64,
+}
+#[post("/form/set_user_window", data = "<window>")]
+pub async fn set_user_window(
+    admin: Admin,
+    window: Form<Strict<SetUserWindow>>,
+    contest_client: &State<ContestClient>,
+) -> Result<Redirect, status::Custom<String>> {
+    let mut contest_client = contest_client.inner().clone();
+    let minutes = |minutes: u64| Some(std::time::Duration::from_secs(minutes * 60).into());
+    let req = contest::SetUserWindowRequest {
+        window: contest::UserWindow {
+            user: window.user.clone(),
+            extra_time: minutes(window.extra_minutes),
+            start_delay: minutes(window.delay_minutes),
+            started_at: None,
+        },
+    };
+    match contest_client.set_user_window(admin.request(req)).await {
+        Ok(_) => Ok(Redirect::to("/users")),
+        Err(err) => Err(status::Custom(
+            Status::InternalServerError,
+            format!("Error in rpc request:\n{:?}", err),
+        )),
+    }
+}
+
+#[derive(FromForm)]
+pub struct SetEnrollment {
+    user: String,
+    enrolled: bool,
+}
+#[post("/form/set_enrollment", data = "<enrollment>")]
+pub async fn set_enrollment(
+    admin: Admin,
+    enrollment: Form<Strict<SetEnrollment>>,
+    contest_client: &State<ContestClient>,
+) -> Result<Redirect, status::Custom<String>> {
+    let mut contest_client = contest_client.inner().clone();
+    let req = contest::SetEnrollmentRequest {
+        user: enrollment.user.clone(),
+        enrolled: enrollment.enrolled,
+    };
+    match contest_client.set_enrollment(admin.request(req)).await {
+        Ok(_) => Ok(Redirect::to("/users")),
+        Err(err) if err.code() == tonic::Code::NotFound => Err(status::Custom(
+            Status::BadRequest,
+            String::from(err.message()),
+        )),
+        Err(err) => Err(status::Custom(
+            Status::InternalServerError,
+            format!("Error in rpc request:\n{:?}", err),
+        )),
+    }
+}
+
+#[derive(FromForm)]
+pub struct SelectContest {
+    id: String,
+}
+// the pages and forms refer to the selected contest,
+// a new id is created when its settings are saved in the contest page
+#[post("/form/select_contest", data = "<contest>")]
+pub async fn select_contest(
+    _admin: Admin,
+    cookies: &CookieJar<'_>,
+    contest: Form<Strict<SelectContest>>,
+) -> Result<Redirect, status::Custom<String>> {
+    let id = contest.id.trim();
+    if !protos::scope::is_valid(id) {
+        return Err(status::Custom(
+            Status::BadRequest,
+            String::from("Contest ids are made of letters, digits, - and _"),
+        ));
+    }
+    cookies.add_private(Cookie::new("contest", id.to_string()));
     Ok(Redirect::to("/contest"))
 }
 
@@ -87,10 +256,11 @@ pub struct SetUser {
     username: String,
     fullname: String,
     password: String,
+    admin: Option<bool>, // an admin account instead of a participant one
 }
 #[post("/form/set_user", data = "<user>")]
 pub async fn set_user(
-    _admin: Admin,
+    admin: Admin,
     user: Form<Strict<SetUser>>,
     contest_client: &State<ContestClient>,
 ) -> Result<Redirect, status::Custom<String>> {
@@ -99,8 +269,9 @@ pub async fn set_user(
         username: user.username.clone(),
         fullname: user.fullname.clone(),
         password: user.password.clone(),
+        admin: user.admin,
     };
-    match contest_client.set_user(tonic::Request::new(req)).await {
+    match contest_client.set_user(admin.request(req)).await {
         Ok(_) => Ok(Redirect::to("/users")),
         Err(err) => Err(status::Custom(
             Status::InternalServerError,
@@ -119,7 +290,7 @@ pub struct Reply {
 }
 #[post("/form/reply", data = "<message>")]
 pub async fn reply(
-    _admin: Admin,
+    admin: Admin,
     message: Form<Strict<Reply>>,
     contest_client: &State<ContestClient>,
 ) -> Result<Redirect, status::Custom<String>> {
@@ -139,7 +310,7 @@ pub async fn reply(
             sent_at: SystemTime::now().into(),
         },
     };
-    match contest_client.add_message(tonic::Request::new(req)).await {
+    match contest_client.add_message(admin.request(req)).await {
         Ok(_) => Ok(Redirect::to(uri!(templates::questions_template))),
         Err(err) => Err(status::Custom(
             Status::InternalServerError,
@@ -148,6 +319,35 @@ pub async fn reply(
     }
 }
 
+// Opens a file uploaded with a form, which rocket keeps in a temporary path
+fn open_upload(file: &TempFile<'_>) -> Result<std::fs::File, status::Custom<String>> {
+    file.path()
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+        .and_then(std::fs::File::open)
+        .map_err(upload_error)
+}
+fn read_upload(file: &TempFile<'_>) -> Result<Vec<u8>, status::Custom<String>> {
+    let mut content = Vec::<u8>::new();
+    open_upload(file)?
+        .read_to_end(&mut content)
+        .map_err(upload_error)?;
+    Ok(content)
+}
+// All the chunks are read before sending, so a read error is not sent as a truncated file
+fn upload_chunks(
+    file: &TempFile<'_>,
+) -> Result<Vec<protos::common::FileChunk>, status::Custom<String>> {
+    utils::chunks::ChunkReader::new(open_upload(file)?)
+        .collect::<Result<_, _>>()
+        .map_err(upload_error)
+}
+fn upload_error(err: std::io::Error) -> status::Custom<String> {
+    status::Custom(
+        Status::InternalServerError,
+        format!("Error reading the uploaded file:\n{:?}", err),
+    )
+}
+
 //TODO: remove unwraps
 
 #[derive(FromForm)]
@@ -163,9 +363,7 @@ pub async fn set_evaluation_file(
     evaluation_client: &State<EvaluationClient>,
 ) -> Result<Redirect, status::Custom<String>> {
     let mut evaluation_client = evaluation_client.inner().clone();
-    let mut file = std::fs::File::open(data.file.path().unwrap()).unwrap();
-    let mut raw = Vec::<u8>::new();
-    file.read_to_end(&mut raw).unwrap();
+    let raw = read_upload(&data.file)?;
     let stuff = evaluation::EvaluationFile {
         r#type: evaluation::evaluation_file::Type::from_str(data.file_type.as_str()).unwrap()
             as i32,
@@ -196,9 +394,9 @@ pub async fn set_evaluation_file(
 #[derive(FromForm)]
 pub struct AddTestcase<'v> {
     problem_id: u64,
-    #[allow(dead_code)]
     subtask_id: u64,
     file: TempFile<'v>,
+    output: Option<TempFile<'v>>,
 }
 #[post("/form/add_testcase", data = "<data>")]
 pub async fn add_testcase(
@@ -206,24 +404,89 @@ pub async fn add_testcase(
     evaluation_client: &State<EvaluationClient>,
 ) -> Result<Redirect, status::Custom<String>> {
     let mut evaluation_client = evaluation_client.inner().clone();
-    let mut file = std::fs::File::open(data.file.path().unwrap()).unwrap();
-    let mut raw = Vec::<u8>::new();
-    file.read_to_end(&mut raw).unwrap();
-    let stuff = evaluation::Testcase {
-        id: utils::gen_uuid(),
-        input: Some(raw),
-        output: None,
+    let testcase_id = utils::gen_uuid();
+    let header = evaluation::SetTestcaseStreamRequest {
+        message: Some(evaluation::set_testcase_stream_request::Message::Header(
+            evaluation::set_testcase_stream_request::Header {
+                problem_id: data.problem_id,
+                subtask_id: data.subtask_id,
+                testcase_id,
+                command: evaluation::set_testcase_stream_request::header::Command::Add as i32,
+            },
+        )),
     };
-    let req = evaluation::SetTestcaseRequest {
-        problem_id: data.problem_id,
-        subtask_id: data.subtask_id,
-        command: Some(evaluation::set_testcase_request::Command::AddTestcase(
-            stuff,
+    let mut files = vec![(
+        evaluation::testcase_chunk::Part::Input,
+        upload_chunks(&data.file)?,
+    )];
+    if let Some(output) = data.output.as_ref().filter(|output| output.len() > 0) {
+        files.push((
+            evaluation::testcase_chunk::Part::Output,
+            upload_chunks(output)?,
+        ));
+    }
+    let chunks = files.into_iter().flat_map(move |(part, chunks)| {
+        chunks
+            .into_iter()
+            .map(move |chunk| evaluation::SetTestcaseStreamRequest {
+                message: Some(evaluation::set_testcase_stream_request::Message::Chunk(
+                    evaluation::TestcaseChunk {
+                        testcase_id,
+                        part: part as i32,
+                        chunk,
+                    },
+                )),
+            })
+    });
+    match set_testcase_stream(
+        &mut evaluation_client,
+        std::iter::once(header).chain(chunks),
+    )
+    .await
+    {
+        Ok(_) => Ok(Redirect::to("/problem_files")),
+        Err(err) => Err(status::Custom(
+            Status::InternalServerError,
+            format!("Error in rpc request:\n{:?}", err),
+        )),
+    }
+}
+
+#[derive(FromForm)]
+pub struct ImportProblem<'v> {
+    problem_id: Option<u64>,
+    format: String,
+    file: TempFile<'v>,
+}
+#[post("/form/import_problem", data = "<data>")]
+pub async fn import_problem(
+    admin: Admin,
+    data: Form<Strict<ImportProblem<'_>>>,
+    evaluation_client: &State<EvaluationClient>,
+) -> Result<Redirect, status::Custom<String>> {
+    let mut evaluation_client = evaluation_client.inner().clone();
+    let format = evaluation::import_problem_request::header::Format::from_str(&data.format)
+        .map_err(|err| status::Custom(Status::BadRequest, format!("{:?}", err)))?;
+    let header = evaluation::ImportProblemRequest {
+        message: Some(evaluation::import_problem_request::Message::Header(
+            evaluation::import_problem_request::Header {
+                problem_id: data.problem_id,
+                format: format as i32,
+            },
         )),
     };
-    match evaluation_client
-        .set_testcase(tonic::Request::new(req))
-        .await
+    let chunks =
+        upload_chunks(&data.file)?
+            .into_iter()
+            .map(|chunk| evaluation::ImportProblemRequest {
+                message: Some(evaluation::import_problem_request::Message::Chunk(chunk)),
+            });
+    match import_problem_stream(
+        &mut evaluation_client,
+        &admin.contest,
+        std::iter::once(header).chain(chunks),
+    )
+    .await
     {
         Ok(_) => Ok(Redirect::to("/problem_files")),
         Err(err) => Err(status::Custom(
@@ -233,6 +496,76 @@ pub async fn add_testcase(
     }
 }
 
+#[derive(FromForm)]
+pub struct RollbackProblem {
+    problem_id: u64,
+    version: u64,
+}
+#[post("/form/rollback_problem", data = "<data>")]
+pub async fn rollback_problem(
+    admin: Admin,
+    data: Form<Strict<RollbackProblem>>,
+    evaluation_client: &State<EvaluationClient>,
+) -> Result<Redirect, status::Custom<String>> {
+    let mut evaluation_client = evaluation_client.inner().clone();
+    let req = evaluation::RollbackProblemRequest {
+        problem_id: data.problem_id,
+        version: data.version,
+    };
+    match evaluation_client.rollback_problem(admin.request(req)).await {
+        Ok(_) => Ok(Redirect::to(format!(
+            "/problem_versions/{}",
+            data.problem_id
+        ))),
+        Err(err) => Err(status::Custom(
+            Status::InternalServerError,
+            format!("Error in rpc request:\n{:?}", err),
+        )),
+    }
+}
+
+// reveals the next result of the public scoreboard, or every result if none is pending
+#[post("/form/reveal_next")]
+pub async fn reveal_next(
+    admin: Admin,
+    submission_client: &State<SubmissionClient>,
+    contest_client: &State<ContestClient>,
+) -> Result<Redirect, status::Custom<String>> {
+    let scoreboard = templates::get_icpc_scoreboard(&admin, submission_client, true).await?;
+    let result = utils::scoring::next_to_reveal(&scoreboard.users).map(|(user, problem_id)| {
+        contest::get_scoreboard_freeze_response::Result { user, problem_id }
+    });
+    unfreeze(&admin, contest_client, result).await
+}
+#[post("/form/unfreeze")]
+pub async fn unfreeze_all(
+    admin: Admin,
+    contest_client: &State<ContestClient>,
+) -> Result<Redirect, status::Custom<String>> {
+    unfreeze(&admin, contest_client, None).await
+}
+async fn unfreeze(
+    admin: &Admin,
+    contest_client: &State<ContestClient>,
+    result: Option<contest::get_scoreboard_freeze_response::Result>,
+) -> Result<Redirect, status::Custom<String>> {
+    let mut contest_client = contest_client.inner().clone();
+    match contest_client
+        .unfreeze_scoreboard(admin.request(contest::UnfreezeScoreboardRequest { result }))
+        .await
+    {
+        Ok(_) => Ok(Redirect::to(uri!(templates::resolver_template))),
+        Err(err) if err.code() == tonic::Code::FailedPrecondition => Err(status::Custom(
+            Status::BadRequest,
+            String::from(err.message()),
+        )),
+        Err(err) => Err(status::Custom(
+            Status::InternalServerError,
+            format!("Error in rpc request:\n{:?}", err),
+        )),
+    }
+}
+
 #[derive(FromForm)]
 pub struct SetStatement<'v> {
     problem_id: u64,
@@ -244,9 +577,7 @@ pub async fn set_statement(
     contest_client: &State<ContestClient>,
 ) -> Result<Redirect, status::Custom<String>> {
     let mut contest_client = contest_client.inner().clone();
-    let mut file = std::fs::File::open(data.file.path().unwrap()).unwrap();
-    let mut statement = Vec::<u8>::new();
-    file.read_to_end(&mut statement).unwrap();
+    let statement = read_upload(&data.file)?;
     let req = contest::UpdateProblemStatementRequest {
         problem_id: data.problem_id,
         statement,
@@ -303,3 +634,67 @@ pub async fn get_testcase(
         .map(|x| String::from_utf8(x.into_inner().testcase.input.unwrap_or_default()).ok()) // TODO: remove unwrap_or_default, return something better than a string(?), testcase output
         .flatten()
 }
+
+#[get("/form/export_problem/<problem_id>?<version>")]
+pub async fn export_problem(
+    admin: Admin,
+    problem_id: u64,
+    version: Option<u64>,
+    evaluation_client: &State<EvaluationClient>,
+) -> Result<(ContentType, Vec<u8>), status::Custom<String>> {
+    let mut evaluation_client = evaluation_client.inner().clone();
+    let rpc_error = |err| {
+        status::Custom(
+            Status::InternalServerError,
+            format!("Error in rpc request:\n{:?}", err),
+        )
+    };
+    let mut stream = evaluation_client
+        .export_problem(admin.request(evaluation::ExportProblemRequest {
+            problem_id,
+            version,
+        }))
+        .await
+        .map_err(rpc_error)?
+        .into_inner();
+    let mut archive = utils::chunks::ChunkWriter::new(Vec::new());
+    while let Some(chunk) = stream.try_next().await.map_err(rpc_error)? {
+        archive
+            .write_chunk(&chunk)
+            .map_err(|err| status::Custom(Status::InternalServerError, format!("{:?}", err)))?;
+    }
+    archive
+        .finish()
+        .map(|archive| (ContentType::ZIP, archive))
+        .map_err(|err| status::Custom(Status::InternalServerError, format!("{:?}", err)))
+}
+
+// format is one of submission::export_results_request::Format
+#[get("/form/export_results?<format>")]
+pub async fn export_results(
+    admin: Admin,
+    format: String,
+    submission_client: &State<SubmissionClient>,
+) -> Result<(ContentType, Vec<u8>), status::Custom<String>> {
+    use submission::export_results_request::Format;
+    let mut submission_client = submission_client.inner().clone();
+    let format = Format::from_str(&format)
+        .map_err(|_| status::Custom(Status::BadRequest, format!("Unknown format {}", format)))?;
+    let content_type = match format {
+        Format::StandingsCsv | Format::SubmissionsCsv => ContentType::CSV,
+        Format::Json => ContentType::JSON,
+        Format::ClicsEventFeed => ContentType::new("application", "x-ndjson"),
+    };
+    submission_client
+        .export_results(admin.request(submission::ExportResultsRequest {
+            format: format as i32,
+        }))
+        .await
+        .map(|response| (content_type, response.into_inner().data))
+        .map_err(|err| {
+            status::Custom(
+                Status::InternalServerError,
+                format!("Error in rpc request:\n{:?}", err),
+            )
+        })
+}
@@ -14,7 +14,7 @@ use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use utils::gen_uuid;
 
-const PASS: &str = "1234";
+use clients::*;
 
 #[cfg(feature = "mock")]
 mod clients {
@@ -44,6 +44,36 @@ mod clients {
         }
         mock.get_announcement_list_set(al);
 
+        mock.unfreeze_scoreboard_set(contest::UnfreezeScoreboardResponse {});
+
+        mock.auth_user_set(contest::AuthUserResponse {
+            response: Some(contest::auth_user_response::Response::Success(
+                contest::auth_user_response::Success {
+                    username: String::from("admin"),
+                    fullname: String::from("admin"),
+                    token: String::from("token"),
+                },
+            )),
+        });
+        mock.validate_session_set(contest::ValidateSessionResponse {
+            username: String::from("admin"),
+            admin: true,
+            team: None,
+            contests: vec![],
+        });
+        mock.logout_set(contest::LogoutResponse {});
+        mock.set_user_set(contest::SetUserResponse {
+            code: contest::set_user_response::Code::Add as i32,
+        });
+        mock.set_team_set(contest::SetTeamResponse {
+            code: contest::set_user_response::Code::Add as i32,
+        });
+        mock.get_team_list_set(Faker.fake());
+        mock.get_user_windows_set(Faker.fake());
+        mock.set_user_window_set(contest::SetUserWindowResponse {});
+        mock.get_contest_list_set(Faker.fake());
+        mock.set_enrollment_set(contest::SetEnrollmentResponse {});
+
         /*mock.get_problem_set(contest::GetProblemResponse {
             info: contest::Problem {
                 id: 42,
@@ -59,6 +89,9 @@ mod clients {
                 description: String::from("wow awesome contest"),
                 start_time: Some(std::time::SystemTime::now().into()),
                 end_time: None,
+                freeze: None,
+                tokens: None,
+                window: None,
             },
             problems: Faker.fake(),
         });
@@ -74,9 +107,42 @@ mod clients {
                 problem_id: 2,
                 state: submission::SubmissionState::Evaluated as i32,
                 timestamp: std::time::SystemTime::now().into(),
-                score: Some(protos::common::Score { score: 42.69 }),
+                score: Some(protos::common::Score {
+                    score: 42.69,
+                    ..Default::default()
+                }),
+                selected: None,
+                token: Some(true),
             }],
         });
+        mock.get_icpc_scoreboard_set(submission::GetIcpcScoreboardResponse {
+            users: vec![submission::get_icpc_scoreboard_response::User {
+                user: String::from("pippo"),
+                solved: 1,
+                penalty_minutes: 62,
+                problems: vec![
+                    submission::get_icpc_scoreboard_response::Problem {
+                        problem_id: 2,
+                        rejected: 1,
+                        solved_at: Some(std::time::Duration::from_secs(42 * 60).into()),
+                        first_to_solve: Some(true),
+                        pending: None,
+                    },
+                    submission::get_icpc_scoreboard_response::Problem {
+                        problem_id: 3,
+                        rejected: 2,
+                        solved_at: None,
+                        first_to_solve: Some(false),
+                        pending: Some(1),
+                    },
+                ],
+            }],
+        });
+        mock.export_results_set(submission::ExportResultsResponse {
+            data: "rank,user,solved,penalty\n1,pippo,1,62\n"
+                .as_bytes()
+                .to_vec(),
+        });
         mock.get_submission_details_set(submission::GetSubmissionDetailsResponse {
             sub: protos::evaluation::Submission {
                 user: String::from("pippo"),
@@ -89,6 +155,7 @@ mod clients {
                 },
             },
             state: protos::service::submission::SubmissionState::Evaluated as i32,
+            token: Some(std::time::SystemTime::now().into()),
             res: Some(protos::evaluation::EvaluationResult {
                 compilation_result: protos::evaluation::CompilationResult {
                     outcome: protos::evaluation::compilation_result::Outcome::Success as i32,
@@ -100,16 +167,26 @@ mod clients {
                         testcase_results: vec![
                             protos::evaluation::TestcaseResult {
                                 outcome: protos::evaluation::testcase_result::Outcome::Ok as i32,
-                                score: protos::common::Score { score: 1.0 },
+                                score: protos::common::Score {
+                                    score: 1.0,
+                                    ..Default::default()
+                                },
                                 ..Default::default()
                             };
                             9
                         ],
-                        score: protos::common::Score { score: 1.0 },
+                        score: protos::common::Score {
+                            score: 1.0,
+                            ..Default::default()
+                        },
                     };
                     5
                 ],
-                score: protos::common::Score { score: 3.3 },
+                score: protos::common::Score {
+                    score: 3.3,
+                    ..Default::default()
+                },
+                problem_version: Some(2),
             }),
         });
         mock
@@ -136,22 +213,61 @@ mod clients {
                 user_scoring_method: protos::scoring::User {
                     main: protos::scoring::user::Method::default(),
                     tiebreakers: vec![protos::scoring::user::Method::default(); 4],
+                    icpc: None,
                 },
             },
         });
+        mock.get_problem_versions_set(evaluation::GetProblemVersionsResponse {
+            versions: vec![
+                evaluation::ProblemVersion {
+                    version: 1,
+                    created: std::time::SystemTime::now().into(),
+                    description: String::from("Import package"),
+                },
+                evaluation::ProblemVersion {
+                    version: 2,
+                    created: std::time::SystemTime::now().into(),
+                    description: String::from("Update testcase 1"),
+                },
+            ],
+        });
+        mock.diff_problem_versions_set(evaluation::DiffProblemVersionsResponse {
+            changes: vec![evaluation::diff_problem_versions_response::FileChange {
+                path: String::from("testcases/1/input.txt"),
+                change: evaluation::diff_problem_versions_response::file_change::Change::Modified
+                    as i32,
+            }],
+        });
+        mock.rollback_problem_set(evaluation::RollbackProblemResponse { version: 3 });
         mock
     }
+    // the mock server cannot receive a client stream, so the messages are just consumed
+    pub async fn set_testcase_stream(
+        _client: &mut EvaluationClient,
+        messages: impl Iterator<Item = evaluation::SetTestcaseStreamRequest> + Send + Sync + 'static,
+    ) -> Result<evaluation::SetTestcaseResponse, tonic::Status> {
+        eprintln!("set_testcase_stream: sent {} messages", messages.count());
+        Ok(evaluation::SetTestcaseResponse {})
+    }
+    pub async fn import_problem_stream(
+        _client: &mut EvaluationClient,
+        _contest_id: &str,
+        messages: impl Iterator<Item = evaluation::ImportProblemRequest> + Send + Sync + 'static,
+    ) -> Result<evaluation::ImportProblemResponse, tonic::Status> {
+        eprintln!("import_problem: sent {} messages", messages.count());
+        Ok(evaluation::ImportProblemResponse::default())
+    }
 }
 
 #[cfg(not(feature = "mock"))]
 mod clients {
     use protos::service::{contest, evaluation, submission};
     // clients for production
-    pub type ContestClient = contest::contest_client::ContestClient<tonic::transport::Channel>;
+    pub type ContestClient = contest::contest_client::ContestClient<protos::utils::AuthChannel>;
     pub type SubmissionClient =
-        submission::submission_client::SubmissionClient<tonic::transport::Channel>;
+        submission::submission_client::SubmissionClient<protos::utils::AuthChannel>;
     pub type EvaluationClient =
-        evaluation::evaluation_client::EvaluationClient<tonic::transport::Channel>;
+        evaluation::evaluation_client::EvaluationClient<protos::utils::AuthChannel>;
     pub fn get_contest_client() -> ContestClient {
         ContestClient::new(protos::utils::get_new_channel(
             protos::utils::Service::CONTEST,
@@ -167,18 +283,77 @@ mod clients {
             protos::utils::Service::EVALUATION,
         ))
     }
+    pub async fn set_testcase_stream(
+        client: &mut EvaluationClient,
+        messages: impl Iterator<Item = evaluation::SetTestcaseStreamRequest> + Send + Sync + 'static,
+    ) -> Result<evaluation::SetTestcaseResponse, tonic::Status> {
+        client
+            .set_testcase_stream(tonic::Request::new(futures::stream::iter(messages)))
+            .await
+            .map(|response| response.into_inner())
+    }
+    pub async fn import_problem_stream(
+        client: &mut EvaluationClient,
+        contest_id: &str,
+        messages: impl Iterator<Item = evaluation::ImportProblemRequest> + Send + Sync + 'static,
+    ) -> Result<evaluation::ImportProblemResponse, tonic::Status> {
+        client
+            .import_problem(protos::scope::in_contest(
+                tonic::Request::new(futures::stream::iter(messages)),
+                contest_id,
+            ))
+            .await
+            .map(|response| response.into_inner())
+    }
 }
 
 // TODO: fix pub
-pub struct Admin {}
+// the cookie keeps the token of the session, validated by the contest service on each request
+pub struct Admin {
+    pub username: String,
+    pub contest: String, // chosen in the contests page, the default contest if none
+}
+impl Admin {
+    /// The rpc request, referring to the contest chosen by the admin
+    pub fn request<T>(&self, message: T) -> tonic::Request<T> {
+        protos::scope::in_contest(tonic::Request::new(message), &self.contest)
+    }
+}
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for Admin {
     type Error = ();
     async fn from_request(request: &'r Request<'_>) -> request::Outcome<Admin, ()> {
-        request
+        let session = request
+            .local_cache_async(async {
+                let token = request.cookies().get_private("admin")?.value().to_string();
+                let mut contest_client = request
+                    .guard::<&State<ContestClient>>()
+                    .await
+                    .succeeded()?
+                    .inner()
+                    .clone();
+                contest_client
+                    .validate_session(tonic::Request::new(contest::ValidateSessionRequest {
+                        token,
+                    }))
+                    .await
+                    .ok()
+                    .map(|response| response.into_inner())
+                    .filter(|session| session.admin)
+            })
+            .await;
+        let contest = request
             .cookies()
-            .get_private("admin")
-            .map(|_cookie| Admin {})
+            .get_private("contest")
+            .map(|cookie| cookie.value().to_string())
+            .filter(|contest| protos::scope::is_valid(contest))
+            .unwrap_or_else(|| protos::scope::DEFAULT_CONTEST.to_string());
+        session
+            .as_ref()
+            .map(|session| Admin {
+                username: session.username.clone(),
+                contest,
+            })
             .or_forward(())
     }
 }
@@ -211,13 +386,31 @@ async fn statics_redirect(_path: PathBuf) -> Redirect {
     Redirect::to(uri!(root))
 }
 
+const DEFAULT_SCORE_DECIMALS: usize = 2;
+
+// `score_decimals` in Rocket.toml sets the rounding of the displayed scores
+fn score_filter(decimals: usize) -> impl rocket_dyn_templates::tera::Filter {
+    use rocket_dyn_templates::tera::{from_value, Value};
+    move |value: &Value, _: &std::collections::HashMap<String, Value>| {
+        let score: protos::common::Score = from_value(value.clone())?;
+        Ok(Value::String(utils::scoring::format_score(
+            &score, decimals,
+        )))
+    }
+}
+
 // main
 #[launch]
 fn rocket() -> _ {
     let contest_client = clients::get_contest_client();
     let submission_client = clients::get_submission_client();
     let evaluation_client = clients::get_evaluation_client();
-    rocket::build()
+    let rocket = rocket::build();
+    let score_decimals = rocket
+        .figment()
+        .extract_inner("score_decimals")
+        .unwrap_or(DEFAULT_SCORE_DECIMALS);
+    rocket
         .manage(contest_client)
         .manage(submission_client)
         .manage(evaluation_client)
@@ -229,21 +422,41 @@ fn rocket() -> _ {
                 statics,
                 statics_redirect,
                 templates::users_template,
+                templates::contests_template,
+                templates::teams_template,
                 templates::problem_files_template,
                 templates::questions_template,
                 templates::submissions_template,
+                templates::scoreboard_template,
+                templates::resolver_template,
                 templates::submission_details_template,
                 templates::contest_template,
+                templates::problem_versions_template,
                 forms::update_contest,
                 forms::reply,
                 forms::set_user,
+                forms::set_team,
+                forms::set_user_window,
+                forms::set_enrollment,
+                forms::select_contest,
                 forms::login,
+                forms::logout,
                 forms::set_evaluation_file,
                 forms::add_testcase,
+                forms::import_problem,
+                forms::export_problem,
+                forms::export_results,
+                forms::rollback_problem,
+                forms::reveal_next,
+                forms::unfreeze_all,
                 forms::set_statement,
                 forms::get_evaluation_file,
                 forms::get_testcase,
             ],
         )
-        .attach(Template::fairing())
+        .attach(Template::custom(move |engines| {
+            engines
+                .tera
+                .register_filter("score", score_filter(score_decimals))
+        }))
 }
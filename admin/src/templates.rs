@@ -1,11 +1,157 @@
 use super::clients::*;
 use super::*;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
 
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateUserWindow {
+    user: String,
+    extra_minutes: u64,
+    delay_minutes: u64,
+    started_at: Option<String>,
+}
+impl From<contest::UserWindow> for TemplateUserWindow {
+    fn from(w: contest::UserWindow) -> Self {
+        let minutes = |d: Option<protos::common::Duration>| d.map_or(0, |d| d.secs / 60);
+        Self {
+            user: w.user,
+            extra_minutes: minutes(w.extra_time),
+            delay_minutes: minutes(w.start_delay),
+            started_at: w
+                .started_at
+                .map(|t| utils::render_protos_timestamp(t, "%F %X")),
+        }
+    }
+}
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateUserWindows {
+    contest: String,
+    windows: Vec<TemplateUserWindow>,
+}
 #[get("/users")]
-pub async fn users_template(_admin: Admin) -> Result<Template, status::Custom<String>> {
+pub async fn users_template(
+    admin: Admin,
+    contest_client: &State<ContestClient>,
+) -> Result<Template, status::Custom<String>> {
+    let mut contest_client = contest_client.inner().clone();
+    let windows = contest_client
+        .get_user_windows(admin.request(contest::GetUserWindowsRequest { user: None }))
+        .await
+        .map_err(|err| {
+            status::Custom(
+                Status::InternalServerError,
+                format!("Error in rpc request:\n{:?}", err),
+            )
+        })?
+        .into_inner()
+        .windows;
     Ok(Template::render(
         "users",
-        std::collections::HashMap::<String, String>::new(),
+        TemplateUserWindows {
+            contest: admin.contest,
+            windows: windows.into_iter().map(TemplateUserWindow::from).collect(),
+        },
+    ))
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateContestItem {
+    id: String,
+    name: String,
+}
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateContests {
+    current: String,
+    contests: Vec<TemplateContestItem>,
+}
+#[get("/contests")]
+pub async fn contests_template(
+    admin: Admin,
+    contest_client: &State<ContestClient>,
+) -> Result<Template, status::Custom<String>> {
+    let mut contest_client = contest_client.inner().clone();
+    let contests = contest_client
+        .get_contest_list(admin.request(contest::GetContestListRequest { user: None }))
+        .await
+        .map_err(|err| {
+            status::Custom(
+                Status::InternalServerError,
+                format!("Error in rpc request:\n{:?}", err),
+            )
+        })?
+        .into_inner()
+        .contests;
+    Ok(Template::render(
+        "contests",
+        TemplateContests {
+            current: admin.contest,
+            contests: contests
+                .into_iter()
+                .map(|contest| TemplateContestItem {
+                    id: contest.id,
+                    name: contest.metadata.name,
+                })
+                .collect(),
+        },
+    ))
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateTeam {
+    id: String,
+    name: String,
+    affiliation: String,
+    country: String,
+    members: String, // comma separated, as in the form
+}
+impl From<contest::Team> for TemplateTeam {
+    fn from(t: contest::Team) -> Self {
+        Self {
+            id: t.id,
+            name: t.name,
+            affiliation: t.affiliation.unwrap_or_default(),
+            country: t.country.unwrap_or_default(),
+            members: t.members.join(", "),
+        }
+    }
+}
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateTeams {
+    teams: Vec<TemplateTeam>,
+}
+pub async fn get_team_list(
+    admin: &Admin,
+    contest_client: &State<ContestClient>,
+) -> Result<Vec<contest::Team>, status::Custom<String>> {
+    let mut contest_client = contest_client.inner().clone();
+    contest_client
+        .get_team_list(admin.request(contest::GetTeamListRequest {}))
+        .await
+        .map(|response| response.into_inner().teams)
+        .map_err(|err| {
+            status::Custom(
+                Status::InternalServerError,
+                format!("Error in rpc request:\n{:?}", err),
+            )
+        })
+}
+#[get("/teams")]
+pub async fn teams_template(
+    admin: Admin,
+    contest_client: &State<ContestClient>,
+) -> Result<Template, status::Custom<String>> {
+    let teams = get_team_list(&admin, contest_client).await?;
+    Ok(Template::render(
+        "teams",
+        TemplateTeams {
+            teams: teams.into_iter().map(TemplateTeam::from).collect(),
+        },
     ))
 }
 
@@ -35,11 +181,43 @@ impl From<Resources> for protos::common::Resources {
     }
 }
 
+// Empty fields keep the limit of the problem
+#[derive(Serialize, FromForm, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct LimitsOverride {
+    nanos: Option<u64>,
+    bytes: Option<u64>,
+}
+impl LimitsOverride {
+    fn is_empty(&self) -> bool {
+        self.nanos.is_none() && self.bytes.is_none()
+    }
+}
+impl From<evaluation::LimitsOverride> for LimitsOverride {
+    fn from(l: evaluation::LimitsOverride) -> Self {
+        Self {
+            nanos: l.time.map(|t| t.nanos as u64 + t.secs * 1000000000),
+            bytes: l.memory_bytes,
+        }
+    }
+}
+impl From<LimitsOverride> for evaluation::LimitsOverride {
+    fn from(l: LimitsOverride) -> Self {
+        Self {
+            time: l.nanos.map(|nanos| protos::common::Duration {
+                nanos: (nanos % 1000000000) as u32,
+                secs: nanos / 1000000000,
+            }),
+            memory_bytes: l.bytes,
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 struct TestcaseResult {
     outcome: String,
-    score: String,
+    score: protos::common::Score,
     resources: Resources,
 }
 impl From<protos::evaluation::TestcaseResult> for TestcaseResult {
@@ -49,8 +227,8 @@ impl From<protos::evaluation::TestcaseResult> for TestcaseResult {
                 "{:?}",
                 protos::evaluation::testcase_result::Outcome::from_i32(tr.outcome).unwrap()
             ),
-            score: format!("{:?}", tr.score),
-            resources: tr.used_resources.into(),
+            score: tr.score,
+            resources: tr.used_resources.unwrap_or_default().into(),
         }
     }
 }
@@ -58,13 +236,13 @@ impl From<protos::evaluation::TestcaseResult> for TestcaseResult {
 #[derive(Serialize, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 struct SubtaskResult {
-    score: String,
+    score: protos::common::Score,
     testcase_results: Vec<TestcaseResult>,
 }
 impl From<protos::evaluation::SubtaskResult> for SubtaskResult {
     fn from(sr: protos::evaluation::SubtaskResult) -> Self {
         Self {
-            score: format!("{:?}", sr.score),
+            score: sr.score,
             testcase_results: sr
                 .testcase_results
                 .into_iter()
@@ -97,19 +275,21 @@ impl From<protos::evaluation::CompilationResult> for CompilationResult {
 #[serde(crate = "rocket::serde")]
 struct EvaluationResult {
     compilation: CompilationResult,
-    score: String,
+    score: protos::common::Score,
     subtask_results: Vec<SubtaskResult>,
+    problem_version: Option<u64>,
 }
 impl From<protos::evaluation::EvaluationResult> for EvaluationResult {
     fn from(er: protos::evaluation::EvaluationResult) -> Self {
         Self {
             compilation: er.compilation_result.into(),
-            score: format!("{:?}", er.score),
+            score: er.score,
             subtask_results: er
                 .subtask_results
                 .into_iter()
                 .map(SubtaskResult::from)
                 .collect(),
+            problem_version: er.problem_version,
         }
     }
 }
@@ -123,6 +303,7 @@ struct SubmissionDetails {
     lang: String,
     code: String,
     evaluation: Option<EvaluationResult>,
+    token: Option<String>, // when the user used a token on it
 }
 impl From<submission::GetSubmissionDetailsResponse> for SubmissionDetails {
     fn from(res: submission::GetSubmissionDetailsResponse) -> Self {
@@ -140,21 +321,25 @@ impl From<submission::GetSubmissionDetailsResponse> for SubmissionDetails {
             code: String::from_utf8(res.sub.source.code.clone())
                 .unwrap_or(format!("{:?}", res.sub.source.code)),
             evaluation: res.res.map(EvaluationResult::from),
+            token: res
+                .token
+                .map(|t| utils::render_protos_timestamp(t, "%F %X")),
         }
     }
 }
 
 #[get("/submission/<id>")]
 pub async fn submission_details_template(
-    _admin: Admin,
+    admin: Admin,
     submission_client: &State<SubmissionClient>,
     id: u64,
 ) -> Result<Template, status::Custom<String>> {
     let mut submission_client = submission_client.inner().clone();
     match submission_client
-        .get_submission_details(tonic::Request::new(
-            submission::GetSubmissionDetailsRequest { submission_id: id },
-        ))
+        .get_submission_details(admin.request(submission::GetSubmissionDetailsRequest {
+            submission_id: id,
+            ..Default::default()
+        }))
         .await
     {
         Ok(response) => {
@@ -207,14 +392,12 @@ impl From<contest::GetQuestionListResponse> for QuestionList {
 
 #[get("/questions")]
 pub async fn questions_template(
-    _admin: Admin,
+    admin: Admin,
     contest_client: &State<ContestClient>,
 ) -> Result<Template, status::Custom<String>> {
     let mut contest_client = contest_client.inner().clone();
     match contest_client
-        .get_question_list(tonic::Request::new(
-            contest::GetQuestionListRequest::default(),
-        ))
+        .get_question_list(admin.request(contest::GetQuestionListRequest::default()))
         .await
     {
         Ok(response) => {
@@ -237,6 +420,7 @@ struct TemplateSubmissionsItem {
     user: String,
     state: String,
     time: String,
+    token: bool,
 }
 #[derive(Serialize, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
@@ -245,14 +429,12 @@ struct TemplateSubmissions {
 }
 #[get("/submissions")]
 pub async fn submissions_template(
-    _admin: Admin,
+    admin: Admin,
     submission_client: &State<SubmissionClient>,
 ) -> Result<Template, status::Custom<String>> {
     let mut submission_client = submission_client.inner().clone();
     match submission_client
-        .get_submission_list(tonic::Request::new(
-            submission::GetSubmissionListRequest::default(),
-        ))
+        .get_submission_list(admin.request(submission::GetSubmissionListRequest::default()))
         .await
     {
         Ok(response) => {
@@ -270,6 +452,7 @@ pub async fn submissions_template(
                             submission::SubmissionState::from_i32(q.state).unwrap()
                         ),
                         time: utils::render_protos_timestamp(q.timestamp.clone(), "%F %X"),
+                        token: q.token(),
                     })
                     .collect(),
             };
@@ -281,11 +464,142 @@ pub async fn submissions_template(
         )),
     }
 }
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateIcpcProblem {
+    problem_id: u64,
+    rejected: u32,
+    solved_at: Option<u64>, // minutes since the contest start
+    first_to_solve: bool,
+    pending: u32,
+}
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateIcpcUser {
+    rank: usize,
+    user: String,
+    name: String, // of the team, the user otherwise
+    solved: u32,
+    penalty_minutes: u64,
+    problems: Vec<TemplateIcpcProblem>,
+}
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct TemplateIcpcScoreboard {
+    users: Vec<TemplateIcpcUser>,
+    resolver: bool, // the public scoreboard, revealed step by step
+}
+impl From<submission::GetIcpcScoreboardResponse> for TemplateIcpcScoreboard {
+    fn from(scoreboard: submission::GetIcpcScoreboardResponse) -> Self {
+        Self {
+            users: scoreboard
+                .users
+                .into_iter()
+                .enumerate()
+                .map(|(i, u)| TemplateIcpcUser {
+                    rank: i + 1,
+                    name: u.user.clone(),
+                    user: u.user,
+                    solved: u.solved,
+                    penalty_minutes: u.penalty_minutes,
+                    problems: u
+                        .problems
+                        .into_iter()
+                        .map(|p| TemplateIcpcProblem {
+                            problem_id: p.problem_id,
+                            rejected: p.rejected,
+                            first_to_solve: p.first_to_solve(),
+                            pending: p.pending(),
+                            solved_at: p.solved_at.map(|t| t.secs / 60),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            resolver: false,
+        }
+    }
+}
+impl TemplateIcpcScoreboard {
+    fn with_team_names(mut self, teams: Vec<contest::Team>) -> Self {
+        for user in self.users.iter_mut() {
+            if let Some(team) = teams.iter().find(|team| team.id == user.user) {
+                user.name = team.name.clone();
+            }
+        }
+        self
+    }
+}
+// the public scoreboard hides the results after the freeze
+pub async fn get_icpc_scoreboard(
+    admin: &Admin,
+    submission_client: &State<SubmissionClient>,
+    public: bool,
+) -> Result<submission::GetIcpcScoreboardResponse, status::Custom<String>> {
+    let mut submission_client = submission_client.inner().clone();
+    submission_client
+        .get_icpc_scoreboard(admin.request(submission::GetIcpcScoreboardRequest {
+            participant: Some(public),
+        }))
+        .await
+        .map(|response| response.into_inner())
+        .map_err(|err| {
+            status::Custom(
+                Status::InternalServerError,
+                format!("Error in rpc request:\n{:?}", err),
+            )
+        })
+}
+#[get("/scoreboard")]
+pub async fn scoreboard_template(
+    admin: Admin,
+    contest_client: &State<ContestClient>,
+    submission_client: &State<SubmissionClient>,
+) -> Result<Template, status::Custom<String>> {
+    let scoreboard = get_icpc_scoreboard(&admin, submission_client, false).await?;
+    let teams = get_team_list(&admin, contest_client).await?;
+    Ok(Template::render(
+        "scoreboard",
+        TemplateIcpcScoreboard::from(scoreboard).with_team_names(teams),
+    ))
+}
+#[get("/resolver")]
+pub async fn resolver_template(
+    admin: Admin,
+    contest_client: &State<ContestClient>,
+    submission_client: &State<SubmissionClient>,
+) -> Result<Template, status::Custom<String>> {
+    let scoreboard = get_icpc_scoreboard(&admin, submission_client, true).await?;
+    let teams = get_team_list(&admin, contest_client).await?;
+    Ok(Template::render(
+        "scoreboard",
+        TemplateIcpcScoreboard {
+            resolver: true,
+            ..TemplateIcpcScoreboard::from(scoreboard).with_team_names(teams)
+        },
+    ))
+}
+
+// None | Minimize | Maximize
+fn relative_goal(relative: Option<protos::scoring::Relative>) -> String {
+    relative.map_or_else(
+        || String::from("None"),
+        |relative| relative.goal().to_string(),
+    )
+}
+fn relative_from_goal(goal: &str) -> Option<protos::scoring::Relative> {
+    protos::scoring::relative::Goal::from_str(goal)
+        .ok()
+        .map(|goal| protos::scoring::Relative { goal: goal as i32 })
+}
+
 #[derive(Serialize, FromForm, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct SubtaskScoring {
-    method: String, // MIN | SUM
+    method: String, // Min | Sum | Product | Threshold | Average | MinPartial
     max_score: f64,
+    threshold: Option<f64>,
+    relative: String, // None uses the one of the problem
 }
 impl From<protos::scoring::Subtask> for SubtaskScoring {
     fn from(s: protos::scoring::Subtask) -> Self {
@@ -295,6 +609,8 @@ impl From<protos::scoring::Subtask> for SubtaskScoring {
                 protos::scoring::subtask::Method::from_i32(s.method).unwrap()
             ),
             max_score: s.max_score.score,
+            threshold: s.threshold.map(|threshold| threshold.score),
+            relative: relative_goal(s.relative),
         }
     }
 }
@@ -304,9 +620,21 @@ impl From<SubtaskScoring> for protos::scoring::Subtask {
             method: match s.method.as_str() {
                 "Min" => protos::scoring::subtask::Method::Min as i32,
                 "Sum" => protos::scoring::subtask::Method::Sum as i32,
+                "Product" => protos::scoring::subtask::Method::Product as i32,
+                "Threshold" => protos::scoring::subtask::Method::Threshold as i32,
+                "Average" => protos::scoring::subtask::Method::Average as i32,
+                "MinPartial" => protos::scoring::subtask::Method::MinPartial as i32,
                 _ => panic!("Bad subtask scoring string"),
             },
-            max_score: protos::common::Score { score: s.max_score },
+            max_score: protos::common::Score {
+                score: s.max_score,
+                ..Default::default()
+            },
+            threshold: s.threshold.map(|threshold| protos::common::Score {
+                score: threshold,
+                ..Default::default()
+            }),
+            relative: relative_from_goal(&s.relative),
         }
     }
 }
@@ -317,6 +645,10 @@ pub struct Subtask {
     id: Option<u64>,
     scoring: SubtaskScoring,
     testcases: Vec<u64>,
+    limits: LimitsOverride,
+    testcase_settings: Vec<TestcaseSettings>, // one for each testcase, in the same order
+    dependencies: Vec<u64>,
+    parent: Option<u64>,
 }
 impl Subtask {
     fn gen_ids_if_none(&mut self) {
@@ -327,10 +659,30 @@ impl Subtask {
 }
 impl From<evaluation::Subtask> for Subtask {
     fn from(s: evaluation::Subtask) -> Self {
+        let testcase_settings = s
+            .testcases_id
+            .iter()
+            .map(|&testcase_id| TestcaseSettings {
+                testcase_id,
+                limits: s
+                    .testcase_limits
+                    .iter()
+                    .find(|l| l.testcase_id == testcase_id)
+                    .map(|l| l.limits.clone().into())
+                    .unwrap_or_default(),
+                name: String::new(),
+                sample: None,
+                feedback: evaluation::testcase_info::Feedback::Full.to_string(),
+            })
+            .collect();
         Self {
             id: Some(s.id),
             scoring: s.scoring.into(),
             testcases: s.testcases_id,
+            limits: s.limits.map(LimitsOverride::from).unwrap_or_default(),
+            testcase_settings,
+            dependencies: s.dependencies,
+            parent: s.parent,
         }
     }
 }
@@ -340,14 +692,82 @@ impl From<Subtask> for evaluation::Subtask {
             id: s.id.unwrap(),
             scoring: s.scoring.into(),
             testcases_id: s.testcases,
+            limits: Some(s.limits)
+                .filter(|l| !l.is_empty())
+                .map(evaluation::LimitsOverride::from),
+            testcase_limits: s
+                .testcase_settings
+                .into_iter()
+                .filter(|t| !t.limits.is_empty())
+                .map(|t| evaluation::TestcaseLimits {
+                    testcase_id: t.testcase_id,
+                    limits: t.limits.into(),
+                })
+                .collect(),
+            dependencies: s.dependencies,
+            parent: s.parent,
         }
     }
 }
 
+#[derive(Serialize, FromForm, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct TestcaseSettings {
+    testcase_id: u64,
+    limits: LimitsOverride,
+    name: String,
+    sample: Option<bool>,
+    feedback: String, // Full | Outcome | Hidden
+}
+impl TestcaseSettings {
+    fn set_info(&mut self, info: &evaluation::TestcaseInfo) {
+        self.name = info.name.clone().unwrap_or_default();
+        self.sample = info.sample;
+        self.feedback = info.feedback().to_string();
+    }
+    fn info(&self) -> Option<evaluation::TestcaseInfo> {
+        let feedback = evaluation::testcase_info::Feedback::from_str(&self.feedback)
+            .unwrap_or(evaluation::testcase_info::Feedback::Full);
+        let info = evaluation::TestcaseInfo {
+            testcase_id: self.testcase_id,
+            name: Some(self.name.clone()).filter(|name| !name.is_empty()),
+            sample: self.sample.filter(|&sample| sample),
+            feedback: Some(feedback as i32)
+                .filter(|_| feedback != evaluation::testcase_info::Feedback::Full),
+        };
+        if info.name.is_none() && info.sample.is_none() && info.feedback.is_none() {
+            None
+        } else {
+            Some(info)
+        }
+    }
+}
+
+// Empty multipliers are the same as 1
+#[derive(Serialize, FromForm, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct TimeMultiplier {
+    lang: String,
+    multiplier: Option<f64>,
+}
+fn time_multipliers(multipliers: Vec<evaluation::LanguageTimeMultiplier>) -> Vec<TimeMultiplier> {
+    protos::common::ProgrammingLanguage::iter()
+        .filter(|&lang| lang != protos::common::ProgrammingLanguage::None)
+        .map(|lang| TimeMultiplier {
+            lang: lang.to_string(),
+            multiplier: multipliers
+                .iter()
+                .find(|m| m.lang == lang as i32)
+                .map(|m| m.multiplier),
+        })
+        .collect()
+}
+
 #[derive(Serialize, FromForm, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct ProblemScoring {
-    method: String, // SUM_MAX | MAX_SUM
+    method: String, // SumMax | MaxSum | Last | Selected
+    relative: String,
 }
 impl From<protos::scoring::Problem> for ProblemScoring {
     fn from(p: protos::scoring::Problem) -> Self {
@@ -356,6 +776,7 @@ impl From<protos::scoring::Problem> for ProblemScoring {
                 "{:?}",
                 protos::scoring::problem::Method::from_i32(p.method).unwrap()
             ),
+            relative: relative_goal(p.relative),
         }
     }
 }
@@ -365,35 +786,99 @@ impl From<ProblemScoring> for protos::scoring::Problem {
             method: match p.method.as_str() {
                 "SumMax" => protos::scoring::problem::Method::SumMax as i32,
                 "MaxSum" => protos::scoring::problem::Method::MaxSum as i32,
+                "Last" => protos::scoring::problem::Method::Last as i32,
+                "Selected" => protos::scoring::problem::Method::Selected as i32,
                 _ => panic!("Invalid problem scoring method string"),
             },
+            relative: relative_from_goal(&p.relative),
         }
     }
 }
 
+#[derive(Serialize, FromForm, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct TokenRules {
+    enabled: Option<bool>,
+    initial: Option<u32>,
+    gen_number: Option<u32>,
+    gen_interval_minutes: Option<u64>, // empty if no tokens are generated
+    max: Option<u32>,
+    total: Option<u32>,
+    min_interval_minutes: Option<u64>,
+}
+impl From<Option<contest::TokenRules>> for TokenRules {
+    fn from(rules: Option<contest::TokenRules>) -> Self {
+        let minutes = |d: Option<protos::common::Duration>| d.map(|d| d.secs / 60);
+        Self {
+            enabled: Some(rules.is_some()),
+            initial: rules.as_ref().map(|rules| rules.initial),
+            gen_number: rules.as_ref().map(|rules| rules.gen_number),
+            gen_interval_minutes: minutes(rules.as_ref().and_then(|r| r.gen_interval.clone())),
+            max: rules.as_ref().and_then(|rules| rules.max),
+            total: rules.as_ref().and_then(|rules| rules.total),
+            min_interval_minutes: minutes(rules.and_then(|rules| rules.min_interval)),
+        }
+    }
+}
+impl From<TokenRules> for Option<contest::TokenRules> {
+    fn from(rules: TokenRules) -> Self {
+        let duration =
+            |minutes: Option<u64>| minutes.map(|m| std::time::Duration::from_secs(m * 60).into());
+        Some(contest::TokenRules {
+            initial: rules.initial.unwrap_or(0),
+            gen_number: rules.gen_number.unwrap_or(0),
+            gen_interval: duration(rules.gen_interval_minutes),
+            max: rules.max,
+            total: rules.total,
+            min_interval: duration(rules.min_interval_minutes),
+        })
+        .filter(|_| rules.enabled.unwrap_or(false))
+    }
+}
+
 #[derive(Serialize, FromForm, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct Problem {
     id: Option<u64>,
     scoring: ProblemScoring,
     problem_type: String,
+    feedback: String, // Full | FirstFailing | SubtaskScores | FinalScore | AfterContest
     execution_limits: Resources,
     compilation_limits: Resources,
     subtasks: Vec<Subtask>,
+    time_multipliers: Vec<TimeMultiplier>,
     name: String,
     longname: String,
+    tokens: TokenRules,
 }
 impl Problem {
     fn new(e: evaluation::Problem, u: contest::Problem) -> Self {
+        let feedback = e.feedback().to_string();
+        let mut subtasks: Vec<Subtask> = e.subtasks.into_iter().map(Subtask::from).collect();
+        for testcase in subtasks
+            .iter_mut()
+            .flat_map(|s| s.testcase_settings.iter_mut())
+        {
+            if let Some(info) = e
+                .testcases_info
+                .iter()
+                .find(|info| info.testcase_id == testcase.testcase_id)
+            {
+                testcase.set_info(info);
+            }
+        }
         Self {
             id: Some(e.id),
             scoring: e.scoring.into(),
             problem_type: format!("{:?}", evaluation::problem::Type::from_i32(e.r#type)),
+            feedback,
             execution_limits: e.execution_limits.into(),
             compilation_limits: e.compilation_limits.into(),
-            subtasks: e.subtasks.into_iter().map(Subtask::from).collect(),
+            subtasks,
+            time_multipliers: time_multipliers(e.time_multipliers),
             name: u.name.clone(),
             longname: u.long_name,
+            tokens: u.tokens.into(),
         }
     }
     fn gen_ids_if_none(&mut self) {
@@ -417,13 +902,33 @@ impl From<Problem> for evaluation::Problem {
                 "Other" => evaluation::problem::Type::Other as i32,
                 _ => panic!("Invalid problem type string"),
             },
+            feedback: Some(
+                evaluation::problem::Feedback::from_str(&p.feedback)
+                    .unwrap_or(evaluation::problem::Feedback::Full) as i32,
+            ),
             execution_limits: p.execution_limits.into(),
             compilation_limits: p.compilation_limits.into(),
+            testcases_info: p
+                .subtasks
+                .iter()
+                .flat_map(|s| s.testcase_settings.iter())
+                .filter_map(TestcaseSettings::info)
+                .collect(),
             subtasks: p
                 .subtasks
                 .into_iter()
                 .map(evaluation::Subtask::from)
                 .collect(),
+            time_multipliers: p
+                .time_multipliers
+                .into_iter()
+                .filter_map(|m| {
+                    Some(evaluation::LanguageTimeMultiplier {
+                        lang: protos::common::ProgrammingLanguage::from_str(&m.lang).ok()? as i32,
+                        multiplier: m.multiplier?,
+                    })
+                })
+                .collect(),
         }
     }
 }
@@ -433,6 +938,7 @@ impl From<Problem> for contest::Problem {
             id: p.id.unwrap(),
             name: p.name,
             long_name: p.longname,
+            tokens: p.tokens.into(),
         }
     }
 }
@@ -473,22 +979,56 @@ impl From<UserScoringMethod> for protos::scoring::user::Method {
             },
             score_weight: protos::common::Score {
                 score: us.score_weight,
+                ..Default::default()
             },
             wrong_submission_count_weight: protos::common::Score {
                 score: us.wrong_submission_count_weight,
+                ..Default::default()
             },
             time_secs_weight: protos::common::Score {
                 score: us.time_secs_weight,
+                ..Default::default()
             },
         }
     }
 }
 
+const DEFAULT_ICPC_PENALTY_MINUTES: u32 = 20;
+
+#[derive(Serialize, FromForm, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct IcpcScoring {
+    enabled: Option<bool>,
+    penalty_minutes: u32,
+    count_compilation_errors: Option<bool>,
+}
+impl From<Option<protos::scoring::user::Icpc>> for IcpcScoring {
+    fn from(icpc: Option<protos::scoring::user::Icpc>) -> Self {
+        Self {
+            enabled: Some(icpc.is_some()),
+            penalty_minutes: icpc
+                .as_ref()
+                .map_or(DEFAULT_ICPC_PENALTY_MINUTES, |icpc| icpc.penalty_minutes),
+            count_compilation_errors: icpc.map(|icpc| icpc.count_compilation_errors),
+        }
+    }
+}
+impl From<IcpcScoring> for Option<protos::scoring::user::Icpc> {
+    fn from(icpc: IcpcScoring) -> Self {
+        Some(protos::scoring::user::Icpc {
+            penalty_minutes: icpc.penalty_minutes,
+            count_compilation_errors: icpc.count_compilation_errors.unwrap_or(false),
+        })
+        .filter(|_| icpc.enabled.unwrap_or(false))
+    }
+}
+
 #[derive(Serialize, FromForm, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct UserScoring {
     main: UserScoringMethod,
     tiebreakers: Vec<UserScoringMethod>,
+    icpc: IcpcScoring,
 }
 impl From<protos::scoring::User> for UserScoring {
     fn from(us: protos::scoring::User) -> Self {
@@ -499,6 +1039,7 @@ impl From<protos::scoring::User> for UserScoring {
                 .into_iter()
                 .map(UserScoringMethod::from)
                 .collect(),
+            icpc: us.icpc.into(),
         }
     }
 }
@@ -511,6 +1052,7 @@ impl From<UserScoring> for protos::scoring::User {
                 .into_iter()
                 .map(protos::scoring::user::Method::from)
                 .collect(),
+            icpc: us.icpc.into(),
         }
     }
 }
@@ -522,8 +1064,25 @@ pub struct ContestTemplate {
     description: String,
     start_time: String,
     end_time: String,
+    freeze_minutes: Option<u64>, // before the end, empty if the scoreboard is never frozen
+    window_minutes: Option<u64>, // from the start of each user, empty if everyone starts together
+    tokens: TokenRules,
     problems: Vec<Problem>,
     user_scoring: UserScoring,
+    semantics: String, // PerProblem | Ioi | Cms
+}
+// IOI and CMS semantics set the scoring method of all the problems
+fn semantics(problems: &[evaluation::Problem]) -> String {
+    let all = |method: protos::scoring::problem::Method| {
+        !problems.is_empty() && problems.iter().all(|p| p.scoring.method == method as i32)
+    };
+    if all(protos::scoring::problem::Method::SumMax) {
+        String::from("Ioi")
+    } else if all(protos::scoring::problem::Method::MaxSum) {
+        String::from("Cms")
+    } else {
+        String::from("PerProblem")
+    }
 }
 impl ContestTemplate {
     fn new(
@@ -534,6 +1093,7 @@ impl ContestTemplate {
         let mut user_problems = user_contest.problems;
         evaluation_problems.sort_by_key(|x| x.id);
         user_problems.sort_by_key(|x| x.id);
+        let semantics = semantics(&evaluation_problems);
         Self {
             name: user_contest.metadata.name,
             description: user_contest.metadata.description,
@@ -551,24 +1111,27 @@ impl ContestTemplate {
                     "%FT%T",
                 ),
             },
+            freeze_minutes: user_contest.metadata.freeze.map(|freeze| freeze.secs / 60),
+            window_minutes: user_contest.metadata.window.map(|window| window.secs / 60),
+            tokens: user_contest.metadata.tokens.into(),
             problems: evaluation_problems
                 .into_iter()
                 .zip(user_problems.into_iter())
                 .map(|x| Problem::new(x.0, x.1))
                 .collect(),
             user_scoring: evaluation_contest.info.user_scoring_method.into(),
+            semantics,
         }
     }
     async fn from_clients(
+        admin: &Admin,
         mut contest_client: ContestClient,
         mut evaluation_client: EvaluationClient,
     ) -> Result<Self, status::Custom<String>> {
         let (user_contest_response, evaluation_contest_response) = future::join(
-            contest_client.get_contest_metadata(tonic::Request::new(
-                contest::GetContestMetadataRequest::default(),
-            )),
-            evaluation_client
-                .get_contest(tonic::Request::new(evaluation::GetContestRequest::default())),
+            contest_client
+                .get_contest_metadata(admin.request(contest::GetContestMetadataRequest::default())),
+            evaluation_client.get_contest(admin.request(evaluation::GetContestRequest::default())),
         )
         .await;
         /*
@@ -621,6 +1184,13 @@ impl From<ContestTemplate> for contest::SetContestMetadataRequest {
                     .datetime_from_str(&contest.end_time, "%FT%T")
                     .ok()
                     .map(|t| SystemTime::from(t).into()),
+                freeze: contest
+                    .freeze_minutes
+                    .map(|minutes| std::time::Duration::from_secs(minutes * 60).into()),
+                tokens: contest.tokens.into(),
+                window: contest
+                    .window_minutes
+                    .map(|minutes| std::time::Duration::from_secs(minutes * 60).into()),
             },
         }
     }
@@ -636,12 +1206,23 @@ impl From<ContestTemplate> for Vec<contest::UpdateProblemInfoRequest> {
 }
 impl From<ContestTemplate> for evaluation::SetContestRequest {
     fn from(contest: ContestTemplate) -> Self {
+        let method = match contest.semantics.as_str() {
+            "Ioi" => Some(protos::scoring::problem::Method::SumMax),
+            "Cms" => Some(protos::scoring::problem::Method::MaxSum),
+            _ => None,
+        };
         Self {
             info: evaluation::Contest {
                 problems: contest
                     .problems
                     .into_iter()
                     .map(evaluation::Problem::from)
+                    .map(|mut problem| {
+                        if let Some(method) = method {
+                            problem.scoring.method = method as i32;
+                        }
+                        problem
+                    })
                     .collect(),
                 user_scoring_method: contest.user_scoring.into(),
             },
@@ -649,26 +1230,135 @@ impl From<ContestTemplate> for evaluation::SetContestRequest {
     }
 }
 
-#[get("/contest")]
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+struct TemplateContest {
+    #[serde(flatten)]
+    contest: ContestTemplate,
+    recomputed: Vec<String>, // users whose scores changed with the last update
+    recompute_error: Option<String>, // the last update was saved without recomputing the scores
+}
+
+#[get("/contest?<recomputed>&<recompute_error>")]
 pub async fn contest_template(
-    _admin: Admin,
+    admin: Admin,
     contest_client: &State<ContestClient>,
     evaluation_client: &State<EvaluationClient>,
+    recomputed: Vec<String>,
+    recompute_error: Option<String>,
 ) -> Result<Template, status::Custom<String>> {
     let contest_client = contest_client.inner().clone();
     let evaluation_client = evaluation_client.inner().clone();
-    let ct = ContestTemplate::from_clients(contest_client, evaluation_client).await?;
-    Ok(Template::render("contest", ct))
+    let contest = ContestTemplate::from_clients(&admin, contest_client, evaluation_client).await?;
+    Ok(Template::render(
+        "contest",
+        TemplateContest {
+            contest,
+            recomputed,
+            recompute_error,
+        },
+    ))
 }
 
 #[get("/problem_files")]
 pub async fn problem_files_template(
-    _admin: Admin,
+    admin: Admin,
     contest_client: &State<ContestClient>,
     evaluation_client: &State<EvaluationClient>,
 ) -> Result<Template, status::Custom<String>> {
     let contest_client = contest_client.inner().clone();
     let evaluation_client = evaluation_client.inner().clone();
-    let ct = ContestTemplate::from_clients(contest_client, evaluation_client).await?;
+    let ct = ContestTemplate::from_clients(&admin, contest_client, evaluation_client).await?;
     Ok(Template::render("problem_files", ct))
 }
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct ProblemVersion {
+    version: u64,
+    created: String,
+    description: String,
+}
+impl From<evaluation::ProblemVersion> for ProblemVersion {
+    fn from(v: evaluation::ProblemVersion) -> Self {
+        Self {
+            version: v.version,
+            created: utils::render_protos_timestamp(v.created, "%F %X"),
+            description: v.description,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct FileChange {
+    path: String,
+    change: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+struct ProblemVersions {
+    problem_id: u64,
+    versions: Vec<ProblemVersion>,
+    from: Option<u64>,
+    to: Option<u64>,
+    changes: Option<Vec<FileChange>>,
+}
+
+#[get("/problem_versions/<problem_id>?<from>&<to>")]
+pub async fn problem_versions_template(
+    admin: Admin,
+    evaluation_client: &State<EvaluationClient>,
+    problem_id: u64,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Template, status::Custom<String>> {
+    let mut evaluation_client = evaluation_client.inner().clone();
+    let rpc_error = |err| {
+        status::Custom(
+            Status::InternalServerError,
+            format!("Error in rpc request:\n{:?}", err),
+        )
+    };
+    let versions = evaluation_client
+        .get_problem_versions(admin.request(evaluation::GetProblemVersionsRequest { problem_id }))
+        .await
+        .map_err(rpc_error)?
+        .into_inner()
+        .versions
+        .into_iter()
+        .map(ProblemVersion::from)
+        .collect();
+    let changes = match (from, to) {
+        (Some(from_version), Some(to_version)) => Some(
+            evaluation_client
+                .diff_problem_versions(admin.request(evaluation::DiffProblemVersionsRequest {
+                    problem_id,
+                    from_version,
+                    to_version,
+                }))
+                .await
+                .map_err(rpc_error)?
+                .into_inner()
+                .changes
+                .into_iter()
+                .map(|fc| FileChange {
+                    change: fc.change().to_string(),
+                    path: fc.path,
+                })
+                .collect(),
+        ),
+        _ => None,
+    };
+    Ok(Template::render(
+        "problem_versions",
+        ProblemVersions {
+            problem_id,
+            versions,
+            from,
+            to,
+            changes,
+        },
+    ))
+}
@@ -10,15 +10,60 @@ pub enum MappingError {
     HashingError(argon2::password_hash::Error),
 }
 
+pub mod tokens {
+    use super::*;
+    use protos::service::contest::TokenRules;
+
+    fn get_duration(value: &Document, key: &str) -> Option<protos::common::Duration> {
+        value
+            .get_i64(key)
+            .ok()
+            .map(|secs| std::time::Duration::from_secs(secs as u64).into())
+    }
+    fn get_u32(value: &Document, key: &str) -> Option<u32> {
+        value.get_i64(key).ok().map(|x| x as u32)
+    }
+    pub fn from_document(value: &Document) -> TokenRules {
+        TokenRules {
+            initial: get_u32(value, "initial").unwrap_or_default(),
+            gen_number: get_u32(value, "genNumber").unwrap_or_default(),
+            gen_interval: get_duration(value, "genIntervalSecs"),
+            max: get_u32(value, "max"),
+            total: get_u32(value, "total"),
+            min_interval: get_duration(value, "minIntervalSecs"),
+        }
+    }
+    pub fn to_document(rules: TokenRules) -> Document {
+        let secs = |d: Option<protos::common::Duration>| d.map(|d| d.secs as i64);
+        let mut result = Document::new();
+        result.insert("initial", rules.initial as i64);
+        result.insert("genNumber", rules.gen_number as i64);
+        result.insert("genIntervalSecs", secs(rules.gen_interval));
+        result.insert("max", rules.max.map(|x| x as i64));
+        result.insert("total", rules.total.map(|x| x as i64));
+        result.insert("minIntervalSecs", secs(rules.min_interval));
+        result
+    }
+}
+
 pub mod contest {
     use std::convert::TryInto;
 
     use super::*;
+    use protos::service::contest::TokenRules;
     pub struct ContestMetadata {
         name: String,
         description: String,
         start_time: Option<std::time::SystemTime>,
         end_time: Option<std::time::SystemTime>,
+        freeze: Option<std::time::Duration>,
+        tokens: Option<TokenRules>,
+        window: Option<std::time::Duration>,
+    }
+    impl ContestMetadata {
+        pub fn get_freeze_time(&self) -> Option<std::time::SystemTime> {
+            self.end_time?.checked_sub(self.freeze?)
+        }
     }
     impl Default for ContestMetadata {
         fn default() -> Self {
@@ -27,6 +72,9 @@ pub mod contest {
                 description: String::from("no description"),
                 start_time: None,
                 end_time: None,
+                freeze: None,
+                tokens: None,
+                window: None,
             }
         }
     }
@@ -43,6 +91,15 @@ pub mod contest {
                     .get("endTime")
                     .map(|x| x.as_timestamp().map(utils::mongo::timestamp_to_systime))
                     .flatten(),
+                freeze: value
+                    .get("freezeSecs")
+                    .and_then(|x| x.as_i64())
+                    .map(|secs| std::time::Duration::from_secs(secs as u64)),
+                tokens: value.get_document("tokens").ok().map(tokens::from_document),
+                window: value
+                    .get("windowSecs")
+                    .and_then(|x| x.as_i64())
+                    .map(|secs| std::time::Duration::from_secs(secs as u64)),
             }
         }
     }
@@ -53,6 +110,9 @@ pub mod contest {
                 description: md.description,
                 start_time: md.start_time.map(protos::common::Timestamp::from),
                 end_time: md.end_time.map(protos::common::Timestamp::from),
+                freeze: md.freeze.map(protos::common::Duration::from),
+                tokens: md.tokens,
+                window: md.window.map(protos::common::Duration::from),
             }
         }
     }
@@ -67,6 +127,9 @@ pub mod contest {
                 description: metadata.description,
                 start_time: metadata.start_time.map(|x| x.try_into().unwrap()),
                 end_time: metadata.end_time.map(|x| x.try_into().unwrap()),
+                freeze: metadata.freeze.map(std::time::Duration::from),
+                tokens: metadata.tokens,
+                window: metadata.window.map(std::time::Duration::from),
             })
         }
 
@@ -86,9 +149,60 @@ pub mod contest {
                 "endTime",
                 m.end_time.map(utils::mongo::systime_to_timestamp),
             );
+            result.insert("freezeSecs", m.freeze.map(|d| d.as_secs() as i64));
+            result.insert("tokens", m.tokens.map(tokens::to_document));
+            result.insert("windowSecs", m.window.map(|d| d.as_secs() as i64));
             result
         }
     }
+
+    /// State of the scoreboard freeze, stored in the contest metadata document
+    pub struct ScoreboardFreeze {
+        freeze_time: Option<std::time::SystemTime>,
+        unfrozen: bool,
+        revealed: Vec<(String, u64)>, // (user, problem id)
+    }
+    impl From<Document> for ScoreboardFreeze {
+        fn from(value: Document) -> Self {
+            Self {
+                unfrozen: value.get_bool("unfrozen").unwrap_or(false),
+                revealed: value
+                    .get_array("revealed")
+                    .map(|results| {
+                        results
+                            .iter()
+                            .filter_map(|result| result.as_document())
+                            .filter_map(|result| {
+                                Some((
+                                    result.get_str("user").ok()?.to_string(),
+                                    result.get_i64("problemId").ok()? as u64,
+                                ))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                freeze_time: ContestMetadata::from(value).get_freeze_time(),
+            }
+        }
+    }
+    impl From<ScoreboardFreeze> for protos::service::contest::GetScoreboardFreezeResponse {
+        fn from(freeze: ScoreboardFreeze) -> Self {
+            Self {
+                freeze_time: freeze.freeze_time.map(protos::common::Timestamp::from),
+                unfrozen: freeze.unfrozen,
+                revealed: freeze
+                    .revealed
+                    .into_iter()
+                    .map(|(user, problem_id)| {
+                        protos::service::contest::get_scoreboard_freeze_response::Result {
+                            user,
+                            problem_id,
+                        }
+                    })
+                    .collect(),
+            }
+        }
+    }
 }
 
 pub mod chat {
@@ -198,13 +312,16 @@ pub mod problem {
             self.1.clone()
         }
     }
+    use super::tokens;
     use mongodb::bson::Document;
+    use protos::service::contest::TokenRules;
 
     #[derive(Default, Clone)]
     pub struct Problem {
         id: u64,
         pub name: String,
         pub long_name: String,
+        pub tokens: Option<TokenRules>,
     }
 
     impl Problem {
@@ -219,6 +336,7 @@ pub mod problem {
                 id: input.id,
                 name: input.name,
                 long_name: input.long_name,
+                tokens: input.tokens,
             }
         }
     }
@@ -229,6 +347,7 @@ pub mod problem {
                 id: p.id,
                 name: p.name,
                 long_name: p.long_name,
+                tokens: p.tokens,
             }
         }
     }
@@ -242,6 +361,10 @@ pub mod problem {
                         .get_str("longName")
                         .unwrap_or_default()
                         .to_owned(),
+                    tokens: mongo_record
+                        .get_document("tokens")
+                        .ok()
+                        .map(tokens::from_document),
                 },
                 match mongo_record.get_binary_generic("statement") {
                     Ok(x) => x.to_owned(),
@@ -258,6 +381,10 @@ pub mod problem {
             result.insert("_id", p.id as i64);
             result.insert("name", p.name.clone());
             result.insert("longName", p.long_name);
+            // imported problems keep the rules set by the admins
+            if let Some(rules) = p.tokens {
+                result.insert("tokens", tokens::to_document(rules));
+            }
             result.insert(
                 "statement",
                 mongodb::bson::Binary {
@@ -320,6 +447,19 @@ pub mod user {
         pub fn get_username(&self) -> &str {
             &self.username
         }
+        /// Successful authentication, with the token of the new session
+        pub fn into_auth_response(
+            self,
+            token: String,
+        ) -> protos::service::contest::auth_user_response::Response {
+            protos::service::contest::auth_user_response::Response::Success(
+                protos::service::contest::auth_user_response::Success {
+                    username: self.username,
+                    fullname: self.fullname,
+                    token,
+                },
+            )
+        }
     }
 
     impl From<Document> for User {
@@ -361,12 +501,127 @@ pub mod user {
             result
         }
     }
-    impl From<User> for protos::service::contest::auth_user_response::Response {
-        fn from(u: User) -> Self {
-            Self::Success(protos::service::contest::auth_user_response::Success {
-                username: u.username,
-                fullname: u.fullname,
-            })
+}
+
+pub mod team {
+    use super::*;
+    use protos::service::contest::Team;
+
+    pub fn from_document(value: &Document) -> Team {
+        Team {
+            id: value.get_str("_id").unwrap_or_default().to_owned(),
+            name: value.get_str("name").unwrap_or_default().to_owned(),
+            affiliation: value.get_str("affiliation").ok().map(str::to_owned),
+            country: value.get_str("country").ok().map(str::to_owned),
+            members: value
+                .get_array("members")
+                .map(|members| {
+                    members
+                        .iter()
+                        .filter_map(|member| member.as_str())
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+    pub fn to_document(team: Team) -> Document {
+        let mut result = Document::new();
+        result.insert("_id", team.id);
+        result.insert("name", team.name);
+        result.insert("affiliation", team.affiliation);
+        result.insert("country", team.country);
+        result.insert("members", team.members);
+        result
+    }
+}
+
+pub mod window {
+    use super::*;
+    use protos::service::contest::UserWindow;
+
+    fn get_duration(value: &Document, key: &str) -> Option<protos::common::Duration> {
+        value
+            .get_i64(key)
+            .ok()
+            .map(|secs| std::time::Duration::from_secs(secs as u64).into())
+    }
+    pub fn from_document(value: &Document) -> UserWindow {
+        UserWindow {
+            user: value.get_str("user").unwrap_or_default().to_owned(),
+            extra_time: get_duration(value, "extraTimeSecs"),
+            start_delay: get_duration(value, "startDelaySecs"),
+            started_at: value
+                .get_timestamp("startedAt")
+                .ok()
+                .map(|t| utils::mongo::timestamp_to_systime(t).into()),
+        }
+    }
+    pub fn to_document(window: UserWindow) -> Document {
+        let secs = |d: Option<protos::common::Duration>| d.map(|d| d.secs as i64);
+        let mut result = Document::new();
+        result.insert("user", window.user);
+        result.insert("extraTimeSecs", secs(window.extra_time));
+        result.insert("startDelaySecs", secs(window.start_delay));
+        result.insert(
+            "startedAt",
+            window
+                .started_at
+                .map(|t| utils::mongo::systime_to_timestamp(t.into())),
+        );
+        result
+    }
+}
+
+pub mod session {
+    use super::*;
+
+    /// Session of a participant or admin account, see utils::session
+    pub struct Session {
+        pub id: u64,
+        pub username: String,
+        pub admin: bool,
+        pub expires: std::time::SystemTime,
+    }
+
+    impl From<Document> for Session {
+        fn from(record: Document) -> Self {
+            Self {
+                id: record.get_i64("_id").unwrap() as u64,
+                username: record.get_str("username").unwrap().to_owned(),
+                admin: record.get_bool("admin").unwrap(),
+                expires: utils::mongo::timestamp_to_systime(
+                    record.get_timestamp("expires").unwrap(),
+                ),
+            }
+        }
+    }
+    impl From<Session> for Document {
+        fn from(s: Session) -> Self {
+            let mut result = Document::new();
+            result.insert("_id", s.id as i64);
+            result.insert("username", s.username);
+            result.insert("admin", s.admin);
+            result.insert("expires", utils::mongo::systime_to_timestamp(s.expires));
+            result
+        }
+    }
+    impl From<&Session> for utils::session::Session {
+        fn from(s: &Session) -> Self {
+            Self {
+                id: s.id,
+                expires: s.expires,
+            }
+        }
+    }
+    impl From<Session> for protos::service::contest::ValidateSessionResponse {
+        fn from(s: Session) -> Self {
+            Self {
+                username: s.username,
+                admin: s.admin,
+                team: None,
+                contests: vec![],
+            }
         }
     }
 }
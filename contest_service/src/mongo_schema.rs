@@ -4,24 +4,181 @@ use mongodb::{
     Database,
 };
 
-pub async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::error::Error>> {
+// contest.TokenRules
+fn token_rules_schema() -> Document {
+    doc! {
+        "bsonType": ["null","object"],
+        "required": ["initial", "genNumber"],
+        "properties": {
+            "initial": { "bsonType": "long" },
+            "genNumber": { "bsonType": "long" },
+            "genIntervalSecs": { "bsonType": ["null","long"] }, // null means no tokens are generated
+            "max": { "bsonType": ["null","long"] },
+            "total": { "bsonType": ["null","long"] },
+            "minIntervalSecs": { "bsonType": ["null","long"] }
+        }
+    }
+}
+
+// participant and admin accounts
+fn user_schema() -> Document {
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["_id", "fullname", "password"],
+            "properties": {
+                "_id": { "bsonType": "string" }, // username
+                "fullname": { "bsonType": "string" },
+                "password": { "bsonType": "string" }, // argon2 hash
+                "contests": {
+                    "bsonType": "array",
+                    "items": { "bsonType": "string" } // ids of the contests of a participant
+                }
+            }
+        }
+    }
+}
+
+// contest.ContestMetadata, by contest id
+fn contest_metadata_schema() -> Document {
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["_id", "name", "description", "startTime", "endTime"],
+            "properties": {
+                "_id": { "bsonType": "string" }, // contest id
+                "name": { "bsonType": "string" },
+                "description": { "bsonType": "string" },
+                "startTime": { "bsonType": ["null","timestamp"] }, // null means there is no start time
+                "endTime": { "bsonType": ["null","timestamp"] }, // null means there is no end time
+                "freezeSecs": { "bsonType": ["null","long"] }, // null means the ranking is never frozen
+                "tokens": token_rules_schema(), // null means tokens are disabled
+                "windowSecs": { "bsonType": ["null","long"] }, // null means everyone starts at startTime
+                "unfrozen": { "bsonType": "bool" }, // set by the resolver
+                "revealed": {
+                    "bsonType": "array",
+                    "items": {
+                        "bsonType": "object",
+                        "required": ["user", "problemId"],
+                        "properties": {
+                            "user": { "bsonType": "string" },
+                            "problemId": { "bsonType": "long" }
+                        }
+                    }
+                } // results revealed by the resolver
+            }
+        }
+    }
+}
+
+// problems, shared by the contests
+fn problem_schema() -> Document {
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["_id", "name", "longName"/*, "statement"*/],
+            "properties": {
+                "_id": { "bsonType": "long" }, // problem id
+                "name": { "bsonType": "string" },
+                "longName": { "bsonType": "string" },
+                "statement": { "bsonType": "binData" },
+                "tokens": token_rules_schema(), // null means only the contest rules apply
+                "contests": {
+                    "bsonType": "array",
+                    "items": { "bsonType": "string" } // ids of the contests using the problem
+                }
+            }
+        }
+    }
+}
+
+// teams of a contest
+fn team_schema() -> Document {
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["_id", "name", "members", "contestId"],
+            "properties": {
+                "_id": { "bsonType": "string" }, // team id
+                "contestId": { "bsonType": "string" },
+                "name": { "bsonType": "string" },
+                "affiliation": { "bsonType": ["null","string"] },
+                "country": { "bsonType": ["null","string"] },
+                "members": {
+                    "bsonType": "array",
+                    "items": { "bsonType": "string" } // usernames
+                }
+            }
+        }
+    }
+}
+
+// time windows of the participants of a contest
+fn user_window_schema() -> Document {
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["user", "contestId"],
+            "properties": {
+                "user": { "bsonType": "string" }, // username or team id
+                "contestId": { "bsonType": "string" },
+                "extraTimeSecs": { "bsonType": ["null","long"] },
+                "startDelaySecs": { "bsonType": ["null","long"] },
+                "startedAt": { "bsonType": ["null","timestamp"] } // in a contest with a window
+            }
+        }
+    }
+}
+
+// messages of the admins to the participants of a contest
+fn announcement_schema() -> Document {
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["_id", "contestId", "subject", "problemId", "text", "to", "created"],
+            "properties": {
+                "_id": { "bsonType": "long" }, // announcement id
+                "contestId": { "bsonType": "string" },
+                "subject": { "bsonType": "string" },
+                "problemId": { "bsonType": ["null","int"] },
+                "text": { "bsonType": ["null","string"] },
+                "to": { "bsonType": ["null","string"] },
+                "created": { "bsonType": "timestamp" }
+            }
+        }
+    }
+}
+
+// messages of the participants of a contest to the admins
+fn question_schema() -> Document {
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["_id", "contestId", "subject", "problemId", "text", "from", "created"],
+            "properties": {
+                "_id": { "bsonType": "long" }, // question id
+                "contestId": { "bsonType": "string" },
+                "subject": { "bsonType": "string" },
+                "problemId": { "bsonType": ["null","int"] },
+                "text": { "bsonType": "string" },
+                "from": { "bsonType": ["null","string"] },
+                "created": { "bsonType": "timestamp" }
+            }
+        }
+    }
+}
+
+/// Creates the collections and the default contest, with an admin account named admin
+pub async fn init_contest_service_db(
+    db: Database,
+    admin_password: String,
+) -> Result<(), Box<dyn std::error::Error>> {
     // TODO: consider using this validator syntax (might be slightly nicer):
     // https://docs.mongodb.com/v5.0/core/schema-validation/#other-query-expressions
     db.create_collection(
         "contest_metadata",
         CreateCollectionOptions::builder()
-            .validator(doc! {
-                "$jsonSchema": {
-                    "bsonType": "object",
-                    "required": ["name", "description", "startTime", "endTime"],
-                    "properties": {
-                        "name": { "bsonType": "string" },
-                        "description": { "bsonType": "string" },
-                        "startTime": { "bsonType": ["null","timestamp"] }, // null means there is no start time
-                        "endTime": { "bsonType": ["null","timestamp"] } // null means there is no end time
-                    }
-                }
-            })
+            .validator(contest_metadata_schema())
             .validation_action(ValidationAction::Error)
             .validation_level(ValidationLevel::Strict)
             /*.capped(true)
@@ -37,18 +194,7 @@ pub async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::er
     db.create_collection(
         "problems",
         CreateCollectionOptions::builder()
-            .validator(doc! {
-                "$jsonSchema": {
-                    "bsonType": "object",
-                    "required": ["_id", "name", "longName"/*, "statement"*/],
-                    "properties": {
-                        "_id": { "bsonType": "long" }, // problem id
-                        "name": { "bsonType": "string" },
-                        "longName": { "bsonType": "string" },
-                        "statement": { "bsonType": "binData" }
-                    }
-                }
-            })
+            .validator(problem_schema())
             .validation_action(ValidationAction::Error)
             .validation_level(ValidationLevel::Strict)
             .build(),
@@ -57,15 +203,56 @@ pub async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::er
 
     db.create_collection(
         "users",
+        CreateCollectionOptions::builder()
+            .validator(user_schema())
+            .validation_action(ValidationAction::Error)
+            .validation_level(ValidationLevel::Strict)
+            .build(),
+    )
+    .await?;
+
+    db.create_collection(
+        "admins",
+        CreateCollectionOptions::builder()
+            .validator(user_schema())
+            .validation_action(ValidationAction::Error)
+            .validation_level(ValidationLevel::Strict)
+            .build(),
+    )
+    .await?;
+
+    db.create_collection(
+        "teams",
+        CreateCollectionOptions::builder()
+            .validator(team_schema())
+            .validation_action(ValidationAction::Error)
+            .validation_level(ValidationLevel::Strict)
+            .build(),
+    )
+    .await?;
+
+    db.create_collection(
+        "user_windows",
+        CreateCollectionOptions::builder()
+            .validator(user_window_schema())
+            .validation_action(ValidationAction::Error)
+            .validation_level(ValidationLevel::Strict)
+            .build(),
+    )
+    .await?;
+
+    db.create_collection(
+        "sessions",
         CreateCollectionOptions::builder()
             .validator(doc! {
                 "$jsonSchema": {
                     "bsonType": "object",
-                    "required": ["_id", "fullname", "password"],
+                    "required": ["_id", "username", "admin", "expires"],
                     "properties": {
-                        "_id": { "bsonType": "string" }, // username
-                        "fullname": { "bsonType": "string" },
-                        "password": { "bsonType": "string" }
+                        "_id": { "bsonType": "long" }, // session id
+                        "username": { "bsonType": "string" },
+                        "admin": { "bsonType": "bool" }, // of an admin account
+                        "expires": { "bsonType": "timestamp" }
                     }
                 }
             })
@@ -78,20 +265,7 @@ pub async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::er
     db.create_collection(
         "announcements",
         CreateCollectionOptions::builder()
-            .validator(doc! {
-                "$jsonSchema": {
-                    "bsonType": "object",
-                    "required": ["_id", "subject", "problemId", "text", "to", "created"],
-                    "properties": {
-                        "_id": { "bsonType": "long" }, // announcement id
-                        "subject": { "bsonType": "string" },
-                        "problemId": { "bsonType": ["null","int"] },
-                        "text": { "bsonType": ["null","string"] },
-                        "to": { "bsonType": ["null","string"] },
-                        "created": { "bsonType": "timestamp" }
-                    }
-                }
-            })
+            .validator(announcement_schema())
             .validation_action(ValidationAction::Error)
             .validation_level(ValidationLevel::Strict)
             .build(),
@@ -101,20 +275,7 @@ pub async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::er
     db.create_collection(
         "questions",
         CreateCollectionOptions::builder()
-            .validator(doc! {
-                "$jsonSchema": {
-                    "bsonType": "object",
-                    "required": ["_id", "subject", "problemId", "text", "from", "created"],
-                    "properties": {
-                        "_id": { "bsonType": "long" }, // question id
-                        "subject": { "bsonType": "string" },
-                        "problemId": { "bsonType": ["null","int"] },
-                        "text": { "bsonType": "string" },
-                        "from": { "bsonType": ["null","string"] },
-                        "created": { "bsonType": "timestamp" }
-                    }
-                }
-            })
+            .validator(question_schema())
             .validation_action(ValidationAction::Error)
             .validation_level(ValidationLevel::Strict)
             .build(),
@@ -124,11 +285,87 @@ pub async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::er
     let metadata = crate::mappings::contest::ContestMetadata::default();
     db.collection::<Document>("contest_metadata")
         .update_one(
-            doc! {},
+            doc! { "_id": protos::scope::DEFAULT_CONTEST },
             doc! { "$set": Document::from(metadata) },
             UpdateOptions::builder().upsert(true).build(),
         )
         .await?;
 
+    let admin = crate::mappings::user::User::from(protos::service::contest::SetUserRequest {
+        username: String::from("admin"),
+        fullname: String::from("admin"),
+        password: admin_password,
+        admin: Some(true),
+    });
+    db.collection::<Document>("admins")
+        .insert_one(Document::from(admin), None)
+        .await?;
+
+    Ok(())
+}
+
+/// Brings a database created before the installation ran several contests to the current
+/// schema: everything that existed then belongs to the default contest. Running it again
+/// changes nothing
+pub async fn migrate_contest_service_db(db: Database) -> Result<(), Box<dyn std::error::Error>> {
+    let default_contest = protos::scope::DEFAULT_CONTEST;
+
+    // the metadata of the only contest had an id generated by mongo
+    let contest_metadata = db.collection::<Document>("contest_metadata");
+    let old_id = doc! { "_id": { "$not": { "$type": "string" } } };
+    if contest_metadata
+        .count_documents(doc! { "_id": default_contest }, None)
+        .await?
+        == 0
+    {
+        let mut metadata = match contest_metadata.find_one(old_id.clone(), None).await? {
+            Some(metadata) => metadata,
+            None => Document::from(crate::mappings::contest::ContestMetadata::default()),
+        };
+        metadata.insert("_id", default_contest);
+        contest_metadata.insert_one(metadata, None).await?;
+    }
+    contest_metadata.delete_many(old_id, None).await?;
+
+    for collection in ["users", "problems"] {
+        db.collection::<Document>(collection)
+            .update_many(
+                doc! { "contests": { "$exists": false } },
+                doc! { "$set": { "contests": [default_contest] } },
+                None,
+            )
+            .await?;
+    }
+    for collection in ["teams", "announcements", "questions"] {
+        db.collection::<Document>(collection)
+            .update_many(
+                doc! { "contestId": { "$exists": false } },
+                doc! { "$set": { "contestId": default_contest } },
+                None,
+            )
+            .await?;
+    }
+    // the windows were identified by the user alone
+    db.collection::<Document>("user_windows")
+        .update_many(
+            doc! { "contestId": { "$exists": false } },
+            vec![doc! { "$set": { "user": "$_id", "contestId": default_contest } }],
+            None,
+        )
+        .await?;
+
+    for (collection, validator) in [
+        ("contest_metadata", contest_metadata_schema()),
+        ("problems", problem_schema()),
+        ("users", user_schema()),
+        ("admins", user_schema()),
+        ("teams", team_schema()),
+        ("user_windows", user_window_schema()),
+        ("announcements", announcement_schema()),
+        ("questions", question_schema()),
+    ] {
+        db.run_command(doc! { "collMod": collection, "validator": validator }, None)
+            .await?;
+    }
     Ok(())
 }
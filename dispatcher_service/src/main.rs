@@ -1,4 +1,5 @@
 use protos::{
+    auth,
     common::Score,
     evaluation::{compilation_result, EvaluationResult, SubtaskResult, TestcaseResult},
     service::{
@@ -9,16 +10,17 @@ use protos::{
         evaluation::{evaluation_server::Evaluation, GetProblemRequest},
         worker::{self, worker_client::WorkerClient},
     },
-    utils::{get_local_address, Service},
+    utils::{authenticated, get_local_address, AuthChannel, Service},
 };
 use std::collections::HashMap;
 use tonic::transport::Channel;
 use tonic::{transport::Server, Request, Response, Status};
+use utils::scoring::subtask_testcases;
 
 mod mock_services;
 
 pub struct DispatcherService {
-    load_balancer: WorkerClient<Channel>,
+    load_balancer: WorkerClient<AuthChannel>,
 }
 
 impl DispatcherService {
@@ -41,7 +43,7 @@ impl DispatcherService {
         let channel = Channel::balance_list(endpoints);
 
         Ok(Self {
-            load_balancer: WorkerClient::new(channel),
+            load_balancer: WorkerClient::new(authenticated(channel)),
         })
     }
 }
@@ -75,12 +77,14 @@ async fn group_testcases(
         .subtasks
         .iter()
         .map(|subtask| SubtaskResult {
-            testcase_results: subtask
-                .testcases_id
+            testcase_results: subtask_testcases(&problem_metadata.info, subtask.id)
                 .iter()
                 .map(|testcase_id| map_id_to_testcase_result[testcase_id].to_owned())
                 .collect(),
-            score: Score { score: 0f64 },
+            score: Score {
+                score: 0f64,
+                ..Default::default()
+            },
             id: subtask.id,
         })
         .collect())
@@ -93,6 +97,7 @@ async fn worker_to_dispatcher_response(
     // in any case:
     //     - res.compilation_result = worker_response.compilation_result
     //     - res.score: not relevant. Use OneOfScore::default()
+    //     - res.problem_version = worker_response.problem_version
     // if compilation is successfull:
     //     - res.subtask_results: group worker_response.testcase_results based on the
     //       metadata of the Problem obtained by the RPC invocation get_problem (of
@@ -109,7 +114,11 @@ async fn worker_to_dispatcher_response(
             } else {
                 vec![]
             },
-            score: Score { score: 0f64 },
+            score: Score {
+                score: 0f64,
+                ..Default::default()
+            },
+            problem_version: worker_response.problem_version,
         },
     })
 }
@@ -120,6 +129,7 @@ impl Dispatcher for DispatcherService {
         &self,
         request: Request<dispatcher::EvaluateSubmissionRequest>,
     ) -> Result<Response<dispatcher::EvaluateSubmissionResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
         let submission_request = request.into_inner();
         let problem_id = submission_request.sub.problem_id;
         let worker_request = Request::new(dispatcher_to_worker_request(&submission_request));
@@ -144,7 +154,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("Starting dispatcher server");
     Server::builder()
-        .add_service(DispatcherServer::new(dispatcher_service))
+        .add_service(DispatcherServer::with_interceptor(
+            dispatcher_service,
+            auth::RoleCheck::from_env()?,
+        ))
         .serve(addr)
         .await?;
     Ok(())
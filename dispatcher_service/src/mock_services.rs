@@ -18,7 +18,12 @@ fn generate_min_subtask_scoring(subtask_num: u64) -> protos::service::evaluation
         id: 0u64,
         scoring: scoring::Subtask {
             method: scoring::subtask::Method::Min as i32,
-            max_score: Score { score: 20f64 },
+            max_score: Score {
+                score: 20f64,
+                ..Default::default()
+            },
+            threshold: None,
+            relative: None,
         },
         testcases_id: {
             let mut testcase_ids = Vec::with_capacity(NUM_OF_TESTCASES_PER_SUBTASK as usize);
@@ -27,6 +32,7 @@ fn generate_min_subtask_scoring(subtask_num: u64) -> protos::service::evaluation
             }
             testcase_ids
         }, // the only relevant field
+        ..Default::default()
     }
 }
 
@@ -36,6 +42,7 @@ fn mock_evaluation_init(mock_evaluation_server: &mut MockEvaluation) {
             id: 0u64,
             scoring: scoring::Problem {
                 method: scoring::problem::Method::MaxSum as i32,
+                relative: None,
             },
             r#type: problem::Type::Other as i32,
             execution_limits: Resources {
@@ -59,6 +66,9 @@ fn mock_evaluation_init(mock_evaluation_server: &mut MockEvaluation) {
                 }
                 subtasks
             },
+            time_multipliers: vec![],
+            testcases_info: vec![],
+            feedback: None,
         },
     });
 }
@@ -82,20 +92,25 @@ fn mock_worker_init(mock_worker_server: &mut MockWorker) {
             for i in 0..NUM_OF_TESTCASES_PER_SUBTASK * NUM_OF_SUBTASKS {
                 testcase_results.push(TestcaseResult {
                     outcome: Outcome::Ok as i32,
-                    score: Score { score: 1f64 },
-                    used_resources: Resources {
+                    score: Score {
+                        score: 1f64,
+                        ..Default::default()
+                    },
+                    used_resources: Some(Resources {
                         time: common::Duration {
                             secs: 0u64,
                             nanos: 1u32,
                         },
                         memory_bytes: 1u64,
-                    },
+                    }),
                     id: i,
+                    objective: None,
                 });
             }
             testcase_results.shuffle(&mut thread_rng());
             testcase_results
         },
+        problem_version: Some(1),
     })
 }
 
@@ -0,0 +1,204 @@
+//! In-memory copy of the problem metadata and of the list of problem files,
+//! so that lookups never walk the storage. It is loaded at startup and
+//! updated after every committed change, while changes start from what is
+//! read again from the storage.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use protos::scoring::User;
+use protos::service::evaluation::{Problem, ProblemUpdateInfo};
+use utils::storage::Storage;
+
+use crate::{
+    serialized_file_name, CONTESTS_FOLDER_NAME, CONTEST_PROBLEMS_FILE_NAME,
+    EVALUATION_FILES_FOLDER_NAME, PROBLEMS_FOLDER_NAME, PROBLEM_METADATA_FILE_NAME,
+    PROBLEM_UPDATE_FILE_NAME, TESTCASES_FOLDER_NAME, USER_SCORING_FILE_NAME,
+};
+
+// only testcases and evaluation files are looked up, everything else is read by path
+fn is_indexed(relative: &Path) -> bool {
+    relative.starts_with(TESTCASES_FOLDER_NAME)
+        || relative.starts_with(EVALUATION_FILES_FOLDER_NAME)
+}
+
+#[derive(Debug, Clone)]
+pub struct ProblemEntry {
+    pub path: PathBuf,
+    pub problem: Problem,
+    pub update_info: ProblemUpdateInfo,
+    // relative to the problem folder
+    files: HashSet<PathBuf>,
+}
+
+impl ProblemEntry {
+    /// Path of the problem file, if present
+    pub fn file(&self, relative: &Path) -> Option<PathBuf> {
+        if self.files.contains(relative) {
+            Some(self.path.join(relative))
+        } else {
+            None
+        }
+    }
+
+    // Same order as a transaction: deletions first, then writes
+    fn apply(&mut self, deleted: &[PathBuf], written: &[PathBuf]) {
+        self.files
+            .retain(|file| !deleted.iter().any(|item| file.starts_with(item)));
+        self.files.extend(
+            written
+                .iter()
+                .filter(|relative| is_indexed(relative))
+                .cloned(),
+        );
+    }
+
+    fn load<S: Storage>(
+        storage: &S,
+        path: &Path,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        // a problem without metadata has not been committed yet
+        let metadata_path = path.join(serialized_file_name(PROBLEM_METADATA_FILE_NAME));
+        if !storage.is_file(&metadata_path)? {
+            return Ok(None);
+        }
+        let update_path = path.join(serialized_file_name(PROBLEM_UPDATE_FILE_NAME));
+        let update_info = if storage.is_file(&update_path)? {
+            storage.read_file_object(&update_path)?
+        } else {
+            ProblemUpdateInfo::default()
+        };
+        let mut files = HashSet::new();
+        for folder in [TESTCASES_FOLDER_NAME, EVALUATION_FILES_FOLDER_NAME] {
+            for file in storage.list_files(&path.join(folder))? {
+                if let Ok(relative) = file.strip_prefix(path) {
+                    files.insert(relative.to_path_buf());
+                }
+            }
+        }
+        Ok(Some(Self {
+            path: path.to_path_buf(),
+            problem: storage.read_file_object(&metadata_path)?,
+            update_info,
+            files,
+        }))
+    }
+}
+
+/// User scoring of a contest and the problems it uses, which other contests can use too
+#[derive(Debug, Clone, Default)]
+pub struct ContestEntry {
+    pub user_scoring: Option<User>,
+    pub problems: Vec<u64>,
+}
+
+impl ContestEntry {
+    fn load<S: Storage>(storage: &S, path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut entry = Self::default();
+        let user_scoring_path = path.join(serialized_file_name(USER_SCORING_FILE_NAME));
+        if storage.is_file(&user_scoring_path)? {
+            entry.user_scoring = Some(storage.read_file_object(&user_scoring_path)?);
+        }
+        let problems_path = path.join(serialized_file_name(CONTEST_PROBLEMS_FILE_NAME));
+        if storage.is_file(&problems_path)? {
+            entry.problems = storage.read_file_object(&problems_path)?;
+        }
+        Ok(entry)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Index {
+    contests: HashMap<String, ContestEntry>,
+    problems: HashMap<u64, ProblemEntry>,
+}
+
+impl Index {
+    /// Reads everything that was committed to the storage
+    pub fn load<S: Storage>(storage: &S) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut index = Self::default();
+        for path in storage.iterate_folder(CONTESTS_FOLDER_NAME, None)? {
+            if let Some(contest_id) = path.file_name().and_then(|name| name.to_str()) {
+                let entry = ContestEntry::load(storage, &path)?;
+                index.contests.insert(contest_id.to_string(), entry);
+            }
+        }
+        for path in storage.iterate_folder(PROBLEMS_FOLDER_NAME, None)? {
+            if let Some(entry) = ProblemEntry::load(storage, &path)? {
+                index.problems.insert(entry.problem.id, entry);
+            }
+        }
+        Ok(index)
+    }
+
+    pub fn problem(&self, problem_id: u64) -> Option<&ProblemEntry> {
+        self.problems.get(&problem_id)
+    }
+
+    /// Reads the problem in the folder again, forgetting it if it is not in the storage anymore
+    pub fn reload_problem<S: Storage>(
+        &mut self,
+        storage: &S,
+        problem_id: u64,
+        path: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match ProblemEntry::load(storage, path)? {
+            Some(entry) => self.problems.insert(problem_id, entry),
+            None => self.problems.remove(&problem_id),
+        };
+        Ok(())
+    }
+
+    /// All the problems, by id
+    pub fn problems(&self) -> Vec<&ProblemEntry> {
+        let mut problems: Vec<_> = self.problems.values().collect();
+        problems.sort_by_key(|entry| entry.problem.id);
+        problems
+    }
+
+    pub fn has_contests(&self) -> bool {
+        !self.contests.is_empty()
+    }
+
+    pub fn contest(&self, contest_id: &str) -> Option<&ContestEntry> {
+        self.contests.get(contest_id)
+    }
+
+    pub fn contest_mut(&mut self, contest_id: &str) -> &mut ContestEntry {
+        self.contests.entry(contest_id.to_string()).or_default()
+    }
+
+    /// The problems of the contest, by id
+    pub fn contest_problems(&self, contest_id: &str) -> Vec<&ProblemEntry> {
+        let ids = self
+            .contest(contest_id)
+            .map_or(&[][..], |contest| &contest.problems[..]);
+        self.problems()
+            .into_iter()
+            .filter(|entry| ids.contains(&entry.problem.id))
+            .collect()
+    }
+
+    /// Records a change committed to a problem folder, with the paths relative to it
+    pub fn update(
+        &mut self,
+        path: &Path,
+        problem: Problem,
+        update_info: ProblemUpdateInfo,
+        deleted: &[PathBuf],
+        written: &[PathBuf],
+    ) {
+        let entry = self
+            .problems
+            .entry(problem.id)
+            .or_insert_with(|| ProblemEntry {
+                path: path.to_path_buf(),
+                problem: Problem::default(),
+                update_info: ProblemUpdateInfo::default(),
+                files: HashSet::new(),
+            });
+        entry.problem = problem;
+        entry.update_info = update_info;
+        entry.apply(deleted, written);
+    }
+}
@@ -1,16 +1,45 @@
+use std::collections::BTreeMap;
+use std::future::Future;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
+use protos::auth;
+use protos::common::FileChunk;
+use protos::scope;
+use protos::service::contest::{self, contest_client::ContestClient};
 use protos::service::evaluation::{evaluation_server::*, *};
+use protos::service::submission::{submission_client::SubmissionClient, RecomputeScoresRequest};
 use protos::utils::*;
 use std::time::SystemTime;
-use tonic::{transport::*, Request, Response, Status};
-use utils::storage::FsStorageHelper;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::*, Request, Response, Status, Streaming};
+use utils::chunks::{ChunkReader, ChunkWriter};
+use utils::history::{self, History};
+use utils::lock::StorageLock;
+use utils::package::{
+    read_problem_package, write_problem_package, PackageTestcase, ProblemPackage,
+};
+use utils::s3::{S3Config, S3Storage};
+use utils::storage::{FsStorageHelper, Storage};
+use utils::transaction::{self, Transaction};
+
+mod index;
+use index::Index;
 
 const ROOT_PATH: &str = "/evaluation_files";
+const STORAGE_ENV: &str = "EVALUATION_STORAGE";
+const FILES_PATH_ENV: &str = "EVALUATION_FILES_PATH";
+const INDEX_REFRESH_ENV: &str = "EVALUATION_INDEX_REFRESH_SECONDS";
 const SERIALIZED_EXTENSION: &str = "ser";
 const USER_SCORING_FILE_NAME: &str = "user_scoring";
+const CONTESTS_FOLDER_NAME: &str = "contests";
+const CONTEST_PROBLEMS_FILE_NAME: &str = "problems";
 const PROBLEMS_FOLDER_NAME: &str = "problems";
+const LOCK_FOLDER_NAME: &str = "lock";
+// longer than any change, after that the lock of a replica is considered abandoned
+const LOCK_LEASE: std::time::Duration = std::time::Duration::from_secs(10 * 60);
 const TESTCASES_FOLDER_NAME: &str = "testcases";
 const EVALUATION_FILES_FOLDER_NAME: &str = "files";
 const INPUT_FILE_NAME: &str = "input";
@@ -18,6 +47,7 @@ const OUTPUT_FILE_NAME: &str = "output";
 const IO_EXTENSION: &str = "txt";
 const PROBLEM_METADATA_FILE_NAME: &str = "metadata";
 const PROBLEM_UPDATE_FILE_NAME: &str = "updates";
+const STREAM_BUFFER_SIZE: usize = 16;
 
 fn internal_error<T>(e: T) -> Status
 where
@@ -40,80 +70,837 @@ where
     io::Error::new(io::ErrorKind::NotFound, format!("{:?}", e))
 }
 
-#[derive(Debug)]
-pub struct EvaluationService {
-    storage: FsStorageHelper,
+fn invalid_argument_error<T>(e: T) -> Status
+where
+    T: core::fmt::Debug,
+{
+    Status::invalid_argument(format!("{:?}", e))
 }
 
-impl EvaluationService {
-    fn get_problem_folder_from_id(&self, problem_id: u64) -> io::Result<PathBuf> {
-        self.storage
-            .search_item(None, PROBLEMS_FOLDER_NAME, None)
-            .and_then(|op| op.ok_or_else(|| not_found_io_error("Problems folder not found")))
-            .and_then(|path| {
-                self.storage
-                    .search_item(Some(&path), &problem_id.to_string(), None)
-            })
-            .and_then(|op| {
-                op.ok_or_else(|| {
-                    not_found_io_error(&format!("Problem not found [id: {}]", problem_id))
+fn io_file_name(part: testcase_chunk::Part) -> &'static str {
+    match part {
+        testcase_chunk::Part::Input => INPUT_FILE_NAME,
+        testcase_chunk::Part::Output => OUTPUT_FILE_NAME,
+    }
+}
+
+type TestcaseChunkStream = ReceiverStream<Result<TestcaseChunk, Status>>;
+
+// Files are read on a blocking thread and sent through a bounded channel,
+// so that at most STREAM_BUFFER_SIZE chunks are kept in memory
+fn stream_testcase_files<S: Storage + 'static>(
+    storage: Arc<S>,
+    files: Vec<(u64, testcase_chunk::Part, PathBuf)>,
+) -> TestcaseChunkStream {
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+    tokio::task::spawn_blocking(move || {
+        for (testcase_id, part, path) in files {
+            let file = match storage.open_file(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    let _ = tx.blocking_send(Err(internal_error(err)));
+                    return;
+                }
+            };
+            for chunk in ChunkReader::new(file) {
+                let message = chunk
+                    .map(|chunk| TestcaseChunk {
+                        testcase_id,
+                        part: part as i32,
+                        chunk,
+                    })
+                    .map_err(internal_error);
+                let failed = message.is_err();
+                if tx.blocking_send(message).is_err() || failed {
+                    return;
+                }
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+// Paths of the problem files, relative to the problem folder
+fn serialized_file_name(name: &str) -> PathBuf {
+    Path::new(name).with_extension(SERIALIZED_EXTENSION)
+}
+fn testcase_folder_name(testcase_id: u64) -> PathBuf {
+    Path::new(TESTCASES_FOLDER_NAME).join(testcase_id.to_string())
+}
+fn testcase_file_name(testcase_id: u64, part: testcase_chunk::Part) -> PathBuf {
+    testcase_folder_name(testcase_id)
+        .join(io_file_name(part))
+        .with_extension(IO_EXTENSION)
+}
+fn evaluation_file_name(file_type: evaluation_file::Type) -> PathBuf {
+    Path::new(EVALUATION_FILES_FOLDER_NAME)
+        .join(file_type.to_string().to_lowercase())
+        .with_extension(SERIALIZED_EXTENSION)
+}
+
+fn testcase_ids(problem: &Problem) -> impl Iterator<Item = u64> + '_ {
+    problem
+        .subtasks
+        .iter()
+        .flat_map(|subtask| subtask.testcases_id.iter().copied())
+}
+
+// Makes the update info list exactly the testcases of the problem,
+// the ones that were not there before are marked as updated now
+fn sync_update_info(problem: &Problem, info: &mut ProblemUpdateInfo) {
+    let now: protos::common::Timestamp = SystemTime::now().into();
+    let mut old: BTreeMap<u64, TestcaseUpdateInfo> = std::mem::take(&mut info.subtasks)
+        .into_iter()
+        .flat_map(|subtask| subtask.testcases)
+        .map(|testcase| (testcase.testcase_id, testcase))
+        .collect();
+    info.problem_id = problem.id;
+    info.subtasks = problem
+        .subtasks
+        .iter()
+        .map(|subtask| SubtaskUpdateInfo {
+            subtask_id: subtask.id,
+            testcases: subtask
+                .testcases_id
+                .iter()
+                .map(|&testcase_id| {
+                    old.remove(&testcase_id)
+                        .unwrap_or_else(|| TestcaseUpdateInfo {
+                            testcase_id,
+                            input_last_update: now.clone(),
+                            output_last_update: now.clone(),
+                        })
                 })
+                .collect(),
+        })
+        .collect();
+}
+
+/// Whether the stored submissions of the problem must be scored again after it is updated
+fn scoring_changed(stored: &Problem, updated: &Problem) -> bool {
+    // the scores depend on the testcases each subtask is scored on too
+    let scoring_tree = |problem: &Problem| {
+        let subtasks: Vec<_> = problem
+            .subtasks
+            .iter()
+            .map(|s| {
+                (
+                    s.id,
+                    s.scoring.clone(),
+                    s.testcases_id.clone(),
+                    s.dependencies.clone(),
+                    s.parent,
+                )
             })
+            .collect();
+        (problem.scoring.clone(), subtasks)
+    };
+    scoring_tree(stored) != scoring_tree(updated)
+}
+
+// Dependencies and parents must be other subtasks of the same problem
+fn check_subtask_references(problem: &Problem) -> Result<(), Status> {
+    for subtask in problem.subtasks.iter() {
+        for &id in subtask.parent.iter().chain(subtask.dependencies.iter()) {
+            if id == subtask.id || problem.subtasks.iter().all(|other| other.id != id) {
+                return Err(invalid_argument_error(format!(
+                    "Invalid subtask dependency [problem id: {}, subtask id: {}, dependency: {}]",
+                    problem.id, subtask.id, id
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn add_testcase(problem: &mut Problem, subtask_id: u64, testcase_id: u64) -> Result<(), Status> {
+    if testcase_ids(problem).any(|id| id == testcase_id) {
+        return Err(Status::already_exists(format!(
+            "Testcase already exists [problem id: {}, id: {}]",
+            problem.id, testcase_id
+        )));
+    }
+    problem
+        .subtasks
+        .iter_mut()
+        .find(|subtask| subtask.id == subtask_id)
+        .ok_or_else(|| not_found_error(format!("Subtask not found [id: {}]", subtask_id)))?
+        .testcases_id
+        .push(testcase_id);
+    Ok(())
+}
+
+fn check_testcase(problem: &Problem, testcase_id: u64) -> Result<(), Status> {
+    if testcase_ids(problem).any(|id| id == testcase_id) {
+        Ok(())
+    } else {
+        Err(not_found_error(format!(
+            "Testcase not found [id: {}]",
+            testcase_id
+        )))
+    }
+}
+
+// Marks the testcase files as updated, so that workers pull them again
+fn touch_testcase(info: &mut ProblemUpdateInfo, testcase_id: u64) -> Result<(), Status> {
+    let tcu = info
+        .subtasks
+        .iter_mut()
+        .flat_map(|subtask| subtask.testcases.iter_mut())
+        .find(|x| x.testcase_id == testcase_id)
+        .ok_or_else(|| internal_error("testcase not found in update info"))?;
+    tcu.input_last_update = SystemTime::now().into();
+    tcu.output_last_update = SystemTime::now().into();
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct EvaluationService<S: Storage = FsStorageHelper> {
+    storage: Arc<S>,
+    contest_client: ContestClient<AuthChannel>,
+    submission_client: SubmissionClient<AuthChannel>,
+    // what is committed to the storage, so that reads never have to search it
+    index: Arc<RwLock<Index>>,
+    // every change to a problem reads and rewrites its metadata, so they are serialized:
+    // between the threads of this process, then with the replicas sharing the storage
+    write_lock: Arc<Mutex<()>>,
+}
+
+// Held while changing the storage, the index may be behind the changes of other replicas
+struct WriteGuard<'a, S: Storage> {
+    _shared: StorageLock<'a, S>,
+    _local: MutexGuard<'a, ()>,
+}
+
+impl<S: Storage + 'static> EvaluationService<S> {
+    fn index(&self) -> RwLockReadGuard<'_, Index> {
+        self.index
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    fn index_mut(&self) -> RwLockWriteGuard<'_, Index> {
+        self.index
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    fn get_problem_folder_from_id(&self, problem_id: u64) -> io::Result<PathBuf> {
+        self.index()
+            .problem(problem_id)
+            .map(|entry| entry.path.clone())
+            .ok_or_else(|| not_found_io_error(&format!("Problem not found [id: {}]", problem_id)))
+    }
+    fn load_problem_metadata(&self, problem_id: u64) -> Result<Problem, Status> {
+        self.index()
+            .problem(problem_id)
+            .map(|entry| entry.problem.clone())
+            .ok_or_else(|| not_found_error(format!("Problem not found [id: {}]", problem_id)))
     }
     fn load_problem_update_file(&self, problem_id: u64) -> Result<ProblemUpdateInfo, Status> {
-        self.get_problem_folder_from_id(problem_id)
-            .map_err(internal_error)
-            .and_then(|path| {
-                self.storage
-                    .search_item(
-                        Some(&path),
-                        PROBLEM_UPDATE_FILE_NAME,
-                        Some(SERIALIZED_EXTENSION),
+        self.index()
+            .problem(problem_id)
+            .map(|entry| entry.update_info.clone())
+            .ok_or_else(|| not_found_error(format!("Problem not found [id: {}]", problem_id)))
+    }
+    fn lock(&self) -> Result<WriteGuard<'_, S>, Status> {
+        let local = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let shared = StorageLock::acquire(
+            &*self.storage,
+            &self.storage.root().join(LOCK_FOLDER_NAME),
+            LOCK_LEASE,
+        )
+        .map_err(|err| internal_error(err.to_string()))?;
+        Ok(WriteGuard {
+            _shared: shared,
+            _local: local,
+        })
+    }
+    /// Reads the problem again from the storage before changing it, as the index could be
+    /// behind it, must be called holding the write lock
+    fn reload_problem(&self, problem_id: u64) -> Result<(), Status> {
+        let path = self
+            .storage
+            .root()
+            .join(PROBLEMS_FOLDER_NAME)
+            .join(problem_id.to_string());
+        self.index_mut()
+            .reload_problem(&*self.storage, problem_id, &path)
+            .map_err(|err| internal_error(err.to_string()))
+    }
+    fn get_contest_folder(&self, contest_id: &str) -> io::Result<PathBuf> {
+        let contests_path = self.storage.add_folder(CONTESTS_FOLDER_NAME, None)?;
+        self.storage.add_folder(contest_id, Some(&contests_path))
+    }
+    /// Fails if one of the problems belongs to another contest, as the problem ids and
+    /// folders are shared by all the contests. Must be called holding the write lock
+    fn check_problems_owner(&self, contest_id: &str, problem_ids: &[u64]) -> Result<(), Status> {
+        for path in self.storage.iterate_folder(CONTESTS_FOLDER_NAME, None)? {
+            let owner = match path.file_name().and_then(|name| name.to_str()) {
+                Some(owner) if owner != contest_id => owner,
+                _ => continue,
+            };
+            let problems_path = path.join(serialized_file_name(CONTEST_PROBLEMS_FILE_NAME));
+            if !self.storage.is_file(&problems_path)? {
+                continue;
+            }
+            let problems: Vec<u64> = self
+                .storage
+                .read_file_object(&problems_path)
+                .map_err(|err| internal_error(err.to_string()))?;
+            if let Some(problem_id) = problem_ids.iter().find(|id| problems.contains(id)) {
+                return Err(Status::already_exists(format!(
+                    "Problem of another contest [id: {}, contest: {}]",
+                    problem_id, owner
+                )));
+            }
+        }
+        Ok(())
+    }
+    /// Adds the problems to the contest, must be called holding the write lock
+    fn add_contest_problems(&self, contest_id: &str, problem_ids: &[u64]) -> Result<(), Status> {
+        self.check_problems_owner(contest_id, problem_ids)?;
+        let problems_path = self
+            .get_contest_folder(contest_id)?
+            .join(serialized_file_name(CONTEST_PROBLEMS_FILE_NAME));
+        let mut problems: Vec<u64> = if self.storage.is_file(&problems_path)? {
+            self.storage
+                .read_file_object(&problems_path)
+                .map_err(|err| internal_error(err.to_string()))?
+        } else {
+            vec![]
+        };
+        for &problem_id in problem_ids {
+            if !problems.contains(&problem_id) {
+                problems.push(problem_id);
+            }
+        }
+        self.storage
+            .save_file_object(
+                Some(&self.get_contest_folder(contest_id)?),
+                CONTEST_PROBLEMS_FILE_NAME,
+                SERIALIZED_EXTENSION,
+                &problems,
+            )
+            .map_err(internal_error)?;
+        self.index_mut().contest_mut(contest_id).problems = problems;
+        Ok(())
+    }
+    /// Saves the contest, must be called holding the write lock.
+    /// Returns the ids of the problems whose scoring changed
+    fn save_contest(
+        &self,
+        contest_id: &str,
+        user_scoring_method: protos::scoring::User,
+        problems: Vec<Problem>,
+    ) -> Result<Vec<u64>, Status> {
+        let problem_ids: Vec<u64> = problems.iter().map(|p| p.id).collect();
+        self.check_problems_owner(contest_id, &problem_ids)?;
+        self.storage
+            .save_file_object(
+                Some(&self.get_contest_folder(contest_id)?),
+                USER_SCORING_FILE_NAME,
+                SERIALIZED_EXTENSION,
+                &user_scoring_method,
+            )
+            .map_err(not_found_error)?;
+        self.index_mut().contest_mut(contest_id).user_scoring = Some(user_scoring_method);
+
+        // Save problems
+        let mut changed_problems = vec![];
+        for mut p in problems {
+            self.reload_problem(p.id)?;
+            let stored = self.load_problem_metadata(p.id).ok();
+            let is_new = stored.is_none();
+            let stored = stored.unwrap_or_default();
+            let mut update_info = self.load_problem_update_file(p.id).ok().unwrap_or_default();
+            for subtask in p.subtasks.iter_mut() {
+                subtask.testcases_id = stored
+                    .subtasks
+                    .iter()
+                    .find(|s| s.id == subtask.id)
+                    .map(|s| s.testcases_id.clone())
+                    .unwrap_or_default();
+                let testcases_id = &subtask.testcases_id;
+                subtask
+                    .testcase_limits
+                    .retain(|l| testcases_id.contains(&l.testcase_id));
+            }
+            let kept: Vec<u64> = testcase_ids(&p).collect();
+            p.testcases_info
+                .retain(|info| kept.contains(&info.testcase_id));
+            if !is_new && scoring_changed(&stored, &p) {
+                changed_problems.push(p.id);
+            }
+            let mut transaction = self.begin_problem_transaction(p.id)?;
+            for testcase_id in testcase_ids(&stored) {
+                if testcase_ids(&p).all(|id| id != testcase_id) {
+                    transaction.delete(testcase_folder_name(testcase_id));
+                }
+            }
+            sync_update_info(&p, &mut update_info);
+            self.commit_problem(transaction, &p, update_info, "Update contest")?;
+        }
+        self.add_contest_problems(contest_id, &problem_ids)?;
+        Ok(changed_problems)
+    }
+    // Starts a transaction on the problem folder, creating it for new problems
+    fn begin_problem_transaction(&self, problem_id: u64) -> Result<Transaction<'_, S>, Status> {
+        let problems_path = self.storage.add_folder(PROBLEMS_FOLDER_NAME, None)?;
+        Ok(Transaction::begin(
+            &*self.storage,
+            &problems_path.join(problem_id.to_string()),
+        )?)
+    }
+    // The update info changes without changing the problem, so it is not versioned
+    fn problem_history(&self, problem_path: &Path) -> History<'_, S> {
+        History::new(
+            &*self.storage,
+            problem_path,
+            &[&serialized_file_name(PROBLEM_UPDATE_FILE_NAME)],
+        )
+    }
+    // Saves the problem metadata and update info along with the rest of the transaction,
+    // making sure every folder the problem needs exists, and updates the index once it
+    // is committed, returns the problem version
+    fn commit_problem(
+        &self,
+        mut transaction: Transaction<'_, S>,
+        problem: &Problem,
+        mut update_info: ProblemUpdateInfo,
+        description: &str,
+    ) -> Result<Option<u64>, Status> {
+        transaction.create_folder(TESTCASES_FOLDER_NAME);
+        transaction.create_folder(EVALUATION_FILES_FOLDER_NAME);
+        for testcase_id in testcase_ids(problem) {
+            transaction.create_folder(testcase_folder_name(testcase_id));
+        }
+        transaction
+            .write_object(serialized_file_name(PROBLEM_METADATA_FILE_NAME), problem)
+            .map_err(internal_error)?;
+        let history = self.problem_history(transaction.path());
+        if let Some(version) = history
+            .record(&mut transaction, description)
+            .map_err(internal_error)?
+        {
+            update_info.version = Some(version);
+        }
+        transaction
+            .write_object(serialized_file_name(PROBLEM_UPDATE_FILE_NAME), &update_info)
+            .map_err(internal_error)?;
+        let path = transaction.path().to_path_buf();
+        let deleted = transaction.deletions().to_vec();
+        let written: Vec<PathBuf> = transaction
+            .staged_files()
+            .into_iter()
+            .map(|(relative, _)| relative)
+            .collect();
+        transaction.commit().map_err(internal_error)?;
+        let version = update_info.version;
+        self.index_mut()
+            .update(&path, problem.clone(), update_info, &deleted, &written);
+        Ok(version)
+    }
+    // Path of a problem file, as it is now or as it was in an older version
+    fn problem_file(
+        &self,
+        problem_id: u64,
+        version: Option<u64>,
+        relative: &Path,
+    ) -> Result<Option<PathBuf>, Status> {
+        let problem_path = self.get_problem_folder_from_id(problem_id)?;
+        match version {
+            None => Ok(self
+                .index()
+                .problem(problem_id)
+                .and_then(|entry| entry.file(relative))),
+            Some(version) => {
+                let history = self.problem_history(&problem_path);
+                let version = history
+                    .get(version)
+                    .map_err(internal_error)?
+                    .ok_or_else(|| {
+                        not_found_error(format!(
+                            "Version not found [problem id: {}, version: {}]",
+                            problem_id, version
+                        ))
+                    })?;
+                Ok(history.file_path(&version, relative))
+            }
+        }
+    }
+    // Replaces the problem with the content of an imported package
+    fn save_package(&self, problem_id: u64, package: ProblemPackage) -> Result<Problem, Status> {
+        let _lock = self.lock()?;
+        self.reload_problem(problem_id)?;
+        let mut problem = package.problem;
+        problem.id = problem_id;
+
+        let mut transaction = self.begin_problem_transaction(problem_id)?;
+        transaction.delete(TESTCASES_FOLDER_NAME);
+        transaction.delete(EVALUATION_FILES_FOLDER_NAME);
+        for (id, tc) in package.testcases {
+            transaction.write(
+                testcase_file_name(id, testcase_chunk::Part::Input),
+                &tc.input,
+            )?;
+            transaction.write(
+                testcase_file_name(id, testcase_chunk::Part::Output),
+                &tc.output,
+            )?;
+        }
+        for (file_type, source) in [
+            (evaluation_file::Type::Checker, package.checker),
+            (evaluation_file::Type::Interactor, package.interactor),
+        ] {
+            if let Some(source) = source {
+                transaction
+                    .write_object(
+                        evaluation_file_name(file_type),
+                        &EvaluationFile {
+                            r#type: file_type as i32,
+                            source,
+                        },
                     )
-                    .map_err(internal_error)
-            })
-            .and_then(|op| {
-                op.ok_or_else(|| {
-                    not_found_error(format!("Problem updates not found [id: {}]", problem_id))
-                })
+                    .map_err(internal_error)?;
+            }
+        }
+
+        let now: protos::common::Timestamp = SystemTime::now().into();
+        let mut update_info = ProblemUpdateInfo {
+            problem_id,
+            subtasks: vec![],
+            checker_last_update: now.clone(),
+            interactor_last_update: now,
+            version: self
+                .load_problem_update_file(problem_id)
+                .ok()
+                .and_then(|info| info.version),
+        };
+        sync_update_info(&problem, &mut update_info);
+        self.commit_problem(transaction, &problem, update_info, "Import package")?;
+        Ok(problem)
+    }
+    // Reads everything the evaluation service knows about a problem at the given version,
+    // except the statement
+    fn load_package(
+        &self,
+        problem_id: u64,
+        version: Option<u64>,
+    ) -> Result<ProblemPackage, Status> {
+        let problem: Problem = self
+            .problem_file(
+                problem_id,
+                version,
+                &serialized_file_name(PROBLEM_METADATA_FILE_NAME),
+            )?
+            .ok_or_else(|| {
+                not_found_error(format!("Problem metadata not found [id: {}]", problem_id))
             })
             .and_then(|path| {
                 self.storage
                     .read_file_object(&path)
                     .map_err(|err| internal_error(err.as_ref()))
+            })?;
+
+        let mut testcases = BTreeMap::new();
+        for id in testcase_ids(&problem) {
+            let read_part = |part| -> Result<Vec<u8>, Status> {
+                match self.problem_file(problem_id, version, &testcase_file_name(id, part))? {
+                    Some(path) => Ok(self.storage.read_file(&path)?),
+                    None => Ok(vec![]),
+                }
+            };
+            let testcase = PackageTestcase {
+                input: read_part(testcase_chunk::Part::Input)?,
+                output: read_part(testcase_chunk::Part::Output)?,
+            };
+            testcases.insert(id, testcase);
+        }
+
+        let mut evaluation_files = vec![];
+        for file_type in [
+            evaluation_file::Type::Checker,
+            evaluation_file::Type::Interactor,
+        ] {
+            let file =
+                match self.problem_file(problem_id, version, &evaluation_file_name(file_type))? {
+                    Some(path) => Some(
+                        self.storage
+                            .read_file_object::<EvaluationFile>(&path)
+                            .map_err(|err| internal_error(err.as_ref()))?
+                            .source,
+                    ),
+                    None => None,
+                };
+            evaluation_files.push(file);
+        }
+        let interactor = evaluation_files.pop().flatten();
+        let checker = evaluation_files.pop().flatten();
+
+        Ok(ProblemPackage {
+            name: String::new(),
+            long_name: String::new(),
+            statement: vec![],
+            problem,
+            testcases,
+            checker,
+            interactor,
+        })
+    }
+    // Input and output files of a testcase, only the ones present
+    fn get_testcase_files(
+        &self,
+        problem_id: u64,
+        testcase_id: u64,
+    ) -> Result<Vec<(u64, testcase_chunk::Part, PathBuf)>, Status> {
+        let index = self.index();
+        let entry = index
+            .problem(problem_id)
+            .ok_or_else(|| not_found_error(format!("Problem not found [id: {}]", problem_id)))?;
+        if testcase_ids(&entry.problem).all(|id| id != testcase_id) {
+            return Err(not_found_error(format!(
+                "Testcase not found [problem id: {}, id: {}]",
+                problem_id, testcase_id
+            )));
+        }
+        Ok([testcase_chunk::Part::Input, testcase_chunk::Part::Output]
+            .iter()
+            .filter_map(|&part| {
+                entry
+                    .file(&testcase_file_name(testcase_id, part))
+                    .map(|path| (testcase_id, part, path))
             })
+            .collect())
     }
-    fn save_problem_update_file(&self, info: ProblemUpdateInfo) -> Result<(), Status> {
-        let path = self
-            .get_problem_folder_from_id(info.problem_id)
+    fn read_testcase(&self, problem_id: u64, testcase_id: u64) -> Result<Testcase, Status> {
+        let mut testcase = Testcase {
+            id: testcase_id,
+            input: None,
+            output: None,
+        };
+        for (_, part, path) in self.get_testcase_files(problem_id, testcase_id)? {
+            let content = Some(self.storage.read_file(&path)?);
+            match part {
+                testcase_chunk::Part::Input => testcase.input = content,
+                testcase_chunk::Part::Output => testcase.output = content,
+            }
+        }
+        Ok(testcase)
+    }
+    // Receives the testcase files of a set_testcase_stream request in the transaction staging area
+    async fn receive_testcase_stream(
+        &self,
+        stream: &mut Streaming<SetTestcaseStreamRequest>,
+        testcase_id: u64,
+        transaction: &mut Transaction<'_, S>,
+    ) -> Result<(), Status> {
+        let mut writers: [Option<ChunkWriter<S::Writer>>; 2] = [None, None];
+        while let Some(message) = stream.message().await? {
+            let chunk = match message.message {
+                Some(set_testcase_stream_request::Message::Chunk(chunk)) => chunk,
+                _ => return Err(invalid_argument_error("Expected a testcase chunk")),
+            };
+            if chunk.testcase_id != testcase_id {
+                return Err(invalid_argument_error(format!(
+                    "Chunk of another testcase [id: {}]",
+                    chunk.testcase_id
+                )));
+            }
+            let part = testcase_chunk::Part::from_i32(chunk.part)
+                .ok_or_else(|| invalid_argument_error("Invalid testcase part"))?;
+            let writer = match &mut writers[part as usize] {
+                Some(writer) => writer,
+                writer => writer
+                    .insert(ChunkWriter::new(self.storage.create_file(
+                        &transaction.stage(testcase_file_name(testcase_id, part)),
+                    )?)),
+            };
+            writer
+                .write_chunk(&chunk.chunk)
+                .map_err(invalid_argument_error)?;
+        }
+        for writer in writers.iter_mut() {
+            if let Some(writer) = writer.take() {
+                self.storage
+                    .close_file(writer.finish().map_err(invalid_argument_error)?)?;
+            }
+        }
+        Ok(())
+    }
+    // Saves an evaluation file and marks it as updated
+    fn save_evaluation_file(&self, problem_id: u64, ef: EvaluationFile) -> Result<(), Status> {
+        let file_type = evaluation_file::Type::from_i32(ef.r#type).ok_or_else(|| {
+            internal_error(format!(
+                "Cannot convert {} to evaluation file type",
+                ef.r#type
+            ))
+        })?;
+        let _lock = self.lock()?;
+        self.reload_problem(problem_id)?;
+        let problem = self.load_problem_metadata(problem_id)?;
+        let mut update_info = self.load_problem_update_file(problem_id)?;
+        match file_type {
+            evaluation_file::Type::Checker => {
+                update_info.checker_last_update = SystemTime::now().into();
+            }
+            evaluation_file::Type::Interactor => {
+                update_info.interactor_last_update = SystemTime::now().into();
+            }
+        }
+        let mut transaction = self.begin_problem_transaction(problem_id)?;
+        transaction
+            .write_object(evaluation_file_name(file_type), &ef)
             .map_err(internal_error)?;
-        self.storage
-            .save_file_object(
-                Some(&path),
-                PROBLEM_UPDATE_FILE_NAME,
+        self.commit_problem(
+            transaction,
+            &problem,
+            update_info,
+            &format!("Set {}", file_type.to_string().to_lowercase()),
+        )?;
+        Ok(())
+    }
+    // Brings the stored problems back to a consistent state after a crash:
+    // interrupted transactions are completed or discarded, problems without
+    // metadata are removed and the update info is matched to the testcases
+    fn repair(&self) -> Result<(), Box<dyn std::error::Error>> {
+        // the other replicas have no change in progress meanwhile
+        let _lock = self.lock()?;
+        self.storage.remove_temp_files(None)?;
+        self.storage.add_folder(PROBLEMS_FOLDER_NAME, None)?;
+        for problem_path in self.storage.iterate_folder(PROBLEMS_FOLDER_NAME, None)? {
+            transaction::recover(&*self.storage, &problem_path)?;
+            let metadata_path = problem_path.join(serialized_file_name(PROBLEM_METADATA_FILE_NAME));
+            if !self.storage.is_file(&metadata_path)? {
+                println!("Removing incomplete problem [path: {:?}]", problem_path);
+                self.storage.delete_item(&problem_path)?;
+            }
+        }
+        *self.index_mut() = Index::load(&*self.storage)?;
+        self.migrate_default_contest()?;
+
+        let problems: Vec<(PathBuf, Problem, ProblemUpdateInfo)> = self
+            .index()
+            .problems()
+            .into_iter()
+            .map(|entry| {
+                (
+                    entry.path.clone(),
+                    entry.problem.clone(),
+                    entry.update_info.clone(),
+                )
+            })
+            .collect();
+        for (problem_path, problem, mut update_info) in problems {
+            sync_update_info(&problem, &mut update_info);
+            let mut transaction = Transaction::begin(&*self.storage, &problem_path)?;
+            for testcase in self
+                .storage
+                .iterate_folder(TESTCASES_FOLDER_NAME, Some(&problem_path))?
+            {
+                let name = testcase.file_name().unwrap_or_default();
+                let known = name
+                    .to_str()
+                    .and_then(|name| name.parse::<u64>().ok())
+                    .filter(|&id| testcase_ids(&problem).any(|tid| tid == id))
+                    .is_some();
+                if !known {
+                    transaction.delete(Path::new(TESTCASES_FOLDER_NAME).join(name));
+                }
+            }
+            self.commit_problem(transaction, &problem, update_info, "Repair")?;
+        }
+        Ok(())
+    }
+    // Before an installation ran several contests the user scoring was at the root of the
+    // storage and every problem was in the only contest, which becomes the default one.
+    // The old user scoring is removed last, so an interrupted migration runs again
+    fn migrate_default_contest(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let user_scoring_path = self
+            .storage
+            .root()
+            .join(serialized_file_name(USER_SCORING_FILE_NAME));
+        let old_user_scoring = self.storage.is_file(&user_scoring_path)?;
+        if !old_user_scoring && self.index().has_contests() {
+            return Ok(());
+        }
+        let problem_ids: Vec<u64> = self
+            .index()
+            .problems()
+            .into_iter()
+            .map(|entry| entry.problem.id)
+            .collect();
+        if !problem_ids.is_empty() {
+            self.add_contest_problems(scope::DEFAULT_CONTEST, &problem_ids)?;
+        }
+        if old_user_scoring {
+            let user_scoring: protos::scoring::User =
+                self.storage.read_file_object(&user_scoring_path)?;
+            self.storage.save_file_object(
+                Some(&self.get_contest_folder(scope::DEFAULT_CONTEST)?),
+                USER_SCORING_FILE_NAME,
                 SERIALIZED_EXTENSION,
-                info,
-            )
-            .map_err(internal_error)?;
+                &user_scoring,
+            )?;
+            self.index_mut()
+                .contest_mut(scope::DEFAULT_CONTEST)
+                .user_scoring = Some(user_scoring);
+            self.storage.delete_item(&user_scoring_path)?;
+        }
         Ok(())
     }
+    // Reloads the whole index from time to time, to see the changes made to the storage
+    // by the other replicas or from outside the service. It only returns the error of a
+    // failed reload
+    fn index_refresh(&self, period: std::time::Duration) -> impl Future<Output = String> {
+        let storage = self.storage.clone();
+        let index = self.index.clone();
+        let write_lock = self.write_lock.clone();
+        async move {
+            loop {
+                tokio::time::sleep(period).await;
+                let (storage, index, write_lock) =
+                    (storage.clone(), index.clone(), write_lock.clone());
+                let reloaded = tokio::task::spawn_blocking(move || {
+                    let _lock = write_lock
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    let _shared = StorageLock::acquire(
+                        &*storage,
+                        &storage.root().join(LOCK_FOLDER_NAME),
+                        LOCK_LEASE,
+                    )
+                    .map_err(|err| err.to_string())?;
+                    let loaded = Index::load(&*storage).map_err(|err| err.to_string())?;
+                    *index
+                        .write()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner()) = loaded;
+                    Ok::<(), String>(())
+                })
+                .await;
+                match reloaded {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => return err,
+                    Err(err) => return err.to_string(),
+                }
+            }
+        }
+    }
 }
 
 #[tonic::async_trait]
-impl Evaluation for EvaluationService {
+impl<S: Storage + 'static> Evaluation for EvaluationService<S> {
     async fn get_user_scoring(
         &self,
-        _request: Request<GetUserScoringRequest>,
+        request: Request<GetUserScoringRequest>,
     ) -> Result<Response<GetUserScoringResponse>, Status> {
-        self.storage
-            .search_item(None, USER_SCORING_FILE_NAME, Some(SERIALIZED_EXTENSION))
-            .map_err(internal_error)
-            .and_then(|op| op.ok_or_else(|| not_found_error("User scoring method not found")))
-            .and_then(|path| {
-                self.storage
-                    .read_file_object(&path)
-                    .map_err(|err| internal_error(err.as_ref()))
-            })
+        auth::require(&request, auth::READERS)?;
+        let contest_id = scope::contest_id(&request)?;
+        self.index()
+            .contest(&contest_id)
+            .and_then(|contest| contest.user_scoring.clone())
+            .ok_or_else(|| not_found_error("User scoring method not found"))
             .map(|user| Response::new(GetUserScoringResponse { method: user }))
     }
 
@@ -121,59 +908,29 @@ impl Evaluation for EvaluationService {
         &self,
         request: Request<GetProblemRequest>,
     ) -> Result<Response<GetProblemResponse>, Status> {
+        auth::require(&request, auth::STAFF)?;
         let request = request.into_inner();
-        self.get_problem_folder_from_id(request.problem_id)
-            .map_err(internal_error)
-            .and_then(|path| {
-                self.storage
-                    .search_item(
-                        Some(&path),
-                        PROBLEM_METADATA_FILE_NAME,
-                        Some(SERIALIZED_EXTENSION),
-                    )
-                    .map_err(internal_error)
-            })
-            .and_then(|op| {
-                op.ok_or_else(|| {
-                    not_found_error(format!(
-                        "Problem metadata not found [id: {}]",
-                        request.problem_id
-                    ))
-                })
-            })
-            .and_then(|path| {
-                self.storage
-                    .read_file_object(&path)
-                    .map_err(|err| internal_error(err.as_ref()))
-            })
+        self.load_problem_metadata(request.problem_id)
             .map(|prob| Response::new(GetProblemResponse { info: prob }))
     }
 
     async fn get_contest(
         &self,
-        _request: Request<GetContestRequest>,
+        request: Request<GetContestRequest>,
     ) -> Result<Response<GetContestResponse>, Status> {
+        auth::require(&request, auth::READERS)?;
+        let contest_id = scope::contest_id(&request)?;
         let user_scoring_method = self
-            .get_user_scoring(Request::new(GetUserScoringRequest {}))
+            .get_user_scoring(auth::forward(&request, GetUserScoringRequest {}))
             .await?
             .into_inner()
             .method;
-        let mut problems: Vec<Problem> = vec![];
-        for entry in self.storage.iterate_folder(PROBLEMS_FOLDER_NAME, None)? {
-            let problem_path = self
-                .storage
-                .search_item(
-                    Some(&entry?.path()),
-                    PROBLEM_METADATA_FILE_NAME,
-                    Some(SERIALIZED_EXTENSION),
-                )?
-                .ok_or_else(|| not_found_error("Problem metadata not found"))?;
-            let p: Problem = self
-                .storage
-                .read_file_object(&problem_path)
-                .map_err(|err| internal_error(err.as_ref()))?;
-            problems.push(p);
-        }
+        let problems: Vec<Problem> = self
+            .index()
+            .contest_problems(&contest_id)
+            .into_iter()
+            .map(|entry| entry.problem.clone())
+            .collect();
         Ok(Response::new(GetContestResponse {
             info: Contest {
                 problems,
@@ -182,171 +939,77 @@ impl Evaluation for EvaluationService {
         }))
     }
 
-    // NOTE: cannot update file related stuff with this request or everything will burn
+    // Testcases are only changed through set_testcase and import_problem: the ones
+    // in the request are ignored, except that removing a subtask deletes its testcases.
+    // The stored submissions of the problems whose scoring changed are scored again
     async fn set_contest(
         &self,
         request: Request<SetContestRequest>,
     ) -> Result<Response<SetContestResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
+        let contest_id = scope::contest_id(&request)?;
         let request = request.into_inner();
         let user_scoring_method = request.info.user_scoring_method;
         let problems = request.info.problems;
-
-        // Save user scoring method
-        self.storage
-            .save_file_object(
-                None,
-                USER_SCORING_FILE_NAME,
-                SERIALIZED_EXTENSION,
-                user_scoring_method,
-            )
-            .map_err(not_found_error)?;
-
-        // Save problems
-        let problems_path = self.storage.add_folder(PROBLEMS_FOLDER_NAME, None)?;
         for p in problems.iter() {
-            // Save problem metadata
-            let p_path = self
-                .storage
-                .add_folder(&p.id.to_string(), Some(&problems_path))
-                .map_err(internal_error)?;
-            self.storage
-                .save_file_object(
-                    Some(&p_path),
-                    PROBLEM_METADATA_FILE_NAME,
-                    SERIALIZED_EXTENSION,
-                    p,
-                )
-                .map_err(internal_error)?;
-
-            // Create folders for testcases
-            let testcases_path = self
-                .storage
-                .add_folder(TESTCASES_FOLDER_NAME, Some(&p_path))?;
-            for tc_id in p.subtasks.iter().flat_map(|sub| sub.testcases_id.iter()) {
-                self.storage
-                    .add_folder(&tc_id.to_string(), Some(&testcases_path))?;
-            }
-
-            // Create folder for evaluation files
-            self.storage
-                .add_folder(EVALUATION_FILES_FOLDER_NAME, Some(&p_path))?;
+            check_subtask_references(p)?;
+        }
 
-            let mut update_info = self.load_problem_update_file(p.id).ok().unwrap_or_default();
-            update_info.problem_id = p.id;
-            self.save_problem_update_file(update_info)?;
+        let changed_problems = {
+            let _lock = self.lock()?;
+            self.save_contest(&contest_id, user_scoring_method, problems)?
+        };
+        if changed_problems.is_empty() {
+            return Ok(Response::new(SetContestResponse::default()));
         }
-        Ok(Response::new(SetContestResponse {}))
+        // the contest is saved anyway, the scores can be recomputed again later
+        let response = match self
+            .submission_client
+            .clone()
+            .recompute_scores(scope::in_contest(
+                Request::new(RecomputeScoresRequest {
+                    problem_ids: changed_problems,
+                }),
+                &contest_id,
+            ))
+            .await
+        {
+            Ok(response) => SetContestResponse {
+                affected_users: response.into_inner().users,
+                recompute_error: None,
+            },
+            Err(err) => SetContestResponse {
+                affected_users: vec![],
+                recompute_error: Some(err.message().to_string()),
+            },
+        };
+        Ok(Response::new(response))
     }
 
     async fn get_testcase(
         &self,
         request: Request<GetTestcaseRequest>,
     ) -> Result<Response<GetTestcaseResponse>, Status> {
+        auth::require(&request, auth::STAFF)?;
         let request = request.into_inner();
         let problem_id = request.problem_id;
         let testcase_id = request.testcase_id;
 
-        // Get testcase's problem path
-        let problem_path = self.get_problem_folder_from_id(problem_id)?;
-
-        // Get testcases folder
-        let testcases_path = self
-            .storage
-            .search_item(Some(&problem_path), TESTCASES_FOLDER_NAME, None)?
-            .ok_or_else(|| {
-                not_found_error(format!(
-                    "Testcases folder not found [problem id: {}]",
-                    problem_id
-                ))
-            })?;
-
-        // Get testcase folder
-        let tc_path = self
-            .storage
-            .search_item(Some(&testcases_path), &testcase_id.to_string(), None)?
-            .ok_or_else(|| {
-                not_found_error(format!(
-                    "Testcase folder not found [problem id: {}, id: {}]",
-                    problem_id, testcase_id
-                ))
-            })?;
-
-        // Get input and output files (if present)
-        let input_path =
-            self.storage
-                .search_item(Some(&tc_path), INPUT_FILE_NAME, Some(IO_EXTENSION))?;
-        let input_bytes = match input_path {
-            Some(ip) => Some(self.storage.read_file(&ip)?),
-            None => None,
-        };
-        let output_path =
-            self.storage
-                .search_item(Some(&tc_path), OUTPUT_FILE_NAME, Some(IO_EXTENSION))?;
-        let output_bytes = match output_path {
-            Some(op) => Some(self.storage.read_file(&op)?),
-            None => None,
-        };
-
-        Ok(Response::new(GetTestcaseResponse {
-            testcase: Testcase {
-                id: testcase_id,
-                input: input_bytes,
-                output: output_bytes,
-            },
-        }))
+        self.read_testcase(problem_id, testcase_id)
+            .map(|testcase| Response::new(GetTestcaseResponse { testcase }))
     }
 
     async fn get_problem_testcases(
         &self,
         request: Request<GetProblemTestcasesRequest>,
     ) -> Result<Response<GetProblemTestcasesResponse>, Status> {
+        auth::require(&request, auth::STAFF)?;
         let request = request.into_inner();
         let problem_id = request.problem_id;
-        let mut testcases: Vec<Testcase> = vec![];
-
-        for entry in self
-            .get_problem_folder_from_id(problem_id)
-            .and_then(|path| {
-                self.storage
-                    .iterate_folder(TESTCASES_FOLDER_NAME, Some(&path))
-            })?
-        {
-            let testcase_path = entry?.path();
-            let testcase_id = testcase_path
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .parse::<u64>()
-                .map_err(internal_error)?;
-
-            // Get input and output files (if present)
-            let input_path = self.storage.search_item(
-                Some(&testcase_path),
-                INPUT_FILE_NAME,
-                Some(IO_EXTENSION),
-            )?;
-            let input_bytes = match input_path {
-                Some(ip) => Some(self.storage.read_file(&ip)?),
-                None => None,
-            };
-            let output_path = self.storage.search_item(
-                Some(&testcase_path),
-                OUTPUT_FILE_NAME,
-                Some(IO_EXTENSION),
-            )?;
-            let output_bytes = match output_path {
-                Some(op) => Some(self.storage.read_file(&op)?),
-                None => None,
-            };
-
-            testcases.push(Testcase {
-                id: testcase_id,
-                input: input_bytes,
-                output: output_bytes,
-            });
-        }
-
+        let problem = self.load_problem_metadata(problem_id)?;
+        let testcases = testcase_ids(&problem)
+            .map(|testcase_id| self.read_testcase(problem_id, testcase_id))
+            .collect::<Result<Vec<Testcase>, Status>>()?;
         Ok(Response::new(GetProblemTestcasesResponse { testcases }))
     }
 
@@ -354,181 +1017,52 @@ impl Evaluation for EvaluationService {
         &self,
         request: Request<SetTestcaseRequest>,
     ) -> Result<Response<SetTestcaseResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
         let request = request.into_inner();
         let problem_id = request.problem_id;
         let subtask_id = request.subtask_id;
 
-        // Get testcase's problem path
-        let problem_path = self.get_problem_folder_from_id(problem_id)?;
-
-        // Get testcases folder
-        let testcases_path = self
-            .storage
-            .search_item(Some(&problem_path), TESTCASES_FOLDER_NAME, None)
-            .map_err(internal_error)
-            .and_then(|op| {
-                op.ok_or_else(|| {
-                    not_found_error(format!(
-                        "Testcases folder not found [problem id: {}]",
-                        problem_id
-                    ))
-                })
-            })?;
+        let _lock = self.lock()?;
+        self.reload_problem(problem_id)?;
+        let mut problem = self.load_problem_metadata(problem_id)?;
+        let mut update_info = self.load_problem_update_file(problem_id)?;
+        let mut transaction = self.begin_problem_transaction(problem_id)?;
 
-        match request.command.unwrap() {
+        let description = match request.command.unwrap() {
             set_testcase_request::Command::AddTestcase(tc) => {
-                // Check and add testcase to problem metadata
-                let mut problem_metadata: Problem = self
-                    .storage
-                    .search_item(
-                        Some(&problem_path),
-                        PROBLEM_METADATA_FILE_NAME,
-                        Some(SERIALIZED_EXTENSION),
-                    )?
-                    .ok_or_else(|| {
-                        not_found_error(format!(
-                            "Testcases folder not found [problem id: {}]",
-                            problem_id
-                        ))
-                    })
-                    .and_then(|path| {
-                        self.storage.read_file_object(&path).map_err(internal_error)
-                    })?;
-
-                if problem_metadata
-                    .subtasks
-                    .iter()
-                    .flat_map(|subtask| subtask.testcases_id.iter())
-                    .any(|&tid| tid == tc.id)
-                {
-                    return Err(Status::already_exists(format!(
-                        "Testcase already exists [problem id: {}, id: {}]",
-                        problem_id, tc.id
-                    )));
-                }
-
-                let subtask = problem_metadata
-                    .subtasks
-                    .iter_mut()
-                    .find(|subtask| subtask.id == subtask_id)
-                    .ok_or_else(|| {
-                        not_found_error(format!("Subtask not found [id: {}]", subtask_id))
-                    })?;
-                subtask.testcases_id.push(tc.id);
-
-                self.storage
-                    .save_file_object(
-                        Some(&problem_path),
-                        PROBLEM_METADATA_FILE_NAME,
-                        SERIALIZED_EXTENSION,
-                        problem_metadata,
-                    )
-                    .map_err(internal_error)?;
+                add_testcase(&mut problem, subtask_id, tc.id)?;
+                sync_update_info(&problem, &mut update_info);
 
                 // Save testcase files into storage (only if present)
-                let tc_path = self
-                    .storage
-                    .add_folder(&tc.id.to_string(), Some(&testcases_path))?;
-
-                let mut update_info = self.load_problem_update_file(problem_id)?;
-                if !update_info
-                    .subtasks
-                    .iter_mut()
-                    .any(|x| x.subtask_id == subtask_id)
-                {
-                    // dirty fix, TODO: fix all these shenanigans
-                    update_info.subtasks.push(SubtaskUpdateInfo {
-                        subtask_id,
-                        testcases: vec![],
-                    });
-                }
-                update_info
-                    .subtasks
-                    .iter_mut()
-                    .find(|x| x.subtask_id == subtask_id)
-                    .ok_or_else(|| internal_error("subtask not found in update info"))?
-                    .testcases
-                    .push(TestcaseUpdateInfo {
-                        testcase_id: tc.id,
-                        input_last_update: SystemTime::now().into(),
-                        output_last_update: SystemTime::now().into(),
-                    });
-                self.save_problem_update_file(update_info)?;
-
-                if tc.input.is_some() {
-                    self.storage.save_file(
-                        Some(&tc_path),
-                        INPUT_FILE_NAME,
-                        IO_EXTENSION,
-                        tc.input(),
+                if let Some(input) = &tc.input {
+                    transaction.write(
+                        testcase_file_name(tc.id, testcase_chunk::Part::Input),
+                        input,
                     )?;
                 }
-                if tc.output.is_some() {
-                    self.storage.save_file(
-                        Some(&tc_path),
-                        OUTPUT_FILE_NAME,
-                        IO_EXTENSION,
-                        tc.output(),
+                if let Some(output) = &tc.output {
+                    transaction.write(
+                        testcase_file_name(tc.id, testcase_chunk::Part::Output),
+                        output,
                     )?;
                 }
+                format!("Add testcase {}", tc.id)
             }
             set_testcase_request::Command::UpdateTestcase(tc) => {
-                let tc_path =
-                    self.storage
-                        .search_item(Some(&testcases_path), &tc.id.to_string(), None)?;
-                if let Some(tc_path) = tc_path {
-                    self.storage.save_file(
-                        Some(&tc_path),
-                        INPUT_FILE_NAME,
-                        IO_EXTENSION,
-                        tc.input(),
-                    )?;
-                    self.storage.save_file(
-                        Some(&tc_path),
-                        OUTPUT_FILE_NAME,
-                        IO_EXTENSION,
-                        tc.output(),
-                    )?;
-                } else {
-                    return Err(not_found_error(format!(
-                        "Testcase not found [id: {}]",
-                        tc.id
-                    )));
-                }
-                let mut update_info = self.load_problem_update_file(problem_id)?;
-                let tcu = update_info
-                    .subtasks
-                    .iter_mut()
-                    .find(|x| x.subtask_id == subtask_id)
-                    .ok_or_else(|| internal_error("subtask not found in update info"))?
-                    .testcases
-                    .iter_mut()
-                    .find(|x| x.testcase_id == tc.id)
-                    .ok_or_else(|| internal_error("testcase not found in update info"))?;
-                tcu.input_last_update = SystemTime::now().into();
-                tcu.output_last_update = SystemTime::now().into();
-                self.save_problem_update_file(update_info)?;
+                check_testcase(&problem, tc.id)?;
+                transaction.write(
+                    testcase_file_name(tc.id, testcase_chunk::Part::Input),
+                    tc.input(),
+                )?;
+                transaction.write(
+                    testcase_file_name(tc.id, testcase_chunk::Part::Output),
+                    tc.output(),
+                )?;
+                touch_testcase(&mut update_info, tc.id)?;
+                format!("Update testcase {}", tc.id)
             }
             set_testcase_request::Command::DeleteTestcaseId(tc_id) => {
-                // Delete from problem metadata
-                let mut problem_metadata: Problem = self
-                    .storage
-                    .search_item(
-                        Some(&problem_path),
-                        PROBLEM_METADATA_FILE_NAME,
-                        Some(SERIALIZED_EXTENSION),
-                    )?
-                    .ok_or_else(|| {
-                        not_found_error(format!(
-                            "Testcases folder not found [problem id: {}]",
-                            problem_id
-                        ))
-                    })
-                    .and_then(|path| {
-                        self.storage.read_file_object(&path).map_err(internal_error)
-                    })?;
-
-                let subtask = problem_metadata
+                let subtask = problem
                     .subtasks
                     .iter_mut()
                     .find(|subtask| subtask.id == subtask_id)
@@ -543,57 +1077,196 @@ impl Evaluation for EvaluationService {
                         not_found_error(format!("Testcase not found [id: {}]", tc_id))
                     })?;
                 subtask.testcases_id.remove(index);
+                subtask.testcase_limits.retain(|l| l.testcase_id != tc_id);
+                problem
+                    .testcases_info
+                    .retain(|info| info.testcase_id != tc_id);
+                sync_update_info(&problem, &mut update_info);
+                transaction.delete(testcase_folder_name(tc_id));
+                format!("Delete testcase {}", tc_id)
+            }
+        };
+        self.commit_problem(transaction, &problem, update_info, &description)?;
+        Ok(Response::new(SetTestcaseResponse {}))
+    }
 
-                self.storage
-                    .save_file_object(
-                        Some(&problem_path),
-                        PROBLEM_METADATA_FILE_NAME,
-                        SERIALIZED_EXTENSION,
-                        problem_metadata,
-                    )
-                    .map_err(internal_error)?;
+    type get_testcase_streamStream = TestcaseChunkStream;
+    async fn get_testcase_stream(
+        &self,
+        request: Request<GetTestcaseRequest>,
+    ) -> Result<Response<Self::get_testcase_streamStream>, Status> {
+        auth::require(&request, auth::STAFF)?;
+        let request = request.into_inner();
+        let files = self.get_testcase_files(request.problem_id, request.testcase_id)?;
+        Ok(Response::new(stream_testcase_files(
+            self.storage.clone(),
+            files,
+        )))
+    }
 
-                // Delete from storage
-                let tc_path =
-                    self.storage
-                        .search_item(Some(&testcases_path), &tc_id.to_string(), None)?;
-                if let Some(tc_path) = tc_path {
-                    self.storage.delete_item(&tc_path)?;
-                } else {
-                    return Err(not_found_error(format!(
-                        "Testcase not found [id: {}]",
-                        tc_id
-                    )));
-                }
+    type get_problem_testcases_streamStream = TestcaseChunkStream;
+    async fn get_problem_testcases_stream(
+        &self,
+        request: Request<GetProblemTestcasesRequest>,
+    ) -> Result<Response<Self::get_problem_testcases_streamStream>, Status> {
+        auth::require(&request, auth::STAFF)?;
+        let request = request.into_inner();
+        let problem = self.load_problem_metadata(request.problem_id)?;
+        let mut files = vec![];
+        for testcase_id in testcase_ids(&problem) {
+            files.append(&mut self.get_testcase_files(request.problem_id, testcase_id)?);
+        }
+        Ok(Response::new(stream_testcase_files(
+            self.storage.clone(),
+            files,
+        )))
+    }
 
-                let mut update_info = self.load_problem_update_file(problem_id)?;
-                let subtask = update_info
-                    .subtasks
-                    .iter_mut()
-                    .find(|x| x.subtask_id == subtask_id)
-                    .ok_or_else(|| internal_error("subtask not found in update info"))?;
-                let index = subtask
-                    .testcases
-                    .iter()
-                    .position(|t| t.testcase_id == tc_id)
-                    .ok_or_else(|| {
-                        not_found_error(format!("Testcase not found [id: {}]", tc_id))
-                    })?;
-                subtask.testcases.remove(index);
-                self.save_problem_update_file(update_info)?;
+    async fn set_testcase_stream(
+        &self,
+        request: Request<Streaming<SetTestcaseStreamRequest>>,
+    ) -> Result<Response<SetTestcaseResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
+        let mut stream = request.into_inner();
+        let header = match stream.message().await?.and_then(|m| m.message) {
+            Some(set_testcase_stream_request::Message::Header(header)) => header,
+            _ => return Err(invalid_argument_error("Expected the request header")),
+        };
+        let command = set_testcase_stream_request::header::Command::from_i32(header.command)
+            .ok_or_else(|| invalid_argument_error("Invalid command"))?;
+
+        // Files are staged in a transaction, so that an interrupted
+        // upload never leaves a partially written testcase behind
+        self.get_problem_folder_from_id(header.problem_id)?;
+        let mut transaction = self.begin_problem_transaction(header.problem_id)?;
+        self.receive_testcase_stream(&mut stream, header.testcase_id, &mut transaction)
+            .await?;
+
+        let _lock = self.lock()?;
+        self.reload_problem(header.problem_id)?;
+        let mut problem = self.load_problem_metadata(header.problem_id)?;
+        let mut update_info = self.load_problem_update_file(header.problem_id)?;
+        let description = match command {
+            set_testcase_stream_request::header::Command::Add => {
+                add_testcase(&mut problem, header.subtask_id, header.testcase_id)?;
+                sync_update_info(&problem, &mut update_info);
+                format!("Add testcase {}", header.testcase_id)
+            }
+            set_testcase_stream_request::header::Command::Update => {
+                check_testcase(&problem, header.testcase_id)?;
+                touch_testcase(&mut update_info, header.testcase_id)?;
+                format!("Update testcase {}", header.testcase_id)
             }
         };
+        self.commit_problem(transaction, &problem, update_info, &description)?;
         Ok(Response::new(SetTestcaseResponse {}))
     }
 
+    async fn import_problem(
+        &self,
+        request: Request<Streaming<ImportProblemRequest>>,
+    ) -> Result<Response<ImportProblemResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
+        let contest_id = scope::contest_id(&request)?;
+        let mut stream = request.into_inner();
+        let header = match stream.message().await?.and_then(|m| m.message) {
+            Some(import_problem_request::Message::Header(header)) => header,
+            _ => return Err(invalid_argument_error("Expected the request header")),
+        };
+        let mut archive = ChunkWriter::new(Vec::new());
+        while let Some(message) = stream.message().await? {
+            match message.message {
+                Some(import_problem_request::Message::Chunk(chunk)) => archive
+                    .write_chunk(&chunk)
+                    .map_err(invalid_argument_error)?,
+                _ => return Err(invalid_argument_error("Expected an archive chunk")),
+            }
+        }
+        let archive = archive.finish().map_err(invalid_argument_error)?;
+        let mut package = read_problem_package(io::Cursor::new(archive), header.format())
+            .map_err(invalid_argument_error)?;
+
+        let problem_id = header.problem_id.unwrap_or_else(utils::gen_uuid);
+        let contest_req = contest::SetProblemRequest {
+            info: contest::Problem {
+                id: problem_id,
+                name: package.name.clone(),
+                long_name: package.long_name.clone(),
+                tokens: None,
+            },
+            statement: std::mem::take(&mut package.statement),
+        };
+        {
+            let _lock = self.lock()?;
+            self.check_problems_owner(&contest_id, &[problem_id])?;
+        }
+        let info = self.save_package(problem_id, package)?;
+        // the problem is added to the contest only once it is complete, the data saved
+        // so far is replaced when importing again with the same id
+        if let Err(err) = self
+            .contest_client
+            .clone()
+            .set_problem(scope::in_contest(Request::new(contest_req), &contest_id))
+            .await
+        {
+            return Err(Status::new(
+                err.code(),
+                format!(
+                    "Partial import, the problem data was saved but not its statement \
+                     [id: {}], import it again with this id: {}",
+                    problem_id,
+                    err.message()
+                ),
+            ));
+        }
+        let _lock = self.lock()?;
+        self.add_contest_problems(&contest_id, &[problem_id])?;
+        Ok(Response::new(ImportProblemResponse { info }))
+    }
+
+    type export_problemStream = tokio_stream::Iter<std::vec::IntoIter<Result<FileChunk, Status>>>;
+    async fn export_problem(
+        &self,
+        request: Request<ExportProblemRequest>,
+    ) -> Result<Response<Self::export_problemStream>, Status> {
+        auth::require(&request, auth::STAFF)?;
+        let contest_id = scope::contest_id(&request)?;
+        let request = request.into_inner();
+        let problem_id = request.problem_id;
+        let mut package = self.load_package(problem_id, request.version)?;
+
+        let mut contest_client = self.contest_client.clone();
+        let in_contest = |message| scope::in_contest(Request::new(message), &contest_id);
+        let info = contest_client
+            .get_problem_info(in_contest(contest::GetProblemRequest { problem_id }))
+            .await?
+            .into_inner()
+            .info;
+        package.name = info.name;
+        package.long_name = info.long_name;
+        package.statement = contest_client
+            .get_problem_statement(in_contest(contest::GetProblemRequest { problem_id }))
+            .await?
+            .into_inner()
+            .statement;
+
+        let archive = write_problem_package(&package, io::Cursor::new(Vec::new()))
+            .map_err(internal_error)?
+            .into_inner();
+        let chunks: Vec<_> = ChunkReader::new(archive.as_slice())
+            .map(|chunk| chunk.map_err(internal_error))
+            .collect();
+        Ok(Response::new(tokio_stream::iter(chunks)))
+    }
+
     async fn get_problem_evaluation_file(
         &self,
         request: Request<GetProblemEvaluationFileRequest>,
     ) -> Result<Response<GetProblemEvaluationFileResponse>, Status> {
+        auth::require(&request, auth::STAFF)?;
         let request = request.into_inner();
         let problem_id = request.problem_id;
 
-        let problem_path = self.get_problem_folder_from_id(problem_id)?;
         let file_type = evaluation_file::Type::from_i32(request.r#type).ok_or_else(|| {
             internal_error(format!(
                 "Cannot convert {} to evaluation file type",
@@ -601,18 +1274,15 @@ impl Evaluation for EvaluationService {
             ))
         })?;
 
-        let eval_path = self
-            .storage
-            .search_item(Some(&problem_path), EVALUATION_FILES_FOLDER_NAME, None)?
-            .ok_or_else(|| not_found_io_error("Evaluation files folder not found"))?;
+        let path = self
+            .index()
+            .problem(problem_id)
+            .ok_or_else(|| not_found_error(format!("Problem not found [id: {}]", problem_id)))?
+            .file(&evaluation_file_name(file_type))
+            .ok_or_else(|| not_found_error("Evaluation file not found"))?;
         self.storage
-            .search_item(
-                Some(&eval_path),
-                &file_type.to_string().to_lowercase(),
-                Some(SERIALIZED_EXTENSION),
-            )?
-            .ok_or_else(|| not_found_error("Evaluation file not found"))
-            .and_then(|path| self.storage.read_file_object(&path).map_err(internal_error))
+            .read_file_object(&path)
+            .map_err(internal_error)
             .map(|file| Response::new(GetProblemEvaluationFileResponse { file }))
     }
 
@@ -620,109 +1290,217 @@ impl Evaluation for EvaluationService {
         &self,
         request: Request<SetProblemEvaluationFileRequest>,
     ) -> Result<Response<SetProblemEvaluationFileResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
         let request = request.into_inner();
         let problem_id = request.problem_id;
 
-        let problem_path = self.get_problem_folder_from_id(problem_id)?;
-
         match request.command.unwrap() {
-            set_problem_evaluation_file_request::Command::AddEvaluationFile(ef) => {
-                let file_type = evaluation_file::Type::from_i32(ef.r#type).ok_or_else(|| {
-                    internal_error(format!(
-                        "Cannot convert {} to evaluation file type",
-                        ef.r#type
-                    ))
-                })?;
-                let eval_path = self
-                    .storage
-                    .add_folder(EVALUATION_FILES_FOLDER_NAME, Some(&problem_path))?;
-                self.storage
-                    .save_file_object(
-                        Some(&eval_path),
-                        &file_type.to_string().to_lowercase(),
-                        SERIALIZED_EXTENSION,
-                        ef,
-                    )
-                    .map_err(internal_error)?;
-                let mut update_info = self.load_problem_update_file(problem_id)?;
-                match file_type {
-                    evaluation_file::Type::Checker => {
-                        update_info.checker_last_update = SystemTime::now().into();
-                    }
-                    evaluation_file::Type::Interactor => {
-                        update_info.interactor_last_update = SystemTime::now().into();
-                    }
-                }
-                self.save_problem_update_file(update_info)?;
-            }
-            set_problem_evaluation_file_request::Command::UpdateEvaluationFile(ef) => {
-                let file_type = evaluation_file::Type::from_i32(ef.r#type).ok_or_else(|| {
-                    internal_error(format!(
-                        "Cannot convert {} to evaluation file type",
-                        ef.r#type
-                    ))
-                })?;
-                let eval_path = self
-                    .storage
-                    .search_item(Some(&problem_path), EVALUATION_FILES_FOLDER_NAME, None)?
-                    .ok_or_else(|| not_found_io_error("Evaluation files folder not found"))?;
-                self.storage
-                    .save_file_object(
-                        Some(&eval_path),
-                        &file_type.to_string().to_lowercase(),
-                        SERIALIZED_EXTENSION,
-                        ef,
-                    )
-                    .map_err(internal_error)?;
-                let mut update_info = self.load_problem_update_file(problem_id)?;
-                match file_type {
-                    evaluation_file::Type::Checker => {
-                        update_info.checker_last_update = SystemTime::now().into();
-                    }
-                    evaluation_file::Type::Interactor => {
-                        update_info.interactor_last_update = SystemTime::now().into();
-                    }
-                }
-                self.save_problem_update_file(update_info)?;
+            set_problem_evaluation_file_request::Command::AddEvaluationFile(ef)
+            | set_problem_evaluation_file_request::Command::UpdateEvaluationFile(ef) => {
+                self.save_evaluation_file(problem_id, ef)?;
             }
         }
         Ok(Response::new(SetProblemEvaluationFileResponse {}))
     }
     async fn get_update_info(
         &self,
-        _request: Request<GetUpdateInfoRequest>,
+        request: Request<GetUpdateInfoRequest>,
     ) -> Result<Response<GetUpdateInfoResponse>, Status> {
-        let mut problems: Vec<ProblemUpdateInfo> = vec![];
-        for entry in self.storage.iterate_folder(PROBLEMS_FOLDER_NAME, None)? {
-            let problem_path = self
-                .storage
-                .search_item(
-                    Some(&entry?.path()),
-                    PROBLEM_UPDATE_FILE_NAME,
-                    Some(SERIALIZED_EXTENSION),
-                )?
-                .ok_or_else(|| not_found_error("Problem update metadata not found"))?;
-            let p: ProblemUpdateInfo = self
-                .storage
-                .read_file_object(&problem_path)
-                .map_err(|err| internal_error(err.as_ref()))?;
-            problems.push(p);
-        }
+        auth::require(&request, auth::STAFF)?;
+        let problems: Vec<ProblemUpdateInfo> = self
+            .index()
+            .problems()
+            .into_iter()
+            .map(|entry| entry.update_info.clone())
+            .collect();
         Ok(Response::new(GetUpdateInfoResponse { problems }))
     }
+
+    async fn get_problem_versions(
+        &self,
+        request: Request<GetProblemVersionsRequest>,
+    ) -> Result<Response<GetProblemVersionsResponse>, Status> {
+        auth::require(&request, auth::STAFF)?;
+        let problem_id = request.into_inner().problem_id;
+        let problem_path = self.get_problem_folder_from_id(problem_id)?;
+        let versions = self
+            .problem_history(&problem_path)
+            .versions()
+            .map_err(internal_error)?
+            .into_iter()
+            .map(|version| ProblemVersion {
+                version: version.version,
+                created: version.created.into(),
+                description: version.description,
+            })
+            .collect();
+        Ok(Response::new(GetProblemVersionsResponse { versions }))
+    }
+
+    async fn diff_problem_versions(
+        &self,
+        request: Request<DiffProblemVersionsRequest>,
+    ) -> Result<Response<DiffProblemVersionsResponse>, Status> {
+        auth::require(&request, auth::STAFF)?;
+        let request = request.into_inner();
+        let problem_path = self.get_problem_folder_from_id(request.problem_id)?;
+        let history = self.problem_history(&problem_path);
+        let get_version = |version| {
+            history
+                .get(version)
+                .map_err(internal_error)?
+                .ok_or_else(|| {
+                    not_found_error(format!(
+                        "Version not found [problem id: {}, version: {}]",
+                        request.problem_id, version
+                    ))
+                })
+        };
+        let from = get_version(request.from_version)?;
+        let to = get_version(request.to_version)?;
+        let changes = history::diff(&from, &to)
+            .into_iter()
+            .map(
+                |(path, change)| diff_problem_versions_response::FileChange {
+                    path: path.to_string_lossy().into_owned(),
+                    change: match change {
+                        history::Change::Added => {
+                            diff_problem_versions_response::file_change::Change::Added
+                        }
+                        history::Change::Removed => {
+                            diff_problem_versions_response::file_change::Change::Removed
+                        }
+                        history::Change::Modified => {
+                            diff_problem_versions_response::file_change::Change::Modified
+                        }
+                    } as i32,
+                },
+            )
+            .collect();
+        Ok(Response::new(DiffProblemVersionsResponse { changes }))
+    }
+
+    async fn rollback_problem(
+        &self,
+        request: Request<RollbackProblemRequest>,
+    ) -> Result<Response<RollbackProblemResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
+        let request = request.into_inner();
+        let problem_id = request.problem_id;
+
+        let _lock = self.lock()?;
+        self.reload_problem(problem_id)?;
+        let problem_path = self.get_problem_folder_from_id(problem_id)?;
+        let history = self.problem_history(&problem_path);
+        let target = history
+            .get(request.version)
+            .map_err(internal_error)?
+            .ok_or_else(|| {
+                not_found_error(format!(
+                    "Version not found [problem id: {}, version: {}]",
+                    problem_id, request.version
+                ))
+            })?;
+        let problem: Problem = history
+            .file_path(&target, &serialized_file_name(PROBLEM_METADATA_FILE_NAME))
+            .ok_or_else(|| internal_error("Problem metadata not found in version"))
+            .and_then(|path| {
+                self.storage
+                    .read_file_object(&path)
+                    .map_err(|err| internal_error(err.as_ref()))
+            })?;
+
+        let current = self.load_problem_metadata(problem_id)?;
+        let mut transaction = self.begin_problem_transaction(problem_id)?;
+        for testcase_id in testcase_ids(&current) {
+            if testcase_ids(&problem).all(|id| id != testcase_id) {
+                transaction.delete(testcase_folder_name(testcase_id));
+            }
+        }
+        history
+            .restore(&mut transaction, &target)
+            .map_err(internal_error)?;
+
+        // every file could have changed, workers have to pull them all again
+        let mut update_info = self.load_problem_update_file(problem_id)?;
+        sync_update_info(&problem, &mut update_info);
+        for testcase_id in testcase_ids(&problem) {
+            touch_testcase(&mut update_info, testcase_id)?;
+        }
+        update_info.checker_last_update = SystemTime::now().into();
+        update_info.interactor_last_update = SystemTime::now().into();
+        let version = self.commit_problem(
+            transaction,
+            &problem,
+            update_info,
+            &format!("Rollback to version {}", request.version),
+        )?;
+        Ok(Response::new(RollbackProblemResponse {
+            version: version.unwrap_or(request.version),
+        }))
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn required_env(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    std::env::var(name).map_err(|_| format!("Missing environment variable {}", name).into())
+}
+
+async fn serve<S: Storage + 'static>(storage: S) -> Result<(), Box<dyn std::error::Error>> {
     let addr = get_local_address(Service::EVALUATION).parse()?;
     let evaluation_service = EvaluationService {
-        storage: FsStorageHelper::new(std::path::Path::new(ROOT_PATH))?,
+        storage: Arc::new(storage),
+        contest_client: ContestClient::new(get_new_channel(Service::CONTEST)),
+        submission_client: SubmissionClient::new(get_new_channel(Service::SUBMISSION)),
+        index: Arc::new(RwLock::new(Index::default())),
+        write_lock: Arc::new(Mutex::new(())),
     };
+    evaluation_service.repair()?;
+    let refresh_seconds: u64 = env_or(INDEX_REFRESH_ENV, "0").parse()?;
+    let index_refresh =
+        evaluation_service.index_refresh(std::time::Duration::from_secs(refresh_seconds));
 
     println!("Starting evaluation server");
-    Server::builder()
-        .add_service(EvaluationServer::new(evaluation_service))
-        .serve(addr)
-        .await?;
+    let server = Server::builder()
+        .add_service(EvaluationServer::with_interceptor(
+            evaluation_service,
+            auth::RoleCheck::from_env()?,
+        ))
+        .serve(addr);
+    if refresh_seconds == 0 {
+        server.await?;
+        return Ok(());
+    }
+    // a failed reload stops the service, as it would keep serving an outdated index
+    tokio::select! {
+        served = server => served?,
+        err = index_refresh => return Err(format!("Failed to reload the index: {}", err).into()),
+    }
     Ok(())
 }
+
+// The storage backend is chosen through environment variables, see the README
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    match env_or(STORAGE_ENV, "fs").as_str() {
+        "fs" => {
+            let root = env_or(FILES_PATH_ENV, ROOT_PATH);
+            serve(FsStorageHelper::new(Path::new(&root))?).await
+        }
+        "s3" => {
+            let config = S3Config {
+                endpoint: required_env("S3_ENDPOINT")?,
+                region: env_or("S3_REGION", "us-east-1"),
+                bucket: required_env("S3_BUCKET")?,
+                access_key: required_env("S3_ACCESS_KEY")?,
+                secret_key: required_env("S3_SECRET_KEY")?,
+                prefix: env_or("S3_PREFIX", ""),
+            };
+            serve(S3Storage::new(config)?).await
+        }
+        backend => Err(format!("Unknown storage backend: {}", backend).into()),
+    }
+}
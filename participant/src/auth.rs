@@ -11,16 +11,120 @@ use rocket::serde::Serialize;
 use rocket::*;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
+use utils::window::{self, Window};
+
+// the cookie keeps the token of the session, validated by the contest service on each request.
+// Members of a team act as the team, which owns their submissions and questions
+pub struct User {
+    pub id: String, // of the team, or the username outside of teams
+    pub username: String,
+    pub team: Option<Team>,
+    pub contest: String, // the one chosen by the user, among the enrolled ones
+    pub contests: Vec<String>, // the user is enrolled in
+    token: String,       // of the session, the services act only for its user
+}
+impl User {
+    fn new(session: &contest::ValidateSessionResponse, contest: String, token: String) -> Self {
+        Self {
+            id: session
+                .team
+                .as_ref()
+                .map_or_else(|| session.username.clone(), |team| team.id.clone()),
+            username: session.username.clone(),
+            team: session.team.as_ref().map(Team::from),
+            contest,
+            contests: session.contests.clone(),
+            token,
+        }
+    }
+    /// The rpc request, referring to the contest and the session of the user
+    pub fn request<T>(&self, message: T) -> tonic::Request<T> {
+        protos::auth::with_session(
+            protos::scope::in_contest(tonic::Request::new(message), &self.contest),
+            &self.token,
+        )
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Team {
+    pub name: String,
+    pub affiliation: Option<String>,
+    pub country: Option<String>,
+    pub members: Vec<String>,
+}
+impl From<&contest::Team> for Team {
+    fn from(t: &contest::Team) -> Self {
+        Self {
+            name: t.name.clone(),
+            affiliation: t.affiliation.clone(),
+            country: t.country.clone(),
+            members: t.members.clone(),
+        }
+    }
+}
+/// The contest chosen with the "contest" cookie, if enrolled in it, otherwise the default
+/// contest or the first one the user is enrolled in
+fn choose_contest(enrolled: &[String], chosen: Option<&str>) -> Option<String> {
+    chosen
+        .into_iter()
+        .chain(std::iter::once(protos::scope::DEFAULT_CONTEST))
+        .find(|contest| enrolled.iter().any(|enrolled| enrolled == contest))
+        .or_else(|| enrolled.first().map(String::as_str))
+        .map(str::to_owned)
+}
+
+// the team of the user, and so the user id, depends on the contest of the session
+async fn validate_session(
+    contest_client: &mut ContestClient,
+    token: &str,
+    contest: &str,
+) -> Option<contest::ValidateSessionResponse> {
+    contest_client
+        .validate_session(protos::scope::in_contest(
+            tonic::Request::new(contest::ValidateSessionRequest {
+                token: token.to_string(),
+            }),
+            contest,
+        ))
+        .await
+        .ok()
+        .map(|response| response.into_inner())
+        .filter(|session| !session.admin)
+}
 
-pub struct User(pub String);
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for User {
     type Error = ();
     async fn from_request(request: &'r Request<'_>) -> request::Outcome<User, ()> {
-        request
-            .cookies()
-            .get_private("user")
-            .map(|cookie| User(cookie.value().into()))
+        let session = request
+            .local_cache_async(async {
+                let token = request.cookies().get_private("user")?.value().to_string();
+                let mut contest_client = request
+                    .guard::<&State<ContestClient>>()
+                    .await
+                    .succeeded()?
+                    .inner()
+                    .clone();
+                let chosen = request
+                    .cookies()
+                    .get_private("contest")
+                    .map(|cookie| cookie.value().to_string());
+                let requested = chosen
+                    .clone()
+                    .unwrap_or_else(|| protos::scope::DEFAULT_CONTEST.to_string());
+                let mut session = validate_session(&mut contest_client, &token, &requested).await?;
+                let contest = choose_contest(&session.contests, chosen.as_deref())?;
+                if contest != requested {
+                    session = validate_session(&mut contest_client, &token, &contest).await?;
+                }
+                Some((session, contest, token))
+            })
+            .await;
+        session
+            .as_ref()
+            .map(|(session, contest, token)| User::new(session, contest.clone(), token.clone()))
             .or_forward(())
     }
 }
@@ -67,6 +171,7 @@ pub async fn login(
         .auth_user(tonic::Request::new(contest::AuthUserRequest {
             username: login.name.clone(),
             password: login.pass.clone(),
+            admin: None,
         }))
         .await
     {
@@ -75,8 +180,8 @@ pub async fn login(
             .response
             .ok_or(status::Custom(Status::InternalServerError, ()))?
         {
-            contest::auth_user_response::Response::Success(_) => {
-                cookies.add_private(Cookie::new("user", login.name.clone()));
+            contest::auth_user_response::Response::Success(success) => {
+                cookies.add_private(Cookie::new("user", success.token));
                 Ok(Redirect::to(uri!(root_logged)))
             }
             _ => Ok(Redirect::to(uri!(root))),
@@ -85,10 +190,23 @@ pub async fn login(
     }
 }
 
+// ends every session of the user, on any device
 #[get("/api/logout")]
-pub async fn logout(cookies: &CookieJar<'_>) -> Redirect {
+pub async fn logout(
+    cookies: &CookieJar<'_>,
+    contest_client: &State<ContestClient>,
+) -> Result<Redirect, status::Custom<()>> {
+    if let Some(cookie) = cookies.get_private("user") {
+        let mut contest_client = contest_client.inner().clone();
+        contest_client
+            .logout(tonic::Request::new(contest::LogoutRequest {
+                token: cookie.value().to_string(),
+            }))
+            .await
+            .map_err(|_| status::Custom(Status::InternalServerError, ()))?;
+    }
     cookies.remove_private(Cookie::named("user"));
-    Redirect::to(uri!(root))
+    Ok(Redirect::to(uri!(root)))
 }
 
 // contest metadata request utilities
@@ -103,6 +221,7 @@ impl<'r> FromRequest<'r> for &'r ContestMetadataWrapper {
         // the number of times the guard is executed.
         let result = request
             .local_cache_async(async {
+                let user = request.guard::<User>().await.succeeded()?;
                 let mut contest_client = request
                     .guard::<&State<ContestClient>>()
                     .await
@@ -110,9 +229,9 @@ impl<'r> FromRequest<'r> for &'r ContestMetadataWrapper {
                     .inner()
                     .clone();
                 let metadata = contest_client
-                    .get_contest_metadata(tonic::Request::new(
-                        contest::GetContestMetadataRequest::default(),
-                    ))
+                    .get_contest_metadata(
+                        user.request(contest::GetContestMetadataRequest::default()),
+                    )
                     .await
                     .ok()?
                     .into_inner();
@@ -138,6 +257,50 @@ impl From<&contest::Problem> for Problem {
     }
 }
 
+// time window of the user, after the start delay and with the extra time of the user
+struct UserWindowWrapper {
+    window: Window,
+    can_start: bool,
+}
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r UserWindowWrapper {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let result = request
+            .local_cache_async(async {
+                let metadata = &request
+                    .guard::<&ContestMetadataWrapper>()
+                    .await
+                    .succeeded()?
+                    .0
+                    .metadata;
+                let user = request.guard::<User>().await.succeeded()?;
+                let mut contest_client = request
+                    .guard::<&State<ContestClient>>()
+                    .await
+                    .succeeded()?
+                    .inner()
+                    .clone();
+                let settings = contest_client
+                    .get_user_windows(user.request(contest::GetUserWindowsRequest {
+                        user: Some(user.id.clone()),
+                    }))
+                    .await
+                    .ok()?
+                    .into_inner()
+                    .windows
+                    .pop();
+                Some(UserWindowWrapper {
+                    window: window::user_window(metadata, settings.as_ref()),
+                    can_start: window::can_start(metadata, settings.as_ref(), SystemTime::now()),
+                })
+            })
+            .await;
+        result.as_ref().or_forward(())
+    }
+}
+
 #[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct RunningContest {
@@ -147,18 +310,10 @@ pub struct RunningContest {
 impl<'r> FromRequest<'r> for RunningContest {
     type Error = std::convert::Infallible;
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let now = SystemTime::now();
         let metadata = &try_outcome!(request.guard::<&ContestMetadataWrapper>().await).0;
-        let is_running = match (
-            metadata.metadata.start_time.as_ref(),
-            metadata.metadata.end_time.as_ref(),
-        ) {
-            (Some(start_time), Some(end_time)) => {
-                now >= SystemTime::from(start_time.clone())
-                    && now < SystemTime::from(end_time.clone())
-            }
-            _ => false,
-        };
+        let window = try_outcome!(request.guard::<&UserWindowWrapper>().await).window;
+        let is_running =
+            window.start.is_some() && window.end.is_some() && window.is_running(SystemTime::now());
         if is_running {
             Outcome::Success(RunningContest {
                 problems: metadata.problems.iter().map(Problem::from).collect(),
@@ -169,45 +324,72 @@ impl<'r> FromRequest<'r> for RunningContest {
     }
 }
 
+fn to_millis(time: Option<SystemTime>) -> Option<String> {
+    time.and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|t| t.as_millis().to_string())
+}
+
 #[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct ContestData {
+    pub id: String,
+    pub contests: Vec<String>, // the user can switch to, when enrolled in more than one
     pub name: String,
-    pub start_time: Option<String>, // millis from unix epoch
+    pub start_time: Option<String>, // millis from unix epoch, of the window of the user
     pub end_time: Option<String>,
-}
-impl From<&contest::ContestMetadata> for ContestData {
-    fn from(c: &contest::ContestMetadata) -> Self {
-        Self {
-            name: c.name.clone(),
-            start_time: c
-                .start_time
-                .clone()
-                .map(|t| {
-                    SystemTime::from(t)
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .ok()
-                })
-                .flatten()
-                .map(|t| t.as_millis().to_string()),
-            end_time: c
-                .end_time
-                .clone()
-                .map(|t| {
-                    SystemTime::from(t)
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .ok()
-                })
-                .flatten()
-                .map(|t| t.as_millis().to_string()),
-        }
-    }
+    pub can_start: bool, // in a contest with a window, before the user starts it
 }
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for ContestData {
     type Error = std::convert::Infallible;
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         let metadata = &try_outcome!(request.guard::<&ContestMetadataWrapper>().await).0;
-        Outcome::Success((&metadata.metadata).into())
+        let user_window = try_outcome!(request.guard::<&UserWindowWrapper>().await);
+        let user = match request.guard::<User>().await.succeeded() {
+            Some(user) => user,
+            None => return Outcome::Forward(()),
+        };
+        Outcome::Success(ContestData {
+            id: user.contest,
+            contests: user.contests,
+            name: metadata.metadata.name.clone(),
+            start_time: to_millis(user_window.window.start),
+            end_time: to_millis(user_window.window.end),
+            can_start: user_window.can_start,
+        })
+    }
+}
+
+// starts the contest with a window for the user
+#[post("/api/start")]
+pub async fn start(
+    user: User,
+    contest_client: &State<ContestClient>,
+) -> Result<Redirect, status::Custom<()>> {
+    let mut contest_client = contest_client.inner().clone();
+    contest_client
+        .start_contest(user.request(contest::StartContestRequest {
+            user: user.id.clone(),
+        }))
+        .await
+        .map_err(|_| status::Custom(Status::PreconditionFailed, ()))?;
+    Ok(Redirect::to(uri!(root_logged)))
+}
+
+#[derive(FromForm)]
+pub struct SelectContest {
+    id: String,
+}
+// the pages and apis refer to the chosen contest, among the ones the user is enrolled in
+#[post("/api/select_contest", data = "<contest>")]
+pub async fn select_contest(
+    user: User,
+    cookies: &CookieJar<'_>,
+    contest: Form<Strict<SelectContest>>,
+) -> Result<Redirect, status::Custom<()>> {
+    if !user.contests.contains(&contest.id) {
+        return Err(status::Custom(Status::Forbidden, ()));
     }
+    cookies.add_private(Cookie::new("contest", contest.id.clone()));
+    Ok(Redirect::to(uri!(root_logged)))
 }
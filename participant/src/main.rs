@@ -30,6 +30,36 @@ mod clients {
         mock.auth_user_set(contest::AuthUserResponse {
             response: Some(contest::auth_user_response::Response::Success(Faker.fake())),
         });
+        let team = contest::Team {
+            id: String::from("team"),
+            name: String::from("Team"),
+            affiliation: Some(String::from("University")),
+            country: Some(String::from("ITA")),
+            members: vec![String::from("hi"), String::from("hello")],
+        };
+        mock.validate_session_set(contest::ValidateSessionResponse {
+            username: String::from("hi"),
+            admin: false,
+            team: Some(team.clone()),
+            contests: vec![
+                String::from(protos::scope::DEFAULT_CONTEST),
+                String::from("final"),
+            ],
+        });
+        mock.get_team_list_set(contest::GetTeamListResponse { teams: vec![team] });
+        mock.logout_set(contest::LogoutResponse {});
+        let user_window = contest::UserWindow {
+            user: String::from("team"),
+            extra_time: Some(Duration::from_secs(600).into()),
+            start_delay: None,
+            started_at: None,
+        };
+        mock.get_user_windows_set(contest::GetUserWindowsResponse {
+            windows: vec![user_window.clone()],
+        });
+        mock.start_contest_set(contest::StartContestResponse {
+            window: user_window,
+        });
 
         let secs_until_start = 10;
         mock.get_contest_metadata_set(contest::GetContestMetadataResponse {
@@ -42,18 +72,52 @@ mod clients {
                 end_time: Some(
                     (SystemTime::now() + Duration::from_secs(3600 + secs_until_start)).into(),
                 ),
+                freeze: Some(Duration::from_secs(600).into()),
+                tokens: Some(contest::TokenRules {
+                    initial: 2,
+                    gen_number: 1,
+                    gen_interval: Some(Duration::from_secs(1800).into()),
+                    max: Some(3),
+                    total: None,
+                    min_interval: None,
+                }),
+                window: None,
             },
             problems: Faker.fake(),
         });
 
+        mock.get_problem_testcases_set(contest::GetProblemTestcasesResponse {
+            testcases: vec![contest::Testcase {
+                id: 0,
+                name: Some(String::from("sample")),
+                sample: true,
+                feedback: protos::service::evaluation::testcase_info::Feedback::Full as i32,
+            }],
+        });
+        mock.get_sample_set(vec![protos::common::FileChunk {
+            data: "1 2\n".as_bytes().to_vec(),
+            checksum: Some(utils::chunks::checksum(b"1 2\n")),
+        }]);
+
         mock
     }
     pub fn get_submission_client() -> SubmissionClient {
         let mut mock = submission::MockSubmission::default();
         mock.get_submission_list_set(Faker.fake());
+        mock.get_problem_score_set(submission::GetProblemScoreResponse {
+            score: Some(protos::common::Score {
+                score: 100.0 / 3.0,
+                ..Default::default()
+            }),
+            method: protos::scoring::problem::Method::Selected as i32,
+        });
+        mock.select_submission_set(submission::SelectSubmissionResponse {});
+        mock.get_icpc_scoreboard_set(Faker.fake());
+        mock.get_tokens_set(submission::GetTokensResponse { available: Some(1) });
+        mock.use_token_set(submission::UseTokenResponse {});
         mock.get_submission_details_set(submission::GetSubmissionDetailsResponse {
             sub: protos::evaluation::Submission {
-                user: String::from("hi"),
+                user: String::from("team"),
                 problem_id: 2,
                 source: protos::common::Source {
                     code: "#define OII\nint main(){\n\treturn 0;\n}\n/*\n<b>a</b>\n*/\n"
@@ -63,6 +127,7 @@ mod clients {
                 },
             },
             state: protos::service::submission::SubmissionState::Evaluated as i32,
+            token: None,
             res: Some(protos::evaluation::EvaluationResult {
                 compilation_result: protos::evaluation::CompilationResult {
                     outcome: protos::evaluation::compilation_result::Outcome::Success as i32,
@@ -84,6 +149,7 @@ mod clients {
                     5
                 ],
                 score: Faker.fake(),
+                problem_version: None,
             }),
         });
         mock
@@ -95,9 +161,9 @@ mod clients {
     use protos::service::contest;
     use protos::service::submission;
     // clients for production
-    pub type ContestClient = contest::contest_client::ContestClient<tonic::transport::Channel>;
+    pub type ContestClient = contest::contest_client::ContestClient<protos::utils::AuthChannel>;
     pub type SubmissionClient =
-        submission::submission_client::SubmissionClient<tonic::transport::Channel>;
+        submission::submission_client::SubmissionClient<protos::utils::AuthChannel>;
     pub fn get_contest_client() -> ContestClient {
         ContestClient::new(protos::utils::get_new_channel(
             protos::utils::Service::CONTEST,
@@ -113,12 +179,31 @@ mod clients {
 mod auth;
 mod problems;
 mod questions;
+mod scoreboard;
+
+const DEFAULT_SCORE_DECIMALS: usize = 2;
+
+// `score_decimals` in Rocket.toml sets the rounding of the displayed scores
+fn score_filter(decimals: usize) -> impl rocket_dyn_templates::tera::Filter {
+    use rocket_dyn_templates::tera::{from_value, Value};
+    move |value: &Value, _: &std::collections::HashMap<String, Value>| {
+        let score: protos::common::Score = from_value(value.clone())?;
+        Ok(Value::String(utils::scoring::format_score(
+            &score, decimals,
+        )))
+    }
+}
 
 #[launch]
 fn rocket() -> _ {
     let contest_client = clients::get_contest_client();
     let submission_client = clients::get_submission_client();
-    rocket::build()
+    let rocket = rocket::build();
+    let score_decimals = rocket
+        .figment()
+        .extract_inner("score_decimals")
+        .unwrap_or(DEFAULT_SCORE_DECIMALS);
+    rocket
         .manage(contest_client)
         .manage(submission_client)
         .mount(
@@ -130,12 +215,22 @@ fn rocket() -> _ {
                 auth::not_logged_redirect,
                 auth::login,
                 auth::logout,
+                auth::start,
+                auth::select_contest,
                 questions::questions,
                 questions::post_question,
                 problems::problems,
                 problems::submit,
+                problems::select,
+                problems::use_token,
+                problems::sample,
                 problems::submission_details_template,
+                scoreboard::scoreboard,
             ],
         )
-        .attach(Template::fairing())
+        .attach(Template::custom(move |engines| {
+            engines
+                .tera
+                .register_filter("score", score_filter(score_decimals))
+        }))
 }
@@ -1,10 +1,11 @@
 use super::auth::*;
 use super::clients::*;
-use protos::service::submission;
+use futures::TryStreamExt;
+use protos::service::{contest, evaluation, submission};
 use rocket::data::Capped;
 use rocket::form::{Form, Strict};
 use rocket::fs::TempFile;
-use rocket::http::Status;
+use rocket::http::{ContentType, Status};
 use rocket::response::{status, Redirect};
 use rocket::serde::Serialize;
 use rocket::*;
@@ -20,13 +21,17 @@ use strum::IntoEnumIterator;
 #[serde(crate = "rocket::serde")]
 pub struct SubmissionTemplate {
     id: u64,
-    score: String,
+    score: Option<protos::common::Score>,
+    selected: bool,
+    token: bool,
 }
 impl From<submission::get_submission_list_response::Item> for SubmissionTemplate {
     fn from(s: submission::get_submission_list_response::Item) -> Self {
         Self {
             id: s.submission_id,
-            score: s.score.unwrap_or_default().score.to_string(),
+            selected: s.selected(),
+            token: s.token(),
+            score: s.score,
         }
     }
 }
@@ -35,11 +40,35 @@ impl From<submission::get_submission_list_response::Item> for SubmissionTemplate
 #[serde(crate = "rocket::serde")]
 pub struct ProblemsTemplate {
     contest: ContestData,
+    team: Option<Team>,
     running_contest: RunningContest,
     problem: Problem,
-    // score: f64,
+    score: Option<protos::common::Score>,
+    selectable: bool,    // the participant chooses the submission that counts
+    tokens: Option<u32>, // available, not set if the contest does not use tokens
     submissions: Vec<SubmissionTemplate>,
     languages: Vec<String>,
+    samples: Vec<SampleTemplate>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SampleTemplate {
+    id: u64,
+    name: String,
+}
+
+async fn get_problem_testcases(
+    user: &User,
+    contest_client: &State<ContestClient>,
+    problem_id: u64,
+) -> Result<Vec<contest::Testcase>, status::Custom<()>> {
+    let mut contest_client = contest_client.inner().clone();
+    contest_client
+        .get_problem_testcases(user.request(contest::GetProblemRequest { problem_id }))
+        .await
+        .map(|response| response.into_inner().testcases)
+        .map_err(|_| status::Custom(Status::InternalServerError, ()))
 }
 
 #[get("/problem/<id>")]
@@ -48,14 +77,44 @@ pub async fn problems(
     id: u64,
     contest: ContestData,
     running_contest: RunningContest,
+    contest_client: &State<ContestClient>,
     submission_client: &State<SubmissionClient>,
 ) -> Result<Template, status::Custom<()>> {
+    let samples = get_problem_testcases(&user, contest_client, id)
+        .await?
+        .into_iter()
+        .filter(|t| t.sample)
+        .enumerate()
+        .map(|(i, t)| SampleTemplate {
+            id: t.id,
+            name: t.name.unwrap_or_else(|| format!("Sample {}", i + 1)),
+        })
+        .collect();
     let mut submission_client = submission_client.inner().clone();
+    let problem_score = submission_client
+        .get_problem_score(user.request(submission::GetProblemScoreRequest {
+            user: user.id.clone(),
+            problem_id: id,
+            participant: Some(true),
+        }))
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, ()))?
+        .into_inner();
+    let tokens = submission_client
+        .get_tokens(user.request(submission::GetTokensRequest {
+            user: user.id.clone(),
+            problem_id: id,
+        }))
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, ()))?
+        .into_inner()
+        .available;
     let submissions = submission_client
-        .get_submission_list(tonic::Request::new(submission::GetSubmissionListRequest {
+        .get_submission_list(user.request(submission::GetSubmissionListRequest {
             limit: None,
-            user: Some(user.0),
+            user: Some(user.id.clone()),
             problem_id: Some(id),
+            participant: Some(true),
         }))
         .await;
     let problem = match running_contest.problems.iter().find(|x| x.id == id) {
@@ -82,14 +141,62 @@ pub async fn problems(
         "problems",
         ProblemsTemplate {
             contest,
+            team: user.team,
             running_contest,
             problem,
+            selectable: problem_score.method() == protos::scoring::problem::Method::Selected,
+            score: problem_score.score,
+            tokens,
             languages,
             submissions,
+            samples,
         },
     ))
 }
 
+#[get("/sample/<problem_id>/<testcase_id>/<file>")]
+pub async fn sample(
+    user: User,
+    _running_contest: RunningContest,
+    problem_id: u64,
+    testcase_id: u64,
+    file: &str,
+    contest_client: &State<ContestClient>,
+) -> Result<(ContentType, Vec<u8>), status::Custom<()>> {
+    let part = match file {
+        "input" => evaluation::testcase_chunk::Part::Input,
+        "output" => evaluation::testcase_chunk::Part::Output,
+        _ => return Err(status::Custom(Status::NotFound, ())),
+    };
+    let mut contest_client = contest_client.inner().clone();
+    let mut stream = contest_client
+        .get_sample(user.request(contest::GetSampleRequest {
+            problem_id,
+            testcase_id,
+            part: part as i32,
+        }))
+        .await
+        .map_err(|err| match err.code() {
+            tonic::Code::NotFound => status::Custom(Status::NotFound, ()),
+            _ => status::Custom(Status::InternalServerError, ()),
+        })?
+        .into_inner();
+    let mut content = utils::chunks::ChunkWriter::new(Vec::new());
+    while let Some(chunk) = stream
+        .try_next()
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, ()))?
+    {
+        content
+            .write_chunk(&chunk)
+            .map_err(|_| status::Custom(Status::InternalServerError, ()))?;
+    }
+    content
+        .finish()
+        .map(|content| (ContentType::Plain, content))
+        .map_err(|_| status::Custom(Status::InternalServerError, ()))
+}
+
 #[derive(FromForm)]
 pub struct SubmitForm<'v> {
     problem_id: u64,
@@ -130,7 +237,7 @@ pub async fn submit(
     }
     let req = submission::EvaluateSubmissionRequest {
         sub: protos::evaluation::Submission {
-            user: user.0,
+            user: user.id.clone(),
             problem_id: submission.problem_id,
             source: protos::common::Source {
                 lang: lang as i32,
@@ -140,7 +247,7 @@ pub async fn submit(
     };
     let mut submission_client = submission_client.inner().clone();
     match submission_client
-        .evaluate_submission(tonic::Request::new(req))
+        .evaluate_submission(user.request(req))
         .await
     {
         Ok(_) => Ok(Redirect::to(uri!(problems(submission.problem_id)))),
@@ -148,6 +255,66 @@ pub async fn submit(
     }
 }
 
+#[derive(FromForm)]
+pub struct SelectForm {
+    problem_id: u64,
+    submission_id: u64,
+}
+#[post("/api/select", data = "<selection>")]
+pub async fn select(
+    user: User,
+    _running_contest: RunningContest,
+    selection: Form<Strict<SelectForm>>,
+    submission_client: &State<SubmissionClient>,
+) -> Result<Redirect, status::Custom<()>> {
+    let mut submission_client = submission_client.inner().clone();
+    match submission_client
+        .select_submission(user.request(submission::SelectSubmissionRequest {
+            user: user.id.clone(),
+            submission_id: selection.submission_id,
+        }))
+        .await
+    {
+        Ok(_) => Ok(Redirect::to(uri!(problems(selection.problem_id)))),
+        Err(status) if status.code() == tonic::Code::NotFound => {
+            Err(status::Custom(Status::NotFound, ()))
+        }
+        Err(_) => Err(status::Custom(Status::InternalServerError, ())),
+    }
+}
+
+#[derive(FromForm)]
+pub struct TokenForm {
+    problem_id: u64,
+    submission_id: u64,
+}
+#[post("/api/token", data = "<token>")]
+pub async fn use_token(
+    user: User,
+    _running_contest: RunningContest,
+    token: Form<Strict<TokenForm>>,
+    submission_client: &State<SubmissionClient>,
+) -> Result<Redirect, status::Custom<()>> {
+    let mut submission_client = submission_client.inner().clone();
+    match submission_client
+        .use_token(user.request(submission::UseTokenRequest {
+            user: user.id.clone(),
+            submission_id: token.submission_id,
+        }))
+        .await
+    {
+        Ok(_) => Ok(Redirect::to(uri!(problems(token.problem_id)))),
+        Err(status) if status.code() == tonic::Code::NotFound => {
+            Err(status::Custom(Status::NotFound, ()))
+        }
+        // no tokens available
+        Err(status) if status.code() == tonic::Code::FailedPrecondition => {
+            Err(status::Custom(Status::Forbidden, ()))
+        }
+        Err(_) => Err(status::Custom(Status::InternalServerError, ())),
+    }
+}
+
 // submission details
 
 #[derive(Serialize, FromForm, Debug, Clone)]
@@ -185,33 +352,37 @@ impl TryFrom<protos::evaluation::CompilationResult> for CompilationResult {
 #[derive(Serialize, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 struct TestcaseResult {
+    id: u64,
+    name: Option<String>,
     outcome: String,
-    score: String,
-    resources: Resources,
+    score: protos::common::Score,
+    resources: Option<Resources>, // None if the feedback of the testcase hides them
 }
 impl TryFrom<protos::evaluation::TestcaseResult> for TestcaseResult {
     type Error = ();
     fn try_from(tr: protos::evaluation::TestcaseResult) -> Result<Self, Self::Error> {
         Ok(Self {
+            id: tr.id,
+            name: None,
             outcome: protos::evaluation::testcase_result::Outcome::from_i32(tr.outcome)
                 .ok_or(())?
                 .to_string(),
-            score: tr.score.score.to_string(),
-            resources: tr.used_resources.try_into()?,
+            score: tr.score,
+            resources: tr.used_resources.map(TryInto::try_into).transpose()?,
         })
     }
 }
 #[derive(Serialize, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 struct SubtaskResult {
-    score: String,
+    score: protos::common::Score,
     testcase_results: Vec<TestcaseResult>,
 }
 impl TryFrom<protos::evaluation::SubtaskResult> for SubtaskResult {
     type Error = ();
     fn try_from(sr: protos::evaluation::SubtaskResult) -> Result<Self, Self::Error> {
         Ok(Self {
-            score: sr.score.score.to_string(),
+            score: sr.score,
             testcase_results: sr
                 .testcase_results
                 .into_iter()
@@ -224,7 +395,7 @@ impl TryFrom<protos::evaluation::SubtaskResult> for SubtaskResult {
 #[serde(crate = "rocket::serde")]
 struct EvaluationResult {
     compilation: CompilationResult,
-    score: String,
+    score: protos::common::Score,
     subtask_results: Vec<SubtaskResult>,
 }
 impl TryFrom<protos::evaluation::EvaluationResult> for EvaluationResult {
@@ -232,7 +403,7 @@ impl TryFrom<protos::evaluation::EvaluationResult> for EvaluationResult {
     fn try_from(er: protos::evaluation::EvaluationResult) -> Result<Self, Self::Error> {
         Ok(Self {
             compilation: er.compilation_result.try_into()?,
-            score: er.score.score.to_string(),
+            score: er.score,
             subtask_results: er
                 .subtask_results
                 .into_iter()
@@ -249,6 +420,7 @@ struct SubmissionDetails {
     lang: String,
     code: String,
     evaluation: Option<EvaluationResult>,
+    token: bool, // the full evaluation is shown
 }
 impl TryFrom<submission::GetSubmissionDetailsResponse> for SubmissionDetails {
     type Error = ();
@@ -262,6 +434,7 @@ impl TryFrom<submission::GetSubmissionDetailsResponse> for SubmissionDetails {
                 .ok_or(())?
                 .to_string(),
             code: String::from_utf8(res.sub.source.code.clone()).map_err(|_| ())?,
+            token: res.token.is_some(),
             evaluation: res
                 .res
                 .map(|x| EvaluationResult::try_from(x).ok())
@@ -269,37 +442,60 @@ impl TryFrom<submission::GetSubmissionDetailsResponse> for SubmissionDetails {
         })
     }
 }
+impl SubmissionDetails {
+    fn set_testcase_names(&mut self, testcases: &[contest::Testcase]) {
+        let testcase_results = self
+            .evaluation
+            .iter_mut()
+            .flat_map(|evaluation| evaluation.subtask_results.iter_mut())
+            .flat_map(|subtask| subtask.testcase_results.iter_mut());
+        for tr in testcase_results {
+            tr.name = testcases
+                .iter()
+                .find(|t| t.id == tr.id)
+                .and_then(|t| t.name.clone());
+        }
+    }
+}
 #[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
 struct SubmissionDetailsTemplate {
     sub: SubmissionDetails,
     contest: ContestData,
+    team: Option<Team>,
 }
 #[get("/submission/<id>")]
 pub async fn submission_details_template(
     user: User,
     contest: ContestData,
     _running_contest: RunningContest,
+    contest_client: &State<ContestClient>,
     submission_client: &State<SubmissionClient>,
     id: u64,
 ) -> Result<Template, status::Custom<()>> {
     let mut submission_client = submission_client.inner().clone();
     match submission_client
-        .get_submission_details(tonic::Request::new(
-            submission::GetSubmissionDetailsRequest { submission_id: id },
-        ))
+        .get_submission_details(user.request(submission::GetSubmissionDetailsRequest {
+            submission_id: id,
+            participant: Some(true),
+        }))
         .await
     {
         Ok(response) => {
             let res = response.into_inner();
-            if res.sub.user != user.0 {
+            if res.sub.user != user.id {
                 return Err(status::Custom(Status::InternalServerError, ()));
             }
-            let submission_details = SubmissionDetails::try_from(res)
+            let problem_id = res.sub.problem_id;
+            let mut submission_details = SubmissionDetails::try_from(res)
                 .map_err(|_| status::Custom(Status::InternalServerError, ()))?;
+            submission_details.set_testcase_names(
+                &get_problem_testcases(&user, contest_client, problem_id).await?,
+            );
             let submission_details = SubmissionDetailsTemplate {
                 sub: submission_details,
                 contest,
+                team: user.team,
             };
             Ok(Template::render("submission_details", submission_details))
         }
@@ -44,6 +44,7 @@ impl MessageTemplate {
 pub struct QuestionsTemplate {
     messages: Vec<MessageTemplate>,
     contest: ContestData,
+    team: Option<Team>,
     running_contest: Option<RunningContest>,
 }
 #[get("/questions")]
@@ -56,11 +57,9 @@ pub async fn questions(
     let mut cc0 = contest_client.inner().clone();
     let mut cc1 = contest_client.inner().clone();
     let (announcements, questions) = futures::join!(
-        cc0.get_announcement_list(tonic::Request::new(
-            contest::GetAnnouncementListRequest::default()
-        )),
-        cc1.get_question_list(tonic::Request::new(contest::GetQuestionListRequest {
-            user_id: None, // TODO: Some(user.0) but rn this is a uint64 o.O
+        cc0.get_announcement_list(user.request(contest::GetAnnouncementListRequest::default())),
+        cc1.get_question_list(user.request(contest::GetQuestionListRequest {
+            user_id: None, // TODO: Some(user.id) but rn this is a uint64 o.O
             ..Default::default()
         })),
     );
@@ -82,7 +81,7 @@ pub async fn questions(
         .chain(
             announcements
                 .into_iter()
-                .filter(|x| x.to.is_none() || x.to.as_ref() == Some(&user.0))
+                .filter(|x| x.to.is_none() || x.to.as_ref() == Some(&user.id))
                 .map(MessageTemplate::from_announcement),
         )
         .collect();
@@ -94,6 +93,7 @@ pub async fn questions(
         QuestionsTemplate {
             messages,
             contest,
+            team: user.team,
             running_contest,
         },
     ))
@@ -119,11 +119,11 @@ pub async fn post_question(
             problem_id: message.problem,
             text: message.text.clone(),
             to: None,
-            from: Some(user.0.clone()),
+            from: Some(user.id.clone()),
             sent_at: SystemTime::now().into(),
         },
     };
-    match contest_client.add_message(tonic::Request::new(req)).await {
+    match contest_client.add_message(user.request(req)).await {
         Ok(_) => Ok(Redirect::to(uri!(questions))),
         Err(_) => Err(status::Custom(Status::InternalServerError, ())),
     }
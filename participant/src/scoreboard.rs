@@ -0,0 +1,116 @@
+use super::auth::*;
+use super::clients::*;
+use protos::service::{contest, submission};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::Serialize;
+use rocket::*;
+use rocket_dyn_templates::Template;
+use std::collections::HashMap;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct IcpcProblemTemplate {
+    name: String,
+    rejected: u32,
+    solved_at: Option<u64>, // minutes since the contest start
+    first_to_solve: bool,
+    pending: u32, // hidden by the scoreboard freeze
+}
+impl IcpcProblemTemplate {
+    // problems are shown by id when the contest is not running
+    fn new(p: submission::get_icpc_scoreboard_response::Problem, problems: &[Problem]) -> Self {
+        Self {
+            name: problems
+                .iter()
+                .find(|problem| problem.id == p.problem_id)
+                .map_or_else(|| p.problem_id.to_string(), |problem| problem.name.clone()),
+            rejected: p.rejected,
+            first_to_solve: p.first_to_solve(),
+            pending: p.pending(),
+            solved_at: p.solved_at.map(|t| t.secs / 60),
+        }
+    }
+}
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct IcpcUserTemplate {
+    rank: usize,
+    user: String,
+    name: String, // of the team, the user otherwise
+    solved: u32,
+    penalty_minutes: u64,
+    problems: Vec<IcpcProblemTemplate>,
+}
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ScoreboardTemplate {
+    users: Vec<IcpcUserTemplate>,
+    user: String,
+    contest: ContestData,
+    team: Option<Team>,
+    running_contest: Option<RunningContest>,
+}
+#[get("/scoreboard")]
+pub async fn scoreboard(
+    user: User,
+    contest: ContestData,
+    running_contest: Option<RunningContest>,
+    contest_client: &State<ContestClient>,
+    submission_client: &State<SubmissionClient>,
+) -> Result<Template, status::Custom<()>> {
+    let mut contest_client = contest_client.inner().clone();
+    let team_names: HashMap<String, String> = contest_client
+        .get_team_list(user.request(contest::GetTeamListRequest {}))
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, ()))?
+        .into_inner()
+        .teams
+        .into_iter()
+        .map(|team| (team.id, team.name))
+        .collect();
+    let mut submission_client = submission_client.inner().clone();
+    let scoreboard = match submission_client
+        .get_icpc_scoreboard(user.request(submission::GetIcpcScoreboardRequest {
+            participant: Some(true),
+        }))
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        Err(status) if status.code() == tonic::Code::FailedPrecondition => {
+            return Err(status::Custom(Status::NotFound, ()));
+        }
+        Err(_) => {
+            return Err(status::Custom(Status::InternalServerError, ()));
+        }
+    };
+    let problems = running_contest
+        .as_ref()
+        .map_or(&[][..], |running_contest| &running_contest.problems[..]);
+    Ok(Template::render(
+        "scoreboard",
+        ScoreboardTemplate {
+            users: scoreboard
+                .users
+                .into_iter()
+                .enumerate()
+                .map(|(i, u)| IcpcUserTemplate {
+                    rank: i + 1,
+                    name: team_names.get(&u.user).unwrap_or(&u.user).clone(),
+                    user: u.user,
+                    solved: u.solved,
+                    penalty_minutes: u.penalty_minutes,
+                    problems: u
+                        .problems
+                        .into_iter()
+                        .map(|p| IcpcProblemTemplate::new(p, problems))
+                        .collect(),
+                })
+                .collect(),
+            user: user.id,
+            contest,
+            team: user.team,
+            running_contest,
+        },
+    ))
+}
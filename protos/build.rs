@@ -11,17 +11,25 @@ const PROTO_FILES: [&str; 10] = [
     "protos/service/test.proto",
 ];
 
-const ENUMS: [&str; 10] = [
+const ENUMS: [&str; 18] = [
     "common.ProgrammingLanguage",
     "evaluation.CompilationResult.Outcome",
     "evaluation.TestcaseResult.Outcome",
     "scoring.Subtask.Method",
     "scoring.Problem.Method",
+    "scoring.Relative.Goal",
     "scoring.User.Method.Aggregation",
     "worker.SourceFile.Type",
     "service.evaluation.EvaluationFile.Type",
+    "service.evaluation.TestcaseInfo.Feedback",
     "service.evaluation.Problem.Type",
+    "service.evaluation.Problem.Feedback",
+    "service.evaluation.TestcaseChunk.Part",
+    "service.evaluation.SetTestcaseStreamRequest.Header.Command",
+    "service.evaluation.ImportProblemRequest.Header.Format",
+    "service.evaluation.DiffProblemVersionsResponse.FileChange.Change",
     "service.submission.SubmissionState",
+    "service.submission.ExportResultsRequest.Format",
 ];
 
 const ENUM_ATTRIBUTES: &str =
@@ -0,0 +1,256 @@
+//! Roles of the callers of the rpc services. Each client attaches a role token, signed with
+//! the secret shared by the services, to the metadata of its requests; the servers check the
+//! token with `RoleCheck` and every service method declares the roles allowed to call it
+//! with `require`.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// Secret signing the role tokens, shared by the services
+pub const RPC_SECRET_ENV: &str = "RPC_SECRET";
+/// Set to run the services without a secret, treating every caller as an admin, only for
+/// development
+pub const RPC_INSECURE_ENV: &str = "RPC_INSECURE";
+/// Role token attached by the clients, takes precedence over minting one from the secret
+pub const RPC_TOKEN_ENV: &str = "RPC_TOKEN";
+const METADATA_KEY: &str = "x-role-token";
+const SESSION_METADATA_KEY: &str = "x-session-token";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::EnumString, strum_macros::ToString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Jury,
+    Observer,
+    Participant,
+}
+
+use Role::*;
+
+pub const ADMIN: &[Role] = &[Admin];
+/// Callers managing problems and answering questions
+pub const STAFF: &[Role] = &[Admin, Jury];
+/// Callers allowed to see the results of the whole contest
+pub const READERS: &[Role] = &[Admin, Jury, Observer];
+/// Callers acting on behalf of a participant
+pub const PARTICIPANTS: &[Role] = &[Admin, Participant];
+pub const EVERYONE: &[Role] = &[Admin, Jury, Observer, Participant];
+
+fn mac(secret: &[u8], role: Role) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(role.to_string().as_bytes());
+    mac
+}
+
+/// Token of a role, as `role.signature`
+pub fn sign(secret: &[u8], role: Role) -> String {
+    let signature = hex::encode(mac(secret, role).finalize().into_bytes());
+    format!("{}.{}", role.to_string(), signature)
+}
+
+/// The role of a token signed with `secret`, None if the token is malformed or forged
+pub fn verify(secret: &[u8], token: &str) -> Option<Role> {
+    let mut parts = token.splitn(2, '.');
+    let role = parts.next()?.parse().ok()?;
+    let signature = hex::decode(parts.next()?).ok()?;
+    mac(secret, role)
+        .verify_slice(&signature)
+        .ok()
+        .map(|_| role)
+}
+
+/// Client interceptor attaching the role token of the component.
+/// The token is taken from `RPC_TOKEN`, otherwise the services knowing `RPC_SECRET` call
+/// each other as admins
+#[derive(Clone, Debug, Default)]
+pub struct RoleToken(Option<MetadataValue<Ascii>>);
+
+impl RoleToken {
+    pub fn new(token: &str) -> Self {
+        RoleToken(Some(
+            MetadataValue::from_str(token).expect("role tokens are ascii"),
+        ))
+    }
+
+    pub fn from_env() -> Self {
+        match (std::env::var(RPC_TOKEN_ENV), std::env::var(RPC_SECRET_ENV)) {
+            (Ok(token), _) => RoleToken::new(&token),
+            (Err(_), Ok(secret)) => RoleToken::new(&sign(secret.as_bytes(), Admin)),
+            _ => RoleToken(None),
+        }
+    }
+}
+
+impl Interceptor for RoleToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request.metadata_mut().insert(METADATA_KEY, token.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Server interceptor storing the role of the caller in the extensions of the request.
+/// Without a secret every caller is an admin, see `RoleCheck::from_env`
+#[derive(Clone, Debug, Default)]
+pub struct RoleCheck(Option<Vec<u8>>);
+
+impl RoleCheck {
+    pub fn new(secret: &[u8]) -> Self {
+        RoleCheck(Some(secret.to_vec()))
+    }
+
+    /// Checks the tokens signed with `RPC_SECRET`, which is required unless `RPC_INSECURE`
+    /// is set
+    pub fn from_env() -> Result<Self, String> {
+        match (
+            std::env::var(RPC_SECRET_ENV),
+            std::env::var(RPC_INSECURE_ENV),
+        ) {
+            (Ok(secret), _) => Ok(RoleCheck::new(secret.as_bytes())),
+            (Err(_), Ok(_)) => {
+                eprintln!(
+                    "{} is not set, every caller is treated as an admin",
+                    RPC_SECRET_ENV
+                );
+                Ok(RoleCheck(None))
+            }
+            (Err(_), Err(_)) => Err(format!(
+                "{} is not set, set {} to run without it",
+                RPC_SECRET_ENV, RPC_INSECURE_ENV
+            )),
+        }
+    }
+}
+
+impl Interceptor for RoleCheck {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let role = match &self.0 {
+            None => Admin,
+            Some(secret) => {
+                let token = request
+                    .metadata()
+                    .get(METADATA_KEY)
+                    .ok_or_else(|| Status::unauthenticated("Missing role token"))?
+                    .to_str()
+                    .map_err(|_| Status::unauthenticated("Invalid role token"))?;
+                verify(secret, token)
+                    .ok_or_else(|| Status::unauthenticated("Invalid role token"))?
+            }
+        };
+        request.extensions_mut().insert(role);
+        Ok(request)
+    }
+}
+
+/// The role of the caller, if it is one of `roles`
+pub fn require<T>(request: &Request<T>, roles: &[Role]) -> Result<Role, Status> {
+    let role = *request
+        .extensions()
+        .get::<Role>()
+        .ok_or_else(|| Status::unauthenticated("The caller has no role"))?;
+    if roles.contains(&role) {
+        Ok(role)
+    } else {
+        Err(Status::permission_denied(format!(
+            "The {} role is not allowed to call this method",
+            role.to_string()
+        )))
+    }
+}
+
+/// The request, on behalf of the participant logged in with the session token. A caller with
+/// the participant role acts only for the user of the session, which the services check
+pub fn with_session<T>(mut request: Request<T>, token: &str) -> Request<T> {
+    if let Ok(value) = MetadataValue::from_str(token) {
+        request.metadata_mut().insert(SESSION_METADATA_KEY, value);
+    }
+    request
+}
+
+/// The session token of the participant the request is made for
+pub fn session_token<T>(request: &Request<T>) -> Result<String, Status> {
+    request
+        .metadata()
+        .get(SESSION_METADATA_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .ok_or_else(|| Status::unauthenticated("Missing session token"))
+}
+
+/// Fails if the caller acts for a participant, given by the session, other than `user`
+pub fn check_user(participant: Option<&str>, user: &str) -> Result<(), Status> {
+    match participant {
+        Some(participant) if participant != user => Err(Status::permission_denied(
+            "Participants can only act for themselves",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Request with the role and the metadata, such as the contest, of `request`,
+/// to call another method of the same service
+pub fn forward<T, U>(request: &Request<T>, message: U) -> Request<U> {
+    let mut forwarded = Request::new(message);
+    *forwarded.metadata_mut() = request.metadata().clone();
+    if let Some(role) = request.extensions().get::<Role>() {
+        forwarded.extensions_mut().insert(*role);
+    }
+    forwarded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn role_token_test() {
+        let token = sign(b"secret", Role::Jury);
+        assert_eq!(verify(b"secret", &token), Some(Role::Jury));
+        assert_eq!(verify(b"other", &token), None);
+        let forged = token.replacen("jury", "admin", 1);
+        assert_eq!(verify(b"secret", &forged), None);
+        assert_eq!(verify(b"secret", "admin"), None);
+
+        // the role attached by the client reaches the service method
+        let mut check = RoleCheck::new(b"secret");
+        let request = RoleToken::new(&token).call(Request::new(())).unwrap();
+        let request = check.call(request).unwrap();
+        assert_eq!(require(&request, STAFF).unwrap(), Role::Jury);
+        assert_eq!(
+            require(&request, ADMIN).unwrap_err().code(),
+            Code::PermissionDenied
+        );
+        let forwarded = forward(&request, 42);
+        assert_eq!(require(&forwarded, READERS).unwrap(), Role::Jury);
+
+        // missing or forged tokens are rejected, requests not checked have no role
+        let unauthenticated = |request| check.clone().call(request).unwrap_err().code();
+        assert_eq!(unauthenticated(Request::new(())), Code::Unauthenticated);
+        let request = RoleToken::new(&forged).call(Request::new(())).unwrap();
+        assert_eq!(unauthenticated(request), Code::Unauthenticated);
+        assert!(require(&Request::new(()), EVERYONE).is_err());
+
+        // the session of the participant travels with the request
+        assert_eq!(
+            session_token(&Request::new(())).unwrap_err().code(),
+            Code::Unauthenticated
+        );
+        let request = with_session(Request::new(()), "session");
+        assert_eq!(session_token(&forward(&request, ())).unwrap(), "session");
+        assert!(check_user(None, "user").is_ok());
+        assert!(check_user(Some("user"), "user").is_ok());
+        assert_eq!(
+            check_user(Some("user"), "other").unwrap_err().code(),
+            Code::PermissionDenied
+        );
+
+        // without a secret, allowed by RPC_INSECURE, every caller is an admin
+        let request = RoleCheck::default().call(Request::new(())).unwrap();
+        assert_eq!(require(&request, ADMIN).unwrap(), Role::Admin);
+    }
+}
@@ -0,0 +1,15 @@
+//! Prints the role token to give to a component through `RPC_TOKEN`,
+//! e.g. `RPC_SECRET=... rpc_token participant`
+
+use protos::auth::{self, Role};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let secret = std::env::var(auth::RPC_SECRET_ENV)
+        .map_err(|_| format!("{} is not set", auth::RPC_SECRET_ENV))?;
+    let role: Role = std::env::args()
+        .nth(1)
+        .ok_or("usage: rpc_token <admin|jury|observer|participant>")?
+        .parse()?;
+    println!("{}", auth::sign(secret.as_bytes(), role));
+    Ok(())
+}
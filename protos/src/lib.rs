@@ -3,6 +3,9 @@
 #[macro_use]
 mod mock_macro;
 
+pub mod auth;
+pub mod scope;
+
 pub mod common {
     tonic::include_proto!("common");
     impl From<std::time::Duration> for Duration {
@@ -49,21 +52,37 @@ pub mod worker {
     tonic::include_proto!("worker");
 }
 pub mod service {
+    #[allow(non_camel_case_types)] // tonic names stream types after the snake case rpcs
     pub mod contest {
         tonic::include_proto!("service.contest");
         rpc_mock_server!(contest_server::Contest; MockContest;
         (auth_user,AuthUserRequest,AuthUserResponse),
         (get_contest_metadata,GetContestMetadataRequest,GetContestMetadataResponse),
+        (get_contest_list,GetContestListRequest,GetContestListResponse),
         (get_problem_statement,GetProblemRequest,GetProblemStatementResponse),
         (get_problem_info,GetProblemRequest,GetProblemInfoResponse),
+        (get_problem_testcases,GetProblemRequest,GetProblemTestcasesResponse),
         (get_announcement_list,GetAnnouncementListRequest,GetAnnouncementListResponse),
         (get_question_list,GetQuestionListRequest,GetQuestionListResponse),
+        (get_scoreboard_freeze,GetScoreboardFreezeRequest,GetScoreboardFreezeResponse),
+        (validate_session,ValidateSessionRequest,ValidateSessionResponse),
+        (logout,LogoutRequest,LogoutResponse),
+        (get_team_list,GetTeamListRequest,GetTeamListResponse),
+        (get_user_windows,GetUserWindowsRequest,GetUserWindowsResponse),
+        (start_contest,StartContestRequest,StartContestResponse),
         (set_user,SetUserRequest,SetUserResponse),
+        (set_team,SetTeamRequest,SetTeamResponse),
+        (set_user_window,SetUserWindowRequest,SetUserWindowResponse),
         (set_contest_metadata,SetContestMetadataRequest,SetContestMetadataResponse),
+        (set_enrollment,SetEnrollmentRequest,SetEnrollmentResponse),
         (set_problem,SetProblemRequest,SetProblemResponse),
         (update_problem_info,UpdateProblemInfoRequest,SetProblemResponse),
         (update_problem_statement,UpdateProblemStatementRequest,SetProblemResponse),
-        (add_message,AddMessageRequest,AddMessageResponse)
+        (add_message,AddMessageRequest,AddMessageResponse),
+        (unfreeze_scoreboard,UnfreezeScoreboardRequest,UnfreezeScoreboardResponse);
+        server_streaming:
+        (get_sample,GetSampleRequest,super::super::common::FileChunk,get_sampleStream);
+        client_streaming:
         );
     }
     pub mod dispatcher {
@@ -72,6 +91,7 @@ pub mod service {
         (evaluate_submission,EvaluateSubmissionRequest,EvaluateSubmissionResponse)
         );
     }
+    #[allow(non_camel_case_types)] // tonic names stream types after the snake case rpcs
     pub mod evaluation {
         tonic::include_proto!("service.evaluation");
         rpc_mock_server!(evaluation_server::Evaluation; MockEvaluation;
@@ -84,7 +104,17 @@ pub mod service {
         (set_testcase,SetTestcaseRequest,SetTestcaseResponse),
         (get_problem_evaluation_file,GetProblemEvaluationFileRequest,GetProblemEvaluationFileResponse),
         (set_problem_evaluation_file,SetProblemEvaluationFileRequest,SetProblemEvaluationFileResponse),
-        (get_update_info,GetUpdateInfoRequest,GetUpdateInfoResponse)
+        (get_update_info,GetUpdateInfoRequest,GetUpdateInfoResponse),
+        (get_problem_versions,GetProblemVersionsRequest,GetProblemVersionsResponse),
+        (diff_problem_versions,DiffProblemVersionsRequest,DiffProblemVersionsResponse),
+        (rollback_problem,RollbackProblemRequest,RollbackProblemResponse);
+        server_streaming:
+        (get_testcase_stream,GetTestcaseRequest,TestcaseChunk,get_testcase_streamStream),
+        (get_problem_testcases_stream,GetProblemTestcasesRequest,TestcaseChunk,get_problem_testcases_streamStream),
+        (export_problem,ExportProblemRequest,super::super::common::FileChunk,export_problemStream);
+        client_streaming:
+        (set_testcase_stream,SetTestcaseStreamRequest,SetTestcaseResponse),
+        (import_problem,ImportProblemRequest,ImportProblemResponse)
         );
     }
     pub mod submission {
@@ -92,7 +122,14 @@ pub mod service {
         rpc_mock_server!(submission_server::Submission; MockSubmission;
         (evaluate_submission,EvaluateSubmissionRequest,EvaluateSubmissionResponse),
         (get_submission_list,GetSubmissionListRequest,GetSubmissionListResponse),
-        (get_submission_details,GetSubmissionDetailsRequest,GetSubmissionDetailsResponse)
+        (get_submission_details,GetSubmissionDetailsRequest,GetSubmissionDetailsResponse),
+        (select_submission,SelectSubmissionRequest,SelectSubmissionResponse),
+        (get_problem_score,GetProblemScoreRequest,GetProblemScoreResponse),
+        (get_icpc_scoreboard,GetIcpcScoreboardRequest,GetIcpcScoreboardResponse),
+        (use_token,UseTokenRequest,UseTokenResponse),
+        (get_tokens,GetTokensRequest,GetTokensResponse),
+        (recompute_scores,RecomputeScoresRequest,RecomputeScoresResponse),
+        (export_results,ExportResultsRequest,ExportResultsResponse)
         );
     }
     pub mod worker {
@@ -117,6 +154,7 @@ pub mod utils {
     use tonic::body::BoxBody;
     use tonic::client::GrpcService;
     use tonic::codegen::Body;
+    use tonic::service::interceptor::InterceptedService;
     use tonic::transport::Channel;
 
     pub trait ChannelTrait = GrpcService<BoxBody> + 'static + Sync + Send + Debug + Clone
@@ -135,10 +173,19 @@ pub mod utils {
         TEST,
     }
 
-    pub fn get_new_channel(s: Service) -> Channel {
-        Channel::from_static(get_remote_address(s))
-            .connect_lazy()
-            .unwrap()
+    /// Channel attaching the role token of the component to every request
+    pub type AuthChannel = InterceptedService<Channel, crate::auth::RoleToken>;
+
+    pub fn authenticated(channel: Channel) -> AuthChannel {
+        InterceptedService::new(channel, crate::auth::RoleToken::from_env())
+    }
+
+    pub fn get_new_channel(s: Service) -> AuthChannel {
+        authenticated(
+            Channel::from_static(get_remote_address(s))
+                .connect_lazy()
+                .unwrap(),
+        )
     }
 
     #[cfg(not(feature = "loopback"))]
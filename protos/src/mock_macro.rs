@@ -35,6 +35,97 @@ macro_rules! rpc_mock_fn {
     };
 }
 
+pub type MockStream<T> = core::pin::Pin<
+    Box<dyn futures::Stream<Item = Result<T, tonic::Status>> + Send + Sync + 'static>,
+>;
+
+// server streaming methods respond with a stream over the vector that was set
+macro_rules! rpc_mock_server_stream_fn {
+    ( $stname:ident, $fname:ident, $rname:ident, $in:ty, $out:ty, $stream:ident ) => {
+        type $stream = crate::mock_macro::MockStream<$out>;
+        fn $fname<'life0, 'async_trait>(
+            &'life0 self,
+            req: tonic::Request<$in>,
+        ) -> core::pin::Pin<
+            Box<
+                dyn core::future::Future<
+                        Output = Result<
+                            tonic::Response<crate::mock_macro::MockStream<$out>>,
+                            tonic::Status,
+                        >,
+                    > + Send
+                    + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+        {
+            async fn f(
+                _self: &$stname,
+                req: tonic::Request<$in>,
+            ) -> Result<tonic::Response<crate::mock_macro::MockStream<$out>>, tonic::Status>
+            {
+                eprintln!(
+                    "{}:\nreceived {:?}\nresponding{:?}",
+                    std::any::type_name::<$stname>(),
+                    req,
+                    _self.$rname
+                );
+                match _self.$rname.clone() {
+                    Ok(x) => Ok(tonic::Response::new(Box::pin(futures::stream::iter(
+                        x.into_iter().map(Ok),
+                    ))
+                        as crate::mock_macro::MockStream<$out>)),
+                    Err(x) => Err(tonic::Status::new(x.0, x.1)),
+                }
+            }
+            Box::pin(f(self, req))
+        }
+    };
+}
+
+// client streaming methods consume the whole request stream before responding
+macro_rules! rpc_mock_client_stream_fn {
+    ( $stname:ident, $fname:ident, $rname:ident, $in:ty, $out:ty ) => {
+        fn $fname<'life0, 'async_trait>(
+            &'life0 self,
+            req: tonic::Request<tonic::Streaming<$in>>,
+        ) -> core::pin::Pin<
+            Box<
+                dyn core::future::Future<Output = Result<tonic::Response<$out>, tonic::Status>>
+                    + Send
+                    + 'async_trait,
+            >,
+        >
+        where
+            'life0: 'async_trait,
+        {
+            async fn f(
+                _self: &$stname,
+                req: tonic::Request<tonic::Streaming<$in>>,
+            ) -> Result<tonic::Response<$out>, tonic::Status> {
+                let mut stream = req.into_inner();
+                let mut received = Vec::new();
+                while let Some(message) = stream.message().await? {
+                    received.push(message);
+                }
+                let res = match _self.$rname.clone() {
+                    Ok(x) => Ok(tonic::Response::new(x)),
+                    Err(x) => Err(tonic::Status::new(x.0, x.1)),
+                };
+                eprintln!(
+                    "{}:\nreceived {:?}\nresponding{:?}",
+                    std::any::type_name::<$stname>(),
+                    received,
+                    res
+                );
+                res
+            }
+            Box::pin(f(self, req))
+        }
+    };
+}
+
 macro_rules! rpc_mock_setters {
     ( $fname:ident, $rname:ident, $in:ty, $out:ty ) => {
         paste::paste! {
@@ -51,22 +142,47 @@ macro_rules! rpc_mock_setters {
 #[macro_export]
 macro_rules! rpc_mock_server {
     ( $trait:ty; $stname:ident; $( ($fname:ident, $in:ty, $out:ty) ),* ) => {
+        rpc_mock_server!($trait; $stname; $( ($fname, $in, $out) ),*;
+            server_streaming: ;
+            client_streaming: );
+    };
+    ( $trait:ty; $stname:ident; $( ($fname:ident, $in:ty, $out:ty) ),*;
+      server_streaming: $( ($sfname:ident, $sin:ty, $sout:ty, $stream:ident) ),*;
+      client_streaming: $( ($cfname:ident, $cin:ty, $cout:ty) ),* ) => {
         paste::paste!{
             #[derive(Debug, Clone)]
             pub struct $stname {
                 $(
                     [<$fname _return>] : Result<$out,(tonic::Code,String)>,
                 )*
+                $(
+                    [<$sfname _return>] : Result<Vec<$sout>,(tonic::Code,String)>,
+                )*
+                $(
+                    [<$cfname _return>] : Result<$cout,(tonic::Code,String)>,
+                )*
             }
             impl $trait for $stname {
                 $(
                     rpc_mock_fn!($stname, $fname, [<$fname _return>], $in, $out);
                 )*
+                $(
+                    rpc_mock_server_stream_fn!($stname, $sfname, [<$sfname _return>], $sin, $sout, $stream);
+                )*
+                $(
+                    rpc_mock_client_stream_fn!($stname, $cfname, [<$cfname _return>], $cin, $cout);
+                )*
             }
             impl $stname {
                 $(
                     rpc_mock_setters!($fname,[<$fname _return>],$in,$out);
                 )*
+                $(
+                    rpc_mock_setters!($sfname,[<$sfname _return>],$sin,Vec<$sout>);
+                )*
+                $(
+                    rpc_mock_setters!($cfname,[<$cfname _return>],$cin,$cout);
+                )*
             }
             impl Default for $stname {
                 fn default() -> Self {
@@ -74,6 +190,12 @@ macro_rules! rpc_mock_server {
                         $(
                             [<$fname _return>] : Err((tonic::Code::Internal,String::from("the response for this mock method was not set"))),
                         )*
+                        $(
+                            [<$sfname _return>] : Err((tonic::Code::Internal,String::from("the response for this mock method was not set"))),
+                        )*
+                        $(
+                            [<$cfname _return>] : Err((tonic::Code::Internal,String::from("the response for this mock method was not set"))),
+                        )*
                     }
                 }
             }
@@ -0,0 +1,68 @@
+//! Contest an rpc refers to, so that one installation runs several contests, e.g. the
+//! qualification, practice and final rounds. Clients put the contest id in the metadata of
+//! the request with `in_contest` and the services read it with `contest_id`; requests
+//! without one refer to `DEFAULT_CONTEST`, so a single contest needs no setup.
+
+use tonic::metadata::MetadataValue;
+use tonic::{Request, Status};
+
+pub const DEFAULT_CONTEST: &str = "default";
+const METADATA_KEY: &str = "x-contest-id";
+
+/// Contest ids are made of letters, digits, `-` and `_`
+pub fn is_valid(contest_id: &str) -> bool {
+    !contest_id.is_empty()
+        && contest_id.len() <= 64
+        && contest_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The request, referring to the contest
+pub fn in_contest<T>(mut request: Request<T>, contest_id: &str) -> Request<T> {
+    if let Ok(value) = MetadataValue::from_str(contest_id) {
+        request.metadata_mut().insert(METADATA_KEY, value);
+    }
+    request
+}
+
+/// The contest the request refers to
+pub fn contest_id<T>(request: &Request<T>) -> Result<String, Status> {
+    match request.metadata().get(METADATA_KEY) {
+        None => Ok(DEFAULT_CONTEST.to_string()),
+        Some(value) => value
+            .to_str()
+            .ok()
+            .filter(|contest_id| is_valid(contest_id))
+            .map(str::to_owned)
+            .ok_or_else(|| Status::invalid_argument("Invalid contest id")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn contest_scope_test() {
+        let request = Request::new(());
+        assert_eq!(contest_id(&request).unwrap(), DEFAULT_CONTEST);
+        let request = in_contest(request, "final-2022_b");
+        assert_eq!(contest_id(&request).unwrap(), "final-2022_b");
+        // the last contest set wins
+        let request = in_contest(request, "practice");
+        assert_eq!(contest_id(&request).unwrap(), "practice");
+
+        assert!(is_valid(DEFAULT_CONTEST));
+        assert!(!is_valid(""));
+        assert!(!is_valid("final round"));
+        assert!(!is_valid("../final"));
+        assert!(!is_valid(&"a".repeat(65)));
+        let request = in_contest(Request::new(()), "final round");
+        assert_eq!(
+            contest_id(&request).unwrap_err().code(),
+            Code::InvalidArgument
+        );
+    }
+}
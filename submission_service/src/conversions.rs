@@ -1,8 +1,9 @@
+use std::collections::HashSet;
 use std::time::SystemTime;
 
 use ::utils::{gen_uuid, mongo::*};
 use mongodb::bson::{bson, doc, spec::BinarySubtype, Binary, Bson, Document};
-use protos::common::Score;
+use protos::common::{Fraction, Score};
 use protos::service::submission::*;
 use protos::utils::*;
 use protos::{
@@ -25,7 +26,38 @@ fn convert_to_i64(x: u64) -> i64 {
     x as i64
 }
 
-pub fn get_item_from_doc(doc: Document) -> get_submission_list_response::Item {
+/// Exact value of a score, stored next to its approximation. Null when the approximation
+/// is exact
+fn exact_to_db(score: &Score) -> Bson {
+    score.exact.as_ref().map_or(Bson::Null, |exact| {
+        bson!({
+            "num": exact.numerator,
+            "den": convert_to_i64(exact.denominator)
+        })
+    })
+}
+
+/// Score stored in `field`, with its exact value in `exact_field` if any
+fn score_from_db(doc: &Document, field: &str, exact_field: &str) -> Option<Score> {
+    Some(Score {
+        score: doc.get_f64(field).ok()?,
+        exact: doc.get_document(exact_field).ok().map(|exact| Fraction {
+            numerator: exact
+                .get_i64("num")
+                .unwrap_or_else(|_| panic!("{}", expected_field("num"))),
+            denominator: exact
+                .get_i64("den")
+                .unwrap_or_else(|_| panic!("{}", expected_field("den")))
+                as u64,
+        }),
+    })
+}
+
+/// `selected` are the ids of the submissions chosen by their users
+pub fn get_item_from_doc(
+    doc: Document,
+    selected: &HashSet<i64>,
+) -> get_submission_list_response::Item {
     get_submission_list_response::Item {
         submission_id: doc.get_i64("_id").unwrap() as u64,
         user: doc.get_str("user").unwrap().to_string(),
@@ -34,16 +66,20 @@ pub fn get_item_from_doc(doc: Document) -> get_submission_list_response::Item {
         state: doc
             .get_i32("state")
             .unwrap_or_else(|_| panic!("{}", expected_field("state"))),
-        score: doc
-            .get_f64("overallScore")
-            .map_or_else(|_| None, |val| Some(Score { score: val })),
+        score: score_from_db(&doc, "overallScore", "overallScoreExact"),
+        selected: Some(selected.contains(&doc.get_i64("_id").unwrap())),
+        token: Some(doc.get_timestamp("token").is_ok()),
     }
 }
 
-pub fn create_pending_submission_document(submission: evaluation::Submission) -> Document {
+pub fn create_pending_submission_document(
+    submission: evaluation::Submission,
+    contest_id: &str,
+) -> Document {
     doc! {
         "_id": convert_to_i64(gen_uuid()),
         "user": submission.user,
+        "contestId": contest_id,
         "problemId": submission.problem_id as i64,
         "created": systime_to_timestamp(SystemTime::now()),
         "source": Bson::Binary(Binary {
@@ -79,12 +115,16 @@ fn compilation_data_to_db_obj(compilation_result: CompilationResult) -> Bson {
 }
 
 fn testcase_data_to_db_obj(testcase_data: &TestcaseResult) -> Bson {
+    let used_resources = testcase_data.used_resources.clone().unwrap_or_default();
     bson! ({
         "testcaseId": convert_to_i64(testcase_data.id),
         "outcome": testcase_data.outcome,
         "score": testcase_data.score.score,
-        "timeNs": duration_to_time_ns(testcase_data.used_resources.time.clone()),
-        "memoryB": convert_to_i64(testcase_data.used_resources.memory_bytes)
+        "scoreExact": exact_to_db(&testcase_data.score),
+        "objective": testcase_data.objective.as_ref().map(|objective| objective.score),
+        "objectiveExact": testcase_data.objective.as_ref().map_or(Bson::Null, exact_to_db),
+        "timeNs": duration_to_time_ns(used_resources.time),
+        "memoryB": convert_to_i64(used_resources.memory_bytes)
     })
 }
 
@@ -92,6 +132,7 @@ fn subtask_data_to_db_obj(subtask_data: &SubtaskResult) -> Bson {
     bson! ({
         "subtaskId": convert_to_i64(subtask_data.id),
         "subtaskScore": subtask_data.score.score,
+        "subtaskScoreExact": exact_to_db(&subtask_data.score),
         "testcases":
             subtask_data.testcase_results
                 .iter()
@@ -109,6 +150,10 @@ pub fn insert_evaluation_data_into_document(
         compilation_data_to_db_obj(evaluation_result.compilation_result.clone()),
     );
     doc_updated.insert("overallScore", evaluation_result.score.score);
+    doc_updated.insert("overallScoreExact", exact_to_db(&evaluation_result.score));
+    if let Some(problem_version) = evaluation_result.problem_version {
+        doc_updated.insert("problemVersion", convert_to_i64(problem_version));
+    }
 
     if evaluation_result.compilation_result.outcome() == compilation_result::Outcome::Success {
         // if compilation succeeded, then fill evaluation and score fields
@@ -152,7 +197,7 @@ fn single_testcase_db_to_struct(testcase_doc: &Document) -> TestcaseResult {
         outcome: testcase_doc
             .get_i32("outcome")
             .unwrap_or_else(|_| panic!("{}", expected_field("outcome"))),
-        used_resources: Resources {
+        used_resources: Some(Resources {
             time: time_ns_to_duration(
                 testcase_doc
                     .get_i64("timeNs")
@@ -162,15 +207,13 @@ fn single_testcase_db_to_struct(testcase_doc: &Document) -> TestcaseResult {
                 .get_i64("memoryB")
                 .unwrap_or_else(|_| panic!("{}", expected_field("memoryB")))
                 as u64,
-        },
-        score: Score {
-            score: testcase_doc
-                .get_f64("score")
-                .unwrap_or_else(|_| panic!("{}", expected_field("score"))),
-        },
+        }),
+        score: score_from_db(testcase_doc, "score", "scoreExact")
+            .unwrap_or_else(|| panic!("{}", expected_field("score"))),
         id: testcase_doc
             .get_i64("testcaseId")
             .unwrap_or_else(|_| panic!("{}", expected_field("testcaseId"))) as u64,
+        objective: score_from_db(testcase_doc, "objective", "objectiveExact"),
     }
 }
 
@@ -185,11 +228,8 @@ fn single_subtask_db_to_struct(subtask_doc: &Document) -> SubtaskResult {
                 single_testcase_db_to_struct(testcase)
             })
             .collect::<Vec<TestcaseResult>>(),
-        score: Score {
-            score: subtask_doc
-                .get_f64("subtaskScore")
-                .unwrap_or_else(|_| panic!("{}", expected_field("subtaskScore"))),
-        },
+        score: score_from_db(subtask_doc, "subtaskScore", "subtaskScoreExact")
+            .unwrap_or_else(|| panic!("{}", expected_field("subtaskScore"))),
         id: subtask_doc
             .get_i64("subtaskId")
             .unwrap_or_else(|_| panic!("{}", expected_field("subtaskId"))) as u64,
@@ -232,10 +272,11 @@ pub fn document_to_evaluation_result_struct(submission_doc: Document) -> Evaluat
         } else {
             vec![]
         },
-        score: Score {
-            score: submission_doc
-                .get_f64("overallScore")
-                .unwrap_or_else(|_| panic!("{}", expected_field("overallScore"))),
-        },
+        score: score_from_db(&submission_doc, "overallScore", "overallScoreExact")
+            .unwrap_or_else(|| panic!("{}", expected_field("overallScore"))),
+        problem_version: submission_doc
+            .get_i64("problemVersion")
+            .ok()
+            .map(|version| version as u64),
     }
 }
@@ -5,23 +5,44 @@ use core::panic;
 
 use futures::stream::StreamExt;
 
-use ::utils::scoring::{calc_submission_score, calc_subtask_score};
+use ::utils::export::{self, Results, Verdict};
+use ::utils::feedback::{apply_feedback, apply_testcase_feedback};
+use ::utils::mongo::{systime_to_timestamp, timestamp_to_systime};
+use ::utils::scoring::{
+    apply_relative_scoring, best_objectives, calc_icpc_scoreboard, calc_problem_score,
+    calc_submission_score, calc_subtask_score, calc_user_ranking, has_relative_scoring,
+    is_max_score, IcpcSubmission, IcpcVerdict, UserProblemResult,
+};
+use ::utils::tokens::available_tokens;
+use ::utils::window::{self, Window};
 use mongodb::{
     bson::{doc, Bson, Document},
     options::{
-        ClientOptions, CreateCollectionOptions, FindOptions, ValidationAction, ValidationLevel,
+        ClientOptions, CreateCollectionOptions, FindOptions, UpdateOptions, ValidationAction,
+        ValidationLevel,
     },
     Client, Database,
 };
+use protos::auth;
+use protos::scope;
 use protos::service::submission::submission_server::*;
 use protos::service::submission::*;
 use protos::utils::*;
 use protos::{self, *};
+use protos::{evaluation::EvaluationResult, service::dispatcher::dispatcher_server::*};
 use protos::{
-    evaluation::compilation_result,
-    service::evaluation::{evaluation_server::Evaluation, GetProblemRequest},
+    evaluation::{compilation_result, testcase_result},
+    service::contest::{
+        contest_client::ContestClient, ContestMetadata, GetContestMetadataRequest,
+        GetContestMetadataResponse, GetScoreboardFreezeRequest, GetScoreboardFreezeResponse,
+        GetTeamListRequest, GetUserWindowsRequest, Team, UserWindow, ValidateSessionRequest,
+    },
+    service::evaluation::{
+        evaluation_client::EvaluationClient, problem, GetProblemRequest, GetUserScoringRequest,
+    },
 };
-use protos::{evaluation::EvaluationResult, service::dispatcher::dispatcher_server::*};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use tonic::{transport::*, Request, Response, Status};
 
 mod conversions;
@@ -55,16 +76,26 @@ fn convert_to_i64(x: u64) -> i64 {
 async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::error::Error>> {
     // TODO: consider using this validator syntax (might be slightly nicer):
     // https://docs.mongodb.com/v5.0/core/schema-validation/#other-query-expressions
+    // exact value of the score next to it, null when the double is exact
+    let exact_score = doc! {
+        "bsonType": ["null", "object"],
+        "required": ["num", "den"],
+        "properties": {
+            "num": { "bsonType": "long" },
+            "den": { "bsonType": "long" }
+        }
+    };
     db.create_collection(
         "submissions",
         CreateCollectionOptions::builder()
             .validator(doc! {
                 "$jsonSchema": {
                     "bsonType": "object",
-                    "required": ["_id", "user", "problemId", "created", "source", "programmingLanguage", "state"],
+                    "required": ["_id", "user", "contestId", "problemId", "created", "source", "programmingLanguage", "state"],
                     "properties": {
                         "_id": { "bsonType": "long" }, // submission id
                         "user": { "bsonType": "string" },
+                        "contestId": { "bsonType": "string" },
                         "problemId": { "bsonType": "long" },
                         "created": { "bsonType": "timestamp" },
                         "source": { "bsonType": "binData" },
@@ -78,6 +109,7 @@ async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::error:
                             ...
                             */
                         },
+                        "token": { "bsonType": "timestamp" }, // when the user used a token on it
                         "state": {
                             "bsonType": "int",
                             "enum": [0, 1, 2]
@@ -118,6 +150,7 @@ async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::error:
                                     "properties": {
                                     "subtaskId": { "bsonType": "long" },
                                     "subtaskScore": { "bsonType": "double" }, // SubtaskResult.subtask_score
+                                    "subtaskScoreExact": exact_score.clone(),
                                     "testcases": {
                                         "bsonType": "array",
                                         "items": {
@@ -138,6 +171,9 @@ async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::error:
                                                     */
                                                 }, // TestcaseResult.outcome
                                                 "score": { "bsonType": "double" }, //TestcaseResult.score
+                                                "scoreExact": exact_score.clone(),
+                                                "objective": { "bsonType": ["null","double"] }, // TestcaseResult.objective
+                                                "objectiveExact": exact_score.clone(),
                                                 "timeNs": { "bsonType": "long" }, // TestcaseResult.used_resources
                                                 "memoryB": { "bsonType": "long" }, // TestcaseResult.used_resources
                                         }
@@ -148,7 +184,9 @@ async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::error:
                                 }
                             } // EvaluationResult.subtask_results
                         },
-                        "overallScore": { "bsonType": "double" } // EvaluationResult.overall_score
+                        "overallScore": { "bsonType": "double" }, // EvaluationResult.overall_score
+                        "overallScoreExact": exact_score,
+                        "problemVersion": { "bsonType": "long" } // EvaluationResult.problem_version
                     }
                 }
             })
@@ -158,12 +196,60 @@ async fn init_contest_service_db(db: Database) -> Result<(), Box<dyn std::error:
     )
     .await?;
 
+    // the submission chosen by each user for a problem of a contest, a single
+    // document so that changing it is atomic
+    db.create_collection(
+        "selections",
+        CreateCollectionOptions::builder()
+            .validator(doc! {
+                "$jsonSchema": {
+                    "bsonType": "object",
+                    "required": ["_id", "submissionId"],
+                    "properties": {
+                        "_id": {
+                            "bsonType": "object",
+                            "required": ["contestId", "user", "problemId"],
+                            "properties": {
+                                "contestId": { "bsonType": "string" },
+                                "user": { "bsonType": "string" },
+                                "problemId": { "bsonType": "long" }
+                            }
+                        },
+                        "submissionId": { "bsonType": "long" }
+                    }
+                }
+            })
+            .validation_action(ValidationAction::Error)
+            .validation_level(ValidationLevel::Strict)
+            .build(),
+    )
+    .await?;
+
     Ok(())
 }
 
+/// The submissions made before the installation ran several contests belong to the default
+/// contest. Running it again changes nothing
+async fn migrate_submission_service_db(db: Database) -> Result<(), Box<dyn std::error::Error>> {
+    db.collection::<Document>("submissions")
+        .update_many(
+            doc! { "contestId": { "$exists": false } },
+            doc! { "$set": { "contestId": scope::DEFAULT_CONTEST } },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+type ProblemLock = Arc<tokio::sync::Mutex<()>>;
+
 #[derive(Debug)]
 pub struct SubmissionService {
     db_client: Client,
+    evaluation_client: EvaluationClient<AuthChannel>,
+    contest_client: ContestClient<AuthChannel>,
+    // scores of the submissions of each problem in each contest, written by one request at a time
+    problem_locks: std::sync::Mutex<HashMap<(String, u64), ProblemLock>>,
 }
 
 impl SubmissionService {
@@ -180,8 +266,171 @@ impl SubmissionService {
             .is_some();
         if !db_already_present {
             init_contest_service_db(db_client.database("submissionsdb")).await?;
+        } else {
+            migrate_submission_service_db(db_client.database("submissionsdb")).await?;
         }
-        Ok(Self { db_client })
+        Ok(Self {
+            db_client,
+            evaluation_client: EvaluationClient::new(get_new_channel(Service::EVALUATION)),
+            contest_client: ContestClient::new(get_new_channel(Service::CONTEST)),
+            problem_locks: Default::default(),
+        })
+    }
+
+    async fn get_problem_metadata(
+        &self,
+        problem_id: u64,
+    ) -> Result<protos::service::evaluation::Problem, Status> {
+        Ok(self
+            .evaluation_client
+            .clone()
+            .get_problem(Request::new(GetProblemRequest { problem_id }))
+            .await?
+            .into_inner()
+            .info)
+    }
+
+    async fn get_problem_feedback(&self, problem_id: u64) -> Result<problem::Feedback, Status> {
+        Ok(self.get_problem_metadata(problem_id).await?.feedback())
+    }
+
+    /// Held while the scores of the submissions of the problem in the contest are computed
+    /// and stored
+    async fn lock_problem(
+        &self,
+        contest_id: &str,
+        problem_id: u64,
+    ) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self
+            .problem_locks
+            .lock()
+            .unwrap()
+            .entry((contest_id.to_string(), problem_id))
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    async fn get_user_scoring(&self, contest_id: &str) -> Result<protos::scoring::User, Status> {
+        Ok(self
+            .evaluation_client
+            .clone()
+            .get_user_scoring(scope::in_contest(
+                Request::new(GetUserScoringRequest {}),
+                contest_id,
+            ))
+            .await?
+            .into_inner()
+            .method)
+    }
+
+    async fn get_teams(&self, contest_id: &str) -> Result<Vec<Team>, Status> {
+        Ok(self
+            .contest_client
+            .clone()
+            .get_team_list(scope::in_contest(
+                Request::new(GetTeamListRequest {}),
+                contest_id,
+            ))
+            .await?
+            .into_inner()
+            .teams)
+    }
+
+    /// The user a caller with the participant role acts for: the team, in the contest, of the
+    /// participant logged in with the session of the request, or the participant outside of
+    /// teams. None for the other roles, which can act for anyone
+    async fn participant<T>(
+        &self,
+        request: &Request<T>,
+        role: auth::Role,
+    ) -> Result<Option<String>, Status> {
+        if role != auth::Role::Participant {
+            return Ok(None);
+        }
+        let contest_id = scope::contest_id(request)?;
+        let session = self
+            .contest_client
+            .clone()
+            .validate_session(scope::in_contest(
+                Request::new(ValidateSessionRequest {
+                    token: auth::session_token(request)?,
+                }),
+                &contest_id,
+            ))
+            .await?
+            .into_inner();
+        if session.admin || !session.contests.contains(&contest_id) {
+            return Err(Status::permission_denied(
+                "The user does not take part in the contest",
+            ));
+        }
+        Ok(Some(session.team.map_or(session.username, |team| team.id)))
+    }
+
+    async fn get_contest(&self, contest_id: &str) -> Result<GetContestMetadataResponse, Status> {
+        Ok(self
+            .contest_client
+            .clone()
+            .get_contest_metadata(scope::in_contest(
+                Request::new(GetContestMetadataRequest {}),
+                contest_id,
+            ))
+            .await?
+            .into_inner())
+    }
+
+    async fn get_contest_metadata(&self, contest_id: &str) -> Result<ContestMetadata, Status> {
+        Ok(self.get_contest(contest_id).await?.metadata)
+    }
+
+    async fn get_scoreboard_freeze(
+        &self,
+        contest_id: &str,
+    ) -> Result<GetScoreboardFreezeResponse, Status> {
+        Ok(self
+            .contest_client
+            .clone()
+            .get_scoreboard_freeze(scope::in_contest(
+                Request::new(GetScoreboardFreezeRequest {}),
+                contest_id,
+            ))
+            .await?
+            .into_inner())
+    }
+
+    async fn get_user_windows(
+        &self,
+        contest_id: &str,
+        user: Option<&str>,
+    ) -> Result<Vec<UserWindow>, Status> {
+        Ok(self
+            .contest_client
+            .clone()
+            .get_user_windows(scope::in_contest(
+                Request::new(GetUserWindowsRequest {
+                    user: user.map(str::to_owned),
+                }),
+                contest_id,
+            ))
+            .await?
+            .into_inner()
+            .windows)
+    }
+
+    async fn get_user_window(&self, contest_id: &str, user: &str) -> Result<Window, Status> {
+        let metadata = self.get_contest_metadata(contest_id).await?;
+        let windows = self.get_user_windows(contest_id, Some(user)).await?;
+        Ok(window::user_window(&metadata, windows.first()))
+    }
+
+    /// Whether the window of every user is over
+    async fn is_contest_ended(&self, contest_id: &str) -> Result<bool, Status> {
+        let metadata = self.get_contest_metadata(contest_id).await?;
+        let windows = self.get_user_windows(contest_id, None).await?;
+        Ok(window::contest_end(&metadata, &windows)
+            .filter(|&end_time| end_time <= std::time::SystemTime::now())
+            .is_some())
     }
 
     fn get_collection(&self) -> mongodb::Collection<Document> {
@@ -189,27 +438,156 @@ impl SubmissionService {
             .database("submissionsdb")
             .collection::<Document>("submissions")
     }
+
+    fn get_selections_collection(&self) -> mongodb::Collection<Document> {
+        self.db_client
+            .database("submissionsdb")
+            .collection::<Document>("selections")
+    }
+
+    /// Ids of the submissions chosen by the users in the contest, optionally
+    /// only by a user or for a problem
+    async fn get_selected(
+        &self,
+        contest_id: &str,
+        user: Option<&str>,
+        problem_id: Option<u64>,
+    ) -> Result<HashSet<i64>, Status> {
+        let mut filter = doc! { "_id.contestId": contest_id };
+        if let Some(user) = user {
+            filter.insert("_id.user", user);
+        }
+        if let Some(problem_id) = problem_id {
+            filter.insert("_id.problemId", convert_to_i64(problem_id));
+        }
+        Ok(self
+            .get_selections_collection()
+            .find(filter, None)
+            .await
+            .map_err(internal_error)?
+            .try_collect::<Vec<Document>>()
+            .await
+            .map_err(internal_error)?
+            .iter()
+            .filter_map(|selection| selection.get_i64("submissionId").ok())
+            .collect())
+    }
+
+    /// Tokens used by the user in the contest, in the order they were used
+    async fn get_used_tokens(
+        &self,
+        contest_id: &str,
+        user: &str,
+    ) -> Result<Vec<UsedToken>, Status> {
+        Ok(self
+            .get_collection()
+            .find(
+                doc! { "user": user, "contestId": contest_id, "token": { "$exists": true } },
+                FindOptions::builder()
+                    .sort(doc! { "token": 1, "_id": 1 })
+                    .build(),
+            )
+            .await
+            .map_err(internal_error)?
+            .try_collect::<Vec<Document>>()
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .map(|document| UsedToken {
+                submission_id: document
+                    .get_i64("_id")
+                    .unwrap_or_else(|_| panic!("{}", expected_field("_id"))),
+                problem_id: document
+                    .get_i64("problemId")
+                    .unwrap_or_else(|_| panic!("{}", expected_field("problemId")))
+                    as u64,
+                time: timestamp_to_systime(document.get_timestamp("token").unwrap()),
+            })
+            .collect())
+    }
+
+    /// Tokens the user can use on the submissions of the problem now,
+    /// None if the contest does not use tokens
+    async fn get_available_tokens(
+        &self,
+        contest_id: &str,
+        user: &str,
+        problem_id: u64,
+    ) -> Result<Option<u32>, Status> {
+        let contest = self.get_contest(contest_id).await?;
+        let start_time = user_start(
+            &contest.metadata,
+            &self.get_user_windows(contest_id, Some(user)).await?,
+            user,
+        );
+        let used = self.get_used_tokens(contest_id, user).await?;
+        Ok(tokens_left(
+            &contest,
+            start_time,
+            &used,
+            problem_id,
+            std::time::SystemTime::now(),
+        ))
+    }
 }
 
-/// safe even if compilation didn't succeed
-async fn evaluate_scores(
-    mut_evaluation_result: &mut EvaluationResult,
+struct UsedToken {
+    submission_id: i64,
     problem_id: u64,
-) -> Result<(), Status> {
+    time: std::time::SystemTime,
+}
+
+/// Tokens left to the user at the given time after using the given ones,
+/// None if the contest does not use tokens
+fn tokens_left(
+    contest: &GetContestMetadataResponse,
+    start_time: std::time::SystemTime,
+    used: &[UsedToken],
+    problem_id: u64,
+    time: std::time::SystemTime,
+) -> Option<u32> {
+    let contest_rules = contest.metadata.tokens.as_ref()?;
+    let contest_used: Vec<_> = used.iter().map(|token| token.time).collect();
+    let mut available = available_tokens(contest_rules, start_time, &contest_used, time);
+    if let Some(problem_rules) = contest
+        .problems
+        .iter()
+        .find(|problem| problem.id == problem_id)
+        .and_then(|problem| problem.tokens.as_ref())
+    {
+        let problem_used: Vec<_> = used
+            .iter()
+            .filter(|token| token.problem_id == problem_id)
+            .map(|token| token.time)
+            .collect();
+        available = available.min(available_tokens(
+            problem_rules,
+            start_time,
+            &problem_used,
+            time,
+        ));
+    }
+    Some(available)
+}
+
+/// safe even if compilation didn't succeed,
+/// testcases with relative scoring are scored against best_objectives
+fn evaluate_scores(
+    mut_evaluation_result: &mut EvaluationResult,
+    problem_metadata: &protos::service::evaluation::Problem,
+    best_objectives: &HashMap<u64, protos::common::Score>,
+) {
     // if compilation failed, update manually submission score and return
     if mut_evaluation_result.compilation_result.outcome() != compilation_result::Outcome::Success {
         assert!(mut_evaluation_result.subtask_results.is_empty());
-        mut_evaluation_result.score = protos::common::Score { score: 0f64 };
-        return Ok(());
+        mut_evaluation_result.score = protos::common::Score {
+            score: 0f64,
+            ..Default::default()
+        };
+        return;
     }
 
-    let problem_metadata_request = GetProblemRequest { problem_id };
-    let mock_evaluation_server = mock_services::get_mock_evaluation(problem_id);
-
-    let problem_metadata = mock_evaluation_server
-        .get_problem(Request::new(problem_metadata_request))
-        .await?
-        .into_inner();
+    apply_relative_scoring(problem_metadata, mut_evaluation_result, best_objectives);
 
     mut_evaluation_result
         .subtask_results
@@ -218,16 +596,174 @@ async fn evaluate_scores(
         .for_each(|(i, subtask)| {
             subtask.score = calc_subtask_score(
                 &subtask.testcase_results,
-                &problem_metadata.info.subtasks[i].scoring,
+                &problem_metadata.subtasks[i].scoring,
             );
         });
 
     mut_evaluation_result.score = calc_submission_score(
         &mut_evaluation_result.subtask_results,
-        &protos::scoring::Problem::default(), // TODO: pass the actual options
+        &problem_metadata.scoring,
     );
+}
 
-    Ok(())
+struct StoredEvaluation {
+    id: i64,
+    user: String,
+    result: EvaluationResult,
+}
+
+async fn get_problem_evaluations(
+    collection: &mongodb::Collection<Document>,
+    contest_id: &str,
+    problem_id: u64,
+) -> Result<Vec<StoredEvaluation>, Status> {
+    Ok(collection
+        .find(
+            doc! {
+                "contestId": contest_id,
+                "problemId": convert_to_i64(problem_id),
+                "state": SubmissionState::Evaluated as i32,
+            },
+            None,
+        )
+        .await
+        .map_err(internal_error)?
+        .try_collect::<Vec<Document>>()
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|document| StoredEvaluation {
+            id: document
+                .get_i64("_id")
+                .unwrap_or_else(|_| panic!("{}", expected_field("_id"))),
+            user: document
+                .get_str("user")
+                .unwrap_or_else(|_| panic!("{}", expected_field("user")))
+                .to_string(),
+            result: conversions::document_to_evaluation_result_struct(document),
+        })
+        .collect())
+}
+
+// the scores stored in the database, with their exact values
+fn stored_scores(result: &EvaluationResult) -> Vec<protos::common::Score> {
+    std::iter::once(result.score.clone())
+        .chain(result.subtask_results.iter().flat_map(|subtask| {
+            std::iter::once(subtask.score.clone()).chain(
+                subtask
+                    .testcase_results
+                    .iter()
+                    .map(|testcase| testcase.score.clone()),
+            )
+        }))
+        .collect()
+}
+
+/// Scores again all the evaluated submissions of a problem in the contest from their testcase
+/// results, with the current scoring of the problem. Returns the users of the changed
+/// submissions, once for each of them
+async fn recompute_problem_scores(
+    collection: mongodb::Collection<Document>,
+    contest_id: &str,
+    problem_metadata: protos::service::evaluation::Problem,
+) -> Result<Vec<String>, Status> {
+    let evaluations = get_problem_evaluations(&collection, contest_id, problem_metadata.id).await?;
+    let best = best_objectives(
+        &problem_metadata,
+        evaluations.iter().map(|evaluation| &evaluation.result),
+    );
+    let mut changed = vec![];
+    for mut evaluation in evaluations {
+        let before = stored_scores(&evaluation.result);
+        evaluate_scores(&mut evaluation.result, &problem_metadata, &best);
+        if stored_scores(&evaluation.result) == before {
+            continue;
+        }
+        let mut doc_updated = Document::new();
+        conversions::insert_evaluation_data_into_document(&mut doc_updated, &evaluation.result);
+        collection
+            .update_one(
+                doc! { "_id": evaluation.id },
+                doc! { "$set": doc_updated },
+                None,
+            )
+            .await
+            .map_err(internal_error)?;
+        changed.push(evaluation.user);
+    }
+    Ok(changed)
+}
+
+/// Start of the window of the user, the times of the user in the contest count from it
+fn user_start(
+    metadata: &ContestMetadata,
+    windows: &[UserWindow],
+    user: &str,
+) -> std::time::SystemTime {
+    window::user_window(metadata, windows.iter().find(|window| window.user == user))
+        .start
+        .or_else(|| metadata.start_time.clone().map(std::time::SystemTime::from))
+        .unwrap_or(std::time::UNIX_EPOCH)
+}
+
+/// Verdict of an evaluated submission in the exported results
+fn export_verdict(
+    evaluation: &EvaluationResult,
+    problem_metadata: &protos::service::evaluation::Problem,
+) -> Verdict {
+    if evaluation.compilation_result.outcome() != compilation_result::Outcome::Success {
+        return Verdict::CompilationError;
+    }
+    if is_max_score(&evaluation.score, problem_metadata) {
+        return Verdict::Accepted;
+    }
+    let failed = evaluation
+        .subtask_results
+        .iter()
+        .flat_map(|subtask| subtask.testcase_results.iter())
+        .map(|testcase| testcase.outcome())
+        .find(|&outcome| outcome != testcase_result::Outcome::Ok);
+    match failed {
+        Some(testcase_result::Outcome::Tle) => Verdict::TimeLimitExceeded,
+        Some(testcase_result::Outcome::Mle) => Verdict::MemoryLimitExceeded,
+        Some(testcase_result::Outcome::Rte) => Verdict::RuntimeError,
+        _ => Verdict::WrongAnswer,
+    }
+}
+
+/// Result of a user on a problem from the evaluated submissions in chronological order,
+/// each with its time since the contest start and whether it is the selected one
+fn user_problem_result(
+    problem_metadata: &protos::service::evaluation::Problem,
+    submissions: &[(std::time::Duration, bool, EvaluationResult)],
+) -> UserProblemResult {
+    let evaluations: Vec<EvaluationResult> = submissions
+        .iter()
+        .map(|(_, _, evaluation)| evaluation.clone())
+        .collect();
+    let selected = submissions.iter().position(|(_, selected, _)| *selected);
+    let mut score = protos::common::Score::default();
+    let mut last_change = std::time::Duration::ZERO;
+    for (i, (elapsed, _, _)) in submissions.iter().enumerate() {
+        let new_score = calc_problem_score(
+            &evaluations[..=i],
+            &problem_metadata.scoring,
+            selected.filter(|&selected| selected <= i),
+        );
+        if new_score != score {
+            score = new_score;
+            last_change = *elapsed;
+        }
+    }
+    UserProblemResult {
+        problem_id: problem_metadata.id,
+        score,
+        wrong_submissions: evaluations
+            .iter()
+            .filter(|evaluation| !is_max_score(&evaluation.score, problem_metadata))
+            .count() as u32,
+        last_change,
+    }
 }
 
 #[tonic::async_trait]
@@ -236,11 +772,26 @@ impl Submission for SubmissionService {
         &self,
         request: Request<EvaluateSubmissionRequest>,
     ) -> Result<Response<protos::service::submission::EvaluateSubmissionResponse>, Status> {
+        let role = auth::require(&request, auth::PARTICIPANTS)?;
+        let contest_id = scope::contest_id(&request)?;
+        let participant = self.participant(&request, role).await?;
+        auth::check_user(participant.as_deref(), &request.get_ref().sub.user)?;
+        if role == auth::Role::Participant
+            && !self
+                .get_user_window(&contest_id, &request.get_ref().sub.user)
+                .await?
+                .is_running(std::time::SystemTime::now())
+        {
+            return Err(Status::failed_precondition(
+                "The contest is not running for the user",
+            ));
+        }
         let evaluate_submission_request = request.into_inner();
         let submission = evaluate_submission_request.sub;
         // 1) write into dabatase with Pending state
 
-        let doc_filter = conversions::create_pending_submission_document(submission.clone());
+        let doc_filter =
+            conversions::create_pending_submission_document(submission.clone(), &contest_id);
         let id = doc_filter.get_i64("_id").unwrap();
 
         self.get_collection()
@@ -277,9 +828,33 @@ impl Submission for SubmissionService {
         };
 
         // evaluate subtasks' and submission's scores starting from testcases' scores
-        // and problem metadata
+        // and problem metadata, the scores of the problem are not changed by other
+        // requests until the submission and the scores relative to it are stored
+        let _problem_lock = self.lock_problem(&contest_id, submission.problem_id).await;
+        let problem_metadata = self.get_problem_metadata(submission.problem_id).await?;
+        let (best_objectives, new_best) = if has_relative_scoring(&problem_metadata) {
+            let previous: Vec<EvaluationResult> =
+                get_problem_evaluations(&self.get_collection(), &contest_id, submission.problem_id)
+                    .await?
+                    .into_iter()
+                    .map(|evaluation| evaluation.result)
+                    .collect();
+            let old_best = best_objectives(&problem_metadata, &previous);
+            let best = best_objectives(
+                &problem_metadata,
+                previous.iter().chain(std::iter::once(&evaluation_result)),
+            );
+            let new_best = best != old_best;
+            (best, new_best)
+        } else {
+            (HashMap::new(), false)
+        };
         let mut mut_evaluation_result = evaluation_result.clone();
-        evaluate_scores(&mut mut_evaluation_result, submission.problem_id).await?;
+        evaluate_scores(
+            &mut mut_evaluation_result,
+            &problem_metadata,
+            &best_objectives,
+        );
 
         // 3) write values returned by the dispatcher into database
         //    changing the state to EVALUATED
@@ -292,6 +867,22 @@ impl Submission for SubmissionService {
             .await
             .map_err(internal_error)?;
 
+        // the scores of the previous submissions are relative to the new best objectives
+        if new_best {
+            recompute_problem_scores(self.get_collection(), &contest_id, problem_metadata)
+                .await
+                .map_err(|err| {
+                    Status::new(
+                        err.code(),
+                        format!(
+                            "The submission was evaluated but the relative scores of the problem \
+                             were not updated, recompute them: {}",
+                            err.message()
+                        ),
+                    )
+                })?;
+        }
+
         Ok(Response::new(
             protos::service::submission::EvaluateSubmissionResponse {
                 res: mut_evaluation_result,
@@ -304,12 +895,27 @@ impl Submission for SubmissionService {
         &self,
         request: Request<GetSubmissionListRequest>,
     ) -> Result<Response<GetSubmissionListResponse>, Status> {
+        let role = auth::require(&request, auth::EVERYONE)?;
+        let contest_id = scope::contest_id(&request)?;
+        let participant = self.participant(&request, role).await?;
         let list_request = request.into_inner();
+        let restricted = participant.is_some() || list_request.participant();
         let opt_limit = list_request.limit;
-        let opt_user = list_request.user.clone();
+        // participants only see their own submissions
+        let opt_user = match (&participant, list_request.user.clone()) {
+            (Some(participant), Some(user)) => {
+                auth::check_user(Some(participant), &user)?;
+                Some(user)
+            }
+            (Some(participant), None) => Some(participant.clone()),
+            (None, user) => user,
+        };
         let opt_problem_id = list_request.problem_id;
 
-        let mut doc_filter = Document::new();
+        let selected = self
+            .get_selected(&contest_id, opt_user.as_deref(), opt_problem_id)
+            .await?;
+        let mut doc_filter = doc! { "contestId": contest_id.clone() };
         if let Some(user) = opt_user {
             doc_filter.insert("user", user);
         }
@@ -317,7 +923,7 @@ impl Submission for SubmissionService {
             doc_filter.insert("problemId", convert_to_i64(problem_id));
         }
 
-        let submissions = self
+        let mut submissions = self
             .get_collection()
             .find(
                 doc_filter,
@@ -329,7 +935,7 @@ impl Submission for SubmissionService {
             .map_err(internal_error)?
             .filter(|opt_submission| futures::future::ready(opt_submission.is_ok()))
             .map(|some_submission| match some_submission {
-                Ok(submission) => Some(conversions::get_item_from_doc(submission)),
+                Ok(submission) => Some(conversions::get_item_from_doc(submission, &selected)),
                 Err(_) => None,
             })
             .filter(|opt_item| futures::future::ready(opt_item.is_some()))
@@ -337,6 +943,17 @@ impl Submission for SubmissionService {
             .collect::<Vec<_>>()
             .await;
 
+        if restricted && !self.is_contest_ended(&contest_id).await? {
+            // the feedback does not apply to the submissions with a token
+            for item in submissions.iter_mut().filter(|item| !item.token()) {
+                if self.get_problem_feedback(item.problem_id).await?
+                    == problem::Feedback::AfterContest
+                {
+                    item.score = None;
+                }
+            }
+        }
+
         Ok(Response::new(GetSubmissionListResponse {
             list: submissions,
         }))
@@ -346,9 +963,20 @@ impl Submission for SubmissionService {
         &self,
         request: Request<GetSubmissionDetailsRequest>,
     ) -> Result<Response<GetSubmissionDetailsResponse>, Status> {
-        self
+        let role = auth::require(&request, auth::EVERYONE)?;
+        let contest_id = scope::contest_id(&request)?;
+        let participant = self.participant(&request, role).await?;
+        let details_request = request.into_inner();
+        let restricted = participant.is_some() || details_request.participant();
+        let mut response = self
             .get_collection()
-            .find_one(doc! { "_id": convert_to_i64(request.into_inner().submission_id) }, None)
+            .find_one(
+                doc! {
+                    "_id": convert_to_i64(details_request.submission_id),
+                    "contestId": contest_id.clone(),
+                },
+                None,
+            )
             .await
             .map_err(internal_error)?
             .map_or_else(|| Err(Status::new(
@@ -385,13 +1013,551 @@ impl Submission for SubmissionService {
                         },
                     },
                     state,
+                    token: document
+                        .get_timestamp("token")
+                        .ok()
+                        .map(|token| timestamp_to_systime(token).into()),
                     res: if state == SubmissionState::Evaluated as i32 {
                         Some(conversions::document_to_evaluation_result_struct(document))
                     } else {
                         None
                     },
                 }))
+            })?;
+
+        auth::check_user(participant.as_deref(), &response.get_ref().sub.user)?;
+        // admins see everything, participants what the problem and testcase feedback allows;
+        // a token on the submission lifts only the problem feedback
+        if restricted {
+            let details = response.get_mut();
+            if let Some(mut res) = details.res.take() {
+                let problem = self.get_problem_metadata(details.sub.problem_id).await?;
+                details.res = if details.token.is_some() {
+                    apply_testcase_feedback(&mut res, &problem);
+                    Some(res)
+                } else {
+                    apply_feedback(res, &problem, self.is_contest_ended(&contest_id).await?)
+                };
+            }
+        }
+        Ok(response)
+    }
+
+    async fn select_submission(
+        &self,
+        request: Request<SelectSubmissionRequest>,
+    ) -> Result<Response<SelectSubmissionResponse>, Status> {
+        let role = auth::require(&request, auth::PARTICIPANTS)?;
+        let contest_id = scope::contest_id(&request)?;
+        let participant = self.participant(&request, role).await?;
+        let select_request = request.into_inner();
+        auth::check_user(participant.as_deref(), &select_request.user)?;
+        let document = self
+            .get_collection()
+            .find_one(
+                doc! {
+                    "_id": convert_to_i64(select_request.submission_id),
+                    "user": select_request.user.clone(),
+                    "contestId": contest_id.clone(),
+                },
+                None,
+            )
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| {
+                Status::not_found("Submission id provided is not present in database")
+            })?;
+        let problem_id = document
+            .get_i64("problemId")
+            .unwrap_or_else(|_| panic!("{}", expected_field("problemId")));
+        if self
+            .get_problem_metadata(problem_id as u64)
+            .await?
+            .scoring
+            .method()
+            != scoring::problem::Method::Selected
+        {
+            return Err(Status::failed_precondition(
+                "The problem is not scored on the selected submission",
+            ));
+        }
+        if role == auth::Role::Participant
+            && !self
+                .get_user_window(&contest_id, &select_request.user)
+                .await?
+                .is_running(std::time::SystemTime::now())
+        {
+            return Err(Status::failed_precondition(
+                "The contest is not running for the user",
+            ));
+        }
+
+        self.get_selections_collection()
+            .update_one(
+                doc! { "_id": {
+                    "contestId": contest_id,
+                    "user": select_request.user,
+                    "problemId": problem_id,
+                } },
+                doc! { "$set": { "submissionId": convert_to_i64(select_request.submission_id) } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map_err(internal_error)?;
+        Ok(Response::new(SelectSubmissionResponse {}))
+    }
+
+    async fn get_problem_score(
+        &self,
+        request: Request<GetProblemScoreRequest>,
+    ) -> Result<Response<GetProblemScoreResponse>, Status> {
+        let role = auth::require(&request, auth::EVERYONE)?;
+        let contest_id = scope::contest_id(&request)?;
+        let participant = self.participant(&request, role).await?;
+        let score_request = request.into_inner();
+        auth::check_user(participant.as_deref(), &score_request.user)?;
+        let problem_metadata = self.get_problem_metadata(score_request.problem_id).await?;
+
+        let documents = self
+            .get_collection()
+            .find(
+                doc! {
+                    "user": score_request.user.clone(),
+                    "contestId": contest_id.clone(),
+                    "problemId": convert_to_i64(score_request.problem_id),
+                    "state": SubmissionState::Evaluated as i32,
+                },
+                FindOptions::builder().sort(doc! { "created": 1 }).build(),
+            )
+            .await
+            .map_err(internal_error)?
+            .try_collect::<Vec<Document>>()
+            .await
+            .map_err(internal_error)?;
+        let selected_ids = self
+            .get_selected(
+                &contest_id,
+                Some(&score_request.user),
+                Some(score_request.problem_id),
+            )
+            .await?;
+        let selected = documents.iter().position(
+            |document| matches!(document.get_i64("_id"), Ok(id) if selected_ids.contains(&id)),
+        );
+        let evaluations: Vec<EvaluationResult> = documents
+            .into_iter()
+            .map(conversions::document_to_evaluation_result_struct)
+            .collect();
+        let score = calc_problem_score(&evaluations, &problem_metadata.scoring, selected);
+
+        let hidden = (participant.is_some() || score_request.participant())
+            && problem_metadata.feedback() == problem::Feedback::AfterContest
+            && !self.is_contest_ended(&contest_id).await?;
+        Ok(Response::new(GetProblemScoreResponse {
+            score: Some(score).filter(|_| !hidden),
+            method: problem_metadata.scoring.method,
+        }))
+    }
+
+    async fn use_token(
+        &self,
+        request: Request<UseTokenRequest>,
+    ) -> Result<Response<UseTokenResponse>, Status> {
+        let role = auth::require(&request, auth::PARTICIPANTS)?;
+        let contest_id = scope::contest_id(&request)?;
+        let participant = self.participant(&request, role).await?;
+        let token_request = request.into_inner();
+        auth::check_user(participant.as_deref(), &token_request.user)?;
+        let document = self
+            .get_collection()
+            .find_one(
+                doc! {
+                    "_id": convert_to_i64(token_request.submission_id),
+                    "user": token_request.user.clone(),
+                    "contestId": contest_id.clone(),
+                },
+                None,
+            )
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| {
+                Status::not_found("Submission id provided is not present in database")
+            })?;
+        if document.get_timestamp("token").is_ok() {
+            return Err(Status::failed_precondition(
+                "A token was already used on the submission",
+            ));
+        }
+        if !self
+            .get_user_window(&contest_id, &token_request.user)
+            .await?
+            .is_running(std::time::SystemTime::now())
+        {
+            return Err(Status::failed_precondition(
+                "Tokens can be used only during the contest",
+            ));
+        }
+        let problem_id = document
+            .get_i64("problemId")
+            .unwrap_or_else(|_| panic!("{}", expected_field("problemId")))
+            as u64;
+        match self
+            .get_available_tokens(&contest_id, &token_request.user, problem_id)
+            .await?
+        {
+            None => {
+                return Err(Status::failed_precondition(
+                    "The contest does not use tokens",
+                ))
+            }
+            Some(0) => return Err(Status::failed_precondition("No tokens available")),
+            Some(_) => {}
+        }
+
+        // another request can use a token in the meantime, on the same submission
+        // or on another one, so the token is set only if missing and then counted
+        // again among the ones used before it
+        let submission_id = convert_to_i64(token_request.submission_id);
+        let token = systime_to_timestamp(std::time::SystemTime::now());
+        let result = self
+            .get_collection()
+            .update_one(
+                doc! { "_id": submission_id, "token": { "$exists": false } },
+                doc! { "$set": { "token": token } },
+                None,
+            )
+            .await
+            .map_err(internal_error)?;
+        if result.modified_count == 0 {
+            return Err(Status::failed_precondition(
+                "A token was already used on the submission",
+            ));
+        }
+        let contest = self.get_contest(&contest_id).await?;
+        let start_time = user_start(
+            &contest.metadata,
+            &self
+                .get_user_windows(&contest_id, Some(&token_request.user))
+                .await?,
+            &token_request.user,
+        );
+        let mut used = self
+            .get_used_tokens(&contest_id, &token_request.user)
+            .await?;
+        let position = used
+            .iter()
+            .position(|used| used.submission_id == submission_id)
+            .ok_or_else(|| internal_error("The token that was just used is missing"))?;
+        used.truncate(position);
+        let time = timestamp_to_systime(token);
+        if tokens_left(&contest, start_time, &used, problem_id, time) == Some(0) {
+            self.get_collection()
+                .update_one(
+                    doc! { "_id": submission_id, "token": token },
+                    doc! { "$unset": { "token": "" } },
+                    None,
+                )
+                .await
+                .map_err(internal_error)?;
+            return Err(Status::failed_precondition("No tokens available"));
+        }
+        Ok(Response::new(UseTokenResponse {}))
+    }
+
+    async fn get_tokens(
+        &self,
+        request: Request<GetTokensRequest>,
+    ) -> Result<Response<GetTokensResponse>, Status> {
+        let role = auth::require(&request, auth::EVERYONE)?;
+        let contest_id = scope::contest_id(&request)?;
+        let participant = self.participant(&request, role).await?;
+        let tokens_request = request.into_inner();
+        auth::check_user(participant.as_deref(), &tokens_request.user)?;
+        Ok(Response::new(GetTokensResponse {
+            available: self
+                .get_available_tokens(&contest_id, &tokens_request.user, tokens_request.problem_id)
+                .await?,
+        }))
+    }
+
+    async fn recompute_scores(
+        &self,
+        request: Request<RecomputeScoresRequest>,
+    ) -> Result<Response<RecomputeScoresResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
+        let contest_id = scope::contest_id(&request)?;
+        let mut problem_ids = request.into_inner().problem_ids;
+        if problem_ids.is_empty() {
+            problem_ids = self
+                .get_collection()
+                .distinct("problemId", doc! { "contestId": &contest_id }, None)
+                .await
+                .map_err(internal_error)?
+                .into_iter()
+                .filter_map(|id| id.as_i64())
+                .map(|id| id as u64)
+                .collect();
+        }
+        let mut changed = vec![];
+        for problem_id in problem_ids {
+            let _problem_lock = self.lock_problem(&contest_id, problem_id).await;
+            let problem_metadata = self.get_problem_metadata(problem_id).await?;
+            changed.extend(
+                recompute_problem_scores(self.get_collection(), &contest_id, problem_metadata)
+                    .await?,
+            );
+        }
+        let submissions = changed.len() as u32;
+        let users: std::collections::BTreeSet<String> = changed.into_iter().collect();
+        Ok(Response::new(RecomputeScoresResponse {
+            users: users.into_iter().collect(),
+            submissions,
+        }))
+    }
+
+    async fn export_results(
+        &self,
+        request: Request<ExportResultsRequest>,
+    ) -> Result<Response<ExportResultsResponse>, Status> {
+        auth::require(&request, auth::READERS)?;
+        let contest_id = scope::contest_id(&request)?;
+        let format = request.get_ref().format();
+        let contest = self.get_contest(&contest_id).await?;
+        let user_scoring = self.get_user_scoring(&contest_id).await?;
+        let windows = self.get_user_windows(&contest_id, None).await?;
+        let start_time = contest
+            .metadata
+            .start_time
+            .clone()
+            .map_or(std::time::UNIX_EPOCH, std::time::SystemTime::from);
+
+        let documents = self
+            .get_collection()
+            .find(
+                doc! { "contestId": contest_id.clone() },
+                FindOptions::builder().sort(doc! { "created": 1 }).build(),
+            )
+            .await
+            .map_err(internal_error)?
+            .try_collect::<Vec<Document>>()
+            .await
+            .map_err(internal_error)?;
+        let selected = self.get_selected(&contest_id, None, None).await?;
+        let mut problems = std::collections::BTreeMap::new();
+        let mut submissions = Vec::with_capacity(documents.len());
+        let mut evaluated = std::collections::BTreeMap::new();
+        for document in documents {
+            let item = conversions::get_item_from_doc(document.clone(), &selected);
+            let time = std::time::SystemTime::from(item.timestamp.clone());
+            let language = document.get_i32("programmingLanguage").unwrap_or_default();
+            if !problems.contains_key(&item.problem_id) {
+                problems.insert(
+                    item.problem_id,
+                    self.get_problem_metadata(item.problem_id).await?,
+                );
+            }
+            let mut submission = export::Submission {
+                id: item.submission_id,
+                user: item.user.clone(),
+                problem_id: item.problem_id,
+                language: common::ProgrammingLanguage::from_i32(language).unwrap_or_default(),
+                time,
+                verdict: None,
+                score: None,
+            };
+            if item.state() == SubmissionState::Evaluated {
+                let evaluation = conversions::document_to_evaluation_result_struct(document);
+                submission.verdict = Some(export_verdict(&evaluation, &problems[&item.problem_id]));
+                submission.score = Some(evaluation.score.score);
+                let elapsed = time
+                    .duration_since(user_start(&contest.metadata, &windows, &item.user))
+                    .unwrap_or_default();
+                let selected = item.selected();
+                evaluated
+                    .entry((item.user, item.problem_id))
+                    .or_insert_with(Vec::new)
+                    .push((elapsed, selected, evaluation));
+            }
+            submissions.push(submission);
+        }
+
+        let standings = if user_scoring.icpc.is_some() {
+            let scoreboard = self
+                .get_icpc_scoreboard(auth::forward(
+                    &request,
+                    GetIcpcScoreboardRequest { participant: None },
+                ))
+                .await?
+                .into_inner()
+                .users;
+            export::icpc_standings(&scoreboard)
+        } else {
+            let mut users: Vec<(String, Vec<UserProblemResult>)> = vec![];
+            for ((user, problem_id), submissions) in evaluated {
+                let result = user_problem_result(&problems[&problem_id], &submissions);
+                match users.last_mut() {
+                    Some((last, results)) if *last == user => results.push(result),
+                    _ => users.push((user, vec![result])),
+                }
+            }
+            let ranking = calc_user_ranking(&users, &user_scoring);
+            export::score_standings(&ranking, &users)
+        };
+
+        // the problems with submissions but no longer in the contest are listed by id
+        let mut exported_problems: Vec<export::Problem> = contest
+            .problems
+            .iter()
+            .map(|problem| export::Problem {
+                id: problem.id,
+                name: problem.name.clone(),
+                long_name: problem.long_name.clone(),
+            })
+            .collect();
+        for &problem_id in problems.keys() {
+            if exported_problems
+                .iter()
+                .all(|problem| problem.id != problem_id)
+            {
+                exported_problems.push(export::Problem {
+                    id: problem_id,
+                    name: problem_id.to_string(),
+                    long_name: problem_id.to_string(),
+                });
+            }
+        }
+
+        let results = Results {
+            contest: export::Contest {
+                name: contest.metadata.name,
+                start_time,
+                end_time: contest.metadata.end_time.map(std::time::SystemTime::from),
+                freeze_minutes: contest
+                    .metadata
+                    .freeze
+                    .map(|freeze| std::time::Duration::from(freeze).as_secs() / 60),
+                penalty_minutes: user_scoring.icpc.as_ref().map(|icpc| icpc.penalty_minutes),
+                compilation_errors_penalized: user_scoring
+                    .icpc
+                    .as_ref()
+                    .map_or(false, |icpc| icpc.count_compilation_errors),
+            },
+            problems: exported_problems,
+            teams: self
+                .get_teams(&contest_id)
+                .await?
+                .into_iter()
+                .map(|team| export::Team {
+                    id: team.id,
+                    name: team.name,
+                    affiliation: team.affiliation,
+                    country: team.country,
+                })
+                .collect(),
+            standings,
+            submissions,
+        };
+        let data = match format {
+            export_results_request::Format::StandingsCsv => export::standings_csv(&results),
+            export_results_request::Format::SubmissionsCsv => export::submissions_csv(&results),
+            export_results_request::Format::Json => export::results_json(&results),
+            export_results_request::Format::ClicsEventFeed => {
+                export::clics_event_feed(&results, std::time::SystemTime::now())
+            }
+        };
+        Ok(Response::new(ExportResultsResponse {
+            data: data.into_bytes(),
+        }))
+    }
+
+    async fn get_icpc_scoreboard(
+        &self,
+        request: Request<GetIcpcScoreboardRequest>,
+    ) -> Result<Response<GetIcpcScoreboardResponse>, Status> {
+        let role = auth::require(&request, auth::EVERYONE)?;
+        let contest_id = scope::contest_id(&request)?;
+        let scoreboard_request = request.into_inner();
+        let icpc = self
+            .get_user_scoring(&contest_id)
+            .await?
+            .icpc
+            .ok_or_else(|| Status::failed_precondition("The contest does not use ICPC scoring"))?;
+        let metadata = self.get_contest_metadata(&contest_id).await?;
+        let windows = self.get_user_windows(&contest_id, None).await?;
+        // only the staff sees the results after the freeze, the others see them when the
+        // resolver reveals them
+        let freeze = if !auth::STAFF.contains(&role) || scoreboard_request.participant() {
+            Some(self.get_scoreboard_freeze(&contest_id).await?).filter(|freeze| !freeze.unfrozen)
+        } else {
+            None
+        };
+        let is_hidden = |user: &str, problem_id: u64, created: std::time::SystemTime| {
+            freeze.as_ref().map_or(false, |freeze| {
+                freeze.freeze_time.clone().map_or(false, |freeze_time| {
+                    created >= std::time::SystemTime::from(freeze_time)
+                }) && !freeze
+                    .revealed
+                    .iter()
+                    .any(|result| result.user == user && result.problem_id == problem_id)
             })
+        };
+
+        let documents = self
+            .get_collection()
+            .find(
+                doc! { "contestId": contest_id, "state": SubmissionState::Evaluated as i32 },
+                FindOptions::builder().sort(doc! { "created": 1 }).build(),
+            )
+            .await
+            .map_err(internal_error)?
+            .try_collect::<Vec<Document>>()
+            .await
+            .map_err(internal_error)?;
+        let mut problems = std::collections::HashMap::new();
+        let mut submissions = Vec::with_capacity(documents.len());
+        for document in documents {
+            let user = document
+                .get_str("user")
+                .unwrap_or_else(|_| panic!("{}", expected_field("user")))
+                .to_string();
+            let problem_id = document
+                .get_i64("problemId")
+                .unwrap_or_else(|_| panic!("{}", expected_field("problemId")))
+                as u64;
+            let created = timestamp_to_systime(
+                document
+                    .get_timestamp("created")
+                    .unwrap_or_else(|_| panic!("{}", expected_field("created"))),
+            );
+            if !problems.contains_key(&problem_id) {
+                problems.insert(problem_id, self.get_problem_metadata(problem_id).await?);
+            }
+            let evaluation = conversions::document_to_evaluation_result_struct(document);
+            let verdict = if is_hidden(&user, problem_id, created) {
+                IcpcVerdict::Pending
+            } else if evaluation.compilation_result.outcome()
+                != compilation_result::Outcome::Success
+            {
+                IcpcVerdict::CompilationError
+            } else if is_max_score(&evaluation.score, &problems[&problem_id]) {
+                IcpcVerdict::Accepted
+            } else {
+                IcpcVerdict::Rejected
+            };
+            let elapsed = created
+                .duration_since(user_start(&metadata, &windows, &user))
+                .unwrap_or_default();
+            submissions.push(IcpcSubmission {
+                user,
+                problem_id,
+                elapsed,
+                verdict,
+            });
+        }
+
+        Ok(Response::new(GetIcpcScoreboardResponse {
+            users: calc_icpc_scoreboard(&submissions, &icpc),
+        }))
     }
 }
 
@@ -402,7 +1568,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("Starting submission server");
     Server::builder()
-        .add_service(SubmissionServer::new(submission_service))
+        .add_service(SubmissionServer::with_interceptor(
+            submission_service,
+            auth::RoleCheck::from_env()?,
+        ))
         .serve(addr)
         .await?;
     Ok(())
@@ -1,3 +1,4 @@
+use protos::common::Score;
 use protos::{
     self,
     common::Resources,
@@ -8,10 +9,6 @@ use protos::{
     service::dispatcher::{EvaluateSubmissionResponse, MockDispatcher},
     *,
 };
-use protos::{
-    common::Score,
-    service::evaluation::{problem, GetProblemResponse, MockEvaluation},
-};
 use rand::Rng;
 
 fn generate_testcase_result() -> TestcaseResult {
@@ -20,18 +17,25 @@ fn generate_testcase_result() -> TestcaseResult {
     TestcaseResult {
         outcome,
         score: if outcome == testcase_result::Outcome::Ok as i32 {
-            Score { score: 1.0 }
+            Score {
+                score: 1.0,
+                ..Default::default()
+            }
         } else {
-            Score { score: 0.0 }
+            Score {
+                score: 0.0,
+                ..Default::default()
+            }
         },
-        used_resources: Resources {
+        used_resources: Some(Resources {
             time: common::Duration {
                 secs: gen.gen(),
                 nanos: gen.gen(),
             },
             memory_bytes: gen.gen(),
-        },
+        }),
         id: 1u64,
+        objective: None,
     }
 }
 
@@ -49,17 +53,6 @@ fn generate_subtask_result() -> SubtaskResult {
     }
 }
 
-fn generate_min_subtask_scoring() -> protos::service::evaluation::Subtask {
-    protos::service::evaluation::Subtask {
-        id: 0u64,
-        scoring: scoring::Subtask {
-            method: scoring::subtask::Method::Min as i32,
-            max_score: common::Score { score: 20f64 },
-        },
-        testcases_id: vec![], // now useless
-    }
-}
-
 fn mock_dispatcher_init(mock_dispatcher: &mut MockDispatcher) {
     mock_dispatcher.evaluate_submission_set(EvaluateSubmissionResponse {
         res: EvaluationResult {
@@ -81,41 +74,11 @@ fn mock_dispatcher_init(mock_dispatcher: &mut MockDispatcher) {
                 generate_subtask_result(),
                 generate_subtask_result(),
             ],
-            score: Score { score: 0f64 },
-        },
-    });
-}
-
-fn mock_evaluation_init(mock_evaluation_server: &mut MockEvaluation, problem_id: u64) {
-    mock_evaluation_server.get_problem_set(GetProblemResponse {
-        info: protos::service::evaluation::Problem {
-            id: problem_id,
-            scoring: scoring::Problem {
-                method: scoring::problem::Method::MaxSum as i32,
-            },
-            r#type: problem::Type::Other as i32,
-            execution_limits: Resources {
-                time: common::Duration {
-                    secs: 0u64,
-                    nanos: 1u32,
-                },
-                memory_bytes: 1u64,
-            },
-            compilation_limits: Resources {
-                time: common::Duration {
-                    secs: 0u64,
-                    nanos: 1u32,
-                },
-                memory_bytes: 1u64,
+            score: Score {
+                score: 0f64,
+                ..Default::default()
             },
-            subtasks: vec![
-                generate_min_subtask_scoring(),
-                generate_min_subtask_scoring(),
-                generate_min_subtask_scoring(),
-                generate_min_subtask_scoring(),
-                generate_min_subtask_scoring(),
-                generate_min_subtask_scoring(),
-            ],
+            problem_version: Some(1),
         },
     });
 }
@@ -125,9 +88,3 @@ pub fn get_mock_dispatcher() -> MockDispatcher {
     mock_dispatcher_init(&mut mock);
     mock
 }
-
-pub fn get_mock_evaluation(problem_id: u64) -> MockEvaluation {
-    let mut mock = MockEvaluation::default();
-    mock_evaluation_init(&mut mock, problem_id);
-    mock
-}
@@ -4,3 +4,53 @@
 
 #[test]
 fn string_test() {}
+
+#[test]
+fn exact_scores_round_trip_test() {
+    use super::conversions::{
+        document_to_evaluation_result_struct, insert_evaluation_data_into_document,
+    };
+    use mongodb::bson::Document;
+    use protos::common::{Fraction, Score};
+    use protos::evaluation::*;
+    let third = Score {
+        score: 1.0 / 3.0,
+        exact: Some(Fraction {
+            numerator: 1,
+            denominator: 3,
+        }),
+    };
+    let result = EvaluationResult {
+        compilation_result: CompilationResult {
+            outcome: compilation_result::Outcome::Success as i32,
+            ..Default::default()
+        },
+        subtask_results: vec![SubtaskResult {
+            id: 1,
+            score: third.clone(),
+            testcase_results: vec![
+                TestcaseResult {
+                    id: 1,
+                    score: third.clone(),
+                    objective: Some(third.clone()),
+                    used_resources: Some(Default::default()),
+                    ..Default::default()
+                },
+                TestcaseResult {
+                    id: 2,
+                    score: Score {
+                        score: 0.5,
+                        exact: None,
+                    },
+                    used_resources: Some(Default::default()),
+                    ..Default::default()
+                },
+            ],
+        }],
+        score: third,
+        problem_version: Some(1),
+    };
+    let mut doc = Document::new();
+    insert_evaluation_data_into_document(&mut doc, &result);
+    assert_eq!(document_to_evaluation_result_struct(doc), result);
+}
@@ -0,0 +1,118 @@
+use std::io::{Read, Write};
+
+use protos::common::FileChunk;
+use sha2::{Digest, Sha256};
+
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+pub fn checksum(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Splits the content of a reader in chunks of at most `CHUNK_SIZE` bytes.
+/// At least one chunk is always produced, the last one carries the checksum.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    hasher: Sha256,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            hasher: Sha256::new(),
+            buffer: vec![],
+            done: false,
+        }
+    }
+
+    fn fill_buffer(&mut self) -> Result<(), std::io::Error> {
+        let mut limited = (&mut self.reader).take((CHUNK_SIZE - self.buffer.len()) as u64);
+        limited.read_to_end(&mut self.buffer)?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<FileChunk, std::io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Err(e) = self.fill_buffer() {
+            self.done = true;
+            return Some(Err(e));
+        }
+        let data = std::mem::take(&mut self.buffer);
+        self.hasher.update(&data);
+        // peek one more read to know whether this is the last chunk
+        if let Err(e) = self.fill_buffer() {
+            self.done = true;
+            return Some(Err(e));
+        }
+        let checksum = if self.buffer.is_empty() {
+            self.done = true;
+            Some(hex::encode(self.hasher.clone().finalize()))
+        } else {
+            None
+        };
+        Some(Ok(FileChunk { data, checksum }))
+    }
+}
+
+/// Writes a sequence of chunks, checking the checksum once the last one arrives
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+    hasher: Sha256,
+    complete: bool,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            hasher: Sha256::new(),
+            complete: false,
+        }
+    }
+
+    pub fn write_chunk(&mut self, chunk: &FileChunk) -> Result<(), std::io::Error> {
+        if self.complete {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Chunk received after the last one",
+            ));
+        }
+        self.hasher.update(&chunk.data);
+        self.writer.write_all(&chunk.data)?;
+        if let Some(checksum) = &chunk.checksum {
+            if hex::encode(self.hasher.clone().finalize()) != *checksum {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Checksum mismatch",
+                ));
+            }
+            self.complete = true;
+        }
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Returns the inner writer, failing if the last chunk was never received
+    pub fn finish(mut self) -> Result<W, std::io::Error> {
+        if !self.complete {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "Missing last chunk",
+            ));
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
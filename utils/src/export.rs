@@ -0,0 +1,562 @@
+//! Results of a contest in the formats read by other tools: CSV for the organizers,
+//! JSON for archives and the event feed of the CLICS Contest API for ICPC tools.
+
+use crate::scoring::UserProblemResult;
+use protos::common::ProgrammingLanguage;
+use protos::service::submission::get_icpc_scoreboard_response::User as IcpcUser;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, SystemTime};
+
+// id of the contest in the CLICS event feed, there is only one
+const CLICS_CONTEST_ID: &str = "contest";
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Contest {
+    pub name: String,
+    #[serde(serialize_with = "serialize_time")]
+    pub start_time: SystemTime,
+    #[serde(serialize_with = "serialize_optional_time")]
+    pub end_time: Option<SystemTime>,
+    pub freeze_minutes: Option<u64>,  // before the end
+    pub penalty_minutes: Option<u32>, // only with ICPC scoring
+    pub compilation_errors_penalized: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Problem {
+    pub id: u64,
+    pub name: String,
+    pub long_name: String,
+}
+
+/// Team of an ICPC contest, see contest.Team
+#[derive(Serialize, Clone, Debug)]
+pub struct Team {
+    pub id: String,
+    pub name: String,
+    pub affiliation: Option<String>,
+    pub country: Option<String>, // ISO 3166-1 alpha-3
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Accepted, // maximum score of the problem
+    WrongAnswer,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    RuntimeError,
+    CompilationError,
+}
+impl Verdict {
+    fn clics_id(self) -> &'static str {
+        match self {
+            Verdict::Accepted => "AC",
+            Verdict::WrongAnswer => "WA",
+            Verdict::TimeLimitExceeded => "TLE",
+            Verdict::MemoryLimitExceeded => "MLE",
+            Verdict::RuntimeError => "RTE",
+            Verdict::CompilationError => "CE",
+        }
+    }
+    fn clics_name(self) -> &'static str {
+        match self {
+            Verdict::Accepted => "correct",
+            Verdict::WrongAnswer => "wrong answer",
+            Verdict::TimeLimitExceeded => "time limit exceeded",
+            Verdict::MemoryLimitExceeded => "memory limit exceeded",
+            Verdict::RuntimeError => "run-time error",
+            Verdict::CompilationError => "compiler error",
+        }
+    }
+    fn iter() -> impl Iterator<Item = Verdict> {
+        [
+            Verdict::Accepted,
+            Verdict::WrongAnswer,
+            Verdict::TimeLimitExceeded,
+            Verdict::MemoryLimitExceeded,
+            Verdict::RuntimeError,
+            Verdict::CompilationError,
+        ]
+        .iter()
+        .copied()
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Submission {
+    pub id: u64,
+    pub user: String,
+    pub problem_id: u64,
+    #[serde(serialize_with = "serialize_language")]
+    pub language: ProgrammingLanguage,
+    #[serde(serialize_with = "serialize_time")]
+    pub time: SystemTime,
+    pub verdict: Option<Verdict>, // not set while pending
+    pub score: Option<f64>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct StandingProblem {
+    pub problem_id: u64,
+    pub score: f64, // 1 if solved, with ICPC scoring
+    pub rejected: u32,
+    pub solved_minutes: Option<u64>, // only with ICPC scoring
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Standing {
+    pub rank: u32, // shared by the users with the same scores
+    pub user: String,
+    pub score: f64,                   // solved problems, with ICPC scoring
+    pub penalty_minutes: Option<u64>, // only with ICPC scoring
+    pub tiebreakers: Vec<f64>,
+    pub problems: Vec<StandingProblem>, // only the attempted ones
+}
+
+/// Everything exported, submissions in chronological order
+#[derive(Serialize, Clone, Debug)]
+pub struct Results {
+    pub contest: Contest,
+    pub problems: Vec<Problem>,
+    pub teams: Vec<Team>, // the users not in a team are exported by their username
+    pub standings: Vec<Standing>,
+    pub submissions: Vec<Submission>,
+}
+
+fn serialize_time<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&absolute_time(*time))
+}
+
+fn serialize_optional_time<S: Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serialize_time(time, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_language<S: Serializer>(
+    language: &ProgrammingLanguage,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&language.to_string())
+}
+
+// ISO 8601, as in the CLICS specification
+fn absolute_time(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time)
+        .format("%Y-%m-%dT%H:%M:%S%.3f+00:00")
+        .to_string()
+}
+
+// h:mm:ss.uuu, as in the CLICS specification
+fn relative_time(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        duration.subsec_millis()
+    )
+}
+
+// competition ranking: users with the same key share the rank, the next one skips the ties
+fn assign_ranks<K: PartialEq>(standings: &mut [Standing], key: impl Fn(&Standing) -> K) {
+    for i in 0..standings.len() {
+        standings[i].rank = if i > 0 && key(&standings[i]) == key(&standings[i - 1]) {
+            standings[i - 1].rank
+        } else {
+            i as u32 + 1
+        };
+    }
+}
+
+/// Standings from the ICPC scoreboard, see scoring::calc_icpc_scoreboard
+pub fn icpc_standings(scoreboard: &[IcpcUser]) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = scoreboard
+        .iter()
+        .map(|user| Standing {
+            user: user.user.clone(),
+            score: user.solved as f64,
+            penalty_minutes: Some(user.penalty_minutes),
+            problems: user
+                .problems
+                .iter()
+                .map(|problem| StandingProblem {
+                    problem_id: problem.problem_id,
+                    score: if problem.solved_at.is_some() {
+                        1.0
+                    } else {
+                        0.0
+                    },
+                    rejected: problem.rejected,
+                    solved_minutes: problem
+                        .solved_at
+                        .clone()
+                        .map(|solved_at| Duration::from(solved_at).as_secs() / 60),
+                })
+                .collect(),
+            ..Default::default()
+        })
+        .collect();
+    assign_ranks(&mut standings, |standing| {
+        (standing.score as u32, standing.penalty_minutes)
+    });
+    standings
+}
+
+/// Standings from the ranking of scoring::calc_user_ranking and the results it was computed from
+pub fn score_standings(
+    ranking: &[(String, Vec<protos::common::Score>)],
+    results: &[(String, Vec<UserProblemResult>)],
+) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = ranking
+        .iter()
+        .map(|(user, scores)| Standing {
+            user: user.clone(),
+            score: scores.first().map_or(0.0, |score| score.score),
+            tiebreakers: scores.iter().skip(1).map(|score| score.score).collect(),
+            problems: results
+                .iter()
+                .find(|(u, _)| u == user)
+                .map(|(_, problems)| {
+                    problems
+                        .iter()
+                        .map(|problem| StandingProblem {
+                            problem_id: problem.problem_id,
+                            score: problem.score.score,
+                            rejected: problem.wrong_submissions,
+                            solved_minutes: None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ..Default::default()
+        })
+        .collect();
+    assign_ranks(&mut standings, |standing| {
+        (standing.score, standing.tiebreakers.clone())
+    });
+    standings
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_line(fields: impl IntoIterator<Item = String>) -> String {
+    let fields: Vec<String> = fields.into_iter().map(|field| csv_field(&field)).collect();
+    fields.join(",") + "\n"
+}
+
+/// One line per user, with a column per problem (two with ICPC scoring: tries and minutes)
+pub fn standings_csv(results: &Results) -> String {
+    let icpc = results.contest.penalty_minutes.is_some();
+    let tiebreakers = results
+        .standings
+        .iter()
+        .map(|standing| standing.tiebreakers.len())
+        .max()
+        .unwrap_or(0);
+    let mut header = vec![String::from("rank"), String::from("user")];
+    if icpc {
+        header.extend([String::from("solved"), String::from("penalty")]);
+    } else {
+        header.push(String::from("score"));
+        header.extend((1..=tiebreakers).map(|i| format!("tiebreaker {}", i)));
+    }
+    for problem in results.problems.iter() {
+        if icpc {
+            header.push(format!("{} tries", problem.name));
+            header.push(format!("{} time", problem.name));
+        } else {
+            header.push(problem.name.clone());
+        }
+    }
+    let mut csv = csv_line(header);
+    for standing in results.standings.iter() {
+        let mut line = vec![standing.rank.to_string(), standing.user.clone()];
+        line.push(standing.score.to_string());
+        if icpc {
+            line.push(standing.penalty_minutes.unwrap_or(0).to_string());
+        } else {
+            line.extend((0..tiebreakers).map(|i| {
+                standing
+                    .tiebreakers
+                    .get(i)
+                    .map(f64::to_string)
+                    .unwrap_or_default()
+            }));
+        }
+        for problem in results.problems.iter() {
+            let result = standing
+                .problems
+                .iter()
+                .find(|p| p.problem_id == problem.id);
+            if icpc {
+                let tries = result.map_or(0, |p| p.rejected + p.solved_minutes.is_some() as u32);
+                line.push(tries.to_string());
+                line.push(
+                    result
+                        .and_then(|p| p.solved_minutes)
+                        .map(|minutes| minutes.to_string())
+                        .unwrap_or_default(),
+                );
+            } else {
+                line.push(result.map_or(0.0, |p| p.score).to_string());
+            }
+        }
+        csv += &csv_line(line);
+    }
+    csv
+}
+
+/// One line per submission, in chronological order
+pub fn submissions_csv(results: &Results) -> String {
+    let mut csv = csv_line(
+        [
+            "id",
+            "time",
+            "contest time",
+            "user",
+            "problem",
+            "language",
+            "verdict",
+            "score",
+        ]
+        .iter()
+        .map(|s| s.to_string()),
+    );
+    for submission in results.submissions.iter() {
+        let problem = results
+            .problems
+            .iter()
+            .find(|p| p.id == submission.problem_id)
+            .map_or_else(|| submission.problem_id.to_string(), |p| p.name.clone());
+        csv += &csv_line([
+            submission.id.to_string(),
+            absolute_time(submission.time),
+            relative_time(contest_time(results, submission.time)),
+            submission.user.clone(),
+            problem,
+            submission.language.to_string(),
+            submission
+                .verdict
+                .map_or_else(|| String::from("Pending"), |v| format!("{:?}", v)),
+            submission.score.map(|s| s.to_string()).unwrap_or_default(),
+        ]);
+    }
+    csv
+}
+
+/// The whole results, for archives
+pub fn results_json(results: &Results) -> String {
+    serde_json::to_string_pretty(results).expect("results are always serializable")
+}
+
+fn contest_time(results: &Results, time: SystemTime) -> Duration {
+    time.duration_since(results.contest.start_time)
+        .unwrap_or_default()
+}
+
+fn language_id(language: ProgrammingLanguage) -> String {
+    language.to_string().to_lowercase()
+}
+
+fn language_name(language: ProgrammingLanguage) -> &'static str {
+    match language {
+        ProgrammingLanguage::None => "None",
+        ProgrammingLanguage::Rust => "Rust",
+        ProgrammingLanguage::Cpp => "C++",
+    }
+}
+
+// CLICS ids only contain letters, digits, '-', '_' and '.'
+fn organization_id(affiliation: &str) -> String {
+    affiliation
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Event feed of the CLICS Contest API, one JSON event per line, as of `now`.
+/// Judgements have the time of their submission, as the evaluation time is not stored
+pub fn clics_event_feed(results: &Results, now: SystemTime) -> String {
+    let contest = &results.contest;
+    let mut events: Vec<(&str, Value, Value)> = vec![];
+
+    let mut contest_data = json!({
+        "id": CLICS_CONTEST_ID,
+        "name": contest.name,
+        "formal_name": contest.name,
+        "start_time": absolute_time(contest.start_time),
+        "penalty_time": contest.penalty_minutes.unwrap_or(0),
+    });
+    if let Some(end_time) = contest.end_time {
+        let duration = end_time
+            .duration_since(contest.start_time)
+            .unwrap_or_default();
+        contest_data["duration"] = json!(relative_time(duration));
+    }
+    if let Some(freeze_minutes) = contest.freeze_minutes {
+        contest_data["scoreboard_freeze_duration"] =
+            json!(relative_time(Duration::from_secs(freeze_minutes * 60)));
+    }
+    events.push(("contests", json!(CLICS_CONTEST_ID), contest_data));
+
+    for verdict in Verdict::iter() {
+        let penalty = match verdict {
+            Verdict::Accepted => false,
+            Verdict::CompilationError => contest.compilation_errors_penalized,
+            _ => true,
+        };
+        events.push((
+            "judgement-types",
+            json!(verdict.clics_id()),
+            json!({
+                "id": verdict.clics_id(),
+                "name": verdict.clics_name(),
+                "penalty": penalty,
+                "solved": verdict == Verdict::Accepted,
+            }),
+        ));
+    }
+
+    let languages: BTreeSet<ProgrammingLanguage> =
+        results.submissions.iter().map(|s| s.language).collect();
+    for language in languages {
+        events.push((
+            "languages",
+            json!(language_id(language)),
+            json!({
+                "id": language_id(language),
+                "name": language_name(language),
+            }),
+        ));
+    }
+
+    for (ordinal, problem) in results.problems.iter().enumerate() {
+        events.push((
+            "problems",
+            json!(problem.id.to_string()),
+            json!({
+                "id": problem.id.to_string(),
+                "label": problem.name,
+                "name": problem.long_name,
+                "ordinal": ordinal,
+            }),
+        ));
+    }
+
+    let organizations: BTreeMap<&String, &Option<String>> = results
+        .teams
+        .iter()
+        .filter_map(|team| Some((team.affiliation.as_ref()?, &team.country)))
+        .collect();
+    for (affiliation, country) in organizations {
+        let mut data = json!({
+            "id": organization_id(affiliation),
+            "name": affiliation,
+            "formal_name": affiliation,
+        });
+        if let Some(country) = country {
+            data["country"] = json!(country);
+        }
+        events.push(("organizations", json!(organization_id(affiliation)), data));
+    }
+
+    let users: BTreeSet<&String> = results
+        .standings
+        .iter()
+        .map(|s| &s.user)
+        .chain(results.submissions.iter().map(|s| &s.user))
+        .collect();
+    for user in users {
+        let team = results.teams.iter().find(|team| &team.id == user);
+        let mut data = json!({
+            "id": user,
+            "label": user,
+            "name": team.map_or(user, |team| &team.name),
+        });
+        if let Some(affiliation) = team.and_then(|team| team.affiliation.as_ref()) {
+            data["organization_id"] = json!(organization_id(affiliation));
+        }
+        events.push(("teams", json!(user), data));
+    }
+
+    for submission in results.submissions.iter() {
+        let time = absolute_time(submission.time);
+        let elapsed = relative_time(contest_time(results, submission.time));
+        events.push((
+            "submissions",
+            json!(submission.id.to_string()),
+            json!({
+                "id": submission.id.to_string(),
+                "language_id": language_id(submission.language),
+                "problem_id": submission.problem_id.to_string(),
+                "team_id": submission.user,
+                "time": time,
+                "contest_time": elapsed,
+                "files": [],
+            }),
+        ));
+        if let Some(verdict) = submission.verdict {
+            events.push((
+                "judgements",
+                json!(submission.id.to_string()),
+                json!({
+                    "id": submission.id.to_string(),
+                    "submission_id": submission.id.to_string(),
+                    "judgement_type_id": verdict.clics_id(),
+                    "start_time": time,
+                    "start_contest_time": elapsed,
+                    "end_time": time,
+                    "end_contest_time": elapsed,
+                }),
+            ));
+        }
+    }
+
+    let started = Some(contest.start_time).filter(|&start| start <= now);
+    let ended = contest.end_time.filter(|&end| end <= now);
+    let frozen = contest
+        .end_time
+        .zip(contest.freeze_minutes)
+        .map(|(end, minutes)| end - Duration::from_secs(minutes * 60))
+        .filter(|&frozen| frozen <= now);
+    events.push((
+        "state",
+        Value::Null,
+        json!({
+            "started": started.map(absolute_time),
+            "frozen": frozen.map(absolute_time),
+            "ended": ended.map(absolute_time),
+            "finalized": ended.map(absolute_time),
+            "end_of_updates": ended.map(absolute_time),
+        }),
+    ));
+
+    events
+        .into_iter()
+        .enumerate()
+        .map(|(token, (kind, id, data))| {
+            json!({ "type": kind, "id": id, "data": data, "token": token.to_string() }).to_string()
+                + "\n"
+        })
+        .collect()
+}
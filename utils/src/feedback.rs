@@ -0,0 +1,82 @@
+//! What participants get to see of the evaluation of their submissions,
+//! according to the feedback of the problem and of its testcases.
+
+use protos::evaluation::{testcase_result, EvaluationResult};
+use protos::service::evaluation::problem::Feedback;
+use protos::service::evaluation::testcase_info::Feedback as TestcaseFeedback;
+use protos::service::evaluation::Problem;
+
+/// Feedback of a testcase of the problem, None if the testcase is not in the problem.
+/// Samples are always shown in full
+fn testcase_feedback(problem: &Problem, testcase_id: u64) -> Option<TestcaseFeedback> {
+    if !problem
+        .subtasks
+        .iter()
+        .any(|subtask| subtask.testcases_id.contains(&testcase_id))
+    {
+        return None;
+    }
+    Some(
+        problem
+            .testcases_info
+            .iter()
+            .find(|info| info.testcase_id == testcase_id)
+            .map_or(TestcaseFeedback::Full, |info| {
+                if info.sample() {
+                    TestcaseFeedback::Full
+                } else {
+                    info.feedback()
+                }
+            }),
+    )
+}
+
+/// Removes the results of the hidden testcases, and of the ones not in the problem anymore,
+/// and the used resources of the testcases showing only the outcome. It applies to every
+/// submission shown to a participant, even with a token
+pub fn apply_testcase_feedback(result: &mut EvaluationResult, problem: &Problem) {
+    for subtask in result.subtask_results.iter_mut() {
+        subtask.testcase_results.retain(|testcase| {
+            matches!(testcase_feedback(problem, testcase.id), Some(feedback) if feedback != TestcaseFeedback::Hidden)
+        });
+        for testcase in subtask.testcase_results.iter_mut() {
+            if testcase_feedback(problem, testcase.id) == Some(TestcaseFeedback::Outcome) {
+                testcase.used_resources = None;
+            }
+        }
+    }
+}
+
+/// Evaluation result as shown to a participant, None if nothing can be shown
+pub fn apply_feedback(
+    mut result: EvaluationResult,
+    problem: &Problem,
+    contest_ended: bool,
+) -> Option<EvaluationResult> {
+    apply_testcase_feedback(&mut result, problem);
+    match problem.feedback() {
+        Feedback::Full => {}
+        Feedback::FirstFailing => {
+            let mut failed = false;
+            for subtask in result.subtask_results.iter_mut() {
+                subtask.testcase_results.retain(|testcase| {
+                    let keep = !failed && testcase.outcome() != testcase_result::Outcome::Ok;
+                    failed |= keep;
+                    keep
+                });
+            }
+        }
+        Feedback::SubtaskScores => {
+            for subtask in result.subtask_results.iter_mut() {
+                subtask.testcase_results.clear();
+            }
+        }
+        Feedback::FinalScore => result.subtask_results.clear(),
+        Feedback::AfterContest => {
+            if !contest_ended {
+                return None;
+            }
+        }
+    }
+    Some(result)
+}
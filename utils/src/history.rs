@@ -0,0 +1,265 @@
+//! Immutable versions of the content of a folder.
+//!
+//! Every version lists the hash of each tracked file, the content is kept once
+//! per hash in a blob folder. Versions are added as part of a transaction, so a
+//! version exists if and only if the changes it describes were applied.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunks::checksum;
+use crate::storage::{FsStorageHelper, Storage};
+use crate::transaction::{self, Transaction};
+
+const HISTORY_FOLDER_NAME: &str = "history";
+const BLOBS_FOLDER_NAME: &str = "blobs";
+const VERSIONS_FOLDER_NAME: &str = "versions";
+const VERSION_EXTENSION: &str = "ser";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Version {
+    pub version: u64,
+    pub created: SystemTime,
+    pub description: String,
+    // content hash of every tracked file, by path relative to the folder
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// Files that differ between two versions, sorted by path
+pub fn diff(from: &Version, to: &Version) -> Vec<(PathBuf, Change)> {
+    let mut changes: Vec<(PathBuf, Change)> = from
+        .files
+        .iter()
+        .filter_map(|(path, hash)| match to.files.get(path) {
+            None => Some((path.clone(), Change::Removed)),
+            Some(new_hash) if new_hash != hash => Some((path.clone(), Change::Modified)),
+            _ => None,
+        })
+        .chain(
+            to.files
+                .keys()
+                .filter(|path| !from.files.contains_key(*path))
+                .map(|path| (path.clone(), Change::Added)),
+        )
+        .collect();
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    changes
+}
+
+fn blob_name(hash: &str) -> PathBuf {
+    Path::new(HISTORY_FOLDER_NAME)
+        .join(BLOBS_FOLDER_NAME)
+        .join(hash)
+}
+
+fn version_name(version: u64) -> PathBuf {
+    Path::new(HISTORY_FOLDER_NAME)
+        .join(VERSIONS_FOLDER_NAME)
+        .join(version.to_string())
+        .with_extension(VERSION_EXTENSION)
+}
+
+#[derive(Debug)]
+pub struct History<'a, S: Storage = FsStorageHelper> {
+    storage: &'a S,
+    path: PathBuf,
+    // paths relative to the folder that are not versioned
+    ignored: Vec<PathBuf>,
+}
+
+impl<'a, S: Storage> History<'a, S> {
+    pub fn new(storage: &'a S, path: &Path, ignored: &[&Path]) -> Self {
+        Self {
+            storage,
+            path: path.to_path_buf(),
+            ignored: ignored.iter().map(|path| path.to_path_buf()).collect(),
+        }
+    }
+
+    fn is_tracked(&self, relative: &Path) -> bool {
+        !relative.starts_with(HISTORY_FOLDER_NAME)
+            && !relative.starts_with(transaction::STAGING_FOLDER_NAME)
+            && !self.ignored.iter().any(|path| relative.starts_with(path))
+    }
+
+    fn version_numbers(&self) -> Result<Vec<u64>, std::io::Error> {
+        let versions_path = self
+            .path
+            .join(HISTORY_FOLDER_NAME)
+            .join(VERSIONS_FOLDER_NAME);
+        let mut numbers = vec![];
+        for path in self.storage.list_folder(&versions_path)? {
+            if path.extension().and_then(|os| os.to_str()) == Some(VERSION_EXTENSION) {
+                if let Some(number) = path
+                    .file_stem()
+                    .and_then(|os| os.to_str())
+                    .and_then(|name| name.parse::<u64>().ok())
+                {
+                    numbers.push(number);
+                }
+            }
+        }
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+
+    pub fn get(&self, version: u64) -> Result<Option<Version>, Box<dyn std::error::Error>> {
+        let path = self.path.join(version_name(version));
+        if !self.storage.is_file(&path)? {
+            return Ok(None);
+        }
+        Ok(Some(self.storage.read_file_object(&path)?))
+    }
+
+    /// All the versions, from the oldest
+    pub fn versions(&self) -> Result<Vec<Version>, Box<dyn std::error::Error>> {
+        let mut versions = vec![];
+        for number in self.version_numbers()? {
+            versions.extend(self.get(number)?);
+        }
+        Ok(versions)
+    }
+
+    pub fn latest(&self) -> Result<Option<Version>, Box<dyn std::error::Error>> {
+        match self.version_numbers()?.last() {
+            Some(&number) => self.get(number),
+            None => Ok(None),
+        }
+    }
+
+    /// Path of the content a file had in the version, if it was present
+    pub fn file_path(&self, version: &Version, relative: &Path) -> Option<PathBuf> {
+        version
+            .files
+            .get(relative)
+            .map(|hash| self.path.join(blob_name(hash)))
+    }
+
+    // Tracked files currently in the folder, with their hash
+    fn scan(
+        &self,
+        relative: &Path,
+        files: &mut BTreeMap<PathBuf, String>,
+    ) -> Result<(), std::io::Error> {
+        for entry_path in self.storage.list_folder(&self.path.join(relative))? {
+            let entry_relative = relative.join(entry_path.file_name().unwrap_or_default());
+            if !self.is_tracked(&entry_relative) {
+                continue;
+            }
+            if self.storage.is_dir(&entry_path)? {
+                self.scan(&entry_relative, files)?;
+            } else {
+                let hash = checksum(&self.storage.read_file(&entry_path)?);
+                files.insert(entry_relative, hash);
+            }
+        }
+        Ok(())
+    }
+
+    // The files of the latest version, or the ones in the folder before the first version
+    fn current_files(
+        &self,
+        latest: Option<&Version>,
+    ) -> Result<BTreeMap<PathBuf, String>, std::io::Error> {
+        match latest {
+            Some(version) => Ok(version.files.clone()),
+            None => {
+                let mut files = BTreeMap::new();
+                self.scan(Path::new(""), &mut files)?;
+                Ok(files)
+            }
+        }
+    }
+
+    /// Adds to the transaction a new version with its changes, unless they change nothing.
+    /// Must be called once every other change was added to the transaction.
+    pub fn record(
+        &self,
+        transaction: &mut Transaction<'_, S>,
+        description: &str,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        let latest = self.latest()?;
+        let mut files = self.current_files(latest.as_ref())?;
+        // the content of the files that were there before the history is not stored yet
+        let mut sources: Vec<(String, PathBuf)> = match latest {
+            Some(_) => vec![],
+            None => files
+                .iter()
+                .map(|(relative, hash)| (hash.clone(), self.path.join(relative)))
+                .collect(),
+        };
+
+        let deletions = transaction.deletions().to_vec();
+        files.retain(|relative, _| {
+            !deletions
+                .iter()
+                .any(|deleted| relative.starts_with(deleted))
+        });
+        for (relative, staged) in transaction.staged_files() {
+            if self.is_tracked(&relative) {
+                let hash = checksum(&self.storage.read_file(&staged)?);
+                sources.push((hash.clone(), staged));
+                files.insert(relative, hash);
+            }
+        }
+        if latest.as_ref().map(|version| &version.files) == Some(&files) {
+            return Ok(None);
+        }
+
+        let needed: BTreeSet<&String> = files.values().collect();
+        let mut linked = BTreeSet::new();
+        for (hash, source) in sources {
+            let blob = blob_name(&hash);
+            if needed.contains(&hash)
+                && !self.storage.is_file(&self.path.join(&blob))?
+                && linked.insert(hash)
+            {
+                let staged = transaction.stage(&blob);
+                self.storage.copy_file(&source, &staged)?;
+            }
+        }
+        let number = latest.map(|version| version.version + 1).unwrap_or(1);
+        transaction.write_object(
+            version_name(number),
+            &Version {
+                version: number,
+                created: SystemTime::now(),
+                description: description.to_string(),
+                files,
+            },
+        )?;
+        Ok(Some(number))
+    }
+
+    /// Adds to the transaction the changes that bring the tracked files back to the version
+    pub fn restore(
+        &self,
+        transaction: &mut Transaction<'_, S>,
+        version: &Version,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let current = self.current_files(self.latest()?.as_ref())?;
+        for relative in current.keys() {
+            if !version.files.contains_key(relative) {
+                transaction.delete(relative);
+            }
+        }
+        for (relative, hash) in version.files.iter() {
+            if current.get(relative) != Some(hash) {
+                let staged = transaction.stage(relative);
+                self.storage
+                    .copy_file(&self.path.join(blob_name(hash)), &staged)?;
+            }
+        }
+        Ok(())
+    }
+}
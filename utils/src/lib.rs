@@ -5,8 +5,20 @@ use rand::Rng;
 use std::convert::TryInto;
 use std::time::SystemTime;
 
+pub mod chunks;
+pub mod export;
+pub mod feedback;
+pub mod history;
+pub mod limits;
+pub mod lock;
 pub mod mongo;
+pub mod package;
+pub mod s3;
+pub mod session;
 pub mod storage;
+pub mod tokens;
+pub mod transaction;
+pub mod window;
 
 pub mod scoring;
 
@@ -0,0 +1,73 @@
+//! Execution limits of a single testcase, from the problem-wide limits and
+//! the subtask and testcase overrides of the problem metadata.
+
+use std::time::Duration;
+
+use protos::common::{ProgrammingLanguage, Resources};
+use protos::service::evaluation::{LimitsOverride, Problem, Subtask};
+
+fn apply(limits: &mut Resources, limits_override: &LimitsOverride) {
+    if let Some(time) = &limits_override.time {
+        limits.time = time.clone();
+    }
+    if let Some(memory_bytes) = limits_override.memory_bytes {
+        limits.memory_bytes = memory_bytes;
+    }
+}
+
+fn subtask_limits(problem: &Problem, subtask: &Subtask, testcase_id: u64) -> Resources {
+    let mut limits = problem.execution_limits.clone();
+    if let Some(subtask_override) = &subtask.limits {
+        apply(&mut limits, subtask_override);
+    }
+    if let Some(testcase) = subtask
+        .testcase_limits
+        .iter()
+        .find(|testcase| testcase.testcase_id == testcase_id)
+    {
+        apply(&mut limits, &testcase.limits);
+    }
+    limits
+}
+
+/// Time multiplier of the language, 1 if the problem does not set one
+pub fn time_multiplier(problem: &Problem, lang: ProgrammingLanguage) -> f64 {
+    problem
+        .time_multipliers
+        .iter()
+        .find(|multiplier| multiplier.lang == lang as i32)
+        .map_or(1f64, |multiplier| multiplier.multiplier)
+}
+
+/// The sandbox only takes whole seconds, so time limits are rounded up for it
+/// and the time used is then checked against the exact limit
+pub fn sandbox_seconds(time: &protos::common::Duration) -> u64 {
+    let time = Duration::from(time.clone());
+    time.as_secs() + u64::from(time.subsec_nanos() > 0)
+}
+
+/// Limits for running a submission in the given language on the testcase.
+/// The testcase override comes before the subtask one, which comes before the
+/// problem limits; a testcase in several subtasks gets the most permissive ones.
+pub fn testcase_limits(
+    problem: &Problem,
+    testcase_id: u64,
+    lang: ProgrammingLanguage,
+) -> Resources {
+    let mut limits = problem
+        .subtasks
+        .iter()
+        .filter(|subtask| subtask.testcases_id.contains(&testcase_id))
+        .map(|subtask| subtask_limits(problem, subtask, testcase_id))
+        .reduce(|a, b| Resources {
+            time: Duration::from(a.time).max(Duration::from(b.time)).into(),
+            memory_bytes: a.memory_bytes.max(b.memory_bytes),
+        })
+        .unwrap_or_else(|| problem.execution_limits.clone());
+    // invalid multipliers are ignored instead of making the limit meaningless
+    let multiplier = time_multiplier(problem, lang);
+    if multiplier.is_finite() && multiplier > 0f64 {
+        limits.time = Duration::from(limits.time).mul_f64(multiplier).into();
+    }
+    limits
+}
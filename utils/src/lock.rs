@@ -0,0 +1,138 @@
+//! Mutual exclusion between the processes sharing a storage, like the replicas of a service.
+//!
+//! The lock is a sequence of numbered files in a folder, each one created only if
+//! missing and never changed afterwards: the last one tells whether the lock is held
+//! and until when. Taking or releasing the lock creates the next file, so when several
+//! processes try at once only one of them succeeds. A lock held past its lease is
+//! considered abandoned by a crashed process, and can be taken by another one.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+
+// how long to wait before looking again at a lock held by another process
+const RETRY_PERIOD: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Serialize, Deserialize)]
+enum State {
+    Free,
+    Held { expires: SystemTime },
+}
+
+fn invalid_data<T: ToString>(e: T) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn state_path(path: &Path, generation: u64) -> PathBuf {
+    path.join(generation.to_string())
+}
+
+// Generations of the states in the folder, not sorted
+fn generations<S: Storage>(storage: &S, path: &Path) -> Result<Vec<u64>, std::io::Error> {
+    Ok(storage
+        .list_folder(path)?
+        .iter()
+        .filter_map(|item| item.file_name()?.to_str()?.parse().ok())
+        .collect())
+}
+
+// The last state of the lock, None if it was replaced while being read
+fn last_state<S: Storage>(
+    storage: &S,
+    path: &Path,
+) -> Result<Option<(u64, State)>, std::io::Error> {
+    let generation = match generations(storage, path)?.into_iter().max() {
+        Some(generation) => generation,
+        None => return Ok(Some((0, State::Free))),
+    };
+    match storage.read_file(&state_path(path, generation)) {
+        Ok(content) => Ok(Some((
+            generation,
+            bincode::deserialize(&content).map_err(invalid_data)?,
+        ))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+// Returns false if another process created the same generation first
+fn create_state<S: Storage>(
+    storage: &S,
+    path: &Path,
+    generation: u64,
+    state: &State,
+) -> Result<bool, std::io::Error> {
+    let content = bincode::serialize(state).map_err(invalid_data)?;
+    storage.create_new_file(&state_path(path, generation), &content)
+}
+
+/// Held by a single process at a time among the ones sharing the storage, released on drop
+#[derive(Debug)]
+pub struct StorageLock<'a, S: Storage> {
+    storage: &'a S,
+    path: PathBuf,
+    generation: u64,
+}
+
+impl<'a, S: Storage> StorageLock<'a, S> {
+    /// Waits for the lock kept in the folder. The lock is only guaranteed for the lease,
+    /// after that another process may take it
+    pub fn acquire(storage: &'a S, path: &Path, lease: Duration) -> Result<Self, std::io::Error> {
+        loop {
+            let free = match last_state(storage, path)? {
+                Some((generation, State::Free)) => Some(generation),
+                Some((generation, State::Held { expires })) if expires <= SystemTime::now() => {
+                    Some(generation)
+                }
+                Some(_) => {
+                    std::thread::sleep(RETRY_PERIOD);
+                    None
+                }
+                None => None,
+            };
+            if let Some(generation) = free {
+                let held = State::Held {
+                    expires: SystemTime::now() + lease,
+                };
+                if create_state(storage, path, generation + 1, &held)? {
+                    let lock = Self {
+                        storage,
+                        path: path.to_path_buf(),
+                        generation: generation + 1,
+                    };
+                    lock.remove_old_states()?;
+                    return Ok(lock);
+                }
+            }
+        }
+    }
+
+    // Only the last state matters, the older ones may be removed by several processes at once
+    fn remove_old_states(&self) -> Result<(), std::io::Error> {
+        for generation in generations(self.storage, &self.path)? {
+            if generation < self.generation {
+                match self
+                    .storage
+                    .delete_item(&state_path(&self.path, generation))
+                {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, S: Storage> Drop for StorageLock<'a, S> {
+    // fails only if the lease expired and another process took the lock meanwhile
+    fn drop(&mut self) {
+        if let Err(err) = create_state(self.storage, &self.path, self.generation + 1, &State::Free)
+        {
+            println!("Cannot release the lock [path: {:?}]: {}", self.path, err);
+        }
+    }
+}
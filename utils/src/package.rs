@@ -0,0 +1,958 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Seek, Write};
+use std::time::Duration;
+
+use protos::common::{ProgrammingLanguage, Resources, Score, Source};
+use protos::scoring::{self, subtask, Subtask};
+pub use protos::service::evaluation::import_problem_request::header::Format;
+use protos::service::evaluation::{self, problem, Problem};
+use serde::{Deserialize, Serialize};
+
+use crate::gen_uuid;
+
+const POLYGON_DESCRIPTOR: &str = "problem.xml";
+const KATTIS_DESCRIPTOR: &str = "problem.yaml";
+const ITALY_YAML_DESCRIPTOR: &str = "task.yaml";
+const NATIVE_DESCRIPTOR: &str = "cmsrs.json";
+
+const DEFAULT_MAX_SCORE: f64 = 100.0;
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(1);
+const DEFAULT_MEMORY_BYTES: u64 = 256 * 1024 * 1024;
+const KATTIS_DEFAULT_MEMORY_BYTES: u64 = 2048 * 1024 * 1024;
+const DEFAULT_COMPILATION_TIME: Duration = Duration::from_secs(10);
+const DEFAULT_COMPILATION_MEMORY_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum PackageError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    Yaml(serde_yaml::Error),
+    Json(serde_json::Error),
+    Xml(roxmltree::Error),
+    UnknownFormat,
+    MissingFile(String),
+    Invalid(String),
+    UnsupportedLanguage(String),
+}
+
+impl From<std::io::Error> for PackageError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+impl From<zip::result::ZipError> for PackageError {
+    fn from(e: zip::result::ZipError) -> Self {
+        Self::Zip(e)
+    }
+}
+impl From<serde_yaml::Error> for PackageError {
+    fn from(e: serde_yaml::Error) -> Self {
+        Self::Yaml(e)
+    }
+}
+impl From<serde_json::Error> for PackageError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+impl From<roxmltree::Error> for PackageError {
+    fn from(e: roxmltree::Error) -> Self {
+        Self::Xml(e)
+    }
+}
+
+pub struct PackageTestcase {
+    pub input: Vec<u8>,
+    pub output: Vec<u8>,
+}
+
+pub struct PackageSubtask {
+    pub scoring: Subtask,
+    pub testcases: Vec<PackageTestcase>,
+    pub dependencies: Vec<usize>, // indices of other subtasks of the package
+}
+
+/// A problem read from a package, independent from the package format
+pub struct Package {
+    pub name: String,
+    pub long_name: String,
+    pub statement: Vec<u8>,
+    pub time_limit: Duration,
+    pub memory_bytes: u64,
+    pub subtasks: Vec<PackageSubtask>,
+    pub checker: Option<Source>,
+    pub interactor: Option<Source>,
+}
+
+/// A problem with all the evaluation metadata of the service, as in the native format
+pub struct ProblemPackage {
+    pub name: String,
+    pub long_name: String,
+    pub statement: Vec<u8>,
+    /// Evaluation metadata, the problem id is assigned when importing
+    pub problem: Problem,
+    pub testcases: BTreeMap<u64, PackageTestcase>,
+    pub checker: Option<Source>,
+    pub interactor: Option<Source>,
+}
+
+// ids are generated on conversion
+impl From<Package> for ProblemPackage {
+    fn from(p: Package) -> Self {
+        let mut testcases = BTreeMap::new();
+        let subtask_ids: Vec<u64> = p.subtasks.iter().map(|_| gen_uuid()).collect();
+        let problem = Problem {
+            id: 0,
+            scoring: scoring::Problem::default(),
+            r#type: if p.interactor.is_some() {
+                problem::Type::Interactive
+            } else {
+                problem::Type::Batch
+            } as i32,
+            execution_limits: Resources {
+                time: p.time_limit.into(),
+                memory_bytes: p.memory_bytes,
+            },
+            compilation_limits: Resources {
+                time: DEFAULT_COMPILATION_TIME.into(),
+                memory_bytes: DEFAULT_COMPILATION_MEMORY_BYTES,
+            },
+            subtasks: p
+                .subtasks
+                .into_iter()
+                .zip(subtask_ids.iter())
+                .map(|(subtask, &id)| evaluation::Subtask {
+                    id,
+                    scoring: subtask.scoring,
+                    testcases_id: subtask
+                        .testcases
+                        .into_iter()
+                        .map(|tc| {
+                            let id = gen_uuid();
+                            testcases.insert(id, tc);
+                            id
+                        })
+                        .collect(),
+                    dependencies: subtask
+                        .dependencies
+                        .iter()
+                        .filter_map(|&i| subtask_ids.get(i).copied())
+                        .collect(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        Self {
+            name: p.name,
+            long_name: p.long_name,
+            statement: p.statement,
+            problem,
+            testcases,
+            checker: p.checker,
+            interactor: p.interactor,
+        }
+    }
+}
+
+/// Files of an archive, with paths relative to the problem root folder
+#[derive(Default)]
+pub struct Archive {
+    name: Option<String>,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Archive {
+    pub fn from_zip<R: Read + Seek>(reader: R) -> Result<Self, PackageError> {
+        let mut zip = zip::ZipArchive::new(reader)?;
+        let mut files = BTreeMap::new();
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            if file.is_dir() {
+                continue;
+            }
+            let mut content = vec![];
+            file.read_to_end(&mut content)?;
+            files.insert(file.name().trim_start_matches('/').to_owned(), content);
+        }
+        Ok(Self { name: None, files })
+    }
+
+    /// Moves the root to the folder containing the descriptor of the given format,
+    /// packages are often zipped together with their enclosing folder
+    fn rooted_at(self, descriptor: &str) -> Option<Self> {
+        let root = self
+            .files
+            .keys()
+            .filter(|path| path.rsplit('/').next() == Some(descriptor))
+            .min_by_key(|path| path.len())?
+            .trim_end_matches(descriptor)
+            .to_owned();
+        if root.is_empty() {
+            return Some(self);
+        }
+        let name = root
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .map(String::from);
+        let files = self
+            .files
+            .into_iter()
+            .filter_map(|(path, content)| {
+                path.strip_prefix(&root)
+                    .map(|relative| (relative.to_owned(), content))
+            })
+            .collect();
+        Some(Self { name, files })
+    }
+
+    pub fn get(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path).map(|content| content.as_slice())
+    }
+
+    fn require(&self, path: &str) -> Result<&[u8], PackageError> {
+        self.get(path)
+            .ok_or_else(|| PackageError::MissingFile(path.to_owned()))
+    }
+
+    fn find_first<'a>(&'a self, paths: &[&str]) -> Option<(&'a str, &'a [u8])> {
+        paths.iter().find_map(|path| {
+            self.files
+                .get_key_value(*path)
+                .map(|(k, v)| (k.as_str(), v.as_slice()))
+        })
+    }
+
+    /// Files directly inside a folder, sorted by name
+    fn files_in<'a>(&'a self, folder: &'a str) -> impl Iterator<Item = (&'a str, &'a [u8])> {
+        self.files.iter().filter_map(move |(path, content)| {
+            path.strip_prefix(folder)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .filter(|name| !name.contains('/'))
+                .map(|name| (name, content.as_slice()))
+        })
+    }
+
+    /// Folders directly inside a folder, sorted by name
+    fn folders_in(&self, folder: &str) -> BTreeSet<String> {
+        self.files
+            .keys()
+            .filter_map(|path| {
+                path.strip_prefix(folder)
+                    .and_then(|rest| rest.strip_prefix('/'))
+                    .and_then(|rest| rest.split_once('/'))
+                    .map(|(name, _)| name.to_owned())
+            })
+            .collect()
+    }
+
+    pub fn insert(&mut self, path: String, content: Vec<u8>) {
+        self.files.insert(path, content);
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_slice()))
+    }
+}
+
+fn utf8(content: &[u8]) -> Result<&str, PackageError> {
+    std::str::from_utf8(content).map_err(|e| PackageError::Invalid(e.to_string()))
+}
+
+fn language_from_extension(path: &str) -> Result<ProgrammingLanguage, PackageError> {
+    match path.rsplit('.').next() {
+        Some("cpp") | Some("cc") | Some("cxx") | Some("c") => Ok(ProgrammingLanguage::Cpp),
+        Some("rs") => Ok(ProgrammingLanguage::Rust),
+        _ => Err(PackageError::UnsupportedLanguage(path.to_owned())),
+    }
+}
+
+fn source_from(path: &str, code: &[u8]) -> Result<Source, PackageError> {
+    Ok(Source {
+        lang: language_from_extension(path)? as i32,
+        code: code.to_vec(),
+    })
+}
+
+fn subtask(
+    method: subtask::Method,
+    max_score: f64,
+    testcases: Vec<PackageTestcase>,
+) -> PackageSubtask {
+    PackageSubtask {
+        scoring: Subtask {
+            method: method as i32,
+            max_score: Score {
+                score: max_score,
+                ..Default::default()
+            },
+            threshold: None,
+            relative: None,
+        },
+        testcases,
+        dependencies: vec![],
+    }
+}
+
+/// Reads a problem package from a zip archive
+pub fn read_package<R: Read + Seek>(reader: R, format: Format) -> Result<Package, PackageError> {
+    read_package_archive(Archive::from_zip(reader)?, format)
+}
+
+fn descriptor(format: Format) -> &'static str {
+    match format {
+        Format::Polygon => POLYGON_DESCRIPTOR,
+        Format::Kattis => KATTIS_DESCRIPTOR,
+        Format::Auto | Format::ItalyYaml => ITALY_YAML_DESCRIPTOR,
+        Format::Cmsrs => NATIVE_DESCRIPTOR,
+    }
+}
+
+pub fn read_package_archive(archive: Archive, format: Format) -> Result<Package, PackageError> {
+    let format = match format {
+        Format::Auto => [Format::Polygon, Format::Kattis, Format::ItalyYaml]
+            .iter()
+            .copied()
+            .find(|&format| {
+                archive
+                    .files
+                    .keys()
+                    .any(|path| path.rsplit('/').next() == Some(descriptor(format)))
+            })
+            .ok_or(PackageError::UnknownFormat)?,
+        format => format,
+    };
+    let archive = archive
+        .rooted_at(descriptor(format))
+        .ok_or_else(|| PackageError::MissingFile(descriptor(format).to_owned()))?;
+    match format {
+        Format::Polygon => polygon::read(&archive),
+        Format::Kattis => kattis::read(&archive),
+        Format::Auto | Format::ItalyYaml => italy_yaml::read(&archive),
+        // the native format describes more than a Package
+        Format::Cmsrs => Err(PackageError::UnknownFormat),
+    }
+}
+
+/// Reads a problem package from a zip archive, in the native format too
+pub fn read_problem_package<R: Read + Seek>(
+    reader: R,
+    format: Format,
+) -> Result<ProblemPackage, PackageError> {
+    let archive = Archive::from_zip(reader)?;
+    let is_native = format == Format::Cmsrs
+        || (format == Format::Auto
+            && archive
+                .files
+                .keys()
+                .any(|path| path.rsplit('/').next() == Some(NATIVE_DESCRIPTOR)));
+    if !is_native {
+        return read_package_archive(archive, format).map(ProblemPackage::from);
+    }
+    let archive = archive
+        .rooted_at(NATIVE_DESCRIPTOR)
+        .ok_or_else(|| PackageError::MissingFile(NATIVE_DESCRIPTOR.to_owned()))?;
+    native::read(&archive)
+}
+
+/// Writes a problem package in the native format, which keeps every detail of the problem
+pub fn write_problem_package<W: Write + Seek>(
+    package: &ProblemPackage,
+    writer: W,
+) -> Result<W, PackageError> {
+    native::write(package, writer)
+}
+
+mod polygon {
+    use super::*;
+
+    /// Expands printf-like patterns such as `tests/%02d`
+    pub fn expand_pattern(pattern: &str, index: usize) -> String {
+        let start = match pattern.find('%') {
+            Some(start) => start,
+            None => return pattern.to_owned(),
+        };
+        let end = match pattern[start..].find('d') {
+            Some(end) => start + end,
+            None => return pattern.to_owned(),
+        };
+        let width = pattern[start + 1..end].parse::<usize>().unwrap_or(0);
+        format!(
+            "{}{:0width$}{}",
+            &pattern[..start],
+            index,
+            &pattern[end + 1..],
+            width = width
+        )
+    }
+
+    fn child<'a, 'input>(
+        node: roxmltree::Node<'a, 'input>,
+        name: &str,
+    ) -> Option<roxmltree::Node<'a, 'input>> {
+        node.children().find(|n| n.has_tag_name(name))
+    }
+
+    fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+        child(node, name).and_then(|n| n.text()).map(str::trim)
+    }
+
+    fn asset_source(
+        archive: &Archive,
+        assets: Option<roxmltree::Node>,
+        name: &str,
+    ) -> Result<Option<Source>, PackageError> {
+        match assets
+            .and_then(|a| child(a, name))
+            .and_then(|n| child(n, "source"))
+            .and_then(|n| n.attribute("path"))
+        {
+            Some(path) => source_from(path, archive.require(path)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read(archive: &Archive) -> Result<Package, PackageError> {
+        let xml = utf8(archive.require(POLYGON_DESCRIPTOR)?)?;
+        let doc = roxmltree::Document::parse(xml)?;
+        let root = doc.root_element();
+
+        let name = root
+            .attribute("short-name")
+            .map(String::from)
+            .or_else(|| archive.name.clone())
+            .unwrap_or_else(|| String::from("problem"));
+        let names: Vec<_> = child(root, "names")
+            .map(|n| n.children().filter(|n| n.has_tag_name("name")).collect())
+            .unwrap_or_default();
+        let long_name = names
+            .iter()
+            .find(|n| n.attribute("language") == Some("english"))
+            .or_else(|| names.first())
+            .and_then(|n| n.attribute("value"))
+            .map(String::from)
+            .unwrap_or_else(|| name.clone());
+
+        let judging = child(root, "judging")
+            .ok_or_else(|| PackageError::Invalid(String::from("missing judging section")))?;
+        let testset = judging
+            .children()
+            .filter(|n| n.has_tag_name("testset"))
+            .find(|n| n.attribute("name") == Some("tests"))
+            .or_else(|| judging.children().find(|n| n.has_tag_name("testset")))
+            .ok_or_else(|| PackageError::Invalid(String::from("missing testset")))?;
+        let time_limit = child_text(testset, "time-limit")
+            .and_then(|t| t.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_TIME_LIMIT);
+        let memory_bytes = child_text(testset, "memory-limit")
+            .and_then(|m| m.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_MEMORY_BYTES);
+        let input_pattern = child_text(testset, "input-path-pattern")
+            .ok_or_else(|| PackageError::Invalid(String::from("missing input path pattern")))?;
+        let answer_pattern = child_text(testset, "answer-path-pattern")
+            .ok_or_else(|| PackageError::Invalid(String::from("missing answer path pattern")))?;
+
+        // (group, points) of each test, tests are numbered from 1
+        let tests: Vec<(Option<&str>, Option<f64>)> = child(testset, "tests")
+            .map(|n| {
+                n.children()
+                    .filter(|n| n.has_tag_name("test"))
+                    .map(|t| {
+                        (
+                            t.attribute("group"),
+                            t.attribute("points").and_then(|p| p.parse().ok()),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut testcases = tests
+            .iter()
+            .enumerate()
+            .map(|(i, _)| {
+                Ok(Some(PackageTestcase {
+                    input: archive
+                        .require(&expand_pattern(input_pattern, i + 1))?
+                        .to_vec(),
+                    output: archive
+                        .require(&expand_pattern(answer_pattern, i + 1))?
+                        .to_vec(),
+                }))
+            })
+            .collect::<Result<Vec<_>, PackageError>>()?;
+        let test_points =
+            |indices: &[usize]| -> f64 { indices.iter().filter_map(|&i| tests[i].1).sum() };
+
+        let mut subtasks = vec![];
+        if tests.iter().any(|(group, _)| group.is_some()) {
+            let groups: Vec<_> = child(testset, "groups")
+                .map(|n| n.children().filter(|n| n.has_tag_name("group")).collect())
+                .unwrap_or_default();
+            let mut group_names: Vec<&str> = vec![];
+            for (group, _) in tests.iter() {
+                let group = group.unwrap_or_default();
+                if !group_names.contains(&group) {
+                    group_names.push(group);
+                }
+            }
+            for &group_name in group_names.iter() {
+                let indices: Vec<usize> = (0..tests.len())
+                    .filter(|&i| tests[i].0.unwrap_or_default() == group_name)
+                    .collect();
+                let group = groups
+                    .iter()
+                    .find(|g| g.attribute("name") == Some(group_name));
+                let method = match group.and_then(|g| g.attribute("points-policy")) {
+                    Some("complete-group") => subtask::Method::Min,
+                    _ => subtask::Method::Sum,
+                };
+                let max_score = group
+                    .and_then(|g| g.attribute("points"))
+                    .and_then(|p| p.parse::<f64>().ok())
+                    .filter(|&p| p > 0.0)
+                    .unwrap_or_else(|| test_points(&indices));
+                let mut group_subtask = subtask(
+                    method,
+                    max_score,
+                    indices
+                        .iter()
+                        .filter_map(|&i| testcases[i].take())
+                        .collect(),
+                );
+                group_subtask.dependencies = group
+                    .and_then(|g| child(*g, "dependencies"))
+                    .map(|n| {
+                        n.children()
+                            .filter(|n| n.has_tag_name("dependency"))
+                            .filter_map(|d| d.attribute("group"))
+                            .filter_map(|name| group_names.iter().position(|&g| g == name))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                subtasks.push(group_subtask);
+            }
+        } else {
+            let indices: Vec<usize> = (0..tests.len()).collect();
+            let points = test_points(&indices);
+            let testcases = testcases.iter_mut().filter_map(Option::take).collect();
+            subtasks.push(if points > 0.0 {
+                subtask(subtask::Method::Sum, points, testcases)
+            } else {
+                subtask(subtask::Method::Min, DEFAULT_MAX_SCORE, testcases)
+            });
+        }
+
+        // prefer pdf statements, in english if possible
+        let statements: Vec<_> = child(root, "statements")
+            .map(|n| {
+                n.children()
+                    .filter(|n| n.has_tag_name("statement"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let statement = statements
+            .iter()
+            .filter(|s| s.attribute("type") == Some("application/pdf"))
+            .max_by_key(|s| s.attribute("language") == Some("english"))
+            .or_else(|| statements.first())
+            .and_then(|s| s.attribute("path"))
+            .and_then(|path| archive.get(path))
+            .map(|s| s.to_vec())
+            .unwrap_or_default();
+
+        let assets = child(root, "assets");
+        Ok(Package {
+            name,
+            long_name,
+            statement,
+            time_limit,
+            memory_bytes,
+            subtasks,
+            checker: asset_source(archive, assets, "checker")?,
+            interactor: asset_source(archive, assets, "interactor")?,
+        })
+    }
+}
+
+mod kattis {
+    use super::*;
+
+    #[derive(Deserialize, Default)]
+    #[serde(default)]
+    struct Limits {
+        time_limit: Option<f64>,
+        memory: Option<u64>,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(default)]
+    struct ProblemYaml {
+        name: Option<serde_yaml::Value>,
+        validation: Option<String>,
+        limits: Limits,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(default)]
+    struct TestdataYaml {
+        range: Option<String>,
+    }
+
+    fn testcases_in(archive: &Archive, folder: &str) -> Result<Vec<PackageTestcase>, PackageError> {
+        archive
+            .files_in(folder)
+            .filter_map(|(name, content)| name.strip_suffix(".in").map(|stem| (stem, content)))
+            .map(|(stem, input)| {
+                Ok(PackageTestcase {
+                    input: input.to_vec(),
+                    output: archive
+                        .require(&format!("{}/{}.ans", folder, stem))?
+                        .to_vec(),
+                })
+            })
+            .collect()
+    }
+
+    fn group_max_score(archive: &Archive, folder: &str) -> Result<Option<f64>, PackageError> {
+        let testdata: TestdataYaml = match archive.get(&format!("{}/testdata.yaml", folder)) {
+            Some(content) => serde_yaml::from_slice(content)?,
+            None => return Ok(None),
+        };
+        Ok(testdata
+            .range
+            .and_then(|range| range.split_whitespace().nth(1).map(String::from))
+            .and_then(|max| max.parse().ok()))
+    }
+
+    pub fn read(archive: &Archive) -> Result<Package, PackageError> {
+        let descriptor: ProblemYaml = serde_yaml::from_slice(archive.require(KATTIS_DESCRIPTOR)?)?;
+        let name = archive
+            .name
+            .clone()
+            .unwrap_or_else(|| String::from("problem"));
+        let long_name = match descriptor.name {
+            Some(serde_yaml::Value::String(long_name)) => long_name,
+            Some(serde_yaml::Value::Mapping(names)) => names
+                .get(&serde_yaml::Value::from("en"))
+                .or_else(|| names.iter().next().map(|(_, v)| v))
+                .and_then(|v| v.as_str())
+                .map(String::from)
+                .unwrap_or_else(|| name.clone()),
+            _ => name.clone(),
+        };
+
+        let time_limit = descriptor
+            .limits
+            .time_limit
+            .or_else(|| {
+                archive
+                    .get(".timelimit")
+                    .and_then(|t| std::str::from_utf8(t).ok())
+                    .and_then(|t| t.trim().parse().ok())
+            })
+            .map(Duration::from_secs_f64)
+            .unwrap_or(DEFAULT_TIME_LIMIT);
+        let memory_bytes = descriptor
+            .limits
+            .memory
+            .map(|mib| mib * 1024 * 1024)
+            .unwrap_or(KATTIS_DEFAULT_MEMORY_BYTES);
+
+        // samples are kept as a subtask worth no points
+        let mut subtasks = vec![];
+        let samples = testcases_in(archive, "data/sample")?;
+        if !samples.is_empty() {
+            subtasks.push(subtask(subtask::Method::Min, 0.0, samples));
+        }
+        let groups = archive.folders_in("data/secret");
+        if groups.is_empty() {
+            let max_score = group_max_score(archive, "data/secret")?.unwrap_or(DEFAULT_MAX_SCORE);
+            subtasks.push(subtask(
+                subtask::Method::Min,
+                max_score,
+                testcases_in(archive, "data/secret")?,
+            ));
+        } else {
+            let default_score = DEFAULT_MAX_SCORE / groups.len() as f64;
+            for group in groups {
+                let folder = format!("data/secret/{}", group);
+                let max_score = group_max_score(archive, &folder)?.unwrap_or(default_score);
+                subtasks.push(subtask(
+                    subtask::Method::Min,
+                    max_score,
+                    testcases_in(archive, &folder)?,
+                ));
+            }
+        }
+
+        let statement = archive
+            .files_in("problem_statement")
+            .filter(|(name, _)| name.ends_with(".pdf"))
+            .max_by_key(|(name, _)| *name == "problem.en.pdf" || *name == "problem.pdf")
+            .or_else(|| {
+                archive.find_first(&[
+                    "problem_statement/problem.en.tex",
+                    "problem_statement/problem.tex",
+                ])
+            })
+            .map(|(_, content)| content.to_vec())
+            .unwrap_or_default();
+
+        // custom validators live in output_validators/<name>/
+        let validator = archive
+            .files()
+            .filter(|(path, _)| {
+                path.strip_prefix("output_validators/")
+                    .map(|rest| rest.matches('/').count() == 1)
+                    .unwrap_or(false)
+            })
+            .find(|(path, _)| language_from_extension(path).is_ok())
+            .map(|(path, content)| source_from(path, content))
+            .transpose()?;
+        let validation = descriptor.validation.unwrap_or_default();
+        let (checker, interactor) = if validation.contains("interactive") {
+            (None, validator)
+        } else if validation.contains("custom") {
+            (validator, None)
+        } else {
+            (None, None)
+        };
+
+        Ok(Package {
+            name,
+            long_name,
+            statement,
+            time_limit,
+            memory_bytes,
+            subtasks,
+            checker,
+            interactor,
+        })
+    }
+}
+
+mod italy_yaml {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct TaskYaml {
+        name: String,
+        title: Option<String>,
+        time_limit: Option<f64>,
+        memory_limit: Option<u64>,
+        n_input: Option<usize>,
+    }
+
+    /// Reads the subtask scores and testcase counts from `gen/GEN`,
+    /// where subtasks start with a `#ST: score` line
+    fn read_gen(gen: &str) -> Vec<(f64, usize)> {
+        let mut subtasks: Vec<(f64, usize)> = vec![];
+        for line in gen.lines().map(str::trim) {
+            if let Some(score) = line.strip_prefix("#ST:") {
+                subtasks.push((score.trim().parse().unwrap_or(0.0), 0));
+            } else if line.starts_with("#COPY:") || (!line.is_empty() && !line.starts_with('#')) {
+                if let Some(last) = subtasks.last_mut() {
+                    last.1 += 1;
+                }
+            }
+        }
+        subtasks
+    }
+
+    pub fn read(archive: &Archive) -> Result<Package, PackageError> {
+        let descriptor: TaskYaml = serde_yaml::from_slice(archive.require(ITALY_YAML_DESCRIPTOR)?)?;
+        let n_input = descriptor
+            .n_input
+            .unwrap_or_else(|| archive.files_in("input").count());
+        let mut testcases = (0..n_input)
+            .map(|i| {
+                Ok(PackageTestcase {
+                    input: archive.require(&format!("input/input{}.txt", i))?.to_vec(),
+                    output: archive
+                        .require(&format!("output/output{}.txt", i))?
+                        .to_vec(),
+                })
+            })
+            .collect::<Result<Vec<_>, PackageError>>()?
+            .into_iter();
+
+        let gen_subtasks = archive
+            .get("gen/GEN")
+            .map(utf8)
+            .transpose()?
+            .map(read_gen)
+            .unwrap_or_default();
+        let subtasks = if gen_subtasks.is_empty() {
+            vec![subtask(
+                subtask::Method::Sum,
+                DEFAULT_MAX_SCORE,
+                testcases.collect(),
+            )]
+        } else {
+            if gen_subtasks.iter().map(|(_, count)| count).sum::<usize>() != n_input {
+                return Err(PackageError::Invalid(String::from(
+                    "gen/GEN does not match the number of testcases",
+                )));
+            }
+            gen_subtasks
+                .into_iter()
+                .map(|(score, count)| {
+                    subtask(
+                        subtask::Method::Min,
+                        score,
+                        testcases.by_ref().take(count).collect(),
+                    )
+                })
+                .collect()
+        };
+
+        let statement = archive
+            .find_first(&["statement/statement.pdf", "testo/testo.pdf"])
+            .map(|(_, content)| content.to_vec())
+            .unwrap_or_default();
+        let checker = archive
+            .find_first(&[
+                "check/checker.cpp",
+                "cor/correttore.cpp",
+                "check/checker.rs",
+            ])
+            .map(|(path, content)| source_from(path, content))
+            .transpose()?;
+        let interactor = archive
+            .find_first(&["check/manager.cpp", "cor/manager.cpp", "check/manager.rs"])
+            .map(|(path, content)| source_from(path, content))
+            .transpose()?;
+
+        Ok(Package {
+            long_name: descriptor
+                .title
+                .clone()
+                .unwrap_or_else(|| descriptor.name.clone()),
+            name: descriptor.name,
+            statement,
+            time_limit: descriptor
+                .time_limit
+                .map(Duration::from_secs_f64)
+                .unwrap_or(DEFAULT_TIME_LIMIT),
+            memory_bytes: descriptor
+                .memory_limit
+                .map(|mib| mib * 1024 * 1024)
+                .unwrap_or(DEFAULT_MEMORY_BYTES),
+            subtasks,
+            checker,
+            interactor,
+        })
+    }
+}
+
+mod native {
+    use super::*;
+
+    const STATEMENT_FILE: &str = "statement.pdf";
+
+    #[derive(Serialize, Deserialize)]
+    struct Descriptor {
+        name: String,
+        long_name: String,
+        problem: Problem,
+        checker: Option<String>,
+        interactor: Option<String>,
+    }
+
+    fn testcase_path(id: u64, file: &str) -> String {
+        format!("testcases/{}/{}.txt", id, file)
+    }
+
+    fn source_path(name: &str, source: &Source) -> String {
+        let extension = match ProgrammingLanguage::from_i32(source.lang) {
+            Some(ProgrammingLanguage::Rust) => "rs",
+            Some(ProgrammingLanguage::Cpp) => "cpp",
+            _ => "txt",
+        };
+        format!("files/{}.{}", name, extension)
+    }
+
+    fn read_source(
+        archive: &Archive,
+        path: Option<String>,
+    ) -> Result<Option<Source>, PackageError> {
+        match path {
+            Some(path) if path.ends_with(".txt") => Ok(Some(Source {
+                lang: ProgrammingLanguage::None as i32,
+                code: archive.require(&path)?.to_vec(),
+            })),
+            Some(path) => source_from(&path, archive.require(&path)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read(archive: &Archive) -> Result<ProblemPackage, PackageError> {
+        let descriptor: Descriptor = serde_json::from_slice(archive.require(NATIVE_DESCRIPTOR)?)?;
+        let testcases = descriptor
+            .problem
+            .subtasks
+            .iter()
+            .flat_map(|subtask| subtask.testcases_id.iter())
+            .map(|&id| {
+                Ok((
+                    id,
+                    PackageTestcase {
+                        input: archive.require(&testcase_path(id, "input"))?.to_vec(),
+                        output: archive.require(&testcase_path(id, "output"))?.to_vec(),
+                    },
+                ))
+            })
+            .collect::<Result<_, PackageError>>()?;
+        Ok(ProblemPackage {
+            name: descriptor.name,
+            long_name: descriptor.long_name,
+            statement: archive.get(STATEMENT_FILE).unwrap_or_default().to_vec(),
+            problem: descriptor.problem,
+            testcases,
+            checker: read_source(archive, descriptor.checker)?,
+            interactor: read_source(archive, descriptor.interactor)?,
+        })
+    }
+
+    pub fn write<W: Write + Seek>(package: &ProblemPackage, writer: W) -> Result<W, PackageError> {
+        let mut zip = zip::ZipWriter::new(writer);
+        let options = zip::write::FileOptions::default();
+        let descriptor = Descriptor {
+            name: package.name.clone(),
+            long_name: package.long_name.clone(),
+            problem: package.problem.clone(),
+            checker: package.checker.as_ref().map(|s| source_path("checker", s)),
+            interactor: package
+                .interactor
+                .as_ref()
+                .map(|s| source_path("interactor", s)),
+        };
+
+        zip.start_file(NATIVE_DESCRIPTOR, options)?;
+        serde_json::to_writer_pretty(&mut zip, &descriptor)?;
+        if !package.statement.is_empty() {
+            zip.start_file(STATEMENT_FILE, options)?;
+            zip.write_all(&package.statement)?;
+        }
+        for (id, tc) in package.testcases.iter() {
+            zip.start_file(testcase_path(*id, "input"), options)?;
+            zip.write_all(&tc.input)?;
+            zip.start_file(testcase_path(*id, "output"), options)?;
+            zip.write_all(&tc.output)?;
+        }
+        for (path, source) in [
+            (&descriptor.checker, &package.checker),
+            (&descriptor.interactor, &package.interactor),
+        ] {
+            if let (Some(path), Some(source)) = (path, source) {
+                zip.start_file(path.as_str(), options)?;
+                zip.write_all(&source.code)?;
+            }
+        }
+        Ok(zip.finish()?)
+    }
+}
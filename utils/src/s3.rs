@@ -0,0 +1,387 @@
+//! Storage in a bucket of an S3-compatible object store (AWS S3, MinIO, ...).
+//!
+//! Keys are the paths relative to the root, separated by `/`. Folders only
+//! exist as common prefixes of the keys, so creating an empty folder stores a
+//! marker object inside it. Requests go through the `object_store` crate, run
+//! on a runtime owned by the storage so that they can be waited for from
+//! synchronous code, async tasks included. New files are created with
+//! conditional writes (`If-None-Match`), which the store must support.
+
+use std::future::Future;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Arc;
+
+use futures::{StreamExt, TryStreamExt};
+use object_store::aws::{AmazonS3, AmazonS3Builder, S3ConditionalPut};
+use object_store::path::Path as Key;
+use object_store::{ObjectStore, PutMode};
+use percent_encoding::percent_decode_str;
+
+use crate::storage::Storage;
+
+// never listed, an empty folder is made only of its marker
+const FOLDER_MARKER: &str = ".folder";
+// chunks of an object being read that are kept in memory
+const READ_BUFFER_SIZE: usize = 16;
+
+// no Debug, it would print the secret key
+#[derive(Clone)]
+pub struct S3Config {
+    /// Base url of the object store, like `http://minio:9000`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Prepended to every key, so that a bucket can be shared
+    pub prefix: String,
+}
+
+fn invalid_input<T: ToString>(e: T) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+}
+
+/// A file being written to memory, uploaded once closed
+#[derive(Debug)]
+pub struct S3FileWriter {
+    content: Vec<u8>,
+    key: Key,
+}
+
+impl Write for S3FileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.content.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The content of an object, received in chunks while it is read
+pub struct S3FileReader {
+    chunks: mpsc::Receiver<Result<Vec<u8>, std::io::Error>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for S3FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.position = 0;
+                }
+                // the whole object was received
+                Err(_) => return Ok(0),
+            }
+        }
+        let read = (&self.chunk[self.position..]).read(buf)?;
+        self.position += read;
+        Ok(read)
+    }
+}
+
+pub struct S3Storage {
+    config: S3Config,
+    store: Arc<AmazonS3>,
+    prefix: Key,
+    runtime: Option<tokio::runtime::Runtime>,
+    root: PathBuf,
+}
+
+impl std::fmt::Debug for S3Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Storage")
+            .field("endpoint", &self.config.endpoint)
+            .field("bucket", &self.config.bucket)
+            .field("prefix", &self.config.prefix)
+            .finish()
+    }
+}
+
+impl Drop for S3Storage {
+    // a runtime cannot be dropped from an async task, where the storage may be dropped
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl S3Storage {
+    /// Uses the bucket as a storage whose root is `/`, the bucket must already exist
+    pub fn new(config: S3Config) -> Result<Self, std::io::Error> {
+        let store = AmazonS3Builder::new()
+            .with_endpoint(&config.endpoint)
+            .with_allow_http(config.endpoint.starts_with("http://"))
+            .with_region(&config.region)
+            .with_bucket_name(&config.bucket)
+            .with_access_key_id(&config.access_key)
+            .with_secret_access_key(&config.secret_key)
+            .with_conditional_put(S3ConditionalPut::ETagMatch)
+            .build()
+            .map_err(invalid_input)?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("s3-storage")
+            .enable_all()
+            .build()?;
+        let storage = Self {
+            prefix: config
+                .prefix
+                .split('/')
+                .filter(|part| !part.is_empty())
+                .collect(),
+            config,
+            store: Arc::new(store),
+            runtime: Some(runtime),
+            root: PathBuf::from("/"),
+        };
+        // fail early on a wrong configuration
+        let (store, prefix) = (storage.store.clone(), storage.prefix.clone());
+        storage.run(async move { store.list_with_delimiter(Some(&prefix)).await })?;
+        Ok(storage)
+    }
+
+    // Waits for a request sent on the runtime of the storage
+    fn run<T: Send + 'static>(
+        &self,
+        request: impl Future<Output = object_store::Result<T>> + Send + 'static,
+    ) -> Result<T, std::io::Error> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.runtime().spawn(async move {
+            let _ = tx.send(request.await);
+        });
+        rx.recv()
+            .map_err(|_| std::io::Error::other("S3 request interrupted"))?
+            .map_err(std::io::Error::from)
+    }
+
+    fn runtime(&self) -> &tokio::runtime::Runtime {
+        self.runtime
+            .as_ref()
+            .expect("The runtime lives as long as the storage")
+    }
+
+    fn key(&self, path: &Path) -> Result<Key, std::io::Error> {
+        let relative = path
+            .strip_prefix(&self.root)
+            .map_err(|_| invalid_input(format!("Path outside of the storage: {:?}", path)))?;
+        let mut parts: Vec<String> = self.prefix.parts().map(|part| decode(&part)).collect();
+        for component in relative.components() {
+            match component {
+                Component::Normal(name) => parts.push(name.to_string_lossy().into_owned()),
+                Component::CurDir => {}
+                _ => return Err(invalid_input(format!("Invalid path: {:?}", path))),
+            }
+        }
+        Ok(parts.iter().map(String::as_str).collect())
+    }
+
+    fn path(&self, key: &Key) -> PathBuf {
+        match key.prefix_match(&self.prefix) {
+            Some(parts) => parts.fold(self.root.clone(), |path, part| path.join(decode(&part))),
+            None => self.root.clone(),
+        }
+    }
+
+    // Keys of every object inside the folder and its subfolders, markers included
+    fn list_keys(&self, key: Key) -> Result<Vec<Key>, std::io::Error> {
+        let store = self.store.clone();
+        self.run(async move {
+            let prefix = Some(&key).filter(|key| key.parts().next().is_some());
+            store
+                .list(prefix)
+                .map_ok(|meta| meta.location)
+                .try_collect()
+                .await
+        })
+    }
+
+    fn put(&self, key: Key, content: Vec<u8>) -> Result<(), std::io::Error> {
+        let store = self.store.clone();
+        self.run(async move { store.put(&key, content.into()).await })
+            .map(|_| ())
+    }
+
+    fn delete_key(&self, key: Key) -> Result<(), std::io::Error> {
+        let store = self.store.clone();
+        self.run(async move { store.delete(&key).await })
+    }
+}
+
+fn decode(part: &object_store::path::PathPart) -> String {
+    percent_decode_str(part.as_ref())
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+fn is_marker(key: &Key) -> bool {
+    key.filename() == Some(FOLDER_MARKER)
+}
+
+impl Storage for S3Storage {
+    type Reader = S3FileReader;
+    type Writer = S3FileWriter;
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn create_folder(&self, path: &Path) -> Result<(), std::io::Error> {
+        let key = self.key(path)?;
+        if key == self.prefix || self.is_dir(path)? {
+            return Ok(());
+        }
+        self.put(key.child(FOLDER_MARKER), vec![])
+    }
+
+    fn list_folder(&self, path: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        let key = self.key(path)?;
+        let store = self.store.clone();
+        let listing = self.run(async move {
+            let prefix = Some(&key).filter(|key| key.parts().next().is_some());
+            store.list_with_delimiter(prefix).await
+        })?;
+        Ok(listing
+            .objects
+            .iter()
+            .map(|meta| &meta.location)
+            .filter(|key| !is_marker(key))
+            .chain(listing.common_prefixes.iter())
+            .map(|key| self.path(key))
+            .collect())
+    }
+
+    // a single listing, without going through every folder
+    fn list_files(&self, path: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        Ok(self
+            .list_keys(self.key(path)?)?
+            .iter()
+            .filter(|key| !is_marker(key))
+            .map(|key| self.path(key))
+            .collect())
+    }
+
+    fn is_file(&self, path: &Path) -> Result<bool, std::io::Error> {
+        let key = self.key(path)?;
+        if key == self.prefix {
+            return Ok(false);
+        }
+        let store = self.store.clone();
+        match self.run(async move { store.head(&key).await }) {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> Result<bool, std::io::Error> {
+        let key = self.key(path)?;
+        let store = self.store.clone();
+        let first = self.run(async move {
+            let prefix = Some(&key).filter(|key| key.parts().next().is_some());
+            store.list(prefix).next().await.transpose()
+        })?;
+        Ok(first.is_some())
+    }
+
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+        self.put(self.key(path)?, content.to_vec())
+    }
+
+    fn create_new_file(&self, path: &Path, content: &[u8]) -> Result<bool, std::io::Error> {
+        let key = self.key(path)?;
+        let (store, content) = (self.store.clone(), content.to_vec());
+        self.run(async move {
+            match store
+                .put_opts(&key, content.into(), PutMode::Create.into())
+                .await
+            {
+                Ok(_) => Ok(true),
+                Err(object_store::Error::AlreadyExists { .. }) => Ok(false),
+                Err(err) => Err(err),
+            }
+        })
+    }
+
+    fn create_file(&self, path: &Path) -> Result<S3FileWriter, std::io::Error> {
+        Ok(S3FileWriter {
+            content: vec![],
+            key: self.key(path)?,
+        })
+    }
+
+    fn close_file(&self, writer: S3FileWriter) -> Result<(), std::io::Error> {
+        self.put(writer.key, writer.content)
+    }
+
+    fn open_file(&self, path: &Path) -> Result<Self::Reader, std::io::Error> {
+        let key = self.key(path)?;
+        let store = self.store.clone();
+        let object = self.run(async move { store.get(&key).await })?;
+        let (tx, rx) = mpsc::sync_channel(READ_BUFFER_SIZE);
+        // the chunks are sent from a blocking thread, as the channel blocks when full
+        let mut chunks = object.into_stream();
+        let handle = self.runtime().handle().clone();
+        self.runtime().spawn_blocking(move || {
+            while let Some(chunk) = handle.block_on(chunks.next()) {
+                let chunk = chunk
+                    .map(|chunk| chunk.to_vec())
+                    .map_err(std::io::Error::from);
+                let failed = chunk.is_err();
+                if tx.send(chunk).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Ok(S3FileReader {
+            chunks: rx,
+            chunk: vec![],
+            position: 0,
+        })
+    }
+
+    // objects cannot be moved, a crash in between leaves both copies behind
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), std::io::Error> {
+        self.copy_file(from, to)?;
+        self.delete_key(self.key(from)?)
+    }
+
+    fn copy_file(&self, from: &Path, to: &Path) -> Result<(), std::io::Error> {
+        let (from, to) = (self.key(from)?, self.key(to)?);
+        let store = self.store.clone();
+        self.run(async move { store.copy(&from, &to).await })
+    }
+
+    fn delete_item(&self, path: &Path) -> Result<(), std::io::Error> {
+        let mut found = false;
+        if self.is_file(path)? {
+            self.delete_key(self.key(path)?)?;
+            found = true;
+        }
+        let key = self.key(path)?;
+        if key != self.prefix {
+            for key in self.list_keys(key)? {
+                self.delete_key(key)?;
+                found = true;
+            }
+        }
+        if !found {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Item not found",
+            ));
+        }
+        Ok(())
+    }
+
+    // objects appear only once completely uploaded, nothing is left behind
+    fn remove_temp_files(&self, _path: Option<&Path>) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use protos::scoring::user::Icpc;
+use protos::service::submission::get_icpc_scoreboard_response::{Problem, User};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Outcome of a submission, as far as ICPC scoring is concerned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IcpcVerdict {
+    Accepted,
+    Rejected,
+    CompilationError,
+    Pending, // the result is hidden by the scoreboard freeze
+}
+
+#[derive(Clone, Debug)]
+pub struct IcpcSubmission {
+    pub user: String,
+    pub problem_id: u64,
+    pub elapsed: Duration, // since the contest start
+    pub verdict: IcpcVerdict,
+}
+
+/// Ranking of the users with at least a submission, best first.
+/// Ties in solved problems and penalty are broken by the earliest last accepted submission,
+/// then by name
+pub fn calc_icpc_scoreboard(submissions: &[IcpcSubmission], opts: &Icpc) -> Vec<User> {
+    let mut submissions: Vec<&IcpcSubmission> = submissions.iter().collect();
+    submissions.sort_by_key(|sub| sub.elapsed);
+
+    let mut users: BTreeMap<&str, BTreeMap<u64, Problem>> = BTreeMap::new();
+    for sub in submissions {
+        let problem = users
+            .entry(&sub.user)
+            .or_default()
+            .entry(sub.problem_id)
+            .or_insert_with(|| Problem {
+                problem_id: sub.problem_id,
+                ..Default::default()
+            });
+        if problem.solved_at.is_some() {
+            continue; // submissions after the accepted one do not count
+        }
+        match sub.verdict {
+            IcpcVerdict::Accepted => problem.solved_at = Some(sub.elapsed.into()),
+            IcpcVerdict::Rejected => problem.rejected += 1,
+            IcpcVerdict::CompilationError if opts.count_compilation_errors => problem.rejected += 1,
+            IcpcVerdict::CompilationError => {}
+            IcpcVerdict::Pending => *problem.pending.get_or_insert(0) += 1,
+        }
+    }
+
+    let mut first_solved: BTreeMap<u64, Duration> = BTreeMap::new();
+    for problem in users.values().flat_map(|problems| problems.values()) {
+        if let Some(solved_at) = problem.solved_at.clone().map(Duration::from) {
+            let first = first_solved.entry(problem.problem_id).or_insert(solved_at);
+            *first = (*first).min(solved_at);
+        }
+    }
+
+    let mut ranking: Vec<(User, Duration)> = users
+        .into_iter()
+        .map(|(user, problems)| {
+            let solved: Vec<(Duration, u32)> = problems
+                .values()
+                .filter_map(|p| p.solved_at.clone().map(|t| (Duration::from(t), p.rejected)))
+                .collect();
+            let penalty_minutes = solved
+                .iter()
+                .map(|&(solved_at, rejected)| {
+                    solved_at.as_secs() / 60 + rejected as u64 * opts.penalty_minutes as u64
+                })
+                .sum();
+            let last_solved = solved.iter().map(|&(solved_at, _)| solved_at).max();
+            let problems = problems
+                .into_values()
+                .map(|mut p| {
+                    let solved_at = p.solved_at.clone().map(Duration::from);
+                    p.first_to_solve = Some(
+                        solved_at.is_some()
+                            && solved_at == first_solved.get(&p.problem_id).copied(),
+                    );
+                    p
+                })
+                .collect();
+            (
+                User {
+                    user: user.to_string(),
+                    solved: solved.len() as u32,
+                    penalty_minutes,
+                    problems,
+                },
+                last_solved.unwrap_or_default(),
+            )
+        })
+        .collect();
+    ranking.sort_by(|(a, a_last), (b, b_last)| {
+        (Reverse(a.solved), a.penalty_minutes, a_last, &a.user).cmp(&(
+            Reverse(b.solved),
+            b.penalty_minutes,
+            b_last,
+            &b.user,
+        ))
+    });
+    ranking.into_iter().map(|(user, _)| user).collect()
+}
+
+/// Next result revealed by the resolver: the first pending problem of the lowest ranked user
+/// with one, as (user, problem id)
+pub fn next_to_reveal(scoreboard: &[User]) -> Option<(String, u64)> {
+    scoreboard.iter().rev().find_map(|user| {
+        user.problems
+            .iter()
+            .find(|p| p.pending() > 0)
+            .map(|p| (user.user.clone(), p.problem_id))
+    })
+}
@@ -1,9 +1,16 @@
-mod my_score;
+mod icpc;
+mod rational;
+mod relative;
 mod score;
+mod user;
+pub use icpc::{calc_icpc_scoreboard, next_to_reveal, IcpcSubmission, IcpcVerdict};
+pub use relative::{apply_relative_scoring, best_objectives, has_relative_scoring, relative_score};
 use score::ScoreTrait;
+use std::collections::{HashSet, VecDeque};
+pub use user::{calc_user_ranking, calc_user_score, UserProblemResult};
 
 type ProtoScore = protos::common::Score;
-type Score = my_score::MyScore;
+type Score = rational::Rational;
 
 fn transpose<T>(v: Vec<Vec<T>>) -> Vec<Vec<T>> {
     assert!(!v.is_empty());
@@ -19,48 +26,98 @@ fn transpose<T>(v: Vec<Vec<T>>) -> Vec<Vec<T>> {
         .collect()
 }
 
+/// Ids of the testcases scored in the subtask: its own ones, then the ones of its
+/// parent and of its dependencies, recursively, each once
+pub fn subtask_testcases(
+    problem: &protos::service::evaluation::Problem,
+    subtask_id: u64,
+) -> Vec<u64> {
+    let mut visited = HashSet::new();
+    let mut seen = HashSet::new();
+    let mut testcases = vec![];
+    let mut queue = VecDeque::from(vec![subtask_id]);
+    while let Some(id) = queue.pop_front() {
+        if !visited.insert(id) {
+            continue;
+        }
+        if let Some(subtask) = problem.subtasks.iter().find(|s| s.id == id) {
+            testcases.extend(subtask.testcases_id.iter().filter(|&&tc| seen.insert(tc)));
+            queue.extend(subtask.parent.iter().chain(subtask.dependencies.iter()));
+        }
+    }
+    testcases
+}
+
+/// Score of a subtask from the results of all the testcases given by `subtask_testcases`
 pub fn calc_subtask_score(
     testcases: &[protos::evaluation::TestcaseResult],
     opts: &protos::scoring::Subtask,
 ) -> ProtoScore {
-    let method = protos::scoring::subtask::Method::from_i32(opts.method).unwrap();
+    use protos::scoring::subtask::Method;
+    let method = Method::from_i32(opts.method).unwrap();
     let testcase_scores = testcases.iter().map(|x| Score::from(x.score.clone()));
+    let testcase_count = Score::from(ProtoScore {
+        score: testcases.len() as f64,
+        ..Default::default()
+    }); // TODO: better (maybe mul for usize for Score)
     let mut ans = match method {
-        protos::scoring::subtask::Method::Min => testcase_scores.min().unwrap_or_else(Score::zero),
-        protos::scoring::subtask::Method::Sum => testcase_scores.sum(),
+        Method::Min => testcase_scores.min().unwrap_or_else(Score::zero),
+        Method::Sum => testcase_scores.sum(),
+        Method::Product => testcase_scores
+            .reduce(|a, b| a * b)
+            .unwrap_or_else(Score::zero),
+        Method::Threshold => {
+            let threshold = opts.threshold.clone().map_or_else(Score::one, Score::from);
+            match testcase_scores.min() {
+                Some(min) if min >= threshold => Score::one(),
+                _ => Score::zero(),
+            }
+        }
+        Method::Average if testcases.is_empty() => Score::zero(),
+        Method::Average => testcase_scores.sum::<Score>() / testcase_count,
+        Method::MinPartial => testcases
+            .iter()
+            .map(|x| match x.outcome() {
+                protos::evaluation::testcase_result::Outcome::Ok => {
+                    Score::from(x.score.clone()).clamp(Score::zero(), Score::one())
+                }
+                _ => Score::zero(),
+            })
+            .min()
+            .unwrap_or_else(Score::zero),
     };
     let max_score = match method {
-        protos::scoring::subtask::Method::Min => Score::one(),
-        protos::scoring::subtask::Method::Sum => Score::from(ProtoScore {
-            score: testcases.len() as f64,
-        }), // TODO: better (maybe mul for usize for Score)
+        Method::Sum => testcase_count,
+        _ => Score::one(),
     };
     ans.rescale(max_score, Score::from(opts.max_score.clone()));
     ans.into()
 }
 
+/// Score of a single submission, the score of the problem if it were the only one
 pub fn calc_submission_score(
     subtasks: &[protos::evaluation::SubtaskResult],
-    _opts: &protos::scoring::Problem,
+    opts: &protos::scoring::Problem,
 ) -> ProtoScore {
-    //let method = protos::scoring::problem::Method::from_i32(opts.method).unwrap();
-
-    subtasks
-        .iter()
-        .map(|x| Score::from(x.score.clone()))
-        .sum::<Score>()
-        .into()
+    let submission = protos::evaluation::EvaluationResult {
+        subtask_results: subtasks.to_vec(),
+        ..Default::default()
+    };
+    calc_problem_score(&[submission], opts, None)
 }
 
+/// Score of a problem from all the submissions of a participant in chronological order,
+/// `selected` is the index of the submission chosen by the participant, if any
 pub fn calc_problem_score(
     submissions: &[protos::evaluation::EvaluationResult],
     opts: &protos::scoring::Problem,
+    selected: Option<usize>,
 ) -> ProtoScore {
     let method = protos::scoring::problem::Method::from_i32(opts.method).unwrap();
 
-    let submission_scores: Vec<Vec<Score>> = submissions
+    // submissions that did not compile have no subtasks, and a score of zero
+    let mut submission_scores: Vec<Vec<Score>> = submissions
         .iter()
-        .filter(|sub| !sub.subtask_results.is_empty())
         .map(|sub| {
             sub.subtask_results
                 .iter()
@@ -70,25 +127,48 @@ pub fn calc_problem_score(
         .collect();
 
     match method {
-        protos::scoring::problem::Method::SumMax => transpose(submission_scores)
-            .into_iter()
-            .map(|subtask_scores| {
-                subtask_scores
-                    .into_iter()
-                    .map(Score::from)
-                    .max()
-                    .unwrap_or_else(Score::zero)
-            })
-            .sum(),
+        protos::scoring::problem::Method::SumMax => {
+            submission_scores.retain(|subtask_scores| !subtask_scores.is_empty());
+            if submission_scores.is_empty() {
+                return Score::zero().into();
+            }
+            transpose(submission_scores)
+                .into_iter()
+                .map(|subtask_scores| subtask_scores.into_iter().max().unwrap_or_else(Score::zero))
+                .sum()
+        }
         protos::scoring::problem::Method::MaxSum => submission_scores
             .into_iter()
-            .map(|subtask_scores| subtask_scores.into_iter().map(Score::from).sum::<Score>())
+            .map(|subtask_scores| subtask_scores.into_iter().sum::<Score>())
             .max()
             .unwrap_or_else(Score::zero),
+        protos::scoring::problem::Method::Last => submission_scores
+            .pop()
+            .map(|subtask_scores| subtask_scores.into_iter().sum::<Score>())
+            .unwrap_or_else(Score::zero),
+        protos::scoring::problem::Method::Selected => {
+            let last = submission_scores.len().checked_sub(1);
+            selected
+                .filter(|&i| i < submission_scores.len())
+                .or(last)
+                .map(|i| submission_scores.swap_remove(i).into_iter().sum::<Score>())
+                .unwrap_or_else(Score::zero)
+        }
     }
     .into()
 }
 
-pub fn calc_user_score() -> ProtoScore {
-    unimplemented!();
+/// Whether the score is the maximum one of the problem, the sum of the ones of its subtasks
+pub fn is_max_score(score: &ProtoScore, problem: &protos::service::evaluation::Problem) -> bool {
+    let max_score: Score = problem
+        .subtasks
+        .iter()
+        .map(|subtask| Score::from(subtask.scoring.max_score.clone()))
+        .sum();
+    Score::from(score.clone()) >= max_score
+}
+
+/// Score rounded to the given number of decimals, for display
+pub fn format_score(score: &ProtoScore, decimals: usize) -> String {
+    Score::from(score.clone()).to_decimal_string(decimals)
 }
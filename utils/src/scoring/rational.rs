@@ -0,0 +1,222 @@
+use super::score::ScoreTrait;
+use super::ProtoScore;
+use protos::common::Fraction;
+use std::cmp::Ord;
+use std::iter::Sum;
+use std::ops::{Add, Div, Mul};
+
+// largest denominator considered when recovering a fraction from a float
+const MAX_FLOAT_DENOMINATOR: u128 = 1_000_000_000;
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+// last convergent of the continued fraction of num/den with both terms within the bounds
+fn approximate(mut num: u128, mut den: u128, max_num: u128, max_den: u128) -> (u128, u128) {
+    let (mut p0, mut q0, mut p1, mut q1) = (0u128, 1u128, 1u128, 0u128);
+    while den != 0 {
+        let a = num / den;
+        let next = a
+            .checked_mul(p1)
+            .and_then(|x| x.checked_add(p0))
+            .zip(a.checked_mul(q1).and_then(|x| x.checked_add(q0)));
+        match next {
+            Some((p2, q2)) if p2 <= max_num && q2 <= max_den => {
+                p0 = p1;
+                q0 = q1;
+                p1 = p2;
+                q1 = q2;
+            }
+            _ => break,
+        }
+        let r = num - a * den;
+        num = den;
+        den = r;
+    }
+    if q1 == 0 {
+        (max_num, 1) // too big to be represented
+    } else {
+        (p1, q1)
+    }
+}
+
+/// Exact score, as a fraction in lowest terms
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rational {
+    num: i64,
+    den: u64,
+}
+impl Rational {
+    // values that do not fit are replaced by their closest representable approximation,
+    // a zero denominator gives 0
+    fn new(num: i128, den: u128) -> Self {
+        if den == 0 {
+            return Self::zero();
+        }
+        let g = gcd(num.unsigned_abs(), den);
+        let (abs_num, den) = (num.unsigned_abs() / g, den / g);
+        let (abs_num, den) = if abs_num > i64::MAX as u128 || den > u64::MAX as u128 {
+            approximate(abs_num, den, i64::MAX as u128, u64::MAX as u128)
+        } else {
+            (abs_num, den)
+        };
+        Self {
+            num: if num < 0 {
+                -(abs_num as i64)
+            } else {
+                abs_num as i64
+            },
+            den: den as u64,
+        }
+    }
+    // NaN is 0, infinities are replaced by the largest representable values
+    fn from_f64(x: f64) -> Self {
+        if x.is_nan() {
+            return Self::zero();
+        }
+        let (mut p0, mut q0, mut p1, mut q1) = (0i128, 1i128, 1i128, 0i128);
+        let mut y = x.abs();
+        loop {
+            let a = y.floor();
+            if a > i64::MAX as f64 {
+                break;
+            }
+            let (p2, q2) = (a as i128 * p1 + p0, a as i128 * q1 + q0);
+            if q2 as u128 > MAX_FLOAT_DENOMINATOR {
+                break;
+            }
+            p0 = p1;
+            q0 = q1;
+            p1 = p2;
+            q1 = q2;
+            if ((p1 as f64 / q1 as f64) - x.abs()).abs() <= f64::EPSILON * x.abs() || y == a {
+                break;
+            }
+            y = 1.0 / (y - a);
+        }
+        if q1 == 0 {
+            return Self::new(if x < 0.0 { i64::MIN + 1 } else { i64::MAX } as i128, 1);
+        }
+        Self::new(if x < 0.0 { -p1 } else { p1 }, q1 as u128)
+    }
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+    /// Decimal representation, rounded half away from zero (to at most 18 decimals)
+    pub fn to_decimal_string(self, decimals: usize) -> String {
+        let decimals = decimals.min(18);
+        let den = self.den as i128;
+        let scaled = (self.num as i128).abs() * 10i128.pow(decimals as u32);
+        let mut rounded = scaled / den;
+        if (scaled % den) * 2 >= den {
+            rounded += 1;
+        }
+        let sign = if self.num < 0 && rounded != 0 {
+            "-"
+        } else {
+            ""
+        };
+        let digits = format!("{:0>width$}", rounded, width = decimals + 1);
+        let (int_part, frac_part) = digits.split_at(digits.len() - decimals);
+        if decimals == 0 {
+            format!("{}{}", sign, int_part)
+        } else {
+            format!("{}{}.{}", sign, int_part, frac_part)
+        }
+    }
+}
+impl Ord for Rational {
+    fn cmp(&self, o: &Self) -> std::cmp::Ordering {
+        (self.num as i128 * o.den as i128).cmp(&(o.num as i128 * self.den as i128))
+    }
+}
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, o: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(o))
+    }
+}
+
+impl Sum for Rational {
+    fn sum<I>(it: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        it.fold(Self::zero(), |a, b| a + b)
+    }
+}
+impl Add for Rational {
+    type Output = Self;
+    fn add(self, o: Self) -> Self {
+        match (self.num as i128 * o.den as i128).checked_add(o.num as i128 * self.den as i128) {
+            Some(num) => Self::new(num, self.den as u128 * o.den as u128),
+            None => Self::from_f64(self.to_f64() + o.to_f64()),
+        }
+    }
+}
+impl Mul for Rational {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        Self::new(
+            self.num as i128 * o.num as i128,
+            self.den as u128 * o.den as u128,
+        )
+    }
+}
+// x/0 = 0, like the rescaling of a score whose maximum is 0
+impl Div for Rational {
+    type Output = Self;
+    fn div(self, o: Self) -> Self {
+        let num = self.num as i128 * o.den as i128;
+        Self::new(
+            if o.num < 0 { -num } else { num },
+            self.den as u128 * o.num.unsigned_abs() as u128,
+        )
+    }
+}
+impl From<ProtoScore> for Rational {
+    fn from(score: ProtoScore) -> Self {
+        match score.exact {
+            Some(exact) if exact.denominator != 0 => {
+                Self::new(exact.numerator as i128, exact.denominator as u128)
+            }
+            _ => Self::from_f64(score.score),
+        }
+    }
+}
+impl From<Rational> for ProtoScore {
+    // the fraction is only sent when it cannot be recovered from the float
+    fn from(score: Rational) -> ProtoScore {
+        let approximation = score.to_f64();
+        ProtoScore {
+            score: approximation,
+            exact: Some(Fraction {
+                numerator: score.num,
+                denominator: score.den,
+            })
+            .filter(|_| Rational::from_f64(approximation) != score),
+        }
+    }
+}
+impl ScoreTrait for Rational {
+    fn rescale(&mut self, old_max_score: Self, new_max_score: Self) {
+        *self = (*self) * new_max_score / old_max_score;
+    }
+    fn one() -> Self {
+        Self { num: 1, den: 1 }
+    }
+    fn is_one(&self) -> bool {
+        *self == Self::one()
+    }
+    fn zero() -> Self {
+        Self { num: 0, den: 1 }
+    }
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+}
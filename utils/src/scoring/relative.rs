@@ -0,0 +1,101 @@
+use super::{ProtoScore, Score, ScoreTrait};
+use protos::evaluation::{testcase_result::Outcome, EvaluationResult, TestcaseResult};
+use protos::scoring::relative::Goal;
+use protos::service::evaluation::Problem;
+use std::collections::HashMap;
+
+/// Goal of the relative scoring of the i-th subtask, None if it is scored normally
+fn subtask_goal(problem: &Problem, subtask: usize) -> Option<Goal> {
+    problem
+        .subtasks
+        .get(subtask)
+        .and_then(|subtask| subtask.scoring.relative.as_ref())
+        .or(problem.scoring.relative.as_ref())
+        .map(|relative| relative.goal())
+}
+
+/// Whether the score of any subtask of the problem is relative to the best objectives
+pub fn has_relative_scoring(problem: &Problem) -> bool {
+    (0..problem.subtasks.len()).any(|i| subtask_goal(problem, i).is_some())
+}
+
+// the checker reports the objective as the score, until it is moved by apply_relative_scoring
+fn objective(testcase: &TestcaseResult) -> ProtoScore {
+    testcase
+        .objective
+        .clone()
+        .unwrap_or_else(|| testcase.score.clone())
+}
+
+/// Best objective of each testcase with relative scoring among the results, by testcase id.
+/// Only testcases that ended OK count
+pub fn best_objectives<'a>(
+    problem: &Problem,
+    results: impl IntoIterator<Item = &'a EvaluationResult>,
+) -> HashMap<u64, ProtoScore> {
+    let mut best: HashMap<u64, (Goal, Score)> = HashMap::new();
+    for result in results {
+        for (i, subtask) in result.subtask_results.iter().enumerate() {
+            let goal = match subtask_goal(problem, i) {
+                Some(goal) => goal,
+                None => continue,
+            };
+            for testcase in subtask.testcase_results.iter() {
+                if testcase.outcome() != Outcome::Ok {
+                    continue;
+                }
+                let objective = Score::from(objective(testcase));
+                let entry = best.entry(testcase.id).or_insert((goal, objective));
+                entry.1 = match goal {
+                    Goal::Minimize => entry.1.min(objective),
+                    Goal::Maximize => entry.1.max(objective),
+                };
+            }
+        }
+    }
+    best.into_iter()
+        .map(|(id, (_, objective))| (id, objective.into()))
+        .collect()
+}
+
+/// Score in [0,1] of an objective, compared to the best one
+pub fn relative_score(objective: ProtoScore, best: ProtoScore, goal: Goal) -> ProtoScore {
+    let (objective, best) = (Score::from(objective), Score::from(best));
+    let (num, den) = match goal {
+        Goal::Minimize => (best, objective),
+        Goal::Maximize => (objective, best),
+    };
+    let score = if objective == best {
+        Score::one()
+    } else if den <= Score::zero() {
+        Score::zero()
+    } else {
+        num / den
+    };
+    score.clamp(Score::zero(), Score::one()).into()
+}
+
+/// Scores the testcases of the subtasks with relative scoring against the best objectives,
+/// keeping the objectives reported by the checker. Subtask and submission scores are not updated
+pub fn apply_relative_scoring(
+    problem: &Problem,
+    result: &mut EvaluationResult,
+    best: &HashMap<u64, ProtoScore>,
+) {
+    for (i, subtask) in result.subtask_results.iter_mut().enumerate() {
+        let goal = match subtask_goal(problem, i) {
+            Some(goal) => goal,
+            None => continue,
+        };
+        for testcase in subtask.testcase_results.iter_mut() {
+            let objective = objective(testcase);
+            testcase.score = match best.get(&testcase.id) {
+                Some(best) if testcase.outcome() == Outcome::Ok => {
+                    relative_score(objective.clone(), best.clone(), goal)
+                }
+                _ => Score::zero().into(),
+            };
+            testcase.objective = Some(objective);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use super::{ProtoScore, Score, ScoreTrait};
+use protos::scoring::user::{method::Aggregation, Method};
+use protos::scoring::User;
+use std::cmp::Reverse;
+use std::time::Duration;
+
+/// Result of a participant on a problem, as far as the user scoring is concerned
+#[derive(Clone, Debug, Default)]
+pub struct UserProblemResult {
+    pub problem_id: u64,
+    pub score: ProtoScore,
+    pub wrong_submissions: u32, // evaluated without the maximum score of the problem
+    pub last_change: Duration, // since the contest start, of the last submission changing the score
+}
+
+fn count(n: u64) -> Score {
+    Score::from(ProtoScore {
+        score: n as f64,
+        ..Default::default()
+    })
+}
+
+/// Value of a user scoring method on the results of a participant, see scoring.User.Method
+pub fn calc_user_score(problems: &[UserProblemResult], method: &Method) -> ProtoScore {
+    let score_weight = Score::from(method.score_weight.clone());
+    let wrong_weight = Score::from(method.wrong_submission_count_weight.clone());
+    let time_weight = Score::from(method.time_secs_weight.clone());
+    let values = problems.iter().map(|problem| {
+        score_weight * Score::from(problem.score.clone())
+            + wrong_weight * count(problem.wrong_submissions as u64)
+            + time_weight * count(problem.last_change.as_secs())
+    });
+    match method.aggregation_method() {
+        Aggregation::Sum => values.sum(),
+        Aggregation::Max => values.max().unwrap_or_else(Score::zero),
+    }
+    .into()
+}
+
+/// Ranking of the participants by decreasing main score, then tiebreakers, then by name.
+/// Returns the users with their main score followed by the tiebreakers
+pub fn calc_user_ranking(
+    users: &[(String, Vec<UserProblemResult>)],
+    opts: &User,
+) -> Vec<(String, Vec<ProtoScore>)> {
+    let mut ranking: Vec<(&String, Vec<Score>)> = users
+        .iter()
+        .map(|(user, problems)| {
+            let scores = std::iter::once(&opts.main)
+                .chain(opts.tiebreakers.iter())
+                .map(|method| Score::from(calc_user_score(problems, method)))
+                .collect();
+            (user, scores)
+        })
+        .collect();
+    ranking.sort_by(|(a, a_scores), (b, b_scores)| {
+        (Reverse(a_scores), a).cmp(&(Reverse(b_scores), b))
+    });
+    ranking
+        .into_iter()
+        .map(|(user, scores)| {
+            (
+                user.clone(),
+                scores.into_iter().map(ProtoScore::from).collect(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,48 @@
+//! Session tokens issued by the contest service. A token carries the id of the session and
+//! its expiry, signed with HMAC-SHA256, so forged and expired tokens are rejected without
+//! reading the database. Revoked sessions are the ones no longer stored by the contest service.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub id: u64,
+    pub expires: SystemTime, // rounded down to the second
+}
+
+fn mac(key: &[u8], payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn payload(session: &Session) -> String {
+    let expires = session
+        .expires
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{:x}.{}", session.id, expires.as_secs())
+}
+
+/// Token of the session, as `id.expiry.signature`
+pub fn sign(key: &[u8], session: &Session) -> String {
+    let payload = payload(session);
+    let signature = hex::encode(mac(key, &payload).finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+/// The session of a token signed with `key`, None if the token is malformed, forged
+/// or expired at `now`
+pub fn verify(key: &[u8], token: &str, now: SystemTime) -> Option<Session> {
+    // nothing is parsed before the signature is checked
+    let (payload, signature) = token.rsplit_once('.')?;
+    mac(key, payload)
+        .verify_slice(&hex::decode(signature).ok()?)
+        .ok()?;
+    let (id, expires) = payload.split_once('.')?;
+    let id = u64::from_str_radix(id, 16).ok()?;
+    let expires = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(expires.parse().ok()?))?;
+    (now < expires).then_some(Session { id, expires })
+}
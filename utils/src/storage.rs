@@ -1,87 +1,125 @@
-use std::fs::{DirBuilder, File, ReadDir};
+use std::fs::{DirBuilder, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
 use bincode;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
-pub struct FsStorageHelper {
-    root: PathBuf,
-}
+const TEMP_EXTENSION: &str = ".tmp";
 
-impl FsStorageHelper {
-    pub fn new(root_path: &Path) -> Result<Self, std::io::Error> {
-        DirBuilder::new()
-            .recursive(true)
-            .create(root_path)
-            .map(|_| Self {
-                root: root_path.to_path_buf(),
-            })
-    }
+/// Files organized in folders, kept by one of the storage backends.
+/// Every path is under the root of the storage, writes replace the whole
+/// content of a file at once so readers see either the old or the new content.
+pub trait Storage: std::fmt::Debug + Send + Sync {
+    type Reader: Read + Send + 'static;
+    type Writer: Write + Send;
+
+    fn root(&self) -> &Path;
+
+    /// Creates the folder along with its parents, if missing
+    fn create_folder(&self, path: &Path) -> Result<(), std::io::Error>;
+
+    /// Paths of the items directly inside the folder, none if it does not exist
+    fn list_folder(&self, path: &Path) -> Result<Vec<PathBuf>, std::io::Error>;
+
+    fn is_file(&self, path: &Path) -> Result<bool, std::io::Error>;
+
+    fn is_dir(&self, path: &Path) -> Result<bool, std::io::Error>;
+
+    /// Replaces the content of the file, creating its folder if needed
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<(), std::io::Error>;
+
+    /// Creates the file with the content only if it does not exist yet, atomically even
+    /// between the processes sharing the storage. Returns false if the file already exists
+    fn create_new_file(&self, path: &Path, content: &[u8]) -> Result<bool, std::io::Error>;
+
+    /// Starts writing the file, the content is replaced only once the writer is closed
+    fn create_file(&self, path: &Path) -> Result<Self::Writer, std::io::Error>;
+
+    fn close_file(&self, writer: Self::Writer) -> Result<(), std::io::Error>;
+
+    fn open_file(&self, path: &Path) -> Result<Self::Reader, std::io::Error>;
+
+    /// Moves the file, replacing the destination. The destination is replaced at once,
+    /// but the move can be a copy followed by a delete, leaving the source behind on a crash
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), std::io::Error>;
+
+    /// Copies the file, replacing the destination
+    fn copy_file(&self, from: &Path, to: &Path) -> Result<(), std::io::Error>;
+
+    /// Deletes a file or a folder with everything inside it
+    fn delete_item(&self, path: &Path) -> Result<(), std::io::Error>;
+
+    /// Removes the leftovers of interrupted writes in the folder and its subfolders
+    fn remove_temp_files(&self, path: Option<&Path>) -> Result<(), std::io::Error>;
 
     fn get_root_path(&self, path: Option<&Path>) -> PathBuf {
         match path {
             Some(p) => p.to_path_buf(),
-            None => self.root.clone(),
+            None => self.root().to_path_buf(),
         }
     }
 
-    pub fn add_folder(
+    /// Paths of the files inside the folder and all its subfolders, none if it does not exist
+    fn list_files(&self, path: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        let mut files = vec![];
+        for item in self.list_folder(path)? {
+            if self.is_dir(&item)? {
+                files.append(&mut self.list_files(&item)?);
+            } else {
+                files.push(item);
+            }
+        }
+        Ok(files)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        Ok(self.is_file(path)? || self.is_dir(path)?)
+    }
+
+    fn add_folder(
         &self,
         folder_name: &str,
         path: Option<&Path>,
     ) -> Result<PathBuf, std::io::Error> {
-        let path = self.get_root_path(path);
-        let folder_path = path.join(folder_name);
-        DirBuilder::new()
-            .recursive(true)
-            .create(folder_path.clone())
-            .map(|_| folder_path)
+        let folder_path = self.get_root_path(path).join(folder_name);
+        self.create_folder(&folder_path).map(|_| folder_path)
     }
 
-    pub fn iterate_folder(
+    fn iterate_folder(
         &self,
         folder_name: &str,
         path: Option<&Path>,
-    ) -> Result<ReadDir, std::io::Error> {
-        let path = self.get_root_path(path);
-        let folder_path = path.join(folder_name);
-        folder_path.read_dir()
+    ) -> Result<Vec<PathBuf>, std::io::Error> {
+        self.list_folder(&self.get_root_path(path).join(folder_name))
     }
 
-    pub fn search_item(
+    fn search_item(
         &self,
         path: Option<&Path>,
         item_name: &str,
         extension: Option<&str>,
     ) -> Result<Option<PathBuf>, std::io::Error> {
         let path = self.get_root_path(path);
-        if path.is_dir() {
-            return Ok(path
-                .read_dir()?
-                .flat_map(|res| res.map(|e| e.path()))
-                .find(|el| {
-                    let ok_name = el
-                        .file_stem()
-                        .and_then(|os| os.to_str())
-                        .filter(|&name| name == item_name)
-                        .is_some();
-                    let ok_ext = match extension {
-                        Some(ext) => el
-                            .extension()
-                            .and_then(|os| os.to_str())
-                            .filter(|&extension| extension == ext)
-                            .is_some(),
-                        None => true,
-                    };
-                    ok_name && ok_ext
-                }));
-        }
-        Ok(None)
+        Ok(self.list_folder(&path)?.into_iter().find(|el| {
+            let ok_name = el
+                .file_stem()
+                .and_then(|os| os.to_str())
+                .filter(|&name| name == item_name)
+                .is_some();
+            let ok_ext = match extension {
+                Some(ext) => el
+                    .extension()
+                    .and_then(|os| os.to_str())
+                    .filter(|&extension| extension == ext)
+                    .is_some(),
+                None => true,
+            };
+            ok_name && ok_ext
+        }))
     }
 
-    pub fn save_file(
+    fn save_file(
         &self,
         path: Option<&Path>,
         file_name: &str,
@@ -90,12 +128,10 @@ impl FsStorageHelper {
     ) -> Result<PathBuf, std::io::Error> {
         let mut path = self.get_root_path(path).join(file_name);
         path.set_extension(extension);
-        File::create(path.clone())
-            .and_then(|mut file| file.write_all(content))
-            .map(|_| path)
+        self.write_file(&path, content).map(|_| path)
     }
 
-    pub fn save_file_object<T: Serialize>(
+    fn save_file_object<T: Serialize>(
         &self,
         path: Option<&Path>,
         file_name: &str,
@@ -106,24 +142,161 @@ impl FsStorageHelper {
         Ok(self.save_file(path, file_name, extension, &serialized)?)
     }
 
-    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>, std::io::Error> {
-        File::open(path).and_then(|mut file| {
-            let mut buffer = vec![];
-            file.read_to_end(&mut buffer)?;
-            Ok(buffer)
-        })
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, std::io::Error> {
+        let mut buffer = vec![];
+        self.open_file(path)?.read_to_end(&mut buffer)?;
+        Ok(buffer)
     }
 
-    pub fn read_file_object<T>(&self, path: &Path) -> Result<T, Box<dyn std::error::Error>>
+    fn read_file_object<T>(&self, path: &Path) -> Result<T, Box<dyn std::error::Error>>
     where
         for<'de> T: Deserialize<'de>,
     {
-        let file = File::open(path)?;
-        let deserialized = bincode::deserialize_from(file)?;
+        let deserialized = bincode::deserialize_from(self.open_file(path)?)?;
         Ok(deserialized)
     }
+}
 
-    pub fn delete_item(&self, path: &Path) -> Result<(), std::io::Error> {
+/// Storage in a folder of the local filesystem
+#[derive(Debug)]
+pub struct FsStorageHelper {
+    root: PathBuf,
+}
+
+impl FsStorageHelper {
+    pub fn new(root_path: &Path) -> Result<Self, std::io::Error> {
+        DirBuilder::new()
+            .recursive(true)
+            .create(root_path)
+            .map(|_| Self {
+                root: root_path.to_path_buf(),
+            })
+    }
+}
+
+/// A file being written next to its final path, moved in place once closed
+#[derive(Debug)]
+pub struct FsFileWriter {
+    file: File,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl Write for FsFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Storage for FsStorageHelper {
+    type Reader = File;
+    type Writer = FsFileWriter;
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn create_folder(&self, path: &Path) -> Result<(), std::io::Error> {
+        DirBuilder::new().recursive(true).create(path)
+    }
+
+    fn list_folder(&self, path: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        if !path.is_dir() {
+            return Ok(vec![]);
+        }
+        path.read_dir()?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect()
+    }
+
+    fn is_file(&self, path: &Path) -> Result<bool, std::io::Error> {
+        Ok(path.is_file())
+    }
+
+    fn is_dir(&self, path: &Path) -> Result<bool, std::io::Error> {
+        Ok(path.is_dir())
+    }
+
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+        let mut writer = self.create_file(path)?;
+        writer.write_all(content)?;
+        self.close_file(writer)
+    }
+
+    // a link to a complete temporary file, made only if the path is free
+    fn create_new_file(&self, path: &Path, content: &[u8]) -> Result<bool, std::io::Error> {
+        if let Some(parent) = path.parent() {
+            self.create_folder(parent)?;
+        }
+        loop {
+            // another process may be creating the same file
+            let tmp_path = unique_temp_path(path);
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(content)?;
+            file.sync_all()?;
+            let linked = std::fs::hard_link(&tmp_path, path);
+            let _ = std::fs::remove_file(&tmp_path);
+            match linked {
+                Ok(()) => return Ok(true),
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+                // removed meanwhile with the temporary files by another process
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // write to a temporary file and rename it, so that readers (and crashes)
+    // only ever see either the old or the new content
+    fn create_file(&self, path: &Path) -> Result<FsFileWriter, std::io::Error> {
+        if let Some(parent) = path.parent() {
+            self.create_folder(parent)?;
+        }
+        let tmp_path = temp_path(path);
+        Ok(FsFileWriter {
+            file: File::create(&tmp_path)?,
+            tmp_path,
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn close_file(&self, writer: FsFileWriter) -> Result<(), std::io::Error> {
+        writer.file.sync_all()?;
+        std::fs::rename(&writer.tmp_path, &writer.path)
+    }
+
+    fn open_file(&self, path: &Path) -> Result<File, std::io::Error> {
+        File::open(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), std::io::Error> {
+        if let Some(parent) = to.parent() {
+            self.create_folder(parent)?;
+        }
+        std::fs::rename(from, to)
+    }
+
+    // Links are enough since files are always replaced and never written in place
+    fn copy_file(&self, from: &Path, to: &Path) -> Result<(), std::io::Error> {
+        if let Some(parent) = to.parent() {
+            self.create_folder(parent)?;
+        }
+        let tmp_path = temp_path(to);
+        if tmp_path.is_file() {
+            std::fs::remove_file(&tmp_path)?;
+        }
+        if std::fs::hard_link(from, &tmp_path).is_err() {
+            std::fs::copy(from, &tmp_path)?;
+            File::open(&tmp_path)?.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, to)
+    }
+
+    fn delete_item(&self, path: &Path) -> Result<(), std::io::Error> {
         if path.is_file() {
             return std::fs::remove_file(path);
         } else if path.is_dir() {
@@ -134,4 +307,38 @@ impl FsStorageHelper {
             "Item not found",
         ))
     }
+
+    fn remove_temp_files(&self, path: Option<&Path>) -> Result<(), std::io::Error> {
+        let path = self.get_root_path(path);
+        for entry in path.read_dir()? {
+            let entry_path = entry?.path();
+            if entry_path.is_dir() {
+                self.remove_temp_files(Some(&entry_path))?;
+            } else if is_temp_path(&entry_path) {
+                std::fs::remove_file(&entry_path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(TEMP_EXTENSION);
+    path.with_file_name(name)
+}
+
+fn unique_temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}{}", crate::gen_uuid(), TEMP_EXTENSION));
+    path.with_file_name(name)
+}
+
+fn is_temp_path(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|os| os.to_str())
+        .filter(|name| name.starts_with('.') && name.ends_with(TEMP_EXTENSION))
+        .is_some()
 }
@@ -1,6 +1,7 @@
 use super::*;
 
 use crate::scoring::calc_subtask_score;
+use crate::storage::Storage;
 use protos::common::{Duration, Resources, Score};
 use protos::evaluation::*;
 use protos::evaluation::{SubtaskResult, TestcaseResult};
@@ -13,17 +14,33 @@ fn get_test_time() -> std::time::SystemTime {
         + std::time::Duration::from_nanos(101)
 }
 
-const TESTCASE_AC_SCORE: Score = Score { score: 1.0 };
-const WA_SCORE: Score = Score { score: 0.0 };
+const TESTCASE_AC_SCORE: Score = Score {
+    score: 1.0,
+    exact: None,
+};
+const WA_SCORE: Score = Score {
+    score: 0.0,
+    exact: None,
+};
 
 const MIN_SCORING_METHOD: Subtask = Subtask {
     method: subtask::Method::Min as i32,
-    max_score: Score { score: 100.0 },
+    max_score: Score {
+        score: 100.0,
+        exact: None,
+    },
+    threshold: None,
+    relative: None,
 };
 
 const SUM_SCORING_METHOD: Subtask = Subtask {
     method: subtask::Method::Sum as i32,
-    max_score: Score { score: 100.0 },
+    max_score: Score {
+        score: 100.0,
+        exact: None,
+    },
+    threshold: None,
+    relative: None,
 };
 
 #[test]
@@ -230,15 +247,966 @@ fn save_and_read_object_file_fs_storage_helper_test() {
     assert_eq!(unwrapped, "testcontent");
 }
 
+#[test]
+fn save_file_leaves_no_temp_files_fs_storage_helper_test() {
+    let temp_dir = TempDir::default();
+    let test_path = temp_dir.to_path_buf().join("test");
+    let storage = storage::FsStorageHelper::new(&test_path).unwrap();
+    storage.save_file(None, "testfile", "txt", b"123").unwrap();
+    let path = storage.save_file(None, "testfile", "txt", b"4567").unwrap();
+    assert_eq!(storage.read_file(&path).unwrap(), b"4567");
+    assert_eq!(test_path.read_dir().unwrap().count(), 1);
+}
+
+#[test]
+fn remove_temp_files_fs_storage_helper_test() {
+    let temp_dir = TempDir::default();
+    let test_path = temp_dir.to_path_buf().join("test");
+    let storage = storage::FsStorageHelper::new(&test_path).unwrap();
+    let folder = storage.add_folder("testfolder", None).unwrap();
+    storage
+        .save_file(Some(&folder), "testfile", "txt", b"123")
+        .unwrap();
+    std::fs::write(folder.join(".testfile.txt.tmp"), b"12").unwrap();
+    storage.remove_temp_files(None).unwrap();
+    assert_eq!(folder.read_dir().unwrap().count(), 1);
+    assert_eq!(
+        storage.read_file(&folder.join("testfile.txt")).unwrap(),
+        b"123"
+    );
+}
+
+#[test]
+fn commit_transaction_test() {
+    let temp_dir = TempDir::default();
+    let test_path = temp_dir.to_path_buf().join("test");
+    let storage = storage::FsStorageHelper::new(&test_path).unwrap();
+    storage.save_file(None, "old", "txt", b"old").unwrap();
+
+    let mut transaction = transaction::Transaction::begin(&storage, &test_path).unwrap();
+    transaction.write("folder/new.txt", b"new").unwrap();
+    transaction.write_object("object.ser", &42u64).unwrap();
+    transaction.create_folder("empty");
+    transaction.delete("old.txt");
+    // nothing changes before the commit
+    assert!(test_path.join("old.txt").exists());
+    assert!(!test_path.join("folder").exists());
+    transaction.commit().unwrap();
+
+    assert!(!test_path.join("old.txt").exists());
+    assert!(test_path.join("empty").is_dir());
+    assert_eq!(
+        storage
+            .read_file(&test_path.join("folder/new.txt"))
+            .unwrap(),
+        b"new"
+    );
+    assert_eq!(
+        storage
+            .read_file_object::<u64>(&test_path.join("object.ser"))
+            .unwrap(),
+        42
+    );
+}
+
+#[test]
+fn drop_transaction_test() {
+    let temp_dir = TempDir::default();
+    let test_path = temp_dir.to_path_buf().join("test");
+    let storage = storage::FsStorageHelper::new(&test_path).unwrap();
+    storage.save_file(None, "old", "txt", b"old").unwrap();
+    {
+        let mut transaction = transaction::Transaction::begin(&storage, &test_path).unwrap();
+        transaction.write("old.txt", b"new").unwrap();
+        transaction.delete("old.txt");
+    }
+    transaction::recover(&storage, &test_path).unwrap();
+    assert_eq!(test_path.read_dir().unwrap().count(), 1);
+    assert_eq!(
+        storage.read_file(&test_path.join("old.txt")).unwrap(),
+        b"old"
+    );
+}
+
+// A filesystem storage that fails to move a file in place, as if the service crashed there
+#[derive(Debug)]
+struct CrashingStorage {
+    inner: storage::FsStorageHelper,
+    crash_on: &'static str,
+}
+
+impl Storage for CrashingStorage {
+    type Reader = <storage::FsStorageHelper as Storage>::Reader;
+    type Writer = <storage::FsStorageHelper as Storage>::Writer;
+
+    fn root(&self) -> &std::path::Path {
+        self.inner.root()
+    }
+    fn create_folder(&self, path: &std::path::Path) -> Result<(), std::io::Error> {
+        self.inner.create_folder(path)
+    }
+    fn list_folder(
+        &self,
+        path: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, std::io::Error> {
+        self.inner.list_folder(path)
+    }
+    fn is_file(&self, path: &std::path::Path) -> Result<bool, std::io::Error> {
+        self.inner.is_file(path)
+    }
+    fn is_dir(&self, path: &std::path::Path) -> Result<bool, std::io::Error> {
+        self.inner.is_dir(path)
+    }
+    fn write_file(&self, path: &std::path::Path, content: &[u8]) -> Result<(), std::io::Error> {
+        self.inner.write_file(path, content)
+    }
+    fn create_new_file(
+        &self,
+        path: &std::path::Path,
+        content: &[u8],
+    ) -> Result<bool, std::io::Error> {
+        self.inner.create_new_file(path, content)
+    }
+    fn create_file(&self, path: &std::path::Path) -> Result<Self::Writer, std::io::Error> {
+        self.inner.create_file(path)
+    }
+    fn close_file(&self, writer: Self::Writer) -> Result<(), std::io::Error> {
+        self.inner.close_file(writer)
+    }
+    fn open_file(&self, path: &std::path::Path) -> Result<Self::Reader, std::io::Error> {
+        self.inner.open_file(path)
+    }
+    fn rename(&self, from: &std::path::Path, to: &std::path::Path) -> Result<(), std::io::Error> {
+        if to.ends_with(self.crash_on) {
+            return Err(std::io::Error::other("crash"));
+        }
+        self.inner.rename(from, to)
+    }
+    fn copy_file(
+        &self,
+        from: &std::path::Path,
+        to: &std::path::Path,
+    ) -> Result<(), std::io::Error> {
+        self.inner.copy_file(from, to)
+    }
+    fn delete_item(&self, path: &std::path::Path) -> Result<(), std::io::Error> {
+        self.inner.delete_item(path)
+    }
+    fn remove_temp_files(&self, path: Option<&std::path::Path>) -> Result<(), std::io::Error> {
+        self.inner.remove_temp_files(path)
+    }
+}
+
+#[test]
+fn recover_interrupted_transaction_test() {
+    let temp_dir = TempDir::default();
+    let test_path = temp_dir.to_path_buf().join("test");
+    let storage = storage::FsStorageHelper::new(&test_path).unwrap();
+    storage.save_file(None, "a", "txt", b"old a").unwrap();
+    storage.save_file(None, "b", "txt", b"old b").unwrap();
+
+    let mut transaction = transaction::Transaction::begin(&storage, &test_path).unwrap();
+    transaction.write("a.txt", b"new a").unwrap();
+    transaction.write("b.txt", b"new b").unwrap();
+    transaction.commit().unwrap();
+    let committed = test_path.read_dir().unwrap().count();
+
+    // a second transaction crashes halfway through its commit,
+    // after its journal was saved and only the first file was moved in place
+    let crashing = CrashingStorage {
+        inner: storage::FsStorageHelper::new(&test_path).unwrap(),
+        crash_on: "b.txt",
+    };
+    let mut transaction = transaction::Transaction::begin(&crashing, &test_path).unwrap();
+    transaction.write("a.txt", b"newer a").unwrap();
+    transaction.write("b.txt", b"newer b").unwrap();
+    assert!(transaction.commit().is_err());
+    assert_eq!(
+        storage.read_file(&test_path.join("b.txt")).unwrap(),
+        b"new b"
+    );
+    // and a third one crashes before saving its journal
+    let mut transaction = transaction::Transaction::begin(&storage, &test_path).unwrap();
+    transaction.write("a.txt", b"newest a").unwrap();
+    std::mem::forget(transaction);
+
+    transaction::recover(&storage, &test_path).unwrap();
+    assert_eq!(test_path.read_dir().unwrap().count(), committed - 1);
+    assert_eq!(
+        storage.read_file(&test_path.join("a.txt")).unwrap(),
+        b"newer a"
+    );
+    assert_eq!(
+        storage.read_file(&test_path.join("b.txt")).unwrap(),
+        b"newer b"
+    );
+}
+
+fn commit_with_history<S: Storage>(
+    storage: &S,
+    path: &std::path::Path,
+    description: &str,
+    change: impl FnOnce(&mut transaction::Transaction<S>),
+) -> Option<u64> {
+    let history = history::History::new(storage, path, &[std::path::Path::new("ignored.txt")]);
+    let mut transaction = transaction::Transaction::begin(storage, path).unwrap();
+    change(&mut transaction);
+    let version = history.record(&mut transaction, description).unwrap();
+    transaction.commit().unwrap();
+    version
+}
+
+#[test]
+fn record_versions_test() {
+    let temp_dir = TempDir::default();
+    let test_path = temp_dir.to_path_buf().join("test");
+    let storage = storage::FsStorageHelper::new(&test_path).unwrap();
+    // files written before the history are part of the first version
+    storage.save_file(None, "old", "txt", b"old").unwrap();
+
+    let first = commit_with_history(&storage, &test_path, "first", |transaction| {
+        transaction.write("folder/a.txt", b"a").unwrap();
+        transaction.write("ignored.txt", b"ignored").unwrap();
+    });
+    assert_eq!(first, Some(1));
+    let unchanged = commit_with_history(&storage, &test_path, "unchanged", |transaction| {
+        transaction.write("folder/a.txt", b"a").unwrap();
+        transaction.write("ignored.txt", b"changed").unwrap();
+    });
+    assert_eq!(unchanged, None);
+    let second = commit_with_history(&storage, &test_path, "second", |transaction| {
+        transaction.write("folder/a.txt", b"new a").unwrap();
+        transaction.write("b.txt", b"old").unwrap();
+        transaction.delete("old.txt");
+    });
+    assert_eq!(second, Some(2));
+
+    let history = history::History::new(&storage, &test_path, &[]);
+    let versions = history.versions().unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].description, "first");
+    assert_eq!(versions[0].files.len(), 2);
+    assert_eq!(history.latest().unwrap(), Some(versions[1].clone()));
+    let old_a = history
+        .file_path(&versions[0], std::path::Path::new("folder/a.txt"))
+        .unwrap();
+    assert_eq!(storage.read_file(&old_a).unwrap(), b"a");
+    assert_eq!(
+        history::diff(&versions[0], &versions[1]),
+        vec![
+            ("b.txt".into(), history::Change::Added),
+            ("folder/a.txt".into(), history::Change::Modified),
+            ("old.txt".into(), history::Change::Removed),
+        ]
+    );
+}
+
+#[test]
+fn restore_version_test() {
+    let temp_dir = TempDir::default();
+    let test_path = temp_dir.to_path_buf().join("test");
+    let storage = storage::FsStorageHelper::new(&test_path).unwrap();
+    commit_with_history(&storage, &test_path, "first", |transaction| {
+        transaction.write("a.txt", b"a").unwrap();
+    });
+    commit_with_history(&storage, &test_path, "second", |transaction| {
+        transaction.write("a.txt", b"new a").unwrap();
+        transaction.write("b.txt", b"b").unwrap();
+    });
+
+    let history = history::History::new(&storage, &test_path, &[]);
+    let first = history.get(1).unwrap().unwrap();
+    let third = commit_with_history(&storage, &test_path, "rollback", |transaction| {
+        history.restore(transaction, &first).unwrap();
+    });
+    assert_eq!(third, Some(3));
+    assert_eq!(storage.read_file(&test_path.join("a.txt")).unwrap(), b"a");
+    assert!(!test_path.join("b.txt").exists());
+    assert_eq!(history.get(3).unwrap().unwrap().files, first.files);
+    // the content of older versions is untouched
+    let second = history.get(2).unwrap().unwrap();
+    let new_a = history
+        .file_path(&second, std::path::Path::new("a.txt"))
+        .unwrap();
+    assert_eq!(storage.read_file(&new_a).unwrap(), b"new a");
+}
+
+// Keeps the objects of a single bucket in memory and answers the few S3
+// requests the storage uses, like a local MinIO would
+struct S3StandIn {
+    endpoint: String,
+    objects: std::sync::Arc<std::sync::Mutex<std::collections::BTreeMap<String, Vec<u8>>>>,
+}
+
+const S3_BUCKET: &str = "bucket";
+const S3_ACCESS_KEY: &str = "access";
+// listings are split in small pages, so that continuation tokens are used
+const S3_PAGE_SIZE: usize = 2;
+
+impl S3StandIn {
+    fn start() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let objects = std::sync::Arc::new(std::sync::Mutex::new(std::collections::BTreeMap::new()));
+        let served = objects.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = S3StandIn::serve(stream.unwrap(), &served);
+            }
+        });
+        Self { endpoint, objects }
+    }
+
+    fn storage(&self, prefix: &str) -> s3::S3Storage {
+        s3::S3Storage::new(s3::S3Config {
+            endpoint: self.endpoint.clone(),
+            region: "us-east-1".into(),
+            bucket: S3_BUCKET.into(),
+            access_key: S3_ACCESS_KEY.into(),
+            secret_key: "secret".into(),
+            prefix: prefix.into(),
+        })
+        .unwrap()
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.objects.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn serve(
+        stream: std::net::TcpStream,
+        objects: &std::sync::Mutex<std::collections::BTreeMap<String, Vec<u8>>>,
+    ) -> std::io::Result<()> {
+        use std::io::{BufRead, Read, Write};
+        let decode = |text: &str| {
+            percent_encoding::percent_decode_str(text)
+                .decode_utf8_lossy()
+                .into_owned()
+        };
+        let mut reader = std::io::BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut request = line.split_whitespace();
+        let method = request.next().unwrap_or_default().to_string();
+        let target = request.next().unwrap_or_default().to_string();
+        let mut headers = std::collections::BTreeMap::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            match line.trim_end().split_once(':') {
+                Some((name, value)) => {
+                    headers.insert(name.trim().to_lowercase(), value.trim().to_string())
+                }
+                None => break,
+            };
+        }
+        let mut body = vec![];
+        let length: u64 = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        reader.take(length).read_to_end(&mut body)?;
+
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let query: std::collections::BTreeMap<String, String> = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (decode(name), decode(value)))
+            .collect();
+        let path = decode(path);
+        let key = path
+            .strip_prefix(&format!("/{}", S3_BUCKET))
+            .map(|key| key.trim_start_matches('/').to_string());
+        let signed = headers
+            .get("authorization")
+            .filter(|auth| {
+                auth.starts_with(&format!("AWS4-HMAC-SHA256 Credential={}/", S3_ACCESS_KEY))
+                    && ["host", "x-amz-content-sha256", "x-amz-date"]
+                        .iter()
+                        .all(|name| auth.contains(name))
+            })
+            .is_some();
+
+        let mut objects = objects.lock().unwrap();
+        let (status, content, pieces) = match (method.as_str(), key) {
+            _ if !signed => (
+                403,
+                "<Error><Code>AccessDenied</Code></Error>".into(),
+                false,
+            ),
+            (_, None) => (
+                404,
+                "<Error><Code>NoSuchBucket</Code></Error>".into(),
+                false,
+            ),
+            ("GET", Some(_)) if query.get("list-type").map(|t| t.as_str()) == Some("2") => {
+                let prefix = query.get("prefix").cloned().unwrap_or_default();
+                let delimiter = query.get("delimiter");
+                let max_keys = query
+                    .get("max-keys")
+                    .and_then(|max| max.parse().ok())
+                    .unwrap_or(S3_PAGE_SIZE)
+                    .min(S3_PAGE_SIZE);
+                let start = query.get("continuation-token").cloned().unwrap_or_default();
+                // entries are the keys, or their common prefixes when there is a delimiter
+                let mut entries: Vec<(String, bool)> = vec![];
+                for key in objects.keys().filter(|key| key.starts_with(&prefix)) {
+                    let entry = match delimiter.and_then(|d| key[prefix.len()..].find(d.as_str())) {
+                        Some(end) => (key[..prefix.len() + end + 1].to_string(), true),
+                        None => (key.clone(), false),
+                    };
+                    if entry.0 >= start && entries.last() != Some(&entry) {
+                        entries.push(entry);
+                    }
+                }
+                let truncated = entries.len() > max_keys;
+                let mut xml = String::from(
+                    "<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">",
+                );
+                for (entry, is_prefix) in entries.iter().take(max_keys) {
+                    if *is_prefix {
+                        xml += &format!(
+                            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                            entry
+                        );
+                    } else {
+                        xml += &format!(
+                            "<Contents><Key>{}</Key><Size>{}</Size>\
+                             <LastModified>2021-01-01T00:00:00.000Z</LastModified></Contents>",
+                            entry,
+                            objects[entry].len()
+                        );
+                    }
+                }
+                xml += &format!("<IsTruncated>{}</IsTruncated>", truncated);
+                if truncated {
+                    xml += &format!(
+                        "<NextContinuationToken>{}</NextContinuationToken>",
+                        entries[max_keys].0
+                    );
+                }
+                xml += "</ListBucketResult>";
+                (200, xml.into_bytes(), false)
+            }
+            ("GET", Some(key)) | ("HEAD", Some(key)) => match objects.get(&key) {
+                Some(content) => (200, content.clone(), method == "GET"),
+                None => (404, "<Error><Code>NoSuchKey</Code></Error>".into(), false),
+            },
+            ("PUT", Some(key)) => match headers.get("x-amz-copy-source") {
+                Some(source) => {
+                    let source = decode(source);
+                    let source = source
+                        .trim_start_matches('/')
+                        .strip_prefix(&format!("{}/", S3_BUCKET))
+                        .unwrap_or_default();
+                    match objects.get(source).cloned() {
+                        Some(content) => {
+                            objects.insert(key, content);
+                            (200, "<CopyObjectResult></CopyObjectResult>".into(), false)
+                        }
+                        None => (404, "<Error><Code>NoSuchKey</Code></Error>".into(), false),
+                    }
+                }
+                None if headers.get("if-none-match").map(|tag| tag.as_str()) == Some("*")
+                    && objects.contains_key(&key) =>
+                {
+                    (
+                        412,
+                        "<Error><Code>PreconditionFailed</Code></Error>".into(),
+                        false,
+                    )
+                }
+                None => {
+                    objects.insert(key, body);
+                    (200, vec![], false)
+                }
+            },
+            ("DELETE", Some(key)) => {
+                objects.remove(&key);
+                (204, vec![], false)
+            }
+            _ => (405, vec![], false),
+        };
+        drop(objects);
+
+        let mut stream = stream;
+        write!(stream, "HTTP/1.1 {} Status\r\n", status)?;
+        write!(stream, "Connection: close\r\n")?;
+        write!(stream, "ETag: \"etag\"\r\n")?;
+        write!(stream, "Last-Modified: Fri, 01 Jan 2021 00:00:00 GMT\r\n")?;
+        write!(stream, "Content-Length: {}\r\n\r\n", content.len())?;
+        stream.flush()?;
+        // objects are sent in pieces of 3 bytes
+        if method != "HEAD" {
+            for piece in content.chunks(if pieces { 3 } else { content.len().max(1) }) {
+                stream.write_all(piece)?;
+                stream.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn s3_storage_files_test() {
+    let stand_in = S3StandIn::start();
+    let storage = stand_in.storage("cms/files");
+    let root = storage.root().to_path_buf();
+
+    let folder = storage.add_folder("folder", None).unwrap();
+    storage.add_folder("empty", Some(&folder)).unwrap();
+    assert!(storage.is_dir(&folder.join("empty")).unwrap());
+    for name in ["a", "b", "c"] {
+        storage
+            .save_file(Some(&folder), name, "txt", name.as_bytes())
+            .unwrap();
+    }
+    storage
+        .write_file(&folder.join("inner/d.txt"), b"d")
+        .unwrap();
+    assert!(stand_in
+        .keys()
+        .iter()
+        .all(|key| key.starts_with("cms/files/")));
+
+    let mut listed = storage.list_folder(&folder).unwrap();
+    listed.sort();
+    assert_eq!(
+        listed,
+        ["a.txt", "b.txt", "c.txt", "empty", "inner"]
+            .iter()
+            .map(|name| folder.join(name))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(storage.list_folder(&root).unwrap(), vec![folder.clone()]);
+    let mut files = storage.list_files(&folder).unwrap();
+    files.sort();
+    assert_eq!(
+        files,
+        ["a.txt", "b.txt", "c.txt", "inner/d.txt"]
+            .iter()
+            .map(|name| folder.join(name))
+            .collect::<Vec<_>>()
+    );
+    assert!(storage
+        .list_folder(&root.join("missing"))
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        storage
+            .search_item(Some(&folder), "b", Some("txt"))
+            .unwrap(),
+        Some(folder.join("b.txt"))
+    );
+    assert!(storage.is_file(&folder.join("a.txt")).unwrap());
+    assert!(!storage.is_file(&folder).unwrap());
+    assert!(storage.is_dir(&folder.join("inner")).unwrap());
+    assert!(!storage.is_dir(&folder.join("a.txt")).unwrap());
+
+    // objects are read back in chunks
+    let long: Vec<u8> = (0..100).collect();
+    let mut writer = storage.create_file(&folder.join("long.bin")).unwrap();
+    std::io::Write::write_all(&mut writer, &long).unwrap();
+    assert!(!storage.is_file(&folder.join("long.bin")).unwrap());
+    storage.close_file(writer).unwrap();
+    assert_eq!(storage.read_file(&folder.join("long.bin")).unwrap(), long);
+
+    storage
+        .copy_file(&folder.join("a.txt"), &folder.join("copy.txt"))
+        .unwrap();
+    storage
+        .rename(&folder.join("b.txt"), &folder.join("inner/b.txt"))
+        .unwrap();
+    assert_eq!(storage.read_file(&folder.join("copy.txt")).unwrap(), b"a");
+    assert_eq!(
+        storage.read_file(&folder.join("inner/b.txt")).unwrap(),
+        b"b"
+    );
+    assert_eq!(
+        storage.read_file(&folder.join("b.txt")).unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+
+    storage.delete_item(&folder.join("inner")).unwrap();
+    assert!(!storage.exists(&folder.join("inner")).unwrap());
+    assert!(storage.delete_item(&folder.join("inner")).is_err());
+    storage.delete_item(&folder).unwrap();
+    assert!(stand_in.keys().is_empty());
+}
+
+#[test]
+fn s3_storage_wrong_credentials_test() {
+    let stand_in = S3StandIn::start();
+    let res = s3::S3Storage::new(s3::S3Config {
+        endpoint: stand_in.endpoint.clone(),
+        region: "us-east-1".into(),
+        bucket: S3_BUCKET.into(),
+        access_key: "someone else".into(),
+        secret_key: "secret".into(),
+        prefix: String::new(),
+    });
+    assert!(res.is_err());
+}
+
+#[test]
+fn s3_transaction_and_history_test() {
+    let stand_in = S3StandIn::start();
+    let storage = stand_in.storage("");
+    let path = storage.add_folder("problem", None).unwrap();
+    storage
+        .save_file(Some(&path), "old", "txt", b"old")
+        .unwrap();
+
+    let first = commit_with_history(&storage, &path, "first", |transaction| {
+        transaction.write("folder/a.txt", b"a").unwrap();
+        let storage = transaction.storage();
+        transaction
+            .write_with("b.txt", |writer| {
+                std::io::Write::write_all(writer, b"b")?;
+                std::io::Write::write_all(writer, b"b")
+            })
+            .unwrap();
+        assert!(!storage.is_file(&path.join("b.txt")).unwrap());
+    });
+    assert_eq!(first, Some(1));
+    let second = commit_with_history(&storage, &path, "second", |transaction| {
+        transaction.write("folder/a.txt", b"new a").unwrap();
+        transaction.delete("old.txt");
+    });
+    assert_eq!(second, Some(2));
+    assert_eq!(storage.read_file(&path.join("b.txt")).unwrap(), b"bb");
+    assert!(!storage.is_file(&path.join("old.txt")).unwrap());
+
+    let history = history::History::new(&storage, &path, &[]);
+    let first = history.get(1).unwrap().unwrap();
+    commit_with_history(&storage, &path, "rollback", |transaction| {
+        history.restore(transaction, &first).unwrap();
+    });
+    assert_eq!(storage.read_file(&path.join("old.txt")).unwrap(), b"old");
+    assert_eq!(storage.read_file(&path.join("folder/a.txt")).unwrap(), b"a");
+
+    // an uncommitted transaction is discarded when recovering
+    let mut transaction = transaction::Transaction::begin(&storage, &path).unwrap();
+    transaction.write("old.txt", b"lost").unwrap();
+    std::mem::forget(transaction);
+    transaction::recover(&storage, &path).unwrap();
+    assert_eq!(storage.read_file(&path.join("old.txt")).unwrap(), b"old");
+    assert!(!storage.exists(&path.join("staging")).unwrap());
+}
+
+// The lock excludes the other holders until released, or until its lease expires
+fn storage_lock_helper<S: Storage>(storage: &S) {
+    use crate::lock::StorageLock;
+    let file = storage.root().join("file.txt");
+    assert!(storage.create_new_file(&file, b"first").unwrap());
+    assert!(!storage.create_new_file(&file, b"second").unwrap());
+    assert_eq!(storage.read_file(&file).unwrap(), b"first");
+
+    let path = storage.root().join("lock");
+    let lease = std::time::Duration::from_secs(60);
+    let held = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        for _ in 0..3 {
+            scope.spawn(|| {
+                for _ in 0..3 {
+                    let _lock = StorageLock::acquire(storage, &path, lease).unwrap();
+                    assert!(!held.swap(true, std::sync::atomic::Ordering::SeqCst));
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                    held.store(false, std::sync::atomic::Ordering::SeqCst);
+                }
+            });
+        }
+    });
+    // only the last states of the lock are kept, the one held and the release
+    assert_eq!(storage.list_folder(&path).unwrap().len(), 2);
+
+    // a crashed holder never releases its lock
+    std::mem::forget(
+        StorageLock::acquire(storage, &path, std::time::Duration::from_millis(200)).unwrap(),
+    );
+    let start = std::time::Instant::now();
+    drop(StorageLock::acquire(storage, &path, lease).unwrap());
+    assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+}
+
+#[test]
+fn fs_storage_lock_test() {
+    let temp_dir = TempDir::default();
+    storage_lock_helper(&storage::FsStorageHelper::new(&temp_dir).unwrap());
+}
+
+#[test]
+fn s3_storage_lock_test() {
+    let stand_in = S3StandIn::start();
+    storage_lock_helper(&stand_in.storage("prefix"));
+}
+
+#[test]
+fn split_and_join_chunks_test() {
+    let content: Vec<u8> = (0..(2 * chunks::CHUNK_SIZE + 3))
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let chunk_list: Vec<_> = chunks::ChunkReader::new(content.as_slice())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(chunk_list.len(), 3);
+    assert!(chunk_list[..2].iter().all(|c| c.checksum.is_none()));
+    assert_eq!(
+        chunk_list[2].checksum.as_deref(),
+        Some(chunks::checksum(&content).as_str())
+    );
+
+    let mut writer = chunks::ChunkWriter::new(Vec::new());
+    for chunk in chunk_list.iter() {
+        writer.write_chunk(chunk).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), content);
+}
+
+#[test]
+fn empty_file_chunks_test() {
+    let chunk_list: Vec<_> = chunks::ChunkReader::new(&b""[..])
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(chunk_list.len(), 1);
+    assert_eq!(chunk_list[0].checksum, Some(chunks::checksum(b"")));
+}
+
+#[test]
+fn wrong_checksum_chunks_test() {
+    let mut writer = chunks::ChunkWriter::new(Vec::new());
+    let res = writer.write_chunk(&protos::common::FileChunk {
+        data: b"123".to_vec(),
+        checksum: Some(chunks::checksum(b"124")),
+    });
+    assert!(res.is_err());
+    assert!(chunks::ChunkWriter::new(Vec::new()).finish().is_err());
+}
+
+fn make_zip(files: &[(&str, &[u8])]) -> std::io::Cursor<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (path, content) in files {
+        zip.start_file(*path, zip::write::FileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, content).unwrap();
+    }
+    let mut cursor = zip.finish().unwrap();
+    cursor.set_position(0);
+    cursor
+}
+
+#[test]
+fn read_polygon_package_test() {
+    let descriptor = br#"<?xml version="1.0" encoding="utf-8"?>
+<problem short-name="sum">
+    <names><name language="english" value="A plus B"/></names>
+    <statements>
+        <statement language="english" path="statements/.pdf/english/problem.pdf" type="application/pdf"/>
+    </statements>
+    <judging>
+        <testset name="tests">
+            <time-limit>2000</time-limit>
+            <memory-limit>268435456</memory-limit>
+            <test-count>3</test-count>
+            <input-path-pattern>tests/%02d</input-path-pattern>
+            <answer-path-pattern>tests/%02d.a</answer-path-pattern>
+            <tests>
+                <test group="1" points="0"/>
+                <test group="2" points="0"/>
+                <test group="2" points="0"/>
+            </tests>
+            <groups>
+                <group name="1" points="30" points-policy="complete-group"/>
+                <group name="2" points="70" points-policy="each-test">
+                    <dependencies><dependency group="1"/></dependencies>
+                </group>
+            </groups>
+        </testset>
+    </judging>
+    <assets>
+        <checker type="testlib"><source path="files/check.cpp" type="cpp.g++17"/></checker>
+    </assets>
+</problem>"#;
+    let archive = make_zip(&[
+        ("sum/problem.xml", descriptor),
+        ("sum/statements/.pdf/english/problem.pdf", b"pdf"),
+        ("sum/tests/01", b"1 2"),
+        ("sum/tests/01.a", b"3"),
+        ("sum/tests/02", b"2 2"),
+        ("sum/tests/02.a", b"4"),
+        ("sum/tests/03", b"3 2"),
+        ("sum/tests/03.a", b"5"),
+        ("sum/files/check.cpp", b"int main(){}"),
+    ]);
+    let package = package::read_package(archive, package::Format::Auto).unwrap();
+    assert_eq!(package.name, "sum");
+    assert_eq!(package.long_name, "A plus B");
+    assert_eq!(package.statement, b"pdf");
+    assert_eq!(package.time_limit, std::time::Duration::from_secs(2));
+    assert_eq!(package.subtasks.len(), 2);
+    assert_eq!(
+        package.subtasks[0].scoring.method,
+        subtask::Method::Min as i32
+    );
+    assert_eq!(
+        package.subtasks[0].scoring.max_score,
+        Score {
+            score: 30.0,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        package.subtasks[1].scoring.method,
+        subtask::Method::Sum as i32
+    );
+    assert_eq!(package.subtasks[1].testcases.len(), 2);
+    assert_eq!(package.subtasks[1].testcases[1].output, b"5");
+    assert!(package.subtasks[0].dependencies.is_empty());
+    assert_eq!(package.subtasks[1].dependencies, vec![0]);
+    assert!(package.checker.is_some());
+    assert!(package.interactor.is_none());
+}
+
+#[test]
+fn read_kattis_package_test() {
+    let archive = make_zip(&[
+        (
+            "hello/problem.yaml",
+            b"name: Hello World\nvalidation: custom\nlimits:\n  memory: 512\n",
+        ),
+        ("hello/.timelimit", b"3"),
+        ("hello/problem_statement/problem.en.tex", b"statement"),
+        ("hello/data/sample/1.in", b""),
+        ("hello/data/sample/1.ans", b"Hello World!"),
+        ("hello/data/secret/group1/testdata.yaml", b"range: 0 40\n"),
+        ("hello/data/secret/group1/a.in", b""),
+        ("hello/data/secret/group1/a.ans", b"Hello World!"),
+        ("hello/data/secret/group2/a.in", b"x"),
+        ("hello/data/secret/group2/a.ans", b"Hello World!"),
+        ("hello/data/secret/group2/b.in", b"y"),
+        ("hello/data/secret/group2/b.ans", b"Hello World!"),
+        (
+            "hello/output_validators/check/validate.cpp",
+            b"int main(){}",
+        ),
+    ]);
+    let package = package::read_package(archive, package::Format::Kattis).unwrap();
+    assert_eq!(package.name, "hello");
+    assert_eq!(package.long_name, "Hello World");
+    assert_eq!(package.statement, b"statement");
+    assert_eq!(package.time_limit, std::time::Duration::from_secs(3));
+    assert_eq!(package.memory_bytes, 512 * 1024 * 1024);
+    // samples, group1 and group2
+    assert_eq!(package.subtasks.len(), 3);
+    assert_eq!(
+        package.subtasks[0].scoring.max_score,
+        Score {
+            score: 0.0,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        package.subtasks[1].scoring.max_score,
+        Score {
+            score: 40.0,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        package.subtasks[2].scoring.max_score,
+        Score {
+            score: 50.0,
+            ..Default::default()
+        }
+    );
+    assert_eq!(package.subtasks[2].testcases.len(), 2);
+    assert!(package.checker.is_some());
+}
+
+#[test]
+fn read_italy_yaml_package_test() {
+    let archive = make_zip(&[
+        (
+            "task.yaml",
+            b"name: somma\ntitle: Somma\ntime_limit: 1.5\nmemory_limit: 64\nn_input: 3\n",
+        ),
+        (
+            "gen/GEN",
+            b"#ST: 0\n#COPY: sample.txt\n#ST: 100\n1 2\n3 4\n",
+        ),
+        ("input/input0.txt", b"1 1"),
+        ("output/output0.txt", b"2"),
+        ("input/input1.txt", b"1 2"),
+        ("output/output1.txt", b"3"),
+        ("input/input2.txt", b"3 4"),
+        ("output/output2.txt", b"7"),
+        ("statement/statement.pdf", b"pdf"),
+    ]);
+    let package = package::read_package(archive, package::Format::Auto).unwrap();
+    assert_eq!(package.name, "somma");
+    assert_eq!(package.long_name, "Somma");
+    assert_eq!(package.time_limit, std::time::Duration::from_millis(1500));
+    assert_eq!(package.memory_bytes, 64 * 1024 * 1024);
+    assert_eq!(package.subtasks.len(), 2);
+    assert_eq!(package.subtasks[0].testcases.len(), 1);
+    assert_eq!(package.subtasks[1].testcases.len(), 2);
+    assert_eq!(
+        package.subtasks[1].scoring.max_score,
+        Score {
+            score: 100.0,
+            ..Default::default()
+        }
+    );
+    assert!(package.checker.is_none());
+}
+
+#[test]
+fn export_and_import_package_test() {
+    let archive = make_zip(&[
+        ("task.yaml", b"name: somma\ntitle: Somma\nn_input: 2\n"),
+        ("input/input0.txt", b"1 1"),
+        ("output/output0.txt", b"2"),
+        ("input/input1.txt", b"1 2"),
+        ("output/output1.txt", b"3"),
+        ("check/checker.cpp", b"int main(){}"),
+    ]);
+    let package = package::read_problem_package(archive, package::Format::ItalyYaml).unwrap();
+    let mut exported =
+        package::write_problem_package(&package, std::io::Cursor::new(Vec::new())).unwrap();
+    exported.set_position(0);
+    let imported = package::read_problem_package(exported, package::Format::Auto).unwrap();
+    assert_eq!(imported.name, package.name);
+    assert_eq!(imported.long_name, package.long_name);
+    assert_eq!(imported.statement, package.statement);
+    assert_eq!(imported.problem, package.problem);
+    assert_eq!(imported.checker, package.checker);
+    assert_eq!(imported.interactor, package.interactor);
+    assert_eq!(imported.testcases.len(), 2);
+    for (id, tc) in package.testcases.iter() {
+        assert_eq!(imported.testcases[id].input, tc.input);
+        assert_eq!(imported.testcases[id].output, tc.output);
+    }
+}
+
+#[test]
+fn read_unknown_package_test() {
+    let archive = make_zip(&[("readme.txt", b"nothing here")]);
+    assert!(matches!(
+        package::read_package(archive, package::Format::Auto),
+        Err(package::PackageError::UnknownFormat)
+    ));
+}
+
 fn get_bool_testcase(result: bool) -> TestcaseResult {
     TestcaseResult {
         outcome: testcase_result::Outcome::Ok as i32,
         score: if result { TESTCASE_AC_SCORE } else { WA_SCORE },
-        used_resources: Resources {
+        used_resources: Some(Resources {
             time: Duration { secs: 0, nanos: 0 },
             memory_bytes: 0u64,
-        },
+        }),
         id: 1u64,
+        objective: None,
     }
 }
 
@@ -248,12 +1216,16 @@ fn get_double_testcase(result: f64) -> TestcaseResult {
     assert!(!result.is_nan());
     TestcaseResult {
         outcome: testcase_result::Outcome::Ok as i32,
-        score: Score { score: result },
-        used_resources: Resources {
+        score: Score {
+            score: result,
+            ..Default::default()
+        },
+        used_resources: Some(Resources {
             time: Duration { secs: 0, nanos: 0 },
             memory_bytes: 0u64,
-        },
+        }),
         id: 1u64,
+        objective: None,
     }
 }
 
@@ -326,6 +1298,7 @@ fn evaluate_partial_bool_subtask_with_sum_test() {
         ],
         score: Score {
             score: SUM_SCORING_METHOD.max_score.score * 3f64 / 5f64,
+            ..Default::default()
         },
         id: 1u64,
     };
@@ -368,7 +1341,10 @@ fn evaluate_wrong_double_subtask_with_sum_test() {
             get_double_testcase(0.0),
             get_double_testcase(0.0),
         ],
-        score: Score { score: 0.0 },
+        score: Score {
+            score: 0.0,
+            ..Default::default()
+        },
         id: 1u64,
     };
 
@@ -376,4 +1352,1146 @@ fn evaluate_wrong_double_subtask_with_sum_test() {
     assert_eq!(score, subtask_result_wrong.score);
 }
 
+fn subtask_scoring(method: subtask::Method, threshold: Option<f64>) -> Subtask {
+    Subtask {
+        method: method as i32,
+        threshold: threshold.map(|score| Score {
+            score,
+            ..Default::default()
+        }),
+        ..SUM_SCORING_METHOD
+    }
+}
+
+fn get_failed_testcase(result: f64) -> TestcaseResult {
+    TestcaseResult {
+        outcome: testcase_result::Outcome::Tle as i32,
+        ..get_double_testcase(result)
+    }
+}
+
+#[test]
+fn evaluate_subtask_with_product_test() {
+    let scoring = subtask_scoring(subtask::Method::Product, None);
+    let testcases = vec![
+        get_double_testcase(0.5),
+        get_double_testcase(1.0),
+        get_double_testcase(0.5),
+    ];
+    assert_eq!(calc_subtask_score(&testcases, &scoring).score, 25.0);
+    let testcases = vec![get_bool_testcase(true); 5];
+    assert_eq!(calc_subtask_score(&testcases, &scoring), scoring.max_score);
+    let mut testcases = vec![get_double_testcase(0.9); 5];
+    testcases.push(get_bool_testcase(false));
+    assert_eq!(calc_subtask_score(&testcases, &scoring), WA_SCORE);
+}
+
+#[test]
+fn evaluate_subtask_with_threshold_test() {
+    let testcases = vec![
+        get_double_testcase(0.7),
+        get_double_testcase(1.0),
+        get_double_testcase(0.8),
+    ];
+    let score = |threshold| {
+        calc_subtask_score(
+            &testcases,
+            &subtask_scoring(subtask::Method::Threshold, threshold),
+        )
+    };
+    assert_eq!(score(None), WA_SCORE);
+    assert_eq!(score(Some(0.8)), WA_SCORE);
+    assert_eq!(score(Some(0.7)), SUM_SCORING_METHOD.max_score);
+    assert_eq!(score(Some(0.0)), SUM_SCORING_METHOD.max_score);
+}
+
+#[test]
+fn evaluate_subtask_with_average_test() {
+    let scoring = subtask_scoring(subtask::Method::Average, None);
+    let testcases = vec![
+        get_double_testcase(0.25),
+        get_double_testcase(1.0),
+        get_double_testcase(0.0),
+        get_double_testcase(0.75),
+    ];
+    assert_eq!(calc_subtask_score(&testcases, &scoring).score, 50.0);
+    assert_eq!(calc_subtask_score(&[], &scoring), WA_SCORE);
+}
+
+#[test]
+fn evaluate_subtask_with_min_partial_test() {
+    let scoring = subtask_scoring(subtask::Method::MinPartial, None);
+    let testcases = vec![
+        get_double_testcase(0.5),
+        get_double_testcase(1.5), // clamped to 1
+        get_double_testcase(0.75),
+    ];
+    assert_eq!(calc_subtask_score(&testcases, &scoring).score, 50.0);
+    let testcases = vec![get_double_testcase(2.0), get_double_testcase(1.0)];
+    assert_eq!(calc_subtask_score(&testcases, &scoring), scoring.max_score);
+    let testcases = vec![get_double_testcase(0.5), get_failed_testcase(0.75)];
+    assert_eq!(calc_subtask_score(&testcases, &scoring), WA_SCORE);
+}
+
+#[test]
+fn subtask_methods_bounds_test() {
+    let methods = [
+        subtask::Method::Min,
+        subtask::Method::Sum,
+        subtask::Method::Product,
+        subtask::Method::Threshold,
+        subtask::Method::Average,
+        subtask::Method::MinPartial,
+    ];
+    let all_correct = vec![get_bool_testcase(true); 4];
+    let all_wrong = vec![get_bool_testcase(false); 4];
+    let partial: Vec<_> = [0.0, 0.3, 0.6, 1.0]
+        .iter()
+        .map(|&x| get_double_testcase(x))
+        .collect();
+    for method in methods {
+        let scoring = subtask_scoring(method, None);
+        // every method is in [0,max_score], reaching both ends
+        assert_eq!(
+            calc_subtask_score(&all_correct, &scoring),
+            scoring.max_score
+        );
+        assert_eq!(calc_subtask_score(&all_wrong, &scoring), WA_SCORE);
+        let score = calc_subtask_score(&partial, &scoring).score;
+        assert!((0.0..=scoring.max_score.score).contains(&score));
+        // the order of the testcases does not matter
+        let reversed: Vec<_> = partial.iter().rev().cloned().collect();
+        assert_eq!(calc_subtask_score(&reversed, &scoring).score, score);
+    }
+}
+
+#[test]
+fn exact_subtask_sum_test() {
+    // each subtask gets 100/7, that does not add up to 100 with floats
+    let mut testcase_results = vec![get_bool_testcase(false); 7];
+    testcase_results[0] = get_bool_testcase(true);
+    let subtask_result = SubtaskResult {
+        score: calc_subtask_score(&testcase_results, &SUM_SCORING_METHOD),
+        testcase_results,
+        id: 1u64,
+    };
+    assert_ne!([subtask_result.score.score; 7].iter().sum::<f64>(), 100.0);
+    let score = crate::scoring::calc_submission_score(
+        &vec![subtask_result; 7],
+        &protos::scoring::Problem::default(),
+    );
+    assert_eq!(
+        score,
+        Score {
+            score: 100.0,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn exact_score_encoding_test() {
+    use protos::common::Fraction;
+    // not recoverable from the float, so it is carried as a fraction
+    let tiny = Score {
+        score: 1.0 / 1_000_000_007.0,
+        exact: Some(Fraction {
+            numerator: 1,
+            denominator: 1_000_000_007,
+        }),
+    };
+    let subtask_result = SubtaskResult {
+        testcase_results: vec![],
+        score: tiny.clone(),
+        id: 1u64,
+    };
+    let score = crate::scoring::calc_submission_score(
+        &[subtask_result],
+        &protos::scoring::Problem::default(),
+    );
+    assert_eq!(score, tiny);
+    assert_eq!(crate::scoring::format_score(&score, 12), "0.000000001000");
+}
+
+#[test]
+fn zero_division_score_test() {
+    // a subtask without testcases rescales from a maximum of 0
+    let score = calc_subtask_score(&[], &SUM_SCORING_METHOD);
+    assert_eq!(score.score, 0.0);
+    let not_a_number = Score {
+        score: f64::NAN,
+        ..Default::default()
+    };
+    let score = crate::scoring::format_score(&not_a_number, 2);
+    assert_eq!(score, "0.00");
+}
+
+#[test]
+fn format_score_test() {
+    use crate::scoring::format_score;
+    let score = |score: f64| Score {
+        score,
+        ..Default::default()
+    };
+    assert_eq!(format_score(&score(100.0 / 3.0), 2), "33.33");
+    assert_eq!(format_score(&score(2.0 / 3.0), 2), "0.67");
+    assert_eq!(format_score(&score(0.125), 2), "0.13");
+    assert_eq!(format_score(&score(-0.5), 0), "-1");
+    assert_eq!(format_score(&score(-0.001), 2), "0.00");
+    assert_eq!(format_score(&score(42.0), 0), "42");
+    assert_eq!(format_score(&score(42.0), 3), "42.000");
+}
+
+fn get_evaluation_result(subtask_scores: &[f64]) -> EvaluationResult {
+    EvaluationResult {
+        subtask_results: subtask_scores
+            .iter()
+            .map(|&score| SubtaskResult {
+                score: Score {
+                    score,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn calc_problem_score_test() {
+    use crate::scoring::calc_problem_score;
+    use protos::scoring::{problem, Problem};
+    let submissions = vec![
+        get_evaluation_result(&[30.0, 0.0]),
+        get_evaluation_result(&[0.0, 50.0]),
+        get_evaluation_result(&[]), // compilation error
+        get_evaluation_result(&[10.0, 10.0]),
+    ];
+    let score = |method: problem::Method, selected| {
+        calc_problem_score(
+            &submissions,
+            &Problem {
+                method: method as i32,
+                relative: None,
+            },
+            selected,
+        )
+        .score
+    };
+    assert_eq!(score(problem::Method::SumMax, None), 80.0);
+    assert_eq!(score(problem::Method::MaxSum, None), 50.0);
+    assert_eq!(score(problem::Method::Last, None), 20.0);
+    assert_eq!(score(problem::Method::Selected, Some(0)), 30.0);
+    assert_eq!(score(problem::Method::Selected, Some(2)), 0.0);
+    assert_eq!(score(problem::Method::Selected, None), 20.0);
+    assert_eq!(score(problem::Method::Selected, Some(42)), 20.0);
+
+    for method in [
+        problem::Method::SumMax,
+        problem::Method::MaxSum,
+        problem::Method::Last,
+        problem::Method::Selected,
+    ] {
+        assert_eq!(
+            calc_problem_score(
+                &[],
+                &Problem {
+                    method: method as i32,
+                    relative: None,
+                },
+                None
+            )
+            .score,
+            0.0
+        );
+        // a single submission scores the same with every method
+        assert_eq!(
+            crate::scoring::calc_submission_score(
+                &submissions[1].subtask_results,
+                &Problem {
+                    method: method as i32,
+                    relative: None,
+                },
+            )
+            .score,
+            50.0
+        );
+    }
+}
+
+#[test]
+fn calc_icpc_scoreboard_test() {
+    use crate::scoring::{calc_icpc_scoreboard, IcpcSubmission, IcpcVerdict};
+    let submission = |user: &str, problem_id, minutes: u64, verdict| IcpcSubmission {
+        user: String::from(user),
+        problem_id,
+        elapsed: std::time::Duration::from_secs(minutes * 60 + 30),
+        verdict,
+    };
+    let submissions = vec![
+        submission("alice", 1, 10, IcpcVerdict::Rejected),
+        submission("alice", 1, 5, IcpcVerdict::CompilationError),
+        submission("alice", 1, 30, IcpcVerdict::Accepted),
+        submission("alice", 1, 40, IcpcVerdict::Rejected), // after accepted, ignored
+        submission("bob", 1, 20, IcpcVerdict::Accepted),
+        submission("bob", 2, 50, IcpcVerdict::Accepted),
+        submission("carol", 2, 50, IcpcVerdict::Accepted),
+        submission("carol", 1, 60, IcpcVerdict::Rejected),
+        submission("dave", 2, 70, IcpcVerdict::Rejected),
+    ];
+    let opts = |count_compilation_errors| protos::scoring::user::Icpc {
+        penalty_minutes: 20,
+        count_compilation_errors,
+    };
+
+    let scoreboard = calc_icpc_scoreboard(&submissions, &opts(false));
+    let ranking: Vec<_> = scoreboard
+        .iter()
+        .map(|u| (u.user.as_str(), u.solved, u.penalty_minutes))
+        .collect();
+    assert_eq!(
+        ranking,
+        vec![
+            ("bob", 2, 70),
+            ("alice", 1, 50),
+            ("carol", 1, 50),
+            ("dave", 0, 0)
+        ]
+    );
+    // bob solved problem 1 first, bob and carol solved problem 2 at the same time
+    let first_to_solve = |user: usize, problem_id| {
+        scoreboard[user]
+            .problems
+            .iter()
+            .find(|p| p.problem_id == problem_id)
+            .unwrap()
+            .first_to_solve()
+    };
+    assert!(first_to_solve(0, 1));
+    assert!(first_to_solve(0, 2));
+    assert!(!first_to_solve(1, 1));
+    assert!(first_to_solve(2, 2));
+    assert!(!first_to_solve(2, 1));
+    assert_eq!(scoreboard[2].problems[0].rejected, 1);
+    assert_eq!(scoreboard[2].problems[0].solved_at, None);
+
+    let scoreboard = calc_icpc_scoreboard(&submissions, &opts(true));
+    assert_eq!(scoreboard[1].user, "carol");
+    assert_eq!(scoreboard[2].user, "alice");
+    assert_eq!(scoreboard[2].problems[0].rejected, 2);
+    assert_eq!(scoreboard[2].penalty_minutes, 70);
+    assert!(calc_icpc_scoreboard(&[], &opts(false)).is_empty());
+}
+
+#[test]
+fn icpc_freeze_test() {
+    use crate::scoring::{calc_icpc_scoreboard, next_to_reveal, IcpcSubmission, IcpcVerdict};
+    let submission = |user: &str, problem_id, minutes: u64, verdict| IcpcSubmission {
+        user: String::from(user),
+        problem_id,
+        elapsed: std::time::Duration::from_secs(minutes * 60),
+        verdict,
+    };
+    let opts = protos::scoring::user::Icpc {
+        penalty_minutes: 20,
+        count_compilation_errors: false,
+    };
+    let mut submissions = vec![
+        submission("alice", 1, 10, IcpcVerdict::Accepted),
+        submission("alice", 2, 250, IcpcVerdict::Pending),
+        submission("bob", 1, 20, IcpcVerdict::Accepted),
+        submission("bob", 2, 260, IcpcVerdict::Pending),
+        submission("bob", 2, 270, IcpcVerdict::Pending),
+        submission("carol", 1, 30, IcpcVerdict::Rejected),
+    ];
+
+    // pending submissions count neither as solved nor as rejected
+    let scoreboard = calc_icpc_scoreboard(&submissions, &opts);
+    let pending: Vec<_> = scoreboard
+        .iter()
+        .map(|u| {
+            (
+                u.user.as_str(),
+                u.solved,
+                u.problems.iter().map(|p| p.pending()).sum(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        pending,
+        vec![("alice", 1, 1), ("bob", 1, 2), ("carol", 0, 0u32)]
+    );
+    assert_eq!(scoreboard[1].problems[1].rejected, 0);
+
+    // the resolver starts from the bottom of the ranking
+    assert_eq!(next_to_reveal(&scoreboard), Some((String::from("bob"), 2)));
+    submissions[3].verdict = IcpcVerdict::Rejected;
+    submissions[4].verdict = IcpcVerdict::Accepted;
+    let scoreboard = calc_icpc_scoreboard(&submissions, &opts);
+    assert_eq!(scoreboard[0].user, "bob");
+    assert_eq!(scoreboard[0].penalty_minutes, 20 + 270 + 20);
+    assert_eq!(
+        next_to_reveal(&scoreboard),
+        Some((String::from("alice"), 2))
+    );
+    submissions[1].verdict = IcpcVerdict::Rejected;
+    assert_eq!(
+        next_to_reveal(&calc_icpc_scoreboard(&submissions, &opts)),
+        None
+    );
+}
+
+#[test]
+fn is_max_score_test() {
+    use crate::scoring::is_max_score;
+    let problem = protos::service::evaluation::Problem {
+        subtasks: vec![
+            protos::service::evaluation::Subtask {
+                scoring: SUM_SCORING_METHOD,
+                ..Default::default()
+            },
+            protos::service::evaluation::Subtask {
+                scoring: MIN_SCORING_METHOD,
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    let score = |score| Score {
+        score,
+        ..Default::default()
+    };
+    assert!(is_max_score(&score(200.0), &problem));
+    assert!(!is_max_score(&score(199.5), &problem));
+    assert!(!is_max_score(&WA_SCORE, &problem));
+}
+
+#[test]
+fn relative_scoring_test() {
+    use crate::scoring::{
+        apply_relative_scoring, best_objectives, has_relative_scoring, relative_score,
+    };
+    use protos::scoring::{relative::Goal, Relative};
+    let score = |score| Score {
+        score,
+        ..Default::default()
+    };
+    assert_eq!(
+        relative_score(score(10.0), score(5.0), Goal::Minimize).score,
+        0.5
+    );
+    assert_eq!(
+        relative_score(score(5.0), score(10.0), Goal::Maximize).score,
+        0.5
+    );
+    assert_eq!(
+        relative_score(score(0.0), score(0.0), Goal::Minimize).score,
+        1.0
+    );
+    assert_eq!(
+        relative_score(score(0.0), score(0.0), Goal::Maximize).score,
+        1.0
+    );
+    assert_eq!(
+        relative_score(score(-1.0), score(5.0), Goal::Minimize).score,
+        0.0
+    );
+    assert_eq!(
+        relative_score(score(20.0), score(10.0), Goal::Maximize).score,
+        1.0
+    );
+
+    // the first subtask minimizes, as the problem, the second one is scored normally
+    let mut problem = protos::service::evaluation::Problem {
+        subtasks: vec![
+            protos::service::evaluation::Subtask {
+                scoring: MIN_SCORING_METHOD,
+                ..Default::default()
+            },
+            protos::service::evaluation::Subtask {
+                scoring: Subtask {
+                    relative: Some(Relative {
+                        goal: Goal::Maximize as i32,
+                    }),
+                    ..MIN_SCORING_METHOD
+                },
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    assert!(has_relative_scoring(&problem));
+    problem.subtasks[1].scoring.relative = None;
+    assert!(!has_relative_scoring(&problem));
+    problem.scoring.relative = Some(Relative {
+        goal: Goal::Minimize as i32,
+    });
+    problem.subtasks[1].scoring.relative = Some(Relative {
+        goal: Goal::Maximize as i32,
+    });
+
+    let result = |objectives: [f64; 3]| EvaluationResult {
+        subtask_results: vec![
+            SubtaskResult {
+                testcase_results: vec![
+                    TestcaseResult {
+                        id: 1,
+                        ..get_double_testcase(objectives[0])
+                    },
+                    TestcaseResult {
+                        id: 2,
+                        ..get_double_testcase(objectives[1])
+                    },
+                ],
+                ..Default::default()
+            },
+            SubtaskResult {
+                testcase_results: vec![TestcaseResult {
+                    id: 3,
+                    ..get_double_testcase(objectives[2])
+                }],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    let mut results = vec![result([10.0, 4.0, 3.0]), result([5.0, 8.0, 6.0])];
+    results[1].subtask_results[0].testcase_results[1].outcome =
+        testcase_result::Outcome::Tle as i32; // does not count
+    let best = best_objectives(&problem, &results);
+    assert_eq!(best[&1].score, 5.0);
+    assert_eq!(best[&2].score, 4.0);
+    assert_eq!(best[&3].score, 6.0);
+
+    for result in results.iter_mut() {
+        apply_relative_scoring(&problem, result, &best);
+    }
+    let scores = |result: &EvaluationResult| {
+        result
+            .subtask_results
+            .iter()
+            .flat_map(|subtask| subtask.testcase_results.iter())
+            .map(|testcase| {
+                (
+                    testcase.score.score,
+                    testcase.objective.clone().unwrap().score,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        scores(&results[0]),
+        vec![(0.5, 10.0), (1.0, 4.0), (0.5, 3.0)]
+    );
+    assert_eq!(
+        scores(&results[1]),
+        vec![(1.0, 5.0), (0.0, 8.0), (1.0, 6.0)]
+    );
+    // applying it again uses the objectives, not the scores
+    let best = best_objectives(&problem, &results);
+    assert_eq!(best[&1].score, 5.0);
+    apply_relative_scoring(&problem, &mut results[0], &best);
+    assert_eq!(
+        scores(&results[0]),
+        vec![(0.5, 10.0), (1.0, 4.0), (0.5, 3.0)]
+    );
+}
+
 // TODO: more scoring tests, separate scoring,conversions,...
+
+#[test]
+fn testcase_limits_test() {
+    use crate::limits::{sandbox_seconds, testcase_limits};
+    use protos::common::ProgrammingLanguage;
+    use protos::service::evaluation::{
+        LanguageTimeMultiplier, LimitsOverride, Problem, Subtask, TestcaseLimits,
+    };
+
+    let seconds = |secs: u64| Duration { secs, nanos: 0 };
+    let problem = Problem {
+        execution_limits: Resources {
+            time: seconds(1),
+            memory_bytes: 256,
+        },
+        subtasks: vec![
+            Subtask {
+                id: 1,
+                testcases_id: vec![1, 2],
+                ..Default::default()
+            },
+            Subtask {
+                id: 2,
+                testcases_id: vec![3, 4, 5],
+                limits: Some(LimitsOverride {
+                    time: Some(seconds(4)),
+                    memory_bytes: None,
+                }),
+                testcase_limits: vec![TestcaseLimits {
+                    testcase_id: 4,
+                    limits: LimitsOverride {
+                        time: None,
+                        memory_bytes: Some(1024),
+                    },
+                }],
+                ..Default::default()
+            },
+            Subtask {
+                id: 3,
+                testcases_id: vec![2, 5],
+                limits: Some(LimitsOverride {
+                    time: Some(seconds(2)),
+                    memory_bytes: Some(512),
+                }),
+                ..Default::default()
+            },
+        ],
+        time_multipliers: vec![LanguageTimeMultiplier {
+            lang: ProgrammingLanguage::Rust as i32,
+            multiplier: 1.5,
+        }],
+        ..Default::default()
+    };
+    let limits = |testcase_id, lang| {
+        let limits = testcase_limits(&problem, testcase_id, lang);
+        (std::time::Duration::from(limits.time), limits.memory_bytes)
+    };
+    let secs = std::time::Duration::from_secs;
+
+    assert_eq!(limits(1, ProgrammingLanguage::Cpp), (secs(1), 256));
+    assert_eq!(limits(3, ProgrammingLanguage::Cpp), (secs(4), 256));
+    assert_eq!(limits(4, ProgrammingLanguage::Cpp), (secs(4), 1024));
+    // in several subtasks, the most permissive limits are used
+    assert_eq!(limits(2, ProgrammingLanguage::Cpp), (secs(2), 512));
+    assert_eq!(limits(5, ProgrammingLanguage::Cpp), (secs(4), 512));
+    assert_eq!(limits(42, ProgrammingLanguage::Cpp), (secs(1), 256));
+    assert_eq!(
+        limits(1, ProgrammingLanguage::Rust),
+        (std::time::Duration::from_millis(1500), 256)
+    );
+    assert_eq!(
+        sandbox_seconds(&testcase_limits(&problem, 1, ProgrammingLanguage::Rust).time),
+        2
+    );
+    assert_eq!(
+        sandbox_seconds(&testcase_limits(&problem, 1, ProgrammingLanguage::Cpp).time),
+        1
+    );
+    assert_eq!(limits(3, ProgrammingLanguage::Rust), (secs(6), 256));
+}
+
+#[test]
+fn subtask_testcases_test() {
+    use crate::scoring::subtask_testcases;
+    use protos::service::evaluation::{Problem, Subtask};
+
+    let problem = Problem {
+        subtasks: vec![
+            Subtask {
+                id: 1,
+                testcases_id: vec![1, 2],
+                ..Default::default()
+            },
+            Subtask {
+                id: 2,
+                testcases_id: vec![3],
+                dependencies: vec![1],
+                ..Default::default()
+            },
+            Subtask {
+                id: 3,
+                testcases_id: vec![4, 2],
+                parent: Some(2),
+                dependencies: vec![1, 42],
+                ..Default::default()
+            },
+            // dependencies in a cycle
+            Subtask {
+                id: 4,
+                testcases_id: vec![5],
+                dependencies: vec![5],
+                ..Default::default()
+            },
+            Subtask {
+                id: 5,
+                testcases_id: vec![6],
+                dependencies: vec![4],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    assert_eq!(subtask_testcases(&problem, 1), vec![1, 2]);
+    assert_eq!(subtask_testcases(&problem, 2), vec![3, 1, 2]);
+    assert_eq!(subtask_testcases(&problem, 3), vec![4, 2, 3, 1]);
+    assert_eq!(subtask_testcases(&problem, 4), vec![5, 6]);
+    assert!(subtask_testcases(&problem, 42).is_empty());
+}
+
+#[test]
+fn available_tokens_test() {
+    use crate::tokens::available_tokens;
+    use protos::service::contest::TokenRules;
+    use std::time::{Duration, SystemTime};
+    let start = SystemTime::UNIX_EPOCH;
+    let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+    let rules = TokenRules {
+        initial: 2,
+        gen_number: 1,
+        gen_interval: Some(Duration::from_secs(30 * 60).into()),
+        max: Some(3),
+        total: None,
+        min_interval: None,
+    };
+    assert_eq!(available_tokens(&rules, start, &[], at(0)), 2);
+    assert_eq!(available_tokens(&rules, start, &[], at(29)), 2);
+    assert_eq!(available_tokens(&rules, start, &[], at(30)), 3);
+    // tokens do not accumulate beyond max
+    assert_eq!(available_tokens(&rules, start, &[], at(120)), 3);
+    assert_eq!(available_tokens(&rules, start, &[at(1), at(2)], at(3)), 0);
+    assert_eq!(available_tokens(&rules, start, &[at(1), at(2)], at(30)), 1);
+    // the cap applied before the uses is not lost
+    assert_eq!(available_tokens(&rules, start, &[at(120)], at(121)), 2);
+    assert_eq!(
+        available_tokens(&rules, start, &[at(120), at(121), at(122)], at(150)),
+        1
+    );
+
+    let rules = TokenRules {
+        total: Some(2),
+        min_interval: Some(Duration::from_secs(10 * 60).into()),
+        ..rules
+    };
+    assert_eq!(available_tokens(&rules, start, &[], at(60)), 2);
+    assert_eq!(available_tokens(&rules, start, &[at(60)], at(65)), 0);
+    assert_eq!(available_tokens(&rules, start, &[at(60)], at(70)), 1);
+    assert_eq!(
+        available_tokens(&rules, start, &[at(60), at(70)], at(200)),
+        0
+    );
+
+    // no generation
+    let rules = TokenRules {
+        initial: 1,
+        gen_number: 5,
+        gen_interval: None,
+        max: None,
+        total: None,
+        min_interval: None,
+    };
+    assert_eq!(available_tokens(&rules, start, &[], at(1000)), 1);
+    assert_eq!(available_tokens(&rules, start, &[at(1)], at(1000)), 0);
+}
+
+#[test]
+fn apply_feedback_test() {
+    use crate::feedback::apply_feedback;
+    use protos::service::evaluation::{problem::Feedback, testcase_info, Subtask, TestcaseInfo};
+    let testcase = |id, outcome: testcase_result::Outcome| TestcaseResult {
+        outcome: outcome as i32,
+        id,
+        used_resources: Some(Resources::default()),
+        ..Default::default()
+    };
+    let result = EvaluationResult {
+        subtask_results: vec![
+            SubtaskResult {
+                testcase_results: vec![
+                    testcase(1, testcase_result::Outcome::Ok),
+                    testcase(2, testcase_result::Outcome::Ok),
+                ],
+                id: 1,
+                ..Default::default()
+            },
+            SubtaskResult {
+                testcase_results: vec![
+                    testcase(3, testcase_result::Outcome::Ok),
+                    testcase(4, testcase_result::Outcome::Tle),
+                    testcase(5, testcase_result::Outcome::Rte),
+                ],
+                id: 2,
+                ..Default::default()
+            },
+        ],
+        score: Score {
+            score: 42.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let problem = |feedback: Feedback| {
+        let mut problem = protos::service::evaluation::Problem {
+            subtasks: vec![
+                Subtask {
+                    id: 1,
+                    testcases_id: vec![1, 2],
+                    ..Default::default()
+                },
+                Subtask {
+                    id: 2,
+                    testcases_id: vec![3, 4, 5],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        problem.set_feedback(feedback);
+        problem
+    };
+    let testcase_ids = |result: &EvaluationResult| -> Vec<u64> {
+        result
+            .subtask_results
+            .iter()
+            .flat_map(|s| s.testcase_results.iter().map(|t| t.id))
+            .collect()
+    };
+
+    let full = apply_feedback(result.clone(), &problem(Feedback::Full), false).unwrap();
+    assert_eq!(full, result);
+
+    let first_failing =
+        apply_feedback(result.clone(), &problem(Feedback::FirstFailing), false).unwrap();
+    assert_eq!(testcase_ids(&first_failing), vec![4]);
+    assert_eq!(first_failing.subtask_results.len(), 2);
+
+    let subtask_scores =
+        apply_feedback(result.clone(), &problem(Feedback::SubtaskScores), false).unwrap();
+    assert_eq!(subtask_scores.subtask_results.len(), 2);
+    assert!(testcase_ids(&subtask_scores).is_empty());
+
+    let final_score =
+        apply_feedback(result.clone(), &problem(Feedback::FinalScore), false).unwrap();
+    assert!(final_score.subtask_results.is_empty());
+    assert_eq!(final_score.score, result.score);
+
+    assert!(apply_feedback(result.clone(), &problem(Feedback::AfterContest), false).is_none());
+    assert_eq!(
+        apply_feedback(result.clone(), &problem(Feedback::AfterContest), true),
+        Some(result.clone())
+    );
+
+    // testcase 5 is not in the problem anymore, 1 is hidden, 3 shows only the outcome,
+    // 2 is a sample and shown in full whatever its feedback
+    let mut with_testcases = problem(Feedback::Full);
+    with_testcases.subtasks[1].testcases_id = vec![3, 4];
+    let info = |testcase_id, sample, feedback: testcase_info::Feedback| {
+        let mut info = TestcaseInfo {
+            testcase_id,
+            sample: Some(sample),
+            ..Default::default()
+        };
+        info.set_feedback(feedback);
+        info
+    };
+    with_testcases.testcases_info = vec![
+        info(1, false, testcase_info::Feedback::Hidden),
+        info(2, true, testcase_info::Feedback::Hidden),
+        info(3, false, testcase_info::Feedback::Outcome),
+    ];
+    let shown = apply_feedback(result.clone(), &with_testcases, false).unwrap();
+    assert_eq!(testcase_ids(&shown), vec![2, 3, 4]);
+    let resources = |result: &EvaluationResult| -> Vec<bool> {
+        result
+            .subtask_results
+            .iter()
+            .flat_map(|s| {
+                s.testcase_results
+                    .iter()
+                    .map(|t| t.used_resources.is_some())
+            })
+            .collect()
+    };
+    assert_eq!(resources(&shown), vec![true, false, true]);
+
+    // the testcase feedback applies before the problem one
+    with_testcases.set_feedback(Feedback::FirstFailing);
+    let first_failing = apply_feedback(result, &with_testcases, false).unwrap();
+    assert_eq!(testcase_ids(&first_failing), vec![4]);
+}
+
+#[test]
+fn user_ranking_test() {
+    use crate::scoring::{calc_user_ranking, UserProblemResult};
+    use protos::scoring::user::{method::Aggregation, Method};
+    let score = |score: f64| Score {
+        score,
+        ..Default::default()
+    };
+    let result = |problem_id, points, wrong_submissions, minutes: u64| UserProblemResult {
+        problem_id,
+        score: score(points),
+        wrong_submissions,
+        last_change: std::time::Duration::from_secs(minutes * 60),
+    };
+    let method = |aggregation: Aggregation, score_weight, wrong_weight, time_weight| Method {
+        aggregation_method: aggregation as i32,
+        score_weight: score(score_weight),
+        wrong_submission_count_weight: score(wrong_weight),
+        time_secs_weight: score(time_weight),
+    };
+    let users = vec![
+        (
+            String::from("alice"),
+            vec![result(1, 100.0, 2, 10), result(2, 50.0, 0, 30)],
+        ),
+        (
+            String::from("bob"),
+            vec![result(1, 100.0, 0, 20), result(2, 50.0, 1, 40)],
+        ),
+        (String::from("carol"), vec![result(1, 120.0, 0, 50)]),
+    ];
+
+    // same total, the tiebreaker prefers fewer wrong submissions
+    let opts = protos::scoring::User {
+        main: method(Aggregation::Sum, 1.0, 0.0, 0.0),
+        tiebreakers: vec![method(Aggregation::Sum, 0.0, -1.0, 0.0)],
+        icpc: None,
+    };
+    let ranking = calc_user_ranking(&users, &opts);
+    let ranked: Vec<(&str, Vec<f64>)> = ranking
+        .iter()
+        .map(|(user, scores)| (user.as_str(), scores.iter().map(|s| s.score).collect()))
+        .collect();
+    assert_eq!(
+        ranked,
+        vec![
+            ("bob", vec![150.0, -1.0]),
+            ("alice", vec![150.0, -2.0]),
+            ("carol", vec![120.0, 0.0])
+        ]
+    );
+
+    // the best problem only, then the earliest
+    let opts = protos::scoring::User {
+        main: method(Aggregation::Max, 1.0, 0.0, 0.0),
+        tiebreakers: vec![method(Aggregation::Max, 0.0, 0.0, -1.0)],
+        icpc: None,
+    };
+    let ranking = calc_user_ranking(&users, &opts);
+    let order: Vec<&str> = ranking.iter().map(|(user, _)| user.as_str()).collect();
+    assert_eq!(order, vec!["carol", "alice", "bob"]);
+    assert_eq!(ranking[1].1[1].score, -600.0);
+}
+
+#[test]
+fn export_test() {
+    use crate::export::*;
+    use protos::service::submission::get_icpc_scoreboard_response::{Problem, User};
+    let start = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    let minutes = |m: u64| std::time::Duration::from_secs(m * 60);
+    let user = |name: &str, solved, penalty_minutes| User {
+        user: String::from(name),
+        solved,
+        penalty_minutes,
+        problems: vec![Problem {
+            problem_id: 1,
+            rejected: 1,
+            solved_at: Some(minutes(penalty_minutes - 20).into()),
+            first_to_solve: None,
+            pending: None,
+        }],
+    };
+    let standings = icpc_standings(&[
+        user("alice", 1, 30),
+        user("bob, jr", 1, 30),
+        user("carol", 1, 40),
+    ]);
+    let ranks: Vec<u32> = standings.iter().map(|s| s.rank).collect();
+    assert_eq!(ranks, vec![1, 1, 3]);
+
+    let submission = |id, user: &str, minute, verdict| Submission {
+        id,
+        user: String::from(user),
+        problem_id: 1,
+        language: protos::common::ProgrammingLanguage::Cpp,
+        time: start + minutes(minute),
+        verdict,
+        score: verdict.map(|v| if v == Verdict::Accepted { 100.0 } else { 0.0 }),
+    };
+    let results = Results {
+        contest: Contest {
+            name: String::from("Finals"),
+            start_time: start,
+            end_time: Some(start + minutes(300)),
+            freeze_minutes: Some(60),
+            penalty_minutes: Some(20),
+            compilation_errors_penalized: false,
+        },
+        problems: vec![crate::export::Problem {
+            id: 1,
+            name: String::from("A"),
+            long_name: String::from("Apples"),
+        }],
+        teams: vec![crate::export::Team {
+            id: String::from("carol"),
+            name: String::from("Carol's team"),
+            affiliation: Some(String::from("University of Pisa")),
+            country: Some(String::from("ITA")),
+        }],
+        standings,
+        submissions: vec![
+            submission(7, "alice", 5, Some(Verdict::WrongAnswer)),
+            submission(8, "alice", 10, Some(Verdict::Accepted)),
+            submission(9, "carol", 250, None),
+        ],
+    };
+
+    let csv = standings_csv(&results);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "rank,user,solved,penalty,A tries,A time");
+    assert_eq!(lines[2], "1,\"bob, jr\",1,30,2,10");
+    let csv = submissions_csv(&results);
+    assert_eq!(
+        csv.lines().nth(2),
+        Some("8,2020-09-13T12:36:40.000+00:00,0:10:00.000,alice,A,Cpp,Accepted,100")
+    );
+    assert!(csv.ends_with(",Pending,\n"));
+
+    let json: serde_json::Value = serde_json::from_str(&results_json(&results)).unwrap();
+    assert_eq!(
+        json["contest"]["start_time"],
+        "2020-09-13T12:26:40.000+00:00"
+    );
+    assert_eq!(json["standings"][2]["rank"], 3);
+    assert_eq!(json["submissions"][0]["language"], "Cpp");
+
+    // during the freeze: the pending submission has no judgement yet
+    let feed = clics_event_feed(&results, start + minutes(260));
+    let events: Vec<serde_json::Value> = feed
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(events[0]["type"], "contests");
+    assert_eq!(events[0]["data"]["duration"], "5:00:00.000");
+    assert_eq!(
+        events[0]["data"]["scoreboard_freeze_duration"],
+        "1:00:00.000"
+    );
+    let count = |kind: &str| events.iter().filter(|e| e["type"] == kind).count();
+    assert_eq!(count("submissions"), 3);
+    assert_eq!(count("judgements"), 2);
+    assert_eq!(count("teams"), 3);
+    assert_eq!(count("organizations"), 1);
+    let team = events
+        .iter()
+        .find(|e| e["type"] == "teams" && e["id"] == "carol")
+        .unwrap();
+    assert_eq!(team["data"]["name"], "Carol's team");
+    assert_eq!(team["data"]["organization_id"], "University-of-Pisa");
+    assert_eq!(count("languages"), 1);
+    let state = &events.last().unwrap()["data"];
+    assert_eq!(state["frozen"], "2020-09-13T16:26:40.000+00:00");
+    assert!(state["ended"].is_null());
+    let judgement = events.iter().find(|e| e["type"] == "judgements").unwrap();
+    assert_eq!(judgement["data"]["judgement_type_id"], "WA");
+    assert_eq!(judgement["data"]["start_contest_time"], "0:05:00.000");
+}
+
+#[test]
+fn session_token_test() {
+    use crate::session::{sign, verify, Session};
+    let now = get_test_time();
+    let session = Session {
+        id: 0xdead_beef_0042,
+        expires: std::time::UNIX_EPOCH + std::time::Duration::from_secs(3600),
+    };
+    let token = sign(b"secret", &session);
+    assert_eq!(verify(b"secret", &token, now), Some(session));
+
+    // expired, signed with another key, or tampered with
+    assert_eq!(verify(b"secret", &token, session.expires), None);
+    assert_eq!(verify(b"other", &token, now), None);
+    let forged = token.replacen("3600", "7200", 1);
+    assert_ne!(forged, token);
+    assert_eq!(verify(b"secret", &forged, now), None);
+    assert_eq!(verify(b"secret", "42", now), None);
+    assert_eq!(verify(b"secret", "", now), None);
+    // an expiry out of the range of the system time
+    let far = format!("{:x}.{}", session.id, u64::MAX);
+    let signature = {
+        use hmac::{Hmac, Mac};
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(far.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    };
+    assert_eq!(
+        verify(b"secret", &format!("{}.{}", far, signature), now),
+        None
+    );
+}
+
+#[test]
+fn user_window_test() {
+    use crate::window::{can_start, contest_end, user_window, Window};
+    use protos::service::contest::{ContestMetadata, UserWindow};
+    use std::time::{Duration, SystemTime};
+    let at = |minutes: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(minutes * 60);
+    let minutes = |minutes: u64| Some(Duration::from_secs(minutes * 60).into());
+    let contest = ContestMetadata {
+        name: "contest".to_string(),
+        description: String::new(),
+        start_time: Some(at(0).into()),
+        end_time: Some(at(300).into()),
+        freeze: None,
+        tokens: None,
+        window: None,
+    };
+    let settings = UserWindow {
+        user: "user".to_string(),
+        extra_time: minutes(30),
+        start_delay: minutes(10),
+        started_at: None,
+    };
+    assert_eq!(
+        user_window(&contest, None),
+        Window {
+            start: Some(at(0)),
+            end: Some(at(300))
+        }
+    );
+    let window = user_window(&contest, Some(&settings));
+    assert_eq!(
+        window,
+        Window {
+            start: Some(at(10)),
+            end: Some(at(340))
+        }
+    );
+    assert!(!window.is_running(at(5)));
+    assert!(window.is_running(at(320)));
+    assert!(window.is_ended(at(340)));
+    assert!(!can_start(&contest, None, at(10)));
+    assert_eq!(contest_end(&contest, &[settings.clone()]), Some(at(340)));
+
+    // USACO-style: 2 hours from the start chosen by the user, within the end of the contest
+    let contest = ContestMetadata {
+        window: minutes(120),
+        ..contest
+    };
+    assert_eq!(user_window(&contest, None), Window::default());
+    assert!(!can_start(&contest, Some(&settings), at(5)));
+    assert!(can_start(&contest, Some(&settings), at(100)));
+    let started = |minute| UserWindow {
+        started_at: Some(at(minute).into()),
+        ..settings.clone()
+    };
+    assert!(!can_start(&contest, Some(&started(100)), at(100)));
+    assert_eq!(
+        user_window(&contest, Some(&started(100))),
+        Window {
+            start: Some(at(100)),
+            end: Some(at(250))
+        }
+    );
+    assert_eq!(
+        user_window(&contest, Some(&started(250))).end,
+        Some(at(340))
+    );
+    assert_eq!(contest_end(&contest, &[]), Some(at(300)));
+
+    let contest = ContestMetadata {
+        end_time: None,
+        ..contest
+    };
+    assert_eq!(contest_end(&contest, &[settings]), None);
+}
@@ -0,0 +1,51 @@
+//! CMS tokens: participants spend them to see the full evaluation of their submissions.
+
+use protos::service::contest::TokenRules;
+use std::time::{Duration, SystemTime};
+
+/// Tokens that can be used at `now` under `rules`, given the times of the previous uses,
+/// in order. Tokens are generated every `gen_interval` since `start`
+pub fn available_tokens(
+    rules: &TokenRules,
+    start: SystemTime,
+    used: &[SystemTime],
+    now: SystemTime,
+) -> u32 {
+    let gen_interval = rules
+        .gen_interval
+        .clone()
+        .map(Duration::from)
+        .filter(|interval| !interval.is_zero());
+    let generations = |time: SystemTime| {
+        gen_interval.map_or(0, |interval| {
+            (time.duration_since(start).unwrap_or_default().as_nanos() / interval.as_nanos()) as u64
+        })
+    };
+    let cap = |tokens: u64| rules.max.map_or(tokens, |max| tokens.min(max as u64));
+
+    let mut available = cap(rules.initial as u64);
+    let mut generated = 0;
+    let mut generate = |available: u64, time: SystemTime| {
+        let total = generations(time);
+        let new = total.saturating_sub(generated);
+        generated = generated.max(total);
+        cap(available + new * rules.gen_number as u64)
+    };
+    for &time in used {
+        available = generate(available, time).saturating_sub(1);
+    }
+    available = generate(available, now);
+
+    if let Some(total) = rules.total {
+        available = available.min((total as u64).saturating_sub(used.len() as u64));
+    }
+    let too_soon = rules.min_interval.clone().map_or(false, |min_interval| {
+        used.last()
+            .map_or(false, |&last| now < last + Duration::from(min_interval))
+    });
+    if too_soon {
+        0
+    } else {
+        available as u32
+    }
+}
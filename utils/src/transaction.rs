@@ -0,0 +1,240 @@
+//! All-or-nothing changes to the content of a folder.
+//!
+//! New files are first written to a staging folder, then a journal listing every
+//! change is saved and only then the changes are applied. The journal is removed
+//! after the last change, so when recovering a staging folder with a journal is
+//! applied again, while one without a journal is discarded.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{FsStorageHelper, Storage};
+
+pub(crate) const STAGING_FOLDER_NAME: &str = "staging";
+const STAGED_FILES_FOLDER_NAME: &str = "files";
+const JOURNAL_FILE_NAME: &str = "journal";
+const JOURNAL_EXTENSION: &str = "ser";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Journal {
+    // all paths are relative to the folder being changed
+    deletes: Vec<PathBuf>,
+    folders: Vec<PathBuf>,
+    writes: Vec<PathBuf>,
+    deletes_applied: bool,
+}
+
+fn invalid_journal<T: ToString>(e: T) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// A set of changes to a folder, applied on commit or discarded on drop.
+/// Deletions are applied before everything else.
+#[derive(Debug)]
+pub struct Transaction<'a, S: Storage = FsStorageHelper> {
+    storage: &'a S,
+    path: PathBuf,
+    staging_path: PathBuf,
+    journal: Journal,
+    committed: bool,
+}
+
+impl<'a, S: Storage> Transaction<'a, S> {
+    /// Starts a transaction on the folder, creating it if needed
+    pub fn begin(storage: &'a S, path: &Path) -> Result<Self, std::io::Error> {
+        let staging_path = storage.add_folder(
+            &crate::gen_uuid().to_string(),
+            Some(&path.join(STAGING_FOLDER_NAME)),
+        )?;
+        Ok(Self {
+            storage,
+            path: path.to_path_buf(),
+            staging_path,
+            journal: Journal::default(),
+            committed: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn storage(&self) -> &'a S {
+        self.storage
+    }
+
+    /// Returns the path in the storage the new content of the file has to be written to.
+    /// Content staged before for the same file is replaced.
+    pub fn stage(&mut self, relative: impl AsRef<Path>) -> PathBuf {
+        let relative = relative.as_ref().to_path_buf();
+        let path = self.staged_path(&relative);
+        if !self.journal.writes.contains(&relative) {
+            self.journal.writes.push(relative);
+        }
+        path
+    }
+
+    pub fn write(
+        &mut self,
+        relative: impl AsRef<Path>,
+        content: &[u8],
+    ) -> Result<(), std::io::Error> {
+        let path = self.stage(relative);
+        self.storage.write_file(&path, content)
+    }
+
+    /// Stages the content of a file written in pieces
+    pub fn write_with(
+        &mut self,
+        relative: impl AsRef<Path>,
+        write: impl FnOnce(&mut S::Writer) -> Result<(), std::io::Error>,
+    ) -> Result<(), std::io::Error> {
+        let mut writer = self.storage.create_file(&self.stage(relative))?;
+        write(&mut writer)?;
+        writer.flush()?;
+        self.storage.close_file(writer)
+    }
+
+    pub fn write_object<T: Serialize>(
+        &mut self,
+        relative: impl AsRef<Path>,
+        content: &T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let serialized = bincode::serialize(content)?;
+        Ok(self.write(relative, &serialized)?)
+    }
+
+    fn staged_path(&self, relative: &Path) -> PathBuf {
+        self.staging_path
+            .join(STAGED_FILES_FOLDER_NAME)
+            .join(relative)
+    }
+
+    /// Relative paths of the files written so far, along with the path of their staged content
+    pub fn staged_files(&self) -> Vec<(PathBuf, PathBuf)> {
+        self.journal
+            .writes
+            .iter()
+            .map(|relative| (relative.clone(), self.staged_path(relative)))
+            .collect()
+    }
+
+    pub fn deletions(&self) -> &[PathBuf] {
+        &self.journal.deletes
+    }
+
+    pub fn create_folder(&mut self, relative: impl AsRef<Path>) {
+        self.journal.folders.push(relative.as_ref().to_path_buf());
+    }
+
+    /// Deletes a file or folder, if present
+    pub fn delete(&mut self, relative: impl AsRef<Path>) {
+        self.journal.deletes.push(relative.as_ref().to_path_buf());
+    }
+
+    pub fn commit(mut self) -> Result<(), std::io::Error> {
+        // the journal must only point to real files, as it is applied again after a crash
+        for relative in self.journal.writes.iter() {
+            if !self.storage.is_file(&self.staged_path(relative))? {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("Staged file not found: {:?}", relative),
+                ));
+            }
+        }
+        self.storage
+            .save_file_object(
+                Some(&self.staging_path),
+                JOURNAL_FILE_NAME,
+                JOURNAL_EXTENSION,
+                &self.journal,
+            )
+            .map_err(invalid_journal)?;
+        self.committed = true;
+        apply(
+            self.storage,
+            &self.path,
+            &self.staging_path,
+            &mut self.journal,
+        )
+    }
+}
+
+impl<S: Storage> Drop for Transaction<'_, S> {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = self.storage.delete_item(&self.staging_path);
+        }
+    }
+}
+
+// Applying is idempotent, so that it can be repeated after a crash: a staged file
+// is moved again as long as it is there, since a move is not atomic on every storage
+fn apply<S: Storage>(
+    storage: &S,
+    path: &Path,
+    staging_path: &Path,
+    journal: &mut Journal,
+) -> Result<(), std::io::Error> {
+    if !journal.deletes_applied {
+        for relative in journal.deletes.iter() {
+            let target = path.join(relative);
+            if storage.exists(&target)? {
+                storage.delete_item(&target)?;
+            }
+        }
+        // writes could recreate deleted items, so deletions must never be repeated after them
+        journal.deletes_applied = true;
+        storage
+            .save_file_object(
+                Some(staging_path),
+                JOURNAL_FILE_NAME,
+                JOURNAL_EXTENSION,
+                &*journal,
+            )
+            .map_err(invalid_journal)?;
+    }
+    for relative in journal.folders.iter() {
+        storage.create_folder(&path.join(relative))?;
+    }
+    for relative in journal.writes.iter() {
+        let staged = staging_path.join(STAGED_FILES_FOLDER_NAME).join(relative);
+        if storage.is_file(&staged)? {
+            storage.rename(&staged, &path.join(relative))?;
+        }
+    }
+    storage.delete_item(staging_path)
+}
+
+/// Completes the committed transactions on the folder and discards the others
+pub fn recover<S: Storage>(storage: &S, path: &Path) -> Result<(), std::io::Error> {
+    let staging_root = path.join(STAGING_FOLDER_NAME);
+    if storage.is_dir(&staging_root)? {
+        for staging_path in storage.list_folder(&staging_root)? {
+            let journal_path = storage.search_item(
+                Some(&staging_path),
+                JOURNAL_FILE_NAME,
+                Some(JOURNAL_EXTENSION),
+            )?;
+            match journal_path {
+                Some(journal_path) => {
+                    let mut journal = storage
+                        .read_file_object(&journal_path)
+                        .map_err(invalid_journal)?;
+                    apply(storage, path, &staging_path, &mut journal)?;
+                }
+                None => storage.delete_item(&staging_path)?,
+            }
+        }
+        // folders only made of the removed stagings can be gone already
+        if storage.exists(&staging_root)? {
+            storage.delete_item(&staging_root)?;
+        }
+    }
+    if storage.is_dir(path)? {
+        storage.remove_temp_files(Some(path))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Time window of each user in the contest. A user can have the start delayed and extra time
+//! at the end; in contests with a window (USACO-style) the user starts whenever they want
+//! between the start and the end of the contest, then has the duration of the window.
+
+use protos::service::contest::{ContestMetadata, UserWindow};
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Window {
+    pub start: Option<SystemTime>, // not set until the user starts a contest with a window
+    pub end: Option<SystemTime>,
+}
+
+impl Window {
+    pub fn is_started(&self, now: SystemTime) -> bool {
+        self.start.map_or(false, |start| start <= now)
+    }
+    pub fn is_ended(&self, now: SystemTime) -> bool {
+        self.end.map_or(false, |end| end <= now)
+    }
+    pub fn is_running(&self, now: SystemTime) -> bool {
+        self.is_started(now) && !self.is_ended(now)
+    }
+}
+
+fn duration(duration: &Option<protos::common::Duration>) -> Duration {
+    duration.clone().map(Duration::from).unwrap_or_default()
+}
+
+/// Contest times shifted by the start delay of the user: the window of the user without
+/// extra time, or the period in which the user can start a contest with a window
+fn period(contest: &ContestMetadata, settings: Option<&UserWindow>) -> Window {
+    let delay = settings.map_or(Duration::ZERO, |s| duration(&s.start_delay));
+    let shift = |time: &Option<protos::common::Timestamp>| {
+        time.clone().map(|time| SystemTime::from(time) + delay)
+    };
+    Window {
+        start: shift(&contest.start_time),
+        end: shift(&contest.end_time),
+    }
+}
+
+pub fn user_window(contest: &ContestMetadata, settings: Option<&UserWindow>) -> Window {
+    let period = period(contest, settings);
+    let extra = settings.map_or(Duration::ZERO, |s| duration(&s.extra_time));
+    match &contest.window {
+        None => Window {
+            start: period.start,
+            end: period.end.map(|end| end + extra),
+        },
+        Some(window) => {
+            let start = settings
+                .and_then(|s| s.started_at.clone())
+                .map(SystemTime::from);
+            let end = start.map(|start| {
+                let end = start + Duration::from(window.clone());
+                period.end.map_or(end, |period_end| end.min(period_end)) + extra
+            });
+            Window { start, end }
+        }
+    }
+}
+
+/// Whether the user can start the contest at `now`: the contest has a window, the user has
+/// not started yet and the period to start is running
+pub fn can_start(
+    contest: &ContestMetadata,
+    settings: Option<&UserWindow>,
+    now: SystemTime,
+) -> bool {
+    contest.window.is_some()
+        && settings.and_then(|s| s.started_at.as_ref()).is_none()
+        && period(contest, settings).is_running(now)
+}
+
+/// When the window of the last user ends, None if the contest has no end
+pub fn contest_end(contest: &ContestMetadata, settings: &[UserWindow]) -> Option<SystemTime> {
+    settings
+        .iter()
+        .try_fold(period(contest, None).end?, |end, s| {
+            let user_end = period(contest, Some(s)).end? + duration(&s.extra_time);
+            Some(end.max(user_end))
+        })
+}
@@ -137,9 +137,12 @@ pub fn get_compilation_config(
 
 pub fn get_execution_config(
     problem_metadata: Problem,
+    testcase_id: TestcaseId,
+    lang: ProgrammingLanguage,
     input_file_path: PathBuf,
 ) -> SandboxConfiguration {
     let mut execution_config = SandboxConfiguration::default();
+    let limits = utils::limits::testcase_limits(&problem_metadata, testcase_id, lang);
 
     let compilation_dir = PathBuf::from("/tmp/tabox/compilation");
     let execution_dir = PathBuf::from("/tmp/tabox/execution");
@@ -148,9 +151,9 @@ pub fn get_execution_config(
         .mount(execution_dir.clone(), execution_dir.clone(), true)
         .mount(compilation_dir.clone(), compilation_dir.clone(), false) // to read the executable
         .working_directory(execution_dir.clone())
-        .memory_limit(problem_metadata.execution_limits.memory_bytes)
-        .time_limit(problem_metadata.execution_limits.time.secs)
-        .wall_time_limit(5 * problem_metadata.execution_limits.time.secs)
+        .memory_limit(limits.memory_bytes)
+        .time_limit(utils::limits::sandbox_seconds(&limits.time))
+        .wall_time_limit(5 * utils::limits::sandbox_seconds(&limits.time))
         .executable(PathBuf::from(join_path_str(
             compilation_dir,
             EXECUTABLE_NAME.to_string(),
@@ -232,6 +235,7 @@ pub fn get_checker_compilation_config(
 
 pub fn get_checker_execution_config(
     problem_metadata: Problem,
+    execution_time_limit: u64,
     correct_output_file_path: PathBuf,
 ) -> Result<SandboxConfiguration, Error> {
     let mut checker_execution_config = SandboxConfiguration::default();
@@ -243,7 +247,7 @@ pub fn get_checker_execution_config(
         .mount(execution_dir.clone(), execution_dir.clone(), false) // to read the execution output file
         .mount(checker_dir.clone(), checker_dir.clone(), true)
         .working_directory(checker_dir.clone())
-        .wall_time_limit(3 * problem_metadata.execution_limits.time.secs) // all the stuff that the checker reads must have also been written within the time limit
+        .wall_time_limit(3 * execution_time_limit) // all the stuff that the checker reads must have also been written within the time limit
         .executable(
             join_path_str(
                 get_problem_dir_path(problem_metadata.id),
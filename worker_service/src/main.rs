@@ -1,14 +1,15 @@
 use failure::{format_err, Error};
 use futures::lock::Mutex;
 use protos::{
+    auth,
     common::{Duration, ProgrammingLanguage, Resources, Score, Timestamp},
     evaluation::{compilation_result, testcase_result::Outcome, CompilationResult, TestcaseResult},
     scoring,
     service::{
         evaluation::{
             evaluation_client::EvaluationClient, evaluation_file, evaluation_server::Evaluation,
-            problem, EvaluationFile, GetProblemEvaluationFileRequest, GetProblemRequest,
-            GetProblemResponse, GetProblemTestcasesResponse, GetTestcaseRequest,
+            problem, testcase_chunk, EvaluationFile, GetProblemEvaluationFileRequest,
+            GetProblemRequest, GetProblemResponse, GetProblemTestcasesResponse, GetTestcaseRequest,
             GetUpdateInfoRequest, GetUpdateInfoResponse, MockEvaluation, Problem, Testcase,
         },
         worker::{
@@ -17,9 +18,11 @@ use protos::{
             UpdateSourceResponse, UpdateTestcaseRequest, UpdateTestcaseResponse,
         },
     },
+    utils::{authenticated, AuthChannel},
 };
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
+    fs::File,
     path::PathBuf,
     sync::Arc,
     thread::{sleep, spawn},
@@ -29,6 +32,7 @@ use tabox::{
 };
 use tabox::{result::ResourceUsage, Sandbox};
 use tonic::{transport::Server, Request, Response, Status};
+use utils::chunks::ChunkWriter;
 
 #[path = "./configurations.rs"]
 mod configurations;
@@ -57,6 +61,8 @@ type TestcaseId = u64;
 struct FileStatus {
     testcases: HashMap<(ProblemId, TestcaseId), Timestamp>,
     checkers: HashMap<(ProblemId, evaluation_file::Type), Timestamp>,
+    // version of the problem data the files above belong to
+    versions: HashMap<ProblemId, u64>,
 }
 
 impl FileStatus {
@@ -64,6 +70,7 @@ impl FileStatus {
         FileStatus {
             testcases: HashMap::new(),
             checkers: HashMap::new(),
+            versions: HashMap::new(),
         }
     }
 }
@@ -72,6 +79,7 @@ struct EvaluationFileStatus {
     // vectors of id and correspondent timestamp
     testcases: Vec<(ProblemId, TestcaseId, Timestamp)>,
     checkers: Vec<(ProblemId, evaluation_file::Type, Timestamp)>,
+    versions: Vec<(ProblemId, Option<u64>)>,
 }
 
 impl From<GetUpdateInfoResponse> for EvaluationFileStatus {
@@ -79,6 +87,7 @@ impl From<GetUpdateInfoResponse> for EvaluationFileStatus {
         let mut status = EvaluationFileStatus {
             testcases: vec![],
             checkers: vec![],
+            versions: vec![],
         };
         update_info.problems.iter().for_each(|problem_info| {
             for subtask in &problem_info.subtasks {
@@ -103,6 +112,9 @@ impl From<GetUpdateInfoResponse> for EvaluationFileStatus {
                 evaluation_file::Type::Interactor,
                 problem_info.interactor_last_update.clone(),
             ));
+            status
+                .versions
+                .push((problem_info.problem_id, problem_info.version));
         });
         status
     }
@@ -110,29 +122,66 @@ impl From<GetUpdateInfoResponse> for EvaluationFileStatus {
 
 pub struct WorkerService {
     status: Arc<Mutex<FileStatus>>,
-    evaluation_service: EvaluationClient<tonic::transport::Channel>,
+    evaluation_service: EvaluationClient<AuthChannel>,
 }
 
 async fn pull_testcase(
-    evaluation_service: &mut EvaluationClient<tonic::transport::Channel>,
+    evaluation_service: &mut EvaluationClient<AuthChannel>,
     problem_id: ProblemId,
     testcase_id: TestcaseId,
-) -> Testcase {
+) {
     loop {
-        if let Ok(testcase) = evaluation_service
-            .get_testcase(Request::new(GetTestcaseRequest {
-                problem_id,
-                testcase_id,
-            }))
-            .await
-        {
-            return testcase.into_inner().testcase;
+        match download_testcase(evaluation_service, problem_id, testcase_id).await {
+            Ok(()) => return,
+            Err(e) => eprintln!(
+                "An error occurred while pulling testcase {} of problem {}: {:?}",
+                testcase_id, problem_id, e
+            ),
         }
     }
 }
 
+async fn download_testcase(
+    evaluation_service: &mut EvaluationClient<AuthChannel>,
+    problem_id: ProblemId,
+    testcase_id: TestcaseId,
+) -> Result<(), Error> {
+    let mut stream = evaluation_service
+        .get_testcase_stream(Request::new(GetTestcaseRequest {
+            problem_id,
+            testcase_id,
+        }))
+        .await?
+        .into_inner();
+
+    // chunks are written to temporary files, renamed only once the checksum matches
+    let testcase_dir = get_testcase_dir_path(problem_id, testcase_id);
+    std::fs::create_dir_all(&testcase_dir)?;
+    let mut writers: HashMap<testcase_chunk::Part, (PathBuf, ChunkWriter<File>)> = HashMap::new();
+    while let Some(message) = stream.message().await? {
+        let part = message.part();
+        let (_, writer) = match writers.entry(part) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = testcase_dir.join(match part {
+                    testcase_chunk::Part::Input => "input.txt",
+                    testcase_chunk::Part::Output => "output.txt",
+                });
+                let file = File::create(path.with_extension("txt.part"))?;
+                entry.insert((path, ChunkWriter::new(file)))
+            }
+        };
+        writer.write_chunk(&message.chunk)?;
+    }
+    for (path, writer) in writers.into_values() {
+        writer.finish()?;
+        std::fs::rename(path.with_extension("txt.part"), path)?;
+    }
+    Ok(())
+}
+
 async fn pull_checker(
-    evaluation_service: &mut EvaluationClient<tonic::transport::Channel>,
+    evaluation_service: &mut EvaluationClient<AuthChannel>,
     problem_id: ProblemId,
     checker_type: evaluation_file::Type,
 ) -> EvaluationFile {
@@ -150,7 +199,7 @@ async fn pull_checker(
 }
 
 async fn diff_and_update_status(
-    evaluation_service: &mut EvaluationClient<tonic::transport::Channel>,
+    evaluation_service: &mut EvaluationClient<AuthChannel>,
     wrapped_status: &Arc<Mutex<FileStatus>>,
     actual_status: EvaluationFileStatus,
 ) {
@@ -164,23 +213,8 @@ async fn diff_and_update_status(
 
         if old_timestamp.is_none() || timestamp_cmp(&old_timestamp.unwrap(), &actual_timestamp) < 0
         {
-            // pull updated testcase
-            let testcase = pull_testcase(evaluation_service, problem_id, testcase_id).await;
-            // save testcase
-            let testcase_dir = get_testcase_dir_path(problem_id, testcase_id);
-            let input_file_path = testcase_dir.join(PathBuf::from("input.txt"));
-            let output_file_path = testcase_dir.join(PathBuf::from("output.txt"));
-
-            save_file(
-                testcase.input.expect("Testcase input should be present"),
-                input_file_path,
-            )
-            .expect("Unable to save the testcase input");
-            save_file(
-                testcase.output.expect("Testcase output should be present"),
-                output_file_path,
-            )
-            .expect("Unable to save the testcase output");
+            // pull and save updated testcase
+            pull_testcase(evaluation_service, problem_id, testcase_id).await;
         }
     }
 
@@ -204,10 +238,18 @@ async fn diff_and_update_status(
             }
         }
     }
+
+    // every file was pulled, so the problems are now at these versions
+    for (problem_id, version) in actual_status.versions {
+        match version {
+            Some(version) => status.versions.insert(problem_id, version),
+            None => status.versions.remove(&problem_id),
+        };
+    }
 }
 
 async fn pull_join_handler_action(
-    evaluation_service: EvaluationClient<tonic::transport::Channel>,
+    evaluation_service: EvaluationClient<AuthChannel>,
     wrapped_status: Arc<Mutex<FileStatus>>,
 ) {
     let mut evaluation_service = evaluation_service.clone();
@@ -237,7 +279,10 @@ async fn pull_join_handler_action(
 
 impl WorkerService {
     async fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let evaluation_service = EvaluationClient::connect("http://[::1]:50051").await?;
+        let channel = tonic::transport::Endpoint::from_static("http://[::1]:50051")
+            .connect()
+            .await?;
+        let evaluation_service = EvaluationClient::new(authenticated(channel));
 
         Ok(WorkerService {
             status: Arc::new(Mutex::new(FileStatus::new())),
@@ -267,7 +312,10 @@ fn map_used_resources(resource_used: ResourceUsage) -> Resources {
 fn read_score() -> Result<Score, Error> {
     let score = std::fs::read_to_string(PathBuf::from("/tmp/tabox/checker/checker-stdout.txt"))?
         .parse::<f64>()?;
-    Ok(Score { score })
+    Ok(Score {
+        score,
+        ..Default::default()
+    })
 }
 
 #[tonic::async_trait]
@@ -277,6 +325,7 @@ impl Worker for WorkerService {
         &self,
         request: Request<EvaluateSubmissionRequest>,
     ) -> Result<Response<EvaluateSubmissionResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
         let request_inner = request.into_inner();
 
         if let ProgrammingLanguage::None = request_inner.source.lang() {
@@ -298,6 +347,7 @@ impl Worker for WorkerService {
             .into_inner()
             .info;
 
+        let lang = request_inner.source.lang();
         let compilation_config =
             get_compilation_config(problem_metadata.clone(), request_inner.source)
                 .map_err(|e| Status::aborted(e.to_string()))?;
@@ -346,6 +396,13 @@ impl Worker for WorkerService {
                     used_resources: map_used_resources(compilation_res.resource_usage),
                 },
                 testcase_results: vec![],
+                problem_version: self
+                    .status
+                    .lock()
+                    .await
+                    .versions
+                    .get(&request_inner.problem_id)
+                    .copied(),
             }));
         }
         // successfull compilation
@@ -356,6 +413,7 @@ impl Worker for WorkerService {
                 used_resources: map_used_resources(compilation_res.resource_usage),
             },
             testcase_results: vec![], // yet to be evaluated
+            problem_version: None,
         };
 
         if cfg!(debug_assertions) {
@@ -377,18 +435,24 @@ impl Worker for WorkerService {
         let input_file_path = PathBuf::from("/tmp/tabox/execution/stdin.txt");
         let output_file_path = PathBuf::from("/tmp/tabox/execution/stdout.txt");
 
-        let exec_config = get_execution_config(problem_metadata.clone(), input_file_path.clone());
-
         let outer_problem_id = request_inner.problem_id;
         let wrapped_status_copy = &Arc::clone(&self.status);
 
-        evaluation_response.testcase_results = wrapped_status_copy
-            .lock()
-            .await
+        let status = wrapped_status_copy.lock().await;
+        // read under the same lock as the testcases, so that it matches the files used
+        evaluation_response.problem_version = status.versions.get(&outer_problem_id).copied();
+        evaluation_response.testcase_results = status
             .testcases
             .keys()
             .filter(|(problem_id, _)| *problem_id == outer_problem_id)
             .map(|(problem_id, testcase_id)| {
+                let exec_config = get_execution_config(
+                    problem_metadata.clone(),
+                    *testcase_id,
+                    lang,
+                    input_file_path.clone(),
+                );
+
                 // save the testcase input in the file
                 let testcase_dir = get_testcase_dir_path(*problem_id, *testcase_id);
                 std::fs::copy(testcase_dir.join("input.txt"), input_file_path.clone())
@@ -429,15 +493,17 @@ impl Worker for WorkerService {
                     );
                 }
 
-                if !execution_res.status.success() {
+                // the sandbox limit is rounded up to whole seconds, the exact one is checked here
+                let time_limit = std::time::Duration::from(
+                    utils::limits::testcase_limits(&problem_metadata, *testcase_id, lang).time,
+                );
+                let is_tle =
+                    std::time::Duration::from_secs_f64(execution_res.resource_usage.user_cpu_time)
+                        > time_limit;
+                if !execution_res.status.success() || is_tle {
                     let is_mle = exec_config.memory_limit.map_or(false, |memory_limit| {
                         memory_limit < execution_res.resource_usage.memory_usage
                     });
-                    let is_tle = exec_config.time_limit.map_or(false, |time_limit| {
-                        time_limit
-                            < ((execution_res.resource_usage.user_cpu_time * 1_000_000_000f64)
-                                as u64)
-                    });
                     return TestcaseResult {
                         outcome: {
                             if is_tle {
@@ -448,9 +514,13 @@ impl Worker for WorkerService {
                                 Outcome::Rte as i32
                             }
                         },
-                        score: Score { score: 0f64 },
-                        used_resources: map_used_resources(execution_res.resource_usage),
+                        score: Score {
+                            score: 0f64,
+                            ..Default::default()
+                        },
+                        used_resources: Some(map_used_resources(execution_res.resource_usage)),
                         id: *testcase_id,
+                        objective: None,
                     };
                 }
 
@@ -460,6 +530,7 @@ impl Worker for WorkerService {
 
                 let checker_exec_config = get_checker_execution_config(
                     problem_metadata.clone(),
+                    exec_config.time_limit.unwrap_or_default(),
                     testcase_dir.join("output.txt"),
                 )
                 .map_err(|e| {
@@ -482,17 +553,25 @@ impl Worker for WorkerService {
                             Ok(_) => Outcome::Ok as i32,
                             Err(_) => Outcome::CheckerError as i32,
                         },
-                        score: score.unwrap_or(Score { score: 0f64 }),
-                        used_resources: map_used_resources(execution_res.resource_usage),
+                        score: score.unwrap_or(Score {
+                            score: 0f64,
+                            ..Default::default()
+                        }),
+                        used_resources: Some(map_used_resources(execution_res.resource_usage)),
                         id: *testcase_id,
+                        objective: None,
                     }
                 } else {
                     // code returned by the checker execution is not zero
                     TestcaseResult {
                         outcome: Outcome::CheckerError as i32,
-                        score: Score { score: 0f64 },
-                        used_resources: map_used_resources(execution_res.resource_usage),
+                        score: Score {
+                            score: 0f64,
+                            ..Default::default()
+                        },
+                        used_resources: Some(map_used_resources(execution_res.resource_usage)),
                         id: *testcase_id,
+                        objective: None,
                     }
                 }
             })
@@ -502,15 +581,17 @@ impl Worker for WorkerService {
 
     async fn update_testcase(
         &self,
-        _request: Request<UpdateTestcaseRequest>,
+        request: Request<UpdateTestcaseRequest>,
     ) -> Result<Response<UpdateTestcaseResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
         todo!()
     }
 
     async fn update_source(
         &self,
-        _request: Request<UpdateSourceRequest>,
+        request: Request<UpdateSourceRequest>,
     ) -> Result<Response<UpdateSourceResponse>, Status> {
+        auth::require(&request, auth::ADMIN)?;
         todo!()
     }
 }
@@ -521,6 +602,7 @@ fn init_evaluation_service(evaluation_service: &mut MockEvaluation, problem_id:
             id: problem_id,
             scoring: scoring::Problem {
                 method: scoring::problem::Method::MaxSum as i32,
+                relative: None,
             },
             r#type: problem::Type::Other as i32,
             execution_limits: Resources {
@@ -538,6 +620,9 @@ fn init_evaluation_service(evaluation_service: &mut MockEvaluation, problem_id:
                 memory_bytes: 256u64 * 1024u64 * 1024u64,
             },
             subtasks: vec![],
+            time_multipliers: vec![],
+            testcases_info: vec![],
+            feedback: None,
         },
     });
 
@@ -565,7 +650,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("Starting a worker server");
     Server::builder()
-        .add_service(WorkerServer::new(worker_service))
+        .add_service(WorkerServer::with_interceptor(
+            worker_service,
+            auth::RoleCheck::from_env()?,
+        ))
         .serve(addr)
         .await?;
 